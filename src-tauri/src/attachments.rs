@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use crate::error::{AppError, AppResult};
+use crate::models::EvidenceInput;
+
+/// Folder under the app data dir holding the attachment store: evidence of
+/// reminders and files attached to wiki pages.
+pub const ATTACHMENTS_DIR: &str = "evidence";
+
+/// A file in the attachment store. Files are named by the SHA-256 of their
+/// content, so storing the same bytes twice yields the same file.
+pub struct StoredFile {
+    pub path: PathBuf,
+    pub hash: String,
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Where the store keeps content with `hash`. The extension stays so that the
+/// file opens, and is served, as the right type.
+pub fn blob_path(dir: &Path, hash: &str, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", hash, extension.to_lowercase()))
+}
+
+/// Stores `data` in `dir`, reusing the file already holding the same content.
+pub fn store_attachment(dir: &Path, extension: &str, data: &[u8]) -> AppResult<StoredFile> {
+    fs::create_dir_all(dir).map_err(|e| AppError::io("Failed to create evidence dir", e))?;
+    let hash = content_hash(data);
+    let path = blob_path(dir, &hash, extension);
+    put_blob(&path, &hash, data)?;
+    Ok(StoredFile { path, hash })
+}
+
+/// Writes `data`, whose hash is `hash`, to `path` unless an intact copy is
+/// already there; a damaged one is replaced. Only a file of the right size
+/// is read back to compare hashes. The data is written aside and renamed
+/// into place, so no reader sees a partly written file.
+pub fn put_blob(path: &Path, hash: &str, data: &[u8]) -> AppResult<()> {
+    let same_size = fs::metadata(path).is_ok_and(|existing| existing.is_file() && existing.len() == data.len() as u64);
+    if same_size && hash_file(path).is_ok_and(|existing| existing == hash) {
+        return Ok(());
+    }
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let partial = dir.join(format!(".{}.partial", uuid::Uuid::new_v4()));
+    fs::write(&partial, data).map_err(|e| AppError::io("Failed to write file", e))?;
+    fs::rename(&partial, path).map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        AppError::io("Failed to write file", e)
+    })
+}
+
+/// The row recording a stored file as an attachment of a wiki page.
+pub fn page_attachment(page_id: &str, stored: &StoredFile, file_name: &str, description: Option<String>) -> EvidenceInput {
+    let mime_type = mime_type(&stored.path);
+    EvidenceInput {
+        reminder_id: None,
+        page_id: Some(page_id.to_string()),
+        file_type: file_type(mime_type).to_string(),
+        file_path: stored.path.to_string_lossy().to_string(),
+        file_name: file_name.to_string(),
+        file_size: fs::metadata(&stored.path).map(|m| m.len() as i64).unwrap_or(0),
+        mime_type: mime_type.to_string(),
+        thumbnail_path: None,
+        description,
+        metadata: None,
+        content_hash: Some(stored.hash.clone()),
+    }
+}
+
+/// The URL the webview loads a local file from, as convertFileSrc builds it.
+pub fn asset_url(path: &Path) -> String {
+    let path = urlencoding::encode(&path.to_string_lossy()).into_owned();
+    if cfg!(windows) {
+        format!("http://asset.localhost/{}", path)
+    } else {
+        format!("asset://localhost/{}", path)
+    }
+}
+
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mov") => "video/quicktime",
+        Some("mp3") => "audio/mpeg",
+        Some("wav") => "audio/wav",
+        Some("ogg") => "audio/ogg",
+        Some("pdf") => "application/pdf",
+        Some("doc") | Some("docx") => "application/msword",
+        Some("xls") | Some("xlsx") => "application/vnd.ms-excel",
+        Some("txt") => "text/plain",
+        Some("json") => "application/json",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+// The kinds the reminder evidence list shows icons for
+pub fn file_type(mime_type: &str) -> &'static str {
+    match mime_type.split('/').next() {
+        Some("image") => "image",
+        Some("video") => "video",
+        Some("audio") => "audio",
+        _ => "document",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_content_is_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = store_attachment(dir.path(), "PNG", b"image").unwrap();
+        let second = store_attachment(dir.path(), "png", b"image").unwrap();
+
+        assert_eq!(first.path, second.path);
+        assert_eq!(first.path, dir.path().join(format!("{}.png", content_hash(b"image"))));
+        assert_eq!(hash_file(&first.path).unwrap(), first.hash);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn a_damaged_copy_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let stored = store_attachment(dir.path(), "txt", b"content").unwrap();
+
+        fs::write(&stored.path, b"cut").unwrap();
+        store_attachment(dir.path(), "txt", b"content").unwrap();
+        assert_eq!(fs::read(&stored.path).unwrap(), b"content");
+
+        // One of the same size is compared by hash
+        fs::write(&stored.path, b"CONTENT").unwrap();
+        store_attachment(dir.path(), "txt", b"content").unwrap();
+        assert_eq!(fs::read(&stored.path).unwrap(), b"content");
+    }
+}
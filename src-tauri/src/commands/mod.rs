@@ -1,7 +1,40 @@
 use log::{info, debug, warn};
 use sqlx::SqlitePool;
 use tauri::{AppHandle, Emitter};
-use crate::models::Reminder;
+use std::sync::Mutex;
+use crate::error::{AppError, AppResult};
+use crate::models::{Reminder, WikiPageList};
+use crate::recurrence::{self, Recurrence};
+
+// Maximum number of fire times returned by preview_occurrences
+const MAX_PREVIEW_OCCURRENCES: u32 = 100;
+
+/// Outcome of opening the database at startup, kept so the UI can explain why
+/// reminders are unavailable even if it missed the `database-error` event.
+#[derive(Default)]
+pub struct DatabaseStatus {
+    error: Mutex<Option<String>>,
+}
+
+impl DatabaseStatus {
+    pub fn set_error(&self, message: String) {
+        *self.error.lock().unwrap() = Some(message);
+    }
+}
+
+// Validates the schedule and moves the start onto the first time the rule actually fires
+fn validate_schedule(time: &str, frequency: &str) -> AppResult<(String, Recurrence)> {
+    let frequency = frequency.parse::<Recurrence>().map_err(AppError::Validation)?;
+    if let Recurrence::Rule(_) = frequency {
+        let start = recurrence::parse_time(time)
+            .ok_or_else(|| AppError::validation(format!("Invalid reminder time: '{}'", time)))?;
+        let first = frequency
+            .align_start(start)
+            .ok_or_else(|| AppError::validation("Recurrence rule never fires after the reminder time"))?;
+        return Ok((recurrence::format_time(first), frequency));
+    }
+    Ok((time.to_string(), frequency))
+}
 
 #[tauri::command]
 pub async fn add_reminder(
@@ -12,35 +45,41 @@ pub async fn add_reminder(
     frequency: String,
     pool: tauri::State<'_, SqlitePool>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> AppResult<()> {
     info!("Adding reminder: title='{}', category='{}', time='{}', frequency='{}'", title, category, time, frequency);
+
+    let (time, frequency) = validate_schedule(&time, &frequency).map_err(|e| {
+        warn!("Rejected reminder: {}", e);
+        e
+    })?;
     
     crate::database::add_reminder(&pool, &title, &description, &time, &category, &frequency)
         .await
         .map_err(|e| {
             warn!("Failed to add reminder: {}", e);
-            e.to_string()
+            AppError::from(e)
         })?;
     
     // Broadcast update event to all windows
     let reminders = crate::database::get_all_reminders(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     let _ = app.emit("reminders-updated", &reminders);
     info!("Broadcasted reminders-updated event to all windows");
+
+    crate::notifications::rearm(&app);
     
     Ok(())
 }
 
 #[tauri::command]
-pub async fn get_reminders(pool: tauri::State<'_, SqlitePool>) -> Result<Vec<Reminder>, String> {
+pub async fn get_reminders(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<Reminder>> {
     debug!("get_reminders command called");
     
     let reminders = crate::database::get_all_reminders(&pool)
         .await
         .map_err(|e| {
             warn!("Failed to get reminders: {}", e);
-            e.to_string()
+            AppError::from(e)
         })?;
     
     info!("Returning {} reminders to frontend", reminders.len());
@@ -48,33 +87,9 @@ pub async fn get_reminders(pool: tauri::State<'_, SqlitePool>) -> Result<Vec<Rem
 }
 
 #[tauri::command]
-pub async fn get_due_reminders(pool: tauri::State<'_, SqlitePool>) -> Result<Vec<Reminder>, String> {
-    debug!("Fetching due reminders");
-    
-    let rows = sqlx::query_as::<_, (i64, String, String, String, i64, String, String)>(
-        r#"
-        SELECT id, title, description, time, completed, category, frequency
-        FROM reminders
-        WHERE completed = 0 AND datetime(time) <= datetime('now')
-        ORDER BY time ASC
-        "#
-    )
-    .fetch_all(pool.inner())
-    .await
-    .map_err(|e| e.to_string())?;
-    
-    let reminders: Vec<Reminder> = rows
-        .into_iter()
-        .map(|(id, title, description, time, completed, category, frequency)| Reminder {
-            id: id as u32,
-            title,
-            description,
-            time,
-            completed: completed != 0,
-            category,
-            frequency,
-        })
-        .collect();
+pub async fn get_due_reminders(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<Reminder>> {
+    let reminders = crate::database::get_due_reminders(&pool)
+        .await?;
     
     info!("Retrieved {} due reminders", reminders.len());
     Ok(reminders)
@@ -85,22 +100,23 @@ pub async fn toggle_reminder(
     id: u32,
     pool: tauri::State<'_, SqlitePool>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> AppResult<()> {
     debug!("Toggling reminder with id={}", id);
     
     crate::database::toggle_reminder(&pool, id)
         .await
         .map_err(|e| {
             warn!("Failed to toggle reminder: {}", e);
-            e.to_string()
+            AppError::from(e)
         })?;
     
     // Broadcast update event to all windows
     let reminders = crate::database::get_all_reminders(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     let _ = app.emit("reminders-updated", &reminders);
     info!("Broadcasted reminders-updated event after toggle");
+
+    crate::notifications::rearm(&app);
     
     Ok(())
 }
@@ -110,28 +126,35 @@ pub async fn delete_reminder(
     id: u32,
     pool: tauri::State<'_, SqlitePool>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> AppResult<()> {
     debug!("Deleting reminder with id={}", id);
     
     crate::database::delete_reminder(&pool, id)
         .await
         .map_err(|e| {
             warn!("Failed to delete reminder: {}", e);
-            e.to_string()
+            AppError::from(e)
         })?;
     
     // Broadcast update event to all windows
     let reminders = crate::database::get_all_reminders(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     let _ = app.emit("reminders-updated", &reminders);
     info!("Broadcasted reminders-updated event after delete");
+
+    crate::notifications::rearm(&app);
     
     Ok(())
 }
 
+// Returns the error that prevented the database from opening, if any
+#[tauri::command]
+pub fn get_database_status(status: tauri::State<'_, DatabaseStatus>) -> AppResult<Option<String>> {
+    Ok(status.error.lock().unwrap().clone())
+}
+
 #[tauri::command]
-pub fn set_debug_mode(enabled: bool) -> Result<(), String> {
+pub fn set_debug_mode(enabled: bool) -> AppResult<()> {
     // Update log level dynamically
     let log_level = if enabled { "debug" } else { "info" };
     std::env::set_var("RUST_LOG", log_level);
@@ -141,7 +164,7 @@ pub fn set_debug_mode(enabled: bool) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub fn get_debug_mode() -> Result<bool, String> {
+pub fn get_debug_mode() -> AppResult<bool> {
     let log_level = std::env::var("RUST_LOG").unwrap_or_else(|_| "debug".to_string());
     Ok(log_level == "debug")
 }
@@ -151,12 +174,11 @@ pub fn get_debug_mode() -> Result<bool, String> {
 pub async fn broadcast_reminders(
     pool: tauri::State<'_, SqlitePool>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> AppResult<()> {
     debug!("Broadcasting current reminders to all windows");
     
     let reminders = crate::database::get_all_reminders(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     
     let _ = app.emit("reminders-updated", &reminders);
     info!("Broadcasted {} reminders to all windows", reminders.len());
@@ -165,6 +187,7 @@ pub async fn broadcast_reminders(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_reminder(
     id: u32,
     title: String,
@@ -174,32 +197,140 @@ pub async fn update_reminder(
     frequency: String,
     pool: tauri::State<'_, SqlitePool>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> AppResult<()> {
     info!("Updating reminder id={}: title='{}', category='{}', time='{}', frequency='{}'", 
           id, title, category, time, frequency);
+
+    let (time, frequency) = validate_schedule(&time, &frequency).map_err(|e| {
+        warn!("Rejected reminder update: {}", e);
+        e
+    })?;
     
-    sqlx::query(
+    let result = sqlx::query(
         "UPDATE reminders SET title = ?, description = ?, time = ?, category = ?, frequency = ? WHERE id = ?"
     )
     .bind(&title)
     .bind(&description)
     .bind(&time)
     .bind(&category)
-    .bind(&frequency)
+    .bind(frequency.to_string())
     .bind(id as i64)
     .execute(pool.inner())
     .await
     .map_err(|e| {
         warn!("Failed to update reminder: {}", e);
-        e.to_string()
+        AppError::from(e)
     })?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found(format!("Reminder {} not found", id)));
+    }
     
     // Broadcast update event to all windows
     let reminders = crate::database::get_all_reminders(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
     let _ = app.emit("reminders-updated", &reminders);
     info!("Broadcasted reminders-updated event after update");
+
+    crate::notifications::rearm(&app);
     
     Ok(())
 }
+
+#[tauri::command]
+pub async fn link_reminder_to_page(
+    reminder_id: u32,
+    page_id: String,
+    pool: tauri::State<'_, SqlitePool>,
+    app: AppHandle,
+) -> AppResult<()> {
+    crate::database::link_reminder_page(&pool, reminder_id, &page_id, chrono::Utc::now().timestamp()).await?;
+
+    // Linked page titles are part of the payload
+    let reminders = crate::database::get_all_reminders(&pool)
+        .await?;
+    let _ = app.emit("reminders-updated", &reminders);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unlink_reminder_from_page(
+    reminder_id: u32,
+    page_id: String,
+    pool: tauri::State<'_, SqlitePool>,
+    app: AppHandle,
+) -> AppResult<()> {
+    crate::database::unlink_reminder_page(&pool, reminder_id, &page_id).await?;
+
+    let reminders = crate::database::get_all_reminders(&pool)
+        .await?;
+    let _ = app.emit("reminders-updated", &reminders);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_reminder_pages(
+    reminder_id: u32,
+    pool: tauri::State<'_, SqlitePool>,
+) -> AppResult<Vec<WikiPageList>> {
+    crate::database::list_reminder_pages(&pool, reminder_id).await
+}
+
+#[tauri::command]
+pub async fn get_page_reminders(
+    page_id: String,
+    pool: tauri::State<'_, SqlitePool>,
+) -> AppResult<Vec<Reminder>> {
+    crate::database::list_page_reminders(&pool, &page_id).await
+}
+
+#[tauri::command]
+pub fn preview_occurrences(rule: String, start: String, n: u32) -> AppResult<Vec<String>> {
+    debug!("Previewing {} occurrences of '{}' from {}", n, rule, start);
+
+    let recurrence = rule.parse::<Recurrence>().map_err(AppError::Validation)?;
+    let start = recurrence::parse_time(&start)
+        .ok_or_else(|| AppError::validation(format!("Invalid start time: '{}'", start)))?;
+
+    Ok(recurrence
+        .preview(start, n.min(MAX_PREVIEW_OCCURRENCES) as usize)
+        .into_iter()
+        .map(recurrence::format_time)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_is_capped() {
+        let times = preview_occurrences("daily".into(), "2024-01-01T09:00".into(), 10_000).unwrap();
+        assert_eq!(times.len(), MAX_PREVIEW_OCCURRENCES as usize);
+        assert_eq!(times[0], "2024-01-01T09:00");
+
+        let times = preview_occurrences("FREQ=WEEKLY;BYDAY=MO,FR".into(), "2024-01-01T09:00".into(), 500).unwrap();
+        assert_eq!(times.len(), MAX_PREVIEW_OCCURRENCES as usize);
+    }
+
+    #[test]
+    fn preview_of_a_finite_rule_stops_at_its_end() {
+        let times = preview_occurrences("FREQ=DAILY;COUNT=3".into(), "2024-01-01T09:00".into(), 50).unwrap();
+        assert_eq!(times.len(), 3);
+        assert!(preview_occurrences("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=31".into(), "2024-01-01T09:00".into(), 50)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn preview_rejects_bad_input() {
+        assert!(matches!(
+            preview_occurrences("FREQ=SOMETIMES".into(), "2024-01-01T09:00".into(), 5),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            preview_occurrences("daily".into(), "tomorrow".into(), 5),
+            Err(AppError::Validation(_))
+        ));
+    }
+}
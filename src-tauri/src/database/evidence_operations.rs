@@ -1,20 +1,39 @@
-use sqlx::SqlitePool;
-use log::{info, error};
+use std::path::PathBuf;
+use sqlx::{SqliteConnection, SqlitePool};
+use log::{debug, info, warn, error};
+use crate::error::{AppError, AppResult};
 use crate::models::{Evidence, EvidenceInput};
 
-pub async fn add_evidence(pool: &SqlitePool, input: EvidenceInput) -> Result<Evidence, String> {
-    info!("Adding evidence for reminder_id: {}", input.reminder_id);
+pub async fn add_evidence(pool: &SqlitePool, input: EvidenceInput) -> AppResult<Evidence> {
+    match (&input.reminder_id, &input.page_id) {
+        (Some(reminder_id), None) => info!("Adding evidence for reminder_id: {}", reminder_id),
+        (None, Some(page_id)) => info!("Adding attachment for page_id: {}", page_id),
+        _ => return Err(AppError::validation("Evidence must belong to either a reminder or a wiki page")),
+    }
+    
+    let id = insert_evidence(&mut *pool.acquire().await?, &input).await.map_err(|e| {
+        error!("Failed to add evidence: {}", e);
+        e
+    })?;
     
+    // Fetch the created evidence
+    get_evidence_by_id(pool, id).await
+}
+
+// Inserts an evidence row inside the caller's transaction and returns its id
+pub async fn insert_evidence(conn: &mut SqliteConnection, input: &EvidenceInput) -> AppResult<i64> {
     let result = sqlx::query(
         r#"
         INSERT INTO evidence (
-            reminder_id, file_type, file_path, file_name, 
-            file_size, mime_type, thumbnail_path, description, metadata
+            reminder_id, page_id, file_type, file_path, file_name, 
+            file_size, mime_type, thumbnail_path, description, metadata,
+            content_hash
         )
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(input.reminder_id)
+    .bind(&input.page_id)
     .bind(&input.file_type)
     .bind(&input.file_path)
     .bind(&input.file_name)
@@ -23,41 +42,35 @@ pub async fn add_evidence(pool: &SqlitePool, input: EvidenceInput) -> Result<Evi
     .bind(&input.thumbnail_path)
     .bind(&input.description)
     .bind(&input.metadata)
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        error!("Failed to add evidence: {}", e);
-        format!("Database error: {}", e)
-    })?;
-    
-    let id = result.last_insert_rowid();
+    .bind(&input.content_hash)
+    .execute(conn)
+    .await?;
     
-    // Fetch the created evidence
-    get_evidence_by_id(pool, id).await
+    Ok(result.last_insert_rowid())
 }
 
-pub async fn get_evidence_by_id(pool: &SqlitePool, id: i64) -> Result<Evidence, String> {
+pub async fn get_evidence_by_id(pool: &SqlitePool, id: i64) -> AppResult<Evidence> {
     sqlx::query_as::<_, Evidence>(
         r#"
-        SELECT id, reminder_id, file_type, file_path, file_name,
+        SELECT id, reminder_id, page_id, file_type, file_path, file_name,
                file_size, mime_type, thumbnail_path, description,
-               metadata, created_at
+               metadata, content_hash, created_at
         FROM evidence
         WHERE id = ?
         "#
     )
     .bind(id)
-    .fetch_one(pool)
-    .await
-    .map_err(|e| format!("Evidence not found: {}", e))
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::not_found(format!("Evidence {} not found", id)))
 }
 
-pub async fn get_evidence_by_reminder(pool: &SqlitePool, reminder_id: i64) -> Result<Vec<Evidence>, String> {
+pub async fn get_evidence_by_reminder(pool: &SqlitePool, reminder_id: i64) -> AppResult<Vec<Evidence>> {
     sqlx::query_as::<_, Evidence>(
         r#"
-        SELECT id, reminder_id, file_type, file_path, file_name,
+        SELECT id, reminder_id, page_id, file_type, file_path, file_name,
                file_size, mime_type, thumbnail_path, description,
-               metadata, created_at
+               metadata, content_hash, created_at
         FROM evidence
         WHERE reminder_id = ?
         ORDER BY created_at DESC
@@ -68,16 +81,101 @@ pub async fn get_evidence_by_reminder(pool: &SqlitePool, reminder_id: i64) -> Re
     .await
     .map_err(|e| {
         error!("Failed to get evidence: {}", e);
-        format!("Database error: {}", e)
+        AppError::from(e)
     })
 }
 
-pub async fn get_all_evidence(pool: &SqlitePool) -> Result<Vec<Evidence>, String> {
+pub async fn get_evidence_by_page(pool: &SqlitePool, page_id: &str) -> AppResult<Vec<Evidence>> {
     sqlx::query_as::<_, Evidence>(
         r#"
-        SELECT id, reminder_id, file_type, file_path, file_name,
+        SELECT id, reminder_id, page_id, file_type, file_path, file_name,
                file_size, mime_type, thumbnail_path, description,
-               metadata, created_at
+               metadata, content_hash, created_at
+        FROM evidence
+        WHERE page_id = ?
+        ORDER BY created_at DESC, id DESC
+        "#
+    )
+    .bind(page_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to get page attachments: {}", e);
+        AppError::from(e)
+    })
+}
+
+// Attachments of wiki pages added before `created_before`, a UTC
+// "YYYY-MM-DD HH:MM:SS" timestamp as the created_at column stores them
+pub async fn get_page_attachments_before(conn: &mut SqliteConnection, created_before: &str) -> AppResult<Vec<Evidence>> {
+    sqlx::query_as::<_, Evidence>(
+        r#"
+        SELECT id, reminder_id, page_id, file_type, file_path, file_name,
+               file_size, mime_type, thumbnail_path, description,
+               metadata, content_hash, created_at
+        FROM evidence
+        WHERE page_id IS NOT NULL AND created_at < ?
+        ORDER BY id
+        "#
+    )
+    .bind(created_before)
+    .fetch_all(conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to get page attachments: {}", e);
+        AppError::from(e)
+    })
+}
+
+// Whether `needle` occurs in any page, trashed ones included, or any revision.
+// A revision stored as a delta holds the text it adds in `delta`, so a name
+// that occurs in the revision occurs in its delta or in its base.
+pub async fn is_referenced_by_wiki(conn: &mut SqliteConnection, needle: &str) -> AppResult<bool> {
+    let (referenced,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM wiki_pages WHERE instr(content, ?1) > 0)
+            OR EXISTS (
+                SELECT 1 FROM wiki_revisions
+                WHERE instr(content, ?1) > 0 OR instr(COALESCE(delta, ''), ?1) > 0
+            )
+        "#
+    )
+    .bind(needle)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(referenced)
+}
+
+// Attachments of trashed pages deleted before `older_than`, or of all
+// trashed pages, so their files can be removed when the trash is purged
+pub async fn get_trashed_page_attachments(
+    conn: &mut SqliteConnection,
+    older_than: Option<i64>,
+) -> AppResult<Vec<Evidence>> {
+    let attachments = sqlx::query_as::<_, Evidence>(
+        r#"
+        SELECT e.id, e.reminder_id, e.page_id, e.file_type, e.file_path, e.file_name,
+               e.file_size, e.mime_type, e.thumbnail_path, e.description,
+               e.metadata, e.content_hash, e.created_at
+        FROM evidence e
+        JOIN wiki_pages p ON p.id = e.page_id
+        WHERE p.deleted_at IS NOT NULL AND (?1 IS NULL OR p.deleted_at < ?1)
+        "#
+    )
+    .bind(older_than)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(attachments)
+}
+
+pub async fn get_all_evidence(pool: &SqlitePool) -> AppResult<Vec<Evidence>> {
+    sqlx::query_as::<_, Evidence>(
+        r#"
+        SELECT id, reminder_id, page_id, file_type, file_path, file_name,
+               file_size, mime_type, thumbnail_path, description,
+               metadata, content_hash, created_at
         FROM evidence
         ORDER BY created_at DESC
         "#
@@ -86,7 +184,7 @@ pub async fn get_all_evidence(pool: &SqlitePool) -> Result<Vec<Evidence>, String
     .await
     .map_err(|e| {
         error!("Failed to get all evidence: {}", e);
-        format!("Database error: {}", e)
+        AppError::from(e)
     })
 }
 
@@ -94,8 +192,8 @@ pub async fn update_evidence_description(
     pool: &SqlitePool,
     id: i64,
     description: Option<String>
-) -> Result<(), String> {
-    sqlx::query(
+) -> AppResult<()> {
+    let result = sqlx::query(
         r#"
         UPDATE evidence
         SET description = ?
@@ -108,69 +206,177 @@ pub async fn update_evidence_description(
     .await
     .map_err(|e| {
         error!("Failed to update evidence description: {}", e);
-        format!("Database error: {}", e)
+        AppError::from(e)
     })?;
     
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found(format!("Evidence {} not found", id)));
+    }
+    
     Ok(())
 }
 
-pub async fn delete_evidence(pool: &SqlitePool, id: i64) -> Result<(), String> {
+pub async fn delete_evidence(pool: &SqlitePool, id: i64) -> AppResult<()> {
     info!("Deleting evidence: {}", id);
     
-    // First get the evidence to delete the file
+    // First get the evidence to release its file
     let evidence = get_evidence_by_id(pool, id).await?;
     
-    // Delete from database
-    sqlx::query("DELETE FROM evidence WHERE id = ?")
-        .bind(id)
-        .execute(pool)
-        .await
-        .map_err(|e| {
-            error!("Failed to delete evidence: {}", e);
-            format!("Database error: {}", e)
-        })?;
-    
-    // Try to delete the physical file (if it's a local file)
-    if !evidence.file_path.starts_with("http") {
-        let _ = std::fs::remove_file(&evidence.file_path);
-        if let Some(thumb) = &evidence.thumbnail_path {
-            let _ = std::fs::remove_file(thumb);
-        }
-    }
+    // The write lock keeps an upload of the same content from referring to
+    // the file between the check and its removal
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    delete_evidence_rows(&mut tx, std::slice::from_ref(&evidence)).await?;
+    release_evidence_files(&mut tx, std::slice::from_ref(&evidence)).await;
+    tx.commit().await?;
     
     info!("Evidence deleted successfully");
     Ok(())
 }
 
-pub async fn delete_evidence_by_reminder(pool: &SqlitePool, reminder_id: i64) -> Result<(), String> {
+// Deletes the rows of a reminder's evidence inside the caller's transaction and
+// returns them, so their files can be removed once the transaction commits
+pub async fn delete_evidence_by_reminder(
+    conn: &mut SqliteConnection,
+    reminder_id: i64,
+) -> Result<Vec<Evidence>, sqlx::Error> {
     info!("Deleting all evidence for reminder: {}", reminder_id);
     
-    // Get all evidence first to delete files
-    let evidence_list = get_evidence_by_reminder(pool, reminder_id).await?;
+    let evidence_list = sqlx::query_as::<_, Evidence>(
+        r#"
+        SELECT id, reminder_id, page_id, file_type, file_path, file_name,
+               file_size, mime_type, thumbnail_path, description,
+               metadata, content_hash, created_at
+        FROM evidence
+        WHERE reminder_id = ?
+        "#
+    )
+    .bind(reminder_id)
+    .fetch_all(&mut *conn)
+    .await?;
     
-    // Delete from database
     sqlx::query("DELETE FROM evidence WHERE reminder_id = ?")
         .bind(reminder_id)
-        .execute(pool)
-        .await
-        .map_err(|e| {
-            error!("Failed to delete evidence: {}", e);
-            format!("Database error: {}", e)
-        })?;
+        .execute(&mut *conn)
+        .await?;
     
-    // Try to delete physical files
+    Ok(evidence_list)
+}
+
+// Reminder evidence rows whose reminder no longer exists
+pub async fn get_orphaned_evidence(pool: &SqlitePool) -> AppResult<Vec<Evidence>> {
+    sqlx::query_as::<_, Evidence>(
+        r#"
+        SELECT e.id, e.reminder_id, e.page_id, e.file_type, e.file_path, e.file_name,
+               e.file_size, e.mime_type, e.thumbnail_path, e.description,
+               e.metadata, e.content_hash, e.created_at
+        FROM evidence e
+        LEFT JOIN reminders r ON r.id = e.reminder_id
+        WHERE e.reminder_id IS NOT NULL AND r.id IS NULL
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to scan for orphaned evidence: {}", e);
+        AppError::from(e)
+    })
+}
+
+// Removes the given evidence rows in one transaction and then their files
+pub async fn purge_evidence(pool: &SqlitePool, evidence_list: &[Evidence]) -> AppResult<()> {
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    delete_evidence_rows(&mut tx, evidence_list).await?;
+    release_evidence_files(&mut tx, evidence_list).await;
+    tx.commit().await?;
+    
+    info!("Purged {} evidence items", evidence_list.len());
+    Ok(())
+}
+
+// Deletes the given evidence rows inside the caller's transaction; their files
+// are released with release_evidence_files in the same transaction
+pub async fn delete_evidence_rows(conn: &mut SqliteConnection, evidence_list: &[Evidence]) -> AppResult<()> {
     for evidence in evidence_list {
-        if !evidence.file_path.starts_with("http") {
-            let _ = std::fs::remove_file(&evidence.file_path);
-            if let Some(thumb) = &evidence.thumbnail_path {
-                let _ = std::fs::remove_file(thumb);
-            }
-        }
+        sqlx::query("DELETE FROM evidence WHERE id = ?")
+            .bind(evidence.id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete evidence: {}", e);
+                AppError::from(e)
+            })?;
     }
     
     Ok(())
 }
 
+// Records the hash of a file stored before evidence was hashed, on every row
+// sharing it that has none yet
+pub async fn set_evidence_hash(pool: &SqlitePool, file_path: &str, content_hash: &str) -> AppResult<u64> {
+    let result = sqlx::query("UPDATE evidence SET content_hash = ? WHERE file_path = ? AND content_hash IS NULL")
+        .bind(content_hash)
+        .bind(file_path)
+        .execute(pool)
+        .await?;
+    
+    Ok(result.rows_affected())
+}
+
+// Releases the files and thumbnails of evidence rows deleted in the caller's
+// transaction; links have no file. Files are shared by rows with the same
+// content, so a file is only removed once no row refers to it any more. The
+// transaction should hold the write lock, taken with BEGIN IMMEDIATE, so no
+// row can come to refer to a file between the check and its removal.
+pub async fn release_evidence_files(conn: &mut SqliteConnection, evidence_list: &[Evidence]) {
+    let paths: Vec<PathBuf> = evidence_list
+        .iter()
+        .filter(|evidence| !evidence.file_path.starts_with("http"))
+        .flat_map(|evidence| std::iter::once(&evidence.file_path).chain(evidence.thumbnail_path.as_ref()))
+        .map(PathBuf::from)
+        .collect();
+    remove_unreferenced_files(conn, &paths).await;
+}
+
+// Removes those of `paths` that no evidence row refers to, as its file or its
+// thumbnail, under the write lock. A file whose references can't be counted
+// is kept.
+pub async fn release_files(pool: &SqlitePool, paths: &[PathBuf]) {
+    let mut tx = match pool.begin_with("BEGIN IMMEDIATE").await {
+        Ok(tx) => tx,
+        Err(e) => {
+            warn!("Keeping {} evidence files, their references could not be counted: {}", paths.len(), e);
+            return;
+        }
+    };
+    remove_unreferenced_files(&mut tx, paths).await;
+    if let Err(e) = tx.commit().await {
+        warn!("Failed to end evidence file release: {}", e);
+    }
+}
+
+async fn remove_unreferenced_files(conn: &mut SqliteConnection, paths: &[PathBuf]) {
+    for path in paths {
+        let referenced = sqlx::query_as::<_, (bool,)>(
+            "SELECT EXISTS (SELECT 1 FROM evidence WHERE file_path = ?1 OR thumbnail_path = ?1)"
+        )
+        .bind(path.to_string_lossy())
+        .fetch_one(&mut *conn)
+        .await;
+        
+        match referenced {
+            Ok((false,)) => {
+                if let Err(e) = std::fs::remove_file(path) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        warn!("Failed to remove evidence file {}: {}", path.display(), e);
+                    }
+                }
+            }
+            Ok((true,)) => debug!("Evidence file {} is still referenced", path.display()),
+            Err(e) => warn!("Keeping evidence file {}, its references could not be counted: {}", path.display(), e),
+        }
+    }
+}
+
 // Implement FromRow for Evidence manually
 impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Evidence {
     fn from_row(row: &sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
@@ -179,6 +385,7 @@ impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Evidence {
         Ok(Evidence {
             id: row.try_get("id")?,
             reminder_id: row.try_get("reminder_id")?,
+            page_id: row.try_get("page_id")?,
             file_type: row.try_get("file_type")?,
             file_path: row.try_get("file_path")?,
             file_name: row.try_get("file_name")?,
@@ -188,6 +395,81 @@ impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for Evidence {
             description: row.try_get("description")?,
             metadata: row.try_get("metadata")?,
             created_at: row.try_get("created_at")?,
+            content_hash: row.try_get("content_hash")?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::attachments::{page_attachment, store_attachment};
+    use crate::database::pool_with_page;
+
+    async fn attach(pool: &SqlitePool, dir: &Path, data: &[u8], thumbnail: Option<&Path>) -> Evidence {
+        let stored = store_attachment(dir, "txt", data).unwrap();
+        let mut input = page_attachment("page", &stored, "file.txt", None);
+        input.thumbnail_path = thumbnail.map(|path| path.to_string_lossy().to_string());
+        add_evidence(pool, input).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_shared_file_stays_until_its_last_row_is_deleted() {
+        let pool = pool_with_page().await;
+        let dir = tempfile::tempdir().unwrap();
+        let first = attach(&pool, dir.path(), b"shared", None).await;
+        let second = attach(&pool, dir.path(), b"shared", None).await;
+        let other = attach(&pool, dir.path(), b"other", None).await;
+        assert_eq!(first.file_path, second.file_path);
+        let path = PathBuf::from(&first.file_path);
+
+        delete_evidence(&pool, first.id).await.unwrap();
+        assert!(path.exists());
+
+        delete_evidence(&pool, second.id).await.unwrap();
+        assert!(!path.exists());
+        assert!(Path::new(&other.file_path).exists());
+    }
+
+    #[tokio::test]
+    async fn a_file_used_as_a_thumbnail_is_kept() {
+        let pool = pool_with_page().await;
+        let dir = tempfile::tempdir().unwrap();
+        let image = attach(&pool, dir.path(), b"image", None).await;
+        let video = attach(&pool, dir.path(), b"video", Some(Path::new(&image.file_path))).await;
+
+        delete_evidence(&pool, image.id).await.unwrap();
+        assert!(Path::new(&image.file_path).exists());
+
+        purge_evidence(&pool, std::slice::from_ref(&video)).await.unwrap();
+        assert!(!Path::new(&image.file_path).exists());
+        assert!(!Path::new(&video.file_path).exists());
+    }
+
+    #[tokio::test]
+    async fn links_and_missing_files_are_released_quietly() {
+        let pool = pool_with_page().await;
+        let dir = tempfile::tempdir().unwrap();
+        let evidence = attach(&pool, dir.path(), b"gone", None).await;
+        std::fs::remove_file(&evidence.file_path).unwrap();
+        delete_evidence(&pool, evidence.id).await.unwrap();
+
+        let link = EvidenceInput {
+            reminder_id: None,
+            page_id: Some("page".into()),
+            file_type: "document".into(),
+            file_path: "https://example.com/file.txt".into(),
+            file_name: "file.txt".into(),
+            file_size: 0,
+            mime_type: "text/plain".into(),
+            thumbnail_path: None,
+            description: None,
+            metadata: None,
+            content_hash: None,
+        };
+        let link = add_evidence(&pool, link).await.unwrap();
+        delete_evidence(&pool, link.id).await.unwrap();
+        assert!(get_all_evidence(&pool).await.unwrap().is_empty());
+    }
+}
@@ -1,15 +1,21 @@
-use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
+use sqlx::{SqlitePool, sqlite::{SqliteConnectOptions, SqlitePoolOptions}};
+#[cfg(test)]
+use std::time::Duration;
 use log::{info, debug, error};
 use std::path::PathBuf;
+use std::str::FromStr;
+use super::migrations::run_migrations;
+#[cfg(test)]
+use crate::models::WikiPage;
 
-pub async fn init_database(db_path: PathBuf) -> Result<SqlitePool, sqlx::Error> {
+pub async fn init_database(db_path: PathBuf) -> Result<SqlitePool, String> {
     info!("Initializing database at: {:?}", db_path);
     
     // Ensure parent directory exists
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent).map_err(|e| {
             error!("Failed to create database directory: {}", e);
-            sqlx::Error::Io(e)
+            format!("Failed to create database directory: {}", e)
         })?;
         info!("Database directory created/verified");
     }
@@ -17,79 +23,81 @@ pub async fn init_database(db_path: PathBuf) -> Result<SqlitePool, sqlx::Error>
     // Convert path to string and create proper SQLite URL
     let db_path_str = db_path.to_str().ok_or_else(|| {
         error!("Invalid database path");
-        sqlx::Error::Configuration("Invalid database path".into())
+        "Invalid database path".to_string()
     })?;
     
     let database_url = format!("sqlite://{}?mode=rwc", db_path_str);
     debug!("Database URL: {}", database_url);
     
+    // Foreign keys are a per-connection setting in SQLite, so set them on every pooled connection
+    let options = SqliteConnectOptions::from_str(&database_url)
+        .map_err(|e| format!("Invalid database URL: {}", e))?
+        .foreign_keys(true);
+    
     // Create connection pool
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
-        .connect(&database_url)
-        .await?;
+        .connect_with(options)
+        .await
+        .map_err(|e| format!("Failed to open database: {}", e))?;
     
     // Run migrations
-    create_tables(&pool).await?;
+    if let Err(e) = run_migrations(&pool).await {
+        pool.close().await;
+        return Err(e);
+    }
     
     info!("Database initialized successfully");
     Ok(pool)
 }
 
-async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    info!("Creating database tables...");
-    
-    // Create reminders table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS reminders (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            title TEXT NOT NULL,
-            description TEXT NOT NULL,
-            time TEXT NOT NULL,
-            completed INTEGER NOT NULL DEFAULT 0,
-            category TEXT NOT NULL,
-            frequency TEXT NOT NULL,
-            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
-        )
-        "#
-    )
-    .execute(pool)
-    .await?;
-    
-    // Create evidence table for rich media attachments
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS evidence (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            reminder_id INTEGER NOT NULL,
-            file_type TEXT NOT NULL,
-            file_path TEXT NOT NULL,
-            file_name TEXT NOT NULL,
-            file_size INTEGER NOT NULL,
-            mime_type TEXT NOT NULL,
-            thumbnail_path TEXT,
-            description TEXT,
-            metadata TEXT,
-            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
-        )
-        "#
-    )
-    .execute(pool)
-    .await?;
-    
-    // Create index for faster lookups
-    sqlx::query(
-        r#"
-        CREATE INDEX IF NOT EXISTS idx_evidence_reminder_id 
-        ON evidence(reminder_id)
-        "#
-    )
-    .execute(pool)
-    .await?;
-    
-    info!("Database tables created successfully");
-    Ok(())
+// An empty in-memory database for tests. An in-memory database lives only as
+// long as its connection, so the pool keeps exactly one.
+#[cfg(test)]
+pub async fn memory_pool() -> SqlitePool {
+    let options = SqliteConnectOptions::from_str("sqlite::memory:")
+        .unwrap()
+        .foreign_keys(true);
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_with(options)
+        .await
+        .unwrap()
+}
+
+// An in-memory database at the latest schema
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    let pool = memory_pool().await;
+    run_migrations(&pool).await.unwrap();
+    pool
+}
+
+// A page titled by its id, in the default notebook, for tests to store
+#[cfg(test)]
+pub fn test_page(id: &str, content: &str) -> WikiPage {
+    WikiPage {
+        id: id.into(),
+        title: id.into(),
+        content: content.into(),
+        tags: Vec::new(),
+        notebook: "Default".into(),
+        section: String::new(),
+        section_id: None,
+        created_at: 1,
+        updated_at: 1,
+        version: 1,
+    }
+}
+
+// test_pool holding one page, "page", for evidence to belong to
+#[cfg(test)]
+pub async fn pool_with_page() -> SqlitePool {
+    let pool = test_pool().await;
+    super::create_wiki_page(&pool, &test_page("page", "")).await.unwrap();
+    pool
 }
@@ -0,0 +1,662 @@
+use sqlx::SqlitePool;
+use log::{info, debug, error};
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    statements: &'static [&'static str],
+}
+
+// Append-only: never edit a migration that has shipped, add a new one instead.
+// Version 1 uses IF NOT EXISTS because databases created before versioning
+// already contain those tables.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create reminders and evidence tables",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS reminders (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL,
+                time TEXT NOT NULL,
+                completed INTEGER NOT NULL DEFAULT 0,
+                category TEXT NOT NULL,
+                frequency TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS evidence (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                reminder_id INTEGER NOT NULL,
+                file_type TEXT NOT NULL,
+                file_path TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                file_size INTEGER NOT NULL,
+                mime_type TEXT NOT NULL,
+                thumbnail_path TEXT,
+                description TEXT,
+                metadata TEXT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_evidence_reminder_id
+            ON evidence(reminder_id)
+            "#,
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "record completed occurrences of recurring reminders",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS reminder_occurrences (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                reminder_id INTEGER NOT NULL,
+                occurrence_time TEXT NOT NULL,
+                completed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_reminder_occurrences_reminder_id
+            ON reminder_occurrences(reminder_id)
+            "#,
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "remember the last notified occurrence of each reminder",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS reminder_notifications (
+                reminder_id INTEGER PRIMARY KEY,
+                notified_time TEXT NOT NULL,
+                last_notified_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
+            )
+            "#,
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "add key/value store for user preferences",
+        statements: &[
+            r#"
+            CREATE TABLE IF NOT EXISTS app_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "store wiki pages, sections and revisions with a full-text index",
+        statements: &[
+            r#"
+            CREATE TABLE wiki_sections (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                parent_id TEXT REFERENCES wiki_sections(id),
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )
+            "#,
+            r#"
+            INSERT INTO wiki_sections (id, name, parent_id, created_at, updated_at)
+            VALUES ('root', 'Notebook', NULL,
+                    CAST(strftime('%s', 'now') AS INTEGER), CAST(strftime('%s', 'now') AS INTEGER))
+            "#,
+            // doc_id is a stable rowid for the external-content FTS index;
+            // everything else refers to pages by their string id
+            r#"
+            CREATE TABLE wiki_pages (
+                doc_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT NOT NULL UNIQUE,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                tags TEXT NOT NULL DEFAULT '[]',
+                notebook TEXT NOT NULL,
+                section TEXT NOT NULL,
+                section_id TEXT REFERENCES wiki_sections(id),
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )
+            "#,
+            r#"
+            CREATE INDEX idx_wiki_pages_section_id ON wiki_pages(section_id)
+            "#,
+            r#"
+            CREATE TABLE wiki_revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                page_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                tags TEXT NOT NULL DEFAULT '[]',
+                notebook TEXT NOT NULL,
+                section TEXT NOT NULL,
+                section_id TEXT,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (page_id) REFERENCES wiki_pages(id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            CREATE INDEX idx_wiki_revisions_page_id ON wiki_revisions(page_id)
+            "#,
+            r#"
+            CREATE VIRTUAL TABLE wiki_pages_fts USING fts5(
+                title, content, tags,
+                content = 'wiki_pages', content_rowid = 'doc_id'
+            )
+            "#,
+            r#"
+            CREATE TRIGGER wiki_pages_fts_insert AFTER INSERT ON wiki_pages BEGIN
+                INSERT INTO wiki_pages_fts (rowid, title, content, tags)
+                VALUES (new.doc_id, new.title, new.content, new.tags);
+            END
+            "#,
+            r#"
+            CREATE TRIGGER wiki_pages_fts_delete AFTER DELETE ON wiki_pages BEGIN
+                INSERT INTO wiki_pages_fts (wiki_pages_fts, rowid, title, content, tags)
+                VALUES ('delete', old.doc_id, old.title, old.content, old.tags);
+            END
+            "#,
+            r#"
+            CREATE TRIGGER wiki_pages_fts_update AFTER UPDATE OF title, content, tags ON wiki_pages BEGIN
+                INSERT INTO wiki_pages_fts (wiki_pages_fts, rowid, title, content, tags)
+                VALUES ('delete', old.doc_id, old.title, old.content, old.tags);
+                INSERT INTO wiki_pages_fts (rowid, title, content, tags)
+                VALUES (new.doc_id, new.title, new.content, new.tags);
+            END
+            "#,
+        ],
+    },
+    // Revisions record the page version they replaced, so an edit based on an
+    // older version can be merged against it; older revisions get 0 (unknown)
+    Migration {
+        version: 6,
+        description: "count saves of wiki pages to detect conflicting edits",
+        statements: &[
+            "ALTER TABLE wiki_pages ADD COLUMN version INTEGER NOT NULL DEFAULT 1",
+            "ALTER TABLE wiki_revisions ADD COLUMN version INTEGER NOT NULL DEFAULT 0",
+        ],
+    },
+    // A revision with a delta_base_id has an empty content column; its text is
+    // rebuilt by applying `delta` to the content of the base revision
+    Migration {
+        version: 7,
+        description: "store older wiki revisions as deltas",
+        statements: &[
+            "ALTER TABLE wiki_revisions ADD COLUMN delta_base_id INTEGER REFERENCES wiki_revisions(id)",
+            "ALTER TABLE wiki_revisions ADD COLUMN delta TEXT",
+            "CREATE INDEX idx_wiki_revisions_delta_base_id ON wiki_revisions(delta_base_id)",
+        ],
+    },
+    // Deleted pages stay in the table until purged. A trashed page is taken out
+    // of its section so the section can still be deleted; where it was is kept
+    // in the trashed_section_* columns to put it back on restore
+    Migration {
+        version: 8,
+        description: "move deleted wiki pages to a trash",
+        statements: &[
+            "ALTER TABLE wiki_pages ADD COLUMN deleted_at INTEGER",
+            "ALTER TABLE wiki_pages ADD COLUMN trashed_section_id TEXT",
+            "ALTER TABLE wiki_pages ADD COLUMN trashed_section_parent_id TEXT",
+            "CREATE INDEX idx_wiki_pages_deleted_at ON wiki_pages(deleted_at)",
+        ],
+    },
+    // [[links]] found in page content. The target is stored as written (a page
+    // id or title) and resolved when read, so renaming or creating pages never
+    // leaves the index stale
+    Migration {
+        version: 9,
+        description: "index links between wiki pages",
+        statements: &[
+            r#"
+            CREATE TABLE wiki_links (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source_id TEXT NOT NULL REFERENCES wiki_pages(id) ON DELETE CASCADE,
+                target TEXT NOT NULL,
+                alias TEXT
+            )
+            "#,
+            "CREATE INDEX idx_wiki_links_source_id ON wiki_links(source_id)",
+        ],
+    },
+    // Deleting a reminder or purging a page from the trash removes its links
+    Migration {
+        version: 10,
+        description: "link reminders and wiki pages",
+        statements: &[
+            r#"
+            CREATE TABLE reminder_wiki_links (
+                reminder_id INTEGER NOT NULL REFERENCES reminders(id) ON DELETE CASCADE,
+                page_id TEXT NOT NULL REFERENCES wiki_pages(id) ON DELETE CASCADE,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (reminder_id, page_id)
+            )
+            "#,
+            "CREATE INDEX idx_reminder_wiki_links_page_id ON reminder_wiki_links(page_id)",
+        ],
+    },
+    // Existing sections are numbered in the order they were listed in so far
+    Migration {
+        version: 11,
+        description: "order sibling wiki sections explicitly",
+        statements: &[
+            "ALTER TABLE wiki_sections ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0",
+            r#"
+            UPDATE wiki_sections
+            SET sort_order = (
+                SELECT COUNT(*) FROM wiki_sections s
+                WHERE s.parent_id IS wiki_sections.parent_id
+                  AND (s.created_at < wiki_sections.created_at
+                       OR (s.created_at = wiki_sections.created_at AND s.rowid < wiki_sections.rowid))
+            )
+            "#,
+            "CREATE INDEX idx_wiki_sections_parent_id ON wiki_sections(parent_id, sort_order)",
+        ],
+    },
+    // Every section belongs to a notebook, whose root section has no parent. The
+    // existing tree becomes the default notebook; pages naming other notebooks
+    // are moved into notebooks of their own at startup (wiki::assign_notebooks).
+    Migration {
+        version: 12,
+        description: "add wiki notebooks owning section trees",
+        statements: &[
+            r#"
+            CREATE TABLE wiki_notebooks (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                root_section_id TEXT NOT NULL UNIQUE REFERENCES wiki_sections(id),
+                sort_order INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )
+            "#,
+            "CREATE UNIQUE INDEX idx_wiki_notebooks_name ON wiki_notebooks(name COLLATE NOCASE)",
+            r#"
+            INSERT INTO wiki_notebooks (id, name, root_section_id, sort_order, created_at, updated_at)
+            SELECT 'default', name, id, 0, created_at, updated_at FROM wiki_sections WHERE id = 'root'
+            "#,
+            "ALTER TABLE wiki_sections ADD COLUMN notebook_id TEXT REFERENCES wiki_notebooks(id)",
+            "UPDATE wiki_sections SET notebook_id = 'default'",
+            "CREATE INDEX idx_wiki_sections_notebook_id ON wiki_sections(notebook_id)",
+        ],
+    },
+    // Templates keep their history like pages: every save stores the replaced
+    // version in wiki_template_revisions
+    Migration {
+        version: 13,
+        description: "store wiki page templates with revisions",
+        statements: &[
+            r#"
+            CREATE TABLE wiki_templates (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                tags TEXT NOT NULL DEFAULT '[]',
+                section_id TEXT REFERENCES wiki_sections(id) ON DELETE SET NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                version INTEGER NOT NULL DEFAULT 1
+            )
+            "#,
+            r#"
+            CREATE TABLE wiki_template_revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                template_id TEXT NOT NULL REFERENCES wiki_templates(id) ON DELETE CASCADE,
+                version INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                tags TEXT NOT NULL DEFAULT '[]',
+                section_id TEXT,
+                created_at INTEGER NOT NULL
+            )
+            "#,
+            "CREATE INDEX idx_wiki_template_revisions_template_id ON wiki_template_revisions(template_id)",
+        ],
+    },
+    // Evidence becomes an attachment store: a row is owned by either a reminder
+    // or a wiki page. SQLite cannot relax NOT NULL in place, so the table is
+    // rebuilt. Rows of reminders deleted while foreign keys were not enforced
+    // cannot be carried over; scan_evidence_orphans removes their files.
+    Migration {
+        version: 14,
+        description: "let wiki pages own evidence as attachments",
+        statements: &[
+            r#"
+            CREATE TABLE evidence_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                reminder_id INTEGER REFERENCES reminders(id) ON DELETE CASCADE,
+                page_id TEXT REFERENCES wiki_pages(id) ON DELETE CASCADE,
+                file_type TEXT NOT NULL,
+                file_path TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                file_size INTEGER NOT NULL,
+                mime_type TEXT NOT NULL,
+                thumbnail_path TEXT,
+                description TEXT,
+                metadata TEXT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                CHECK ((reminder_id IS NULL) <> (page_id IS NULL))
+            )
+            "#,
+            r#"
+            INSERT INTO evidence_new (
+                id, reminder_id, file_type, file_path, file_name, file_size,
+                mime_type, thumbnail_path, description, metadata, created_at
+            )
+            SELECT id, reminder_id, file_type, file_path, file_name, file_size,
+                   mime_type, thumbnail_path, description, metadata, created_at
+            FROM evidence
+            WHERE reminder_id IN (SELECT id FROM reminders)
+            "#,
+            "DROP TABLE evidence",
+            "ALTER TABLE evidence_new RENAME TO evidence",
+            "CREATE INDEX idx_evidence_reminder_id ON evidence(reminder_id)",
+            "CREATE INDEX idx_evidence_page_id ON evidence(page_id)",
+        ],
+    },
+    // Files are stored once per content and shared by every row that has it.
+    // Rows from before keep their files; verify_evidence_store records their
+    // hash the first time it reads them.
+    Migration {
+        version: 15,
+        description: "record the content hash of evidence files",
+        statements: &[
+            "ALTER TABLE evidence ADD COLUMN content_hash TEXT",
+            "CREATE INDEX idx_evidence_file_path ON evidence(file_path)",
+        ],
+    },
+];
+
+fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Brings the schema up to the latest version. All pending migrations run in a
+/// single transaction, so a failure leaves the database as it was.
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), String> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start migration transaction: {}", e))?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to create schema_version table: {}", e))?;
+
+    let (current,) = sqlx::query_as::<_, (i64,)>("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to read schema version: {}", e))?;
+
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {} is newer than this application supports ({}). Please update the application.",
+            current,
+            latest_version()
+        ));
+    }
+
+    let pending: Vec<&Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current)
+        .collect();
+
+    if pending.is_empty() {
+        debug!("Database schema is up to date (version {})", current);
+        return Ok(());
+    }
+
+    for migration in pending {
+        info!("Applying migration {}: {}", migration.version, migration.description);
+
+        for statement in migration.statements {
+            sqlx::query(statement).execute(&mut *tx).await.map_err(|e| {
+                error!("Migration {} failed: {}", migration.version, e);
+                format!(
+                    "Database migration {} ({}) failed: {}",
+                    migration.version, migration.description, e
+                )
+            })?;
+        }
+
+        sqlx::query("INSERT INTO schema_version (version, description) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.description)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to record migration {}: {}", migration.version, e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit database migrations: {}", e))?;
+
+    info!("Database schema migrated from version {} to {}", current, latest_version());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_pool;
+
+    // The tables as the app created them before schema versioning
+    const UNVERSIONED_SCHEMA: &[&str] = &[
+        r#"
+        CREATE TABLE IF NOT EXISTS reminders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            description TEXT NOT NULL,
+            time TEXT NOT NULL,
+            completed INTEGER NOT NULL DEFAULT 0,
+            category TEXT NOT NULL,
+            frequency TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS evidence (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            reminder_id INTEGER NOT NULL,
+            file_type TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            file_size INTEGER NOT NULL,
+            mime_type TEXT NOT NULL,
+            thumbnail_path TEXT,
+            description TEXT,
+            metadata TEXT,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (reminder_id) REFERENCES reminders(id) ON DELETE CASCADE
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_evidence_reminder_id ON evidence(reminder_id)",
+    ];
+
+    async fn exec(pool: &SqlitePool, sql: &str) {
+        sqlx::query(sql).execute(pool).await.unwrap_or_else(|e| panic!("{}: {}", sql, e));
+    }
+
+    async fn count(pool: &SqlitePool, sql: &str) -> i64 {
+        sqlx::query_as::<_, (i64,)>(sql).fetch_one(pool).await.unwrap().0
+    }
+
+    // A database as the app left it at `version`, 0 being before versioning
+    async fn fixture(version: i64) -> SqlitePool {
+        let pool = memory_pool().await;
+        if version == 0 {
+            for statement in UNVERSIONED_SCHEMA {
+                exec(&pool, statement).await;
+            }
+            return pool;
+        }
+
+        exec(&pool, "CREATE TABLE schema_version (version INTEGER PRIMARY KEY, description TEXT NOT NULL, applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP)").await;
+        for migration in MIGRATIONS.iter().take_while(|m| m.version <= version) {
+            for statement in migration.statements {
+                exec(&pool, statement).await;
+            }
+            sqlx::query("INSERT INTO schema_version (version, description) VALUES (?, ?)")
+                .bind(migration.version)
+                .bind(migration.description)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    // Rows in every table that exists at `version`. Foreign keys are off while
+    // seeding, as they were before the app enabled them, so an evidence row can
+    // outlive its reminder.
+    async fn seed(pool: &SqlitePool, version: i64) {
+        exec(pool, "PRAGMA foreign_keys = OFF").await;
+        exec(pool, "INSERT INTO reminders (id, title, description, time, category, frequency) VALUES (1, 'Backup', 'Copy the photos', '2024-01-01T09:00', 'home', 'weekly')").await;
+        exec(pool, "INSERT INTO evidence (reminder_id, file_type, file_path, file_name, file_size, mime_type) VALUES (1, 'image', '/evidence/kept.png', 'kept.png', 3, 'image/png')").await;
+        if version < 14 {
+            exec(pool, "INSERT INTO evidence (reminder_id, file_type, file_path, file_name, file_size, mime_type) VALUES (99, 'image', '/evidence/orphan.png', 'orphan.png', 3, 'image/png')").await;
+        }
+        if version >= 2 {
+            exec(pool, "INSERT INTO reminder_occurrences (reminder_id, occurrence_time) VALUES (1, '2023-12-25T09:00')").await;
+        }
+        if version >= 3 {
+            exec(pool, "INSERT INTO reminder_notifications (reminder_id, notified_time) VALUES (1, '2024-01-01T09:00')").await;
+        }
+        if version >= 4 {
+            exec(pool, "INSERT INTO app_settings (key, value) VALUES ('notification_channel', 'both')").await;
+        }
+        if version >= 5 {
+            exec(pool, "INSERT INTO wiki_pages (id, title, content, tags, notebook, section, section_id, created_at, updated_at) VALUES ('p1', 'Runbook', 'Restore from [[Backups]]', '[\"ops\"]', 'Notebook', 'Notebook', 'root', 1, 2)").await;
+            exec(pool, "INSERT INTO wiki_revisions (page_id, title, content, tags, notebook, section, section_id, created_at) VALUES ('p1', 'Runbook', 'Draft', '[]', 'Notebook', 'Notebook', 'root', 1)").await;
+        }
+        if version >= 10 {
+            exec(pool, "INSERT INTO reminder_wiki_links (reminder_id, page_id, created_at) VALUES (1, 'p1', 1)").await;
+        }
+        if version >= 14 {
+            exec(pool, "INSERT INTO evidence (page_id, file_type, file_path, file_name, file_size, mime_type) VALUES ('p1', 'image', '/evidence/diagram.png', 'diagram.png', 3, 'image/png')").await;
+        }
+        exec(pool, "PRAGMA foreign_keys = ON").await;
+    }
+
+    // file_name, reminder_id, page_id, content_hash
+    type EvidenceOwner = (String, Option<i64>, Option<String>, Option<String>);
+
+    async fn assert_upgraded(pool: &SqlitePool, from: i64) {
+        let context = format!("upgrading from version {}", from);
+        assert_eq!(count(pool, "SELECT MAX(version) FROM schema_version").await, 15, "{}", context);
+        assert_eq!(count(pool, "SELECT COUNT(*) FROM schema_version").await, 15, "{}", context);
+        assert_eq!(count(pool, "SELECT COUNT(*) FROM reminders WHERE title = 'Backup'").await, 1, "{}", context);
+
+        // The orphaned row is dropped by the evidence rebuild, the other one kept
+        let evidence: Vec<EvidenceOwner> =
+            sqlx::query_as("SELECT file_name, reminder_id, page_id, content_hash FROM evidence ORDER BY id")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        let mut expected = vec![("kept.png".to_string(), Some(1), None, None)];
+        if from >= 14 {
+            expected.push(("diagram.png".to_string(), None, Some("p1".to_string()), None));
+        }
+        assert_eq!(evidence, expected, "{}", context);
+
+        if from >= 2 {
+            assert_eq!(count(pool, "SELECT COUNT(*) FROM reminder_occurrences").await, 1, "{}", context);
+        }
+        if from >= 4 {
+            assert_eq!(count(pool, "SELECT COUNT(*) FROM app_settings WHERE value = 'both'").await, 1, "{}", context);
+        }
+        if from >= 5 {
+            let (content, version, deleted_at): (String, i64, Option<i64>) =
+                sqlx::query_as("SELECT content, version, deleted_at FROM wiki_pages WHERE id = 'p1'")
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            assert_eq!((content.as_str(), version, deleted_at), ("Restore from [[Backups]]", 1, None), "{}", context);
+            assert_eq!(count(pool, "SELECT COUNT(*) FROM wiki_revisions WHERE page_id = 'p1'").await, 1, "{}", context);
+            assert_eq!(count(pool, "SELECT COUNT(*) FROM wiki_pages_fts WHERE wiki_pages_fts MATCH 'runbook'").await, 1, "{}", context);
+        }
+        assert_eq!(count(pool, "SELECT COUNT(*) FROM wiki_sections WHERE id = 'root' AND notebook_id = 'default'").await, 1, "{}", context);
+        assert_eq!(count(pool, "SELECT COUNT(*) FROM pragma_foreign_key_check").await, 0, "{}", context);
+    }
+
+    #[tokio::test]
+    async fn upgrades_every_past_version_to_the_latest() {
+        for version in 0..=latest_version() {
+            let pool = fixture(version).await;
+            seed(&pool, version).await;
+
+            run_migrations(&pool).await.unwrap_or_else(|e| panic!("from version {}: {}", version, e));
+            assert_upgraded(&pool, version).await;
+
+            // A second start finds nothing to do
+            run_migrations(&pool).await.unwrap();
+            assert_eq!(count(&pool, "SELECT COUNT(*) FROM schema_version").await, 15);
+        }
+    }
+
+    #[tokio::test]
+    async fn evidence_needs_exactly_one_owner() {
+        let pool = fixture(13).await;
+        seed(&pool, 13).await;
+        run_migrations(&pool).await.unwrap();
+
+        let insert = |reminder_id: Option<i64>, page_id: Option<&'static str>| {
+            sqlx::query("INSERT INTO evidence (reminder_id, page_id, file_type, file_path, file_name, file_size, mime_type) VALUES (?, ?, 'image', '/e/x.png', 'x.png', 1, 'image/png')")
+                .bind(reminder_id)
+                .bind(page_id)
+                .execute(&pool)
+        };
+        assert!(insert(None, None).await.is_err());
+        assert!(insert(Some(1), Some("p1")).await.is_err());
+        assert!(insert(Some(1), None).await.is_ok());
+        assert!(insert(None, Some("p1")).await.is_ok());
+        // Owners must exist now that foreign keys are enforced
+        assert!(insert(Some(99), None).await.is_err());
+        assert!(insert(None, Some("missing")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_a_database_from_a_newer_app() {
+        let pool = fixture(latest_version()).await;
+        exec(&pool, "INSERT INTO schema_version (version, description) VALUES (99, 'from the future')").await;
+
+        let error = run_migrations(&pool).await.unwrap_err();
+        assert!(error.contains("newer than this application"), "{}", error);
+        assert_eq!(count(&pool, "SELECT MAX(version) FROM schema_version").await, 99);
+    }
+
+    #[tokio::test]
+    async fn failed_migration_leaves_the_database_unchanged() {
+        let pool = fixture(13).await;
+        // A table in the way of the evidence rebuild makes version 14 fail
+        exec(&pool, "CREATE TABLE evidence_new (id INTEGER)").await;
+
+        let error = run_migrations(&pool).await.unwrap_err();
+        assert!(error.contains("migration 14"), "{}", error);
+        assert_eq!(count(&pool, "SELECT MAX(version) FROM schema_version").await, 13);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM pragma_table_info('evidence') WHERE name = 'page_id'").await, 0);
+    }
+}
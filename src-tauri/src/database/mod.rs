@@ -1,19 +1,125 @@
 mod init;
+mod migrations;
 mod operations;
 mod evidence_operations;
+mod notebook_operations;
+mod settings_operations;
+mod template_operations;
+mod reminder_link_operations;
+mod wiki_operations;
 
 pub use init::init_database;
+#[cfg(test)]
+pub use init::{memory_pool, pool_with_page, test_page, test_pool};
 pub use operations::{
     add_reminder,
     get_all_reminders,
+    get_due_reminders,
+    get_unnotified_due_reminders,
+    get_next_pending_time,
+    mark_reminders_notified,
     toggle_reminder,
     delete_reminder,
+    complete_reminder,
+    snooze_reminder,
 };
 pub use evidence_operations::{
     add_evidence,
+    insert_evidence,
     get_evidence_by_id,
     get_evidence_by_reminder,
+    get_evidence_by_page,
+    get_page_attachments_before,
+    is_referenced_by_wiki,
     get_all_evidence,
     update_evidence_description,
     delete_evidence,
+    get_orphaned_evidence,
+    purge_evidence,
+    delete_evidence_rows,
+    release_evidence_files,
+    release_files,
+    set_evidence_hash,
+};
+pub use notebook_operations::{
+    DEFAULT_NOTEBOOK_ID,
+    list_notebooks,
+    find_notebook,
+    find_notebook_by_name,
+    section_notebook,
+    insert_notebook,
+    create_notebook,
+    rename_notebook,
+    delete_notebook,
+    list_notebook_pages,
+    move_pages_to_notebook,
+    list_misplaced_wiki_pages,
+    place_wiki_page,
+};
+pub use reminder_link_operations::{
+    link_reminder_page,
+    unlink_reminder_page,
+    list_reminder_pages,
+    list_page_reminders,
+};
+pub use settings_operations::{
+    get_setting,
+    set_setting,
+};
+pub use template_operations::{
+    list_templates,
+    get_template,
+    create_template,
+    update_template,
+    delete_template,
+    list_template_revisions,
+    restore_template_revision,
+};
+pub use wiki_operations::{
+    ROOT_SECTION_ID,
+    list_wiki_pages,
+    list_wiki_page_contents,
+    find_wiki_page,
+    get_wiki_page,
+    insert_wiki_page,
+    create_wiki_page,
+    save_wiki_page,
+    update_wiki_page,
+    find_wiki_page_version,
+    trash_wiki_page,
+    list_trashed_wiki_pages,
+    restore_trashed_wiki_page,
+    purge_wiki_trash,
+    search_wiki_pages,
+    find_unreadable_wiki_pages,
+    repair_wiki_page_tags,
+    check_wiki_index,
+    rebuild_wiki_index,
+    list_wiki_revisions,
+    find_wiki_revision,
+    list_wiki_revision_pages,
+    load_wiki_revision_contents,
+    store_wiki_revision_content,
+    store_wiki_revision_delta,
+    delete_wiki_revisions,
+    list_revised_wiki_pages,
+    delete_orphaned_wiki_revisions,
+    get_wiki_history_usage,
+    insert_wiki_revision,
+    restore_wiki_revision,
+    reindex_wiki_links,
+    list_wiki_page_titles,
+    list_section_page_titles,
+    list_wiki_links,
+    list_sections,
+    find_section,
+    insert_section,
+    create_section,
+    ensure_section,
+    rename_section,
+    section_path,
+    find_child_section,
+    move_section,
+    move_pages,
+    delete_section,
 };
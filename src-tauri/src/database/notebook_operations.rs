@@ -0,0 +1,261 @@
+use sqlx::{SqliteConnection, SqlitePool};
+use log::info;
+use crate::error::{AppError, AppResult};
+use crate::models::{Notebook, Section, SectionDeleteMode, WikiPage, WikiPageList};
+use super::wiki_operations::{find_section, insert_section, move_pages, page_list_from_row, remove_section, PageListRow};
+
+// The notebook created from the section tree of earlier versions; it always exists
+pub const DEFAULT_NOTEBOOK_ID: &str = "default";
+
+type NotebookRow = (String, String, String, i64, i64, i64);
+
+const NOTEBOOK_COLUMNS: &str = "id, name, root_section_id, sort_order, created_at, updated_at";
+
+fn notebook_from_row((id, name, root_section_id, sort_order, created_at, updated_at): NotebookRow) -> Notebook {
+    Notebook { id, name, root_section_id, sort_order, created_at, updated_at }
+}
+
+pub async fn list_notebooks(pool: &SqlitePool) -> AppResult<Vec<Notebook>> {
+    let rows = sqlx::query_as::<_, NotebookRow>(&format!(
+        "SELECT {} FROM wiki_notebooks ORDER BY sort_order ASC, created_at ASC",
+        NOTEBOOK_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(notebook_from_row).collect())
+}
+
+pub async fn find_notebook(conn: &mut SqliteConnection, id: &str) -> AppResult<Option<Notebook>> {
+    let row = sqlx::query_as::<_, NotebookRow>(&format!("SELECT {} FROM wiki_notebooks WHERE id = ?", NOTEBOOK_COLUMNS))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(row.map(notebook_from_row))
+}
+
+// Notebook names are unique regardless of case
+pub async fn find_notebook_by_name(conn: &mut SqliteConnection, name: &str) -> AppResult<Option<Notebook>> {
+    let row = sqlx::query_as::<_, NotebookRow>(&format!(
+        "SELECT {} FROM wiki_notebooks WHERE name = ? COLLATE NOCASE",
+        NOTEBOOK_COLUMNS
+    ))
+    .bind(name.trim())
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(notebook_from_row))
+}
+
+// The notebook a section belongs to; pages without a section are in the default notebook
+pub async fn section_notebook(conn: &mut SqliteConnection, section_id: Option<&str>) -> AppResult<Notebook> {
+    let row = sqlx::query_as::<_, NotebookRow>(
+        r#"
+        SELECT n.id, n.name, n.root_section_id, n.sort_order, n.created_at, n.updated_at
+        FROM wiki_notebooks n
+        WHERE n.id = COALESCE((SELECT notebook_id FROM wiki_sections WHERE id = ?), ?)
+        "#
+    )
+    .bind(section_id)
+    .bind(DEFAULT_NOTEBOOK_ID)
+    .fetch_optional(conn)
+    .await?;
+
+    row.map(notebook_from_row)
+        .ok_or_else(|| AppError::internal("The default notebook is missing"))
+}
+
+// Inserts a notebook along with the root section of its tree, named after it
+pub async fn insert_notebook(conn: &mut SqliteConnection, id: &str, name: &str, now: i64) -> AppResult<Notebook> {
+    let name = name.trim();
+    if find_notebook_by_name(conn, name).await?.is_some() {
+        return Err(AppError::conflict(format!("A notebook named \"{}\" already exists", name)));
+    }
+
+    // The notebook and its root section refer to each other
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *conn).await?;
+
+    let root_section_id = uuid::Uuid::new_v4().to_string();
+    insert_section(conn, &Section {
+        id: root_section_id.clone(),
+        name: name.to_string(),
+        parent_id: None,
+        created_at: now,
+        updated_at: now,
+        sort_order: 0,
+        notebook_id: Some(id.to_string()),
+    })
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_notebooks (id, name, root_section_id, sort_order, created_at, updated_at)
+        VALUES (?, ?, ?, (SELECT COALESCE(MAX(sort_order) + 1, 0) FROM wiki_notebooks), ?, ?)
+        "#
+    )
+    .bind(id)
+    .bind(name)
+    .bind(&root_section_id)
+    .bind(now)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    info!("Created notebook {} ({})", id, name);
+    find_notebook(conn, id)
+        .await?
+        .ok_or_else(|| AppError::internal(format!("Notebook {} was not saved", id)))
+}
+
+pub async fn create_notebook(pool: &SqlitePool, id: &str, name: &str, now: i64) -> AppResult<Notebook> {
+    let mut tx = pool.begin().await?;
+    let notebook = insert_notebook(&mut tx, id, name, now).await?;
+    tx.commit().await?;
+    Ok(notebook)
+}
+
+// Renames the notebook and its root section, and updates the notebook name
+// stored with its pages (including those in the trash)
+pub async fn rename_notebook(pool: &SqlitePool, id: &str, name: &str, now: i64) -> AppResult<Notebook> {
+    let name = name.trim();
+    let mut tx = pool.begin().await?;
+
+    let notebook = find_notebook(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Notebook not found"))?;
+    if find_notebook_by_name(&mut tx, name).await?.is_some_and(|other| other.id != id) {
+        return Err(AppError::conflict(format!("A notebook named \"{}\" already exists", name)));
+    }
+
+    sqlx::query("UPDATE wiki_notebooks SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(name)
+        .bind(now)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE wiki_sections SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(name)
+        .bind(now)
+        .bind(&notebook.root_section_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        UPDATE wiki_pages SET notebook = ?
+        WHERE section_id IN (SELECT id FROM wiki_sections WHERE notebook_id = ?)
+           OR trashed_section_id IN (SELECT id FROM wiki_sections WHERE notebook_id = ?)
+        "#
+    )
+    .bind(name)
+    .bind(id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+
+    let notebook = find_notebook(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Notebook not found"))?;
+    tx.commit().await?;
+
+    info!("Notebook {} renamed to {}", id, name);
+    Ok(notebook)
+}
+
+// Deletes a notebook with all its sections; its pages go to the trash, and are
+// restored into the default notebook
+pub async fn delete_notebook(pool: &SqlitePool, id: &str, now: i64) -> AppResult<()> {
+    if id == DEFAULT_NOTEBOOK_ID {
+        return Err(AppError::validation("The default notebook cannot be deleted"));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let notebook = find_notebook(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Notebook not found"))?;
+    let root = find_section(&mut tx, &notebook.root_section_id)
+        .await?
+        .ok_or_else(|| AppError::internal(format!("Root section of notebook {} is missing", id)))?;
+
+    // Its sections refer to the notebook until they are deleted too
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM wiki_notebooks WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    remove_section(&mut tx, &root, SectionDeleteMode::Cascade, now).await?;
+
+    tx.commit().await?;
+    info!("Notebook {} deleted", id);
+    Ok(())
+}
+
+pub async fn list_notebook_pages(pool: &SqlitePool, notebook_id: &str) -> AppResult<Vec<WikiPageList>> {
+    let rows = sqlx::query_as::<_, PageListRow>(
+        r#"
+        SELECT p.id, p.title, p.tags, p.notebook, p.section, p.section_id, p.updated_at
+        FROM wiki_pages p
+        JOIN wiki_sections s ON s.id = p.section_id
+        WHERE s.notebook_id = ? AND p.deleted_at IS NULL
+        ORDER BY p.updated_at DESC
+        "#
+    )
+    .bind(notebook_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(page_list_from_row).collect())
+}
+
+// Moves pages to the top of another notebook's section tree
+pub async fn move_pages_to_notebook(
+    pool: &SqlitePool,
+    page_ids: &[String],
+    notebook_id: &str,
+    now: i64,
+) -> AppResult<Vec<WikiPage>> {
+    let notebook = find_notebook(&mut *pool.acquire().await?, notebook_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Notebook not found"))?;
+    move_pages(pool, page_ids, &notebook.root_section_id, now).await
+}
+
+// Active pages whose `notebook` does not name the notebook their section is in,
+// as (page id, notebook name, section id)
+pub async fn list_misplaced_wiki_pages(
+    conn: &mut SqliteConnection,
+) -> AppResult<Vec<(String, String, Option<String>)>> {
+    let rows = sqlx::query_as::<_, (String, String, Option<String>)>(
+        r#"
+        SELECT p.id, p.notebook, p.section_id
+        FROM wiki_pages p
+        LEFT JOIN wiki_sections s ON s.id = p.section_id
+        LEFT JOIN wiki_notebooks n ON n.id = s.notebook_id
+        WHERE p.deleted_at IS NULL AND (n.id IS NULL OR n.name != trim(p.notebook) COLLATE NOCASE)
+        ORDER BY p.created_at ASC
+        "#
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows)
+}
+
+// Files a page under a section of `notebook` without recording a revision
+pub async fn place_wiki_page(
+    conn: &mut SqliteConnection,
+    page_id: &str,
+    section: &Section,
+    notebook: &Notebook,
+) -> AppResult<()> {
+    sqlx::query("UPDATE wiki_pages SET notebook = ?, section = ?, section_id = ? WHERE id = ?")
+        .bind(&notebook.name)
+        .bind(&section.name)
+        .bind(&section.id)
+        .bind(page_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
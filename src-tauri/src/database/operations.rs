@@ -1,6 +1,10 @@
 use sqlx::SqlitePool;
 use log::{info, debug, warn};
+use chrono::{Local, NaiveDateTime};
 use crate::models::Reminder;
+use crate::recurrence::{self, Recurrence};
+use super::evidence_operations::{delete_evidence_by_reminder, release_evidence_files};
+use super::reminder_link_operations::fill_linked_pages;
 
 pub async fn add_reminder(
     pool: &SqlitePool,
@@ -8,7 +12,7 @@ pub async fn add_reminder(
     description: &str,
     time: &str,
     category: &str,
-    frequency: &str,
+    frequency: &Recurrence,
 ) -> Result<i64, sqlx::Error> {
     debug!("Adding reminder to database: {}", title);
     
@@ -22,7 +26,7 @@ pub async fn add_reminder(
     .bind(description)
     .bind(time)
     .bind(category)
-    .bind(frequency)
+    .bind(frequency.to_string())
     .execute(pool)
     .await?;
     
@@ -35,7 +39,7 @@ pub async fn add_reminder(
 pub async fn get_all_reminders(pool: &SqlitePool) -> Result<Vec<Reminder>, sqlx::Error> {
     debug!("Fetching all reminders from database");
     
-    let rows = sqlx::query_as::<_, (i64, String, String, String, i64, String, String)>(
+    let rows = sqlx::query_as::<_, ReminderRow>(
         r#"
         SELECT id, title, description, time, completed, category, frequency
         FROM reminders
@@ -45,26 +49,180 @@ pub async fn get_all_reminders(pool: &SqlitePool) -> Result<Vec<Reminder>, sqlx:
     .fetch_all(pool)
     .await?;
     
-    let reminders: Vec<Reminder> = rows
-        .into_iter()
-        .map(|(id, title, description, time, completed, category, frequency)| Reminder {
-            id: id as u32,
-            title,
-            description,
-            time,
-            completed: completed != 0,
-            category,
-            frequency,
-        })
-        .collect();
+    let mut reminders: Vec<Reminder> = rows.into_iter().map(reminder_from_row).collect();
+    fill_linked_pages(pool, &mut reminders).await?;
     
     info!("Retrieved {} reminders", reminders.len());
     Ok(reminders)
 }
 
+// Rows written before validation existed may hold anything; treat those as one-off reminders
+fn parse_stored_frequency(id: i64, frequency: &str) -> Recurrence {
+    frequency.parse().unwrap_or_else(|e| {
+        warn!("Reminder id={}: {}, treating as once", id, e);
+        Recurrence::default()
+    })
+}
+
+pub(super) type ReminderRow = (i64, String, String, String, i64, String, String);
+
+pub(super) fn reminder_from_row((id, title, description, time, completed, category, frequency): ReminderRow) -> Reminder {
+    Reminder {
+        id: id as u32,
+        title,
+        description,
+        time,
+        completed: completed != 0,
+        category,
+        frequency: parse_stored_frequency(id, &frequency),
+        linked_pages: Vec::new(),
+    }
+}
+
+// Reminder times are local wall-clock values from the datetime-local picker
+pub async fn get_due_reminders(pool: &SqlitePool) -> Result<Vec<Reminder>, sqlx::Error> {
+    debug!("Fetching due reminders");
+
+    let rows = sqlx::query_as::<_, ReminderRow>(
+        r#"
+        SELECT id, title, description, time, completed, category, frequency
+        FROM reminders
+        WHERE completed = 0 AND datetime(time) <= datetime('now', 'localtime')
+        ORDER BY time ASC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(reminder_from_row).collect())
+}
+
+// Due reminders whose current occurrence hasn't been announced yet
+pub async fn get_unnotified_due_reminders(pool: &SqlitePool) -> Result<Vec<Reminder>, sqlx::Error> {
+    debug!("Fetching due reminders that have not been notified");
+
+    let rows = sqlx::query_as::<_, ReminderRow>(
+        r#"
+        SELECT r.id, r.title, r.description, r.time, r.completed, r.category, r.frequency
+        FROM reminders r
+        LEFT JOIN reminder_notifications n ON n.reminder_id = r.id
+        WHERE r.completed = 0
+          AND datetime(r.time) <= datetime('now', 'localtime')
+          AND (n.notified_time IS NULL OR n.notified_time != r.time)
+        ORDER BY r.time ASC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(reminder_from_row).collect())
+}
+
+// Earliest time at which an incomplete reminder still needs to be announced
+pub async fn get_next_pending_time(pool: &SqlitePool) -> Result<Option<NaiveDateTime>, sqlx::Error> {
+    let (next,) = sqlx::query_as::<_, (Option<String>,)>(
+        r#"
+        SELECT MIN(datetime(r.time))
+        FROM reminders r
+        LEFT JOIN reminder_notifications n ON n.reminder_id = r.id
+        WHERE r.completed = 0
+          AND datetime(r.time) IS NOT NULL
+          AND (n.notified_time IS NULL OR n.notified_time != r.time)
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(next.as_deref().and_then(recurrence::parse_time))
+}
+
+pub async fn mark_reminders_notified(pool: &SqlitePool, reminders: &[Reminder]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    for reminder in reminders {
+        sqlx::query(
+            r#"
+            INSERT INTO reminder_notifications (reminder_id, notified_time, last_notified_at)
+            VALUES (?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(reminder_id) DO UPDATE SET
+                notified_time = excluded.notified_time,
+                last_notified_at = excluded.last_notified_at
+            "#
+        )
+        .bind(reminder.id as i64)
+        .bind(&reminder.time)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    debug!("Marked {} reminders as notified", reminders.len());
+    Ok(())
+}
+
 pub async fn toggle_reminder(pool: &SqlitePool, id: u32) -> Result<(), sqlx::Error> {
     debug!("Toggling reminder id={}", id);
-    
+
+    let mut tx = pool.begin().await?;
+
+    let (time, completed, frequency) = sqlx::query_as::<_, (String, i64, String)>(
+        "SELECT time, completed, frequency FROM reminders WHERE id = ?"
+    )
+    .bind(id as i64)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
+
+    // Completing a recurring reminder records the occurrence and moves it to the next one
+    if completed == 0 {
+        let frequency = parse_stored_frequency(id as i64, &frequency);
+
+        if let (true, Some(current)) = (frequency.is_recurring(), recurrence::parse_time(&time)) {
+            let recorded = sqlx::query_as::<_, (String,)>(
+                "SELECT occurrence_time FROM reminder_occurrences WHERE reminder_id = ? ORDER BY id ASC"
+            )
+            .bind(id as i64)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            // Keep the original series start so month-end dates don't drift after clamping
+            let anchor = recorded
+                .iter()
+                .filter_map(|(t,)| recurrence::parse_time(t))
+                .find(|a| frequency.is_occurrence(*a, current))
+                .unwrap_or(current);
+
+            // Skip occurrences that were already missed
+            let now = Local::now().naive_local();
+            if let Some(next) = frequency.next_after(anchor, current.max(now)) {
+                sqlx::query(
+                    "INSERT INTO reminder_occurrences (reminder_id, occurrence_time) VALUES (?, ?)"
+                )
+                .bind(id as i64)
+                .bind(recurrence::format_time(current))
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query(
+                    r#"
+                    UPDATE reminders
+                    SET time = ?,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE id = ?
+                    "#
+                )
+                .bind(recurrence::format_time(next))
+                .bind(id as i64)
+                .execute(&mut *tx)
+                .await?;
+
+                tx.commit().await?;
+                info!("Reminder id={} completed occurrence {}, next at {}", id, current, next);
+                return Ok(());
+            }
+        }
+    }
+
     sqlx::query(
         r#"
         UPDATE reminders
@@ -74,26 +232,161 @@ pub async fn toggle_reminder(pool: &SqlitePool, id: u32) -> Result<(), sqlx::Err
         "#
     )
     .bind(id as i64)
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
-    
+
+    tx.commit().await?;
     info!("Reminder id={} toggled", id);
     Ok(())
 }
 
+// Marks a reminder done without un-completing it if it already was
+pub async fn complete_reminder(pool: &SqlitePool, id: u32) -> Result<(), sqlx::Error> {
+    let (completed,) = sqlx::query_as::<_, (i64,)>("SELECT completed FROM reminders WHERE id = ?")
+        .bind(id as i64)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    if completed != 0 {
+        debug!("Reminder id={} already completed", id);
+        return Ok(());
+    }
+    toggle_reminder(pool, id).await
+}
+
+// Pushes the reminder `minutes` past its time, or past now if it is already overdue
+pub async fn snooze_reminder(pool: &SqlitePool, id: i64, minutes: i64) -> Result<(), sqlx::Error> {
+    debug!("Snoozing reminder id={} for {} minutes", id, minutes);
+
+    sqlx::query(
+        r#"
+        UPDATE reminders
+        SET time = datetime(max(datetime(time), datetime('now', 'localtime')), ? || ' minutes'),
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?
+        "#
+    )
+    .bind(format!("+{}", minutes))
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn delete_reminder(pool: &SqlitePool, id: u32) -> Result<(), sqlx::Error> {
     debug!("Deleting reminder id={}", id);
     
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    
+    let evidence = delete_evidence_by_reminder(&mut tx, id as i64).await?;
+    
     let result = sqlx::query("DELETE FROM reminders WHERE id = ?")
         .bind(id as i64)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
     
-    if result.rows_affected() > 0 {
-        info!("Reminder id={} deleted successfully", id);
-        Ok(())
-    } else {
+    if result.rows_affected() == 0 {
         warn!("Reminder id={} not found", id);
-        Err(sqlx::Error::RowNotFound)
+        return Err(sqlx::Error::RowNotFound);
+    }
+    
+    // Still under the write lock, so no new row can come to share a file
+    release_evidence_files(&mut tx, &evidence).await;
+    tx.commit().await?;
+    
+    info!("Reminder id={} deleted successfully with {} evidence items", id, evidence.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::recurrence::Frequency;
+    use chrono::Datelike;
+
+    async fn reminder_time(pool: &SqlitePool, id: i64) -> (String, bool) {
+        sqlx::query_as("SELECT time, completed FROM reminders WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn completing_an_overdue_reminder_skips_missed_occurrences() {
+        let pool = test_pool().await;
+        let daily = Recurrence::Preset(Frequency::Daily);
+        let id = add_reminder(&pool, "Water plants", "", "2020-01-01T08:00", "home", &daily).await.unwrap();
+
+        toggle_reminder(&pool, id as u32).await.unwrap();
+
+        let (time, completed) = reminder_time(&pool, id).await;
+        let next = recurrence::parse_time(&time).unwrap();
+        let now = Local::now().naive_local();
+        assert!(!completed);
+        assert!(next > now && next - now <= chrono::Duration::days(1));
+        assert_eq!(time[11..], *"08:00");
+
+        let recorded: Vec<(String,)> = sqlx::query_as("SELECT occurrence_time FROM reminder_occurrences WHERE reminder_id = ?")
+            .bind(id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(recorded, [("2020-01-01T08:00".to_string(),)]);
+    }
+
+    #[tokio::test]
+    async fn month_end_series_keeps_its_anchor_across_completions() {
+        let pool = test_pool().await;
+        let monthly = Recurrence::Preset(Frequency::Monthly);
+        let start = format!("{}-01-31T09:00", Local::now().year() + 1);
+        let id = add_reminder(&pool, "Invoice", "", &start, "work", &monthly).await.unwrap();
+
+        toggle_reminder(&pool, id as u32).await.unwrap();
+        let (february, _) = reminder_time(&pool, id).await;
+        assert!(february[5..10] == *"02-28" || february[5..10] == *"02-29");
+
+        toggle_reminder(&pool, id as u32).await.unwrap();
+        let (march, _) = reminder_time(&pool, id).await;
+        assert_eq!(march[5..], *"03-31T09:00");
+    }
+
+    #[tokio::test]
+    async fn completing_a_one_off_reminder_marks_it_done() {
+        let pool = test_pool().await;
+        let id = add_reminder(&pool, "Call", "", "2020-01-01T08:00", "", &Recurrence::default()).await.unwrap();
+
+        toggle_reminder(&pool, id as u32).await.unwrap();
+
+        assert_eq!(reminder_time(&pool, id).await, ("2020-01-01T08:00".to_string(), true));
+    }
+
+    #[tokio::test]
+    async fn snoozing_an_overdue_reminder_counts_from_now() {
+        let pool = test_pool().await;
+        let id = add_reminder(&pool, "Call", "", "2020-01-01T08:00", "", &Recurrence::default()).await.unwrap();
+
+        let before = Local::now().naive_local();
+        snooze_reminder(&pool, id, 10).await.unwrap();
+
+        let (time, _) = reminder_time(&pool, id).await;
+        let snoozed = recurrence::parse_time(&time).unwrap();
+        assert!(snoozed >= before + chrono::Duration::minutes(10) - chrono::Duration::seconds(1));
+        assert!(snoozed <= Local::now().naive_local() + chrono::Duration::minutes(10));
+    }
+
+    #[tokio::test]
+    async fn snoozing_a_future_reminder_counts_from_its_time() {
+        let pool = test_pool().await;
+        let start = format!("{}-01-31T09:00", Local::now().year() + 1);
+        let id = add_reminder(&pool, "Call", "", &start, "", &Recurrence::default()).await.unwrap();
+
+        snooze_reminder(&pool, id, 10).await.unwrap();
+
+        let (time, _) = reminder_time(&pool, id).await;
+        assert_eq!(time, format!("{}-01-31 09:10:00", Local::now().year() + 1));
     }
 }
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use sqlx::SqlitePool;
+use log::info;
+use crate::error::{AppError, AppResult};
+use crate::models::{LinkedPage, Reminder, WikiPageList};
+use super::operations::{reminder_from_row, ReminderRow};
+use super::wiki_operations::{page_list_from_row, PageListRow};
+
+pub async fn link_reminder_page(pool: &SqlitePool, reminder_id: u32, page_id: &str, now: i64) -> AppResult<()> {
+    let reminder = sqlx::query_as::<_, (i64,)>("SELECT id FROM reminders WHERE id = ?")
+        .bind(reminder_id as i64)
+        .fetch_optional(pool)
+        .await?;
+    if reminder.is_none() {
+        return Err(AppError::not_found(format!("Reminder {} not found", reminder_id)));
+    }
+
+    let page = sqlx::query_as::<_, (String,)>("SELECT id FROM wiki_pages WHERE id = ? AND deleted_at IS NULL")
+        .bind(page_id)
+        .fetch_optional(pool)
+        .await?;
+    if page.is_none() {
+        return Err(AppError::not_found(format!("Page {} not found", page_id)));
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO reminder_wiki_links (reminder_id, page_id, created_at) VALUES (?, ?, ?)")
+        .bind(reminder_id as i64)
+        .bind(page_id)
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    info!("Linked reminder {} to wiki page {}", reminder_id, page_id);
+    Ok(())
+}
+
+pub async fn unlink_reminder_page(pool: &SqlitePool, reminder_id: u32, page_id: &str) -> AppResult<()> {
+    let result = sqlx::query("DELETE FROM reminder_wiki_links WHERE reminder_id = ? AND page_id = ?")
+        .bind(reminder_id as i64)
+        .bind(page_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found(format!("Reminder {} is not linked to page {}", reminder_id, page_id)));
+    }
+
+    info!("Unlinked reminder {} from wiki page {}", reminder_id, page_id);
+    Ok(())
+}
+
+// Pages in the trash keep their links but are left out until restored
+pub async fn list_reminder_pages(pool: &SqlitePool, reminder_id: u32) -> AppResult<Vec<WikiPageList>> {
+    let rows = sqlx::query_as::<_, PageListRow>(
+        r#"
+        SELECT p.id, p.title, p.tags, p.notebook, p.section, p.section_id, p.updated_at
+        FROM reminder_wiki_links l
+        JOIN wiki_pages p ON p.id = l.page_id
+        WHERE l.reminder_id = ? AND p.deleted_at IS NULL
+        ORDER BY l.created_at
+        "#
+    )
+    .bind(reminder_id as i64)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(page_list_from_row).collect())
+}
+
+pub async fn list_page_reminders(pool: &SqlitePool, page_id: &str) -> AppResult<Vec<Reminder>> {
+    let rows = sqlx::query_as::<_, ReminderRow>(
+        r#"
+        SELECT r.id, r.title, r.description, r.time, r.completed, r.category, r.frequency
+        FROM reminder_wiki_links l
+        JOIN reminders r ON r.id = l.reminder_id
+        WHERE l.page_id = ?
+        ORDER BY r.time
+        "#
+    )
+    .bind(page_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut reminders: Vec<Reminder> = rows.into_iter().map(reminder_from_row).collect();
+    fill_linked_pages(pool, &mut reminders).await?;
+    Ok(reminders)
+}
+
+// Sets `linked_pages` on each reminder, leaving out pages in the trash
+pub(super) async fn fill_linked_pages(pool: &SqlitePool, reminders: &mut [Reminder]) -> Result<(), sqlx::Error> {
+    let rows = sqlx::query_as::<_, (i64, String, String)>(
+        r#"
+        SELECT l.reminder_id, p.id, p.title
+        FROM reminder_wiki_links l
+        JOIN wiki_pages p ON p.id = l.page_id
+        WHERE p.deleted_at IS NULL
+        ORDER BY l.created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut pages: HashMap<u32, Vec<LinkedPage>> = HashMap::new();
+    for (reminder_id, id, title) in rows {
+        pages.entry(reminder_id as u32).or_default().push(LinkedPage { id, title });
+    }
+    for reminder in reminders {
+        reminder.linked_pages = pages.remove(&reminder.id).unwrap_or_default();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use sqlx::{SqliteConnection, SqlitePool};
+use log::{debug, info};
+use crate::error::{AppError, AppResult};
+use crate::models::{WikiTemplate, WikiTemplateRevision};
+use super::wiki_operations::{find_section, tags_from_json, tags_to_json};
+
+type TemplateRow = (String, String, String, String, String, Option<String>, i64, i64, i64);
+
+const TEMPLATE_COLUMNS: &str = "id, name, title, content, tags, section_id, created_at, updated_at, version";
+
+fn template_from_row(
+    (id, name, title, content, tags, section_id, created_at, updated_at, version): TemplateRow,
+) -> WikiTemplate {
+    let tags = tags_from_json(&id, &tags);
+    WikiTemplate { id, name, title, content, tags, section_id, created_at, updated_at, version }
+}
+
+pub async fn list_templates(pool: &SqlitePool) -> AppResult<Vec<WikiTemplate>> {
+    let rows = sqlx::query_as::<_, TemplateRow>(&format!(
+        "SELECT {} FROM wiki_templates ORDER BY name COLLATE NOCASE ASC",
+        TEMPLATE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(template_from_row).collect())
+}
+
+async fn find_template(conn: &mut SqliteConnection, id: &str) -> AppResult<Option<WikiTemplate>> {
+    let row = sqlx::query_as::<_, TemplateRow>(&format!("SELECT {} FROM wiki_templates WHERE id = ?", TEMPLATE_COLUMNS))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(row.map(template_from_row))
+}
+
+pub async fn get_template(pool: &SqlitePool, id: &str) -> AppResult<WikiTemplate> {
+    find_template(&mut *pool.acquire().await?, id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Template {} not found", id)))
+}
+
+async fn check_section(conn: &mut SqliteConnection, template: &WikiTemplate) -> AppResult<()> {
+    if let Some(section_id) = &template.section_id {
+        if find_section(conn, section_id).await?.is_none() {
+            return Err(AppError::not_found(format!("Section {} not found", section_id)));
+        }
+    }
+    Ok(())
+}
+
+pub async fn create_template(pool: &SqlitePool, template: &WikiTemplate) -> AppResult<()> {
+    let mut conn = pool.acquire().await?;
+    check_section(&mut conn, template).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_templates (id, name, title, content, tags, section_id, created_at, updated_at, version)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&template.id)
+    .bind(&template.name)
+    .bind(&template.title)
+    .bind(&template.content)
+    .bind(tags_to_json(&template.tags))
+    .bind(&template.section_id)
+    .bind(template.created_at)
+    .bind(template.updated_at)
+    .bind(template.version)
+    .execute(&mut *conn)
+    .await?;
+
+    info!("Created wiki template {} ({})", template.id, template.name);
+    Ok(())
+}
+
+// Saves a template as its next version, keeping the replaced version as a
+// revision. With `expected_version`, fails with a conflict if the template has
+// been saved since that version.
+async fn save_template(
+    conn: &mut SqliteConnection,
+    template: &WikiTemplate,
+    expected_version: Option<i64>,
+) -> AppResult<WikiTemplate> {
+    let current = find_template(conn, &template.id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Template {} not found", template.id)))?;
+    if expected_version.is_some_and(|expected| expected != current.version) {
+        return Err(AppError::conflict(format!(
+            "Template \"{}\" was changed elsewhere (now at version {})",
+            current.name, current.version
+        )));
+    }
+    check_section(conn, template).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_template_revisions (template_id, version, name, title, content, tags, section_id, created_at)
+        SELECT id, version, name, title, content, tags, section_id, updated_at
+        FROM wiki_templates WHERE id = ?
+        "#
+    )
+    .bind(&template.id)
+    .execute(&mut *conn)
+    .await?;
+
+    let template = WikiTemplate { version: current.version + 1, created_at: current.created_at, ..template.clone() };
+    sqlx::query(
+        r#"
+        UPDATE wiki_templates
+        SET name = ?, title = ?, content = ?, tags = ?, section_id = ?, updated_at = ?, version = ?
+        WHERE id = ?
+        "#
+    )
+    .bind(&template.name)
+    .bind(&template.title)
+    .bind(&template.content)
+    .bind(tags_to_json(&template.tags))
+    .bind(&template.section_id)
+    .bind(template.updated_at)
+    .bind(template.version)
+    .bind(&template.id)
+    .execute(&mut *conn)
+    .await?;
+
+    debug!("Wiki template {} updated to version {}", template.id, template.version);
+    Ok(template)
+}
+
+pub async fn update_template(
+    pool: &SqlitePool,
+    template: &WikiTemplate,
+    expected_version: Option<i64>,
+) -> AppResult<WikiTemplate> {
+    let mut tx = pool.begin().await?;
+    let template = save_template(&mut tx, template, expected_version).await?;
+    tx.commit().await?;
+    Ok(template)
+}
+
+// Deletes the template along with its revisions; pages created from it are unaffected
+pub async fn delete_template(pool: &SqlitePool, id: &str) -> AppResult<()> {
+    let result = sqlx::query("DELETE FROM wiki_templates WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found(format!("Template {} not found", id)));
+    }
+
+    info!("Wiki template {} deleted", id);
+    Ok(())
+}
+
+type TemplateRevisionRow = (i64, String, i64, String, String, String, String, Option<String>, i64);
+
+fn template_revision_from_row(
+    (id, template_id, version, name, title, content, tags, section_id, created_at): TemplateRevisionRow,
+) -> WikiTemplateRevision {
+    let tags = tags_from_json(&template_id, &tags);
+    WikiTemplateRevision { id, template_id, version, name, title, content, tags, section_id, created_at }
+}
+
+// Newest first
+pub async fn list_template_revisions(pool: &SqlitePool, template_id: &str) -> AppResult<Vec<WikiTemplateRevision>> {
+    let rows = sqlx::query_as::<_, TemplateRevisionRow>(
+        r#"
+        SELECT id, template_id, version, name, title, content, tags, section_id, created_at
+        FROM wiki_template_revisions
+        WHERE template_id = ?
+        ORDER BY version DESC, id DESC
+        "#
+    )
+    .bind(template_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(template_revision_from_row).collect())
+}
+
+// Saves a stored revision as the next version of the template. A section that
+// has been deleted since is dropped rather than recreated.
+pub async fn restore_template_revision(
+    pool: &SqlitePool,
+    template_id: &str,
+    revision_id: i64,
+    restored_at: i64,
+) -> AppResult<WikiTemplate> {
+    let mut tx = pool.begin().await?;
+
+    let revision = sqlx::query_as::<_, TemplateRevisionRow>(
+        r#"
+        SELECT id, template_id, version, name, title, content, tags, section_id, created_at
+        FROM wiki_template_revisions
+        WHERE id = ? AND template_id = ?
+        "#
+    )
+    .bind(revision_id)
+    .bind(template_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(template_revision_from_row)
+    .ok_or_else(|| AppError::not_found(format!("Revision {} of template {} not found", revision_id, template_id)))?;
+
+    let current = find_template(&mut tx, template_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Template {} not found", template_id)))?;
+    let section_id = match revision.section_id {
+        Some(id) if find_section(&mut tx, &id).await?.is_some() => Some(id),
+        _ => None,
+    };
+
+    let restored = WikiTemplate {
+        name: revision.name,
+        title: revision.title,
+        content: revision.content,
+        tags: revision.tags,
+        section_id,
+        updated_at: restored_at,
+        ..current
+    };
+    let restored = save_template(&mut tx, &restored, None).await?;
+    tx.commit().await?;
+
+    info!("Wiki template {} restored to version {}", template_id, revision.version);
+    Ok(restored)
+}
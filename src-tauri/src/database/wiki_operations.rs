@@ -0,0 +1,1397 @@
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use log::{info, debug, warn};
+use std::collections::HashMap;
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    PageHistoryUsage, SearchHighlight, Section, SectionDeleteMode, TrashedWikiPage, UnreadableWikiPage, WikiHistoryUsage, WikiPage,
+    WikiPageList, WikiRevisionMeta, WikiSearchResult,
+};
+use crate::wiki::{self, SearchField, SearchQuery};
+use super::evidence_operations;
+use super::notebook_operations::{find_notebook_by_name, section_notebook};
+
+pub const ROOT_SECTION_ID: &str = "root";
+
+type PageRow = (String, String, String, String, String, String, Option<String>, i64, i64, i64);
+pub(super) type PageListRow = (String, String, String, String, String, Option<String>, i64);
+type SectionRow = (String, String, Option<String>, i64, i64, i64, Option<String>);
+
+const SECTION_COLUMNS: &str = "id, name, parent_id, created_at, updated_at, sort_order, notebook_id";
+type RevisionRow = (
+    i64, String, String, String, String, String, Option<String>, i64, i64, Option<i64>, Option<String>,
+);
+
+const REVISION_COLUMNS: &str =
+    "id, title, content, tags, notebook, section, section_id, created_at, version, delta_base_id, delta";
+
+// Tags are stored as a JSON array, which the FTS tokenizer splits into words as is
+pub(super) fn tags_from_json(page_id: &str, tags: &str) -> Vec<String> {
+    serde_json::from_str(tags).unwrap_or_else(|e| {
+        warn!("Page {}: invalid tags {}: {}", page_id, tags, e);
+        Vec::new()
+    })
+}
+
+pub fn tags_to_json(tags: &[String]) -> String {
+    serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string())
+}
+
+fn page_from_row(
+    (id, title, content, tags, notebook, section, section_id, created_at, updated_at, version): PageRow,
+) -> WikiPage {
+    let tags = tags_from_json(&id, &tags);
+    WikiPage { id, title, content, tags, notebook, section, section_id, created_at, updated_at, version }
+}
+
+pub(super) fn page_list_from_row((id, title, tags, notebook, section, section_id, updated_at): PageListRow) -> WikiPageList {
+    let tags = tags_from_json(&id, &tags);
+    WikiPageList { id, title, tags, notebook, section, section_id, updated_at }
+}
+
+// A revision as the page it is a snapshot of, with the revision id alongside
+fn page_from_revision(
+    page: &WikiPage,
+    (id, title, content, tags, notebook, section, section_id, created_at, version, _, _): RevisionRow,
+) -> (String, WikiPage) {
+    let revision = WikiPage {
+        id: page.id.clone(),
+        title,
+        content,
+        tags: tags_from_json(&page.id, &tags),
+        notebook,
+        section,
+        section_id,
+        created_at: page.created_at,
+        updated_at: created_at,
+        version,
+    };
+    (id.to_string(), revision)
+}
+
+fn section_from_row((id, name, parent_id, created_at, updated_at, sort_order, notebook_id): SectionRow) -> Section {
+    Section { id, name, parent_id, created_at, updated_at, sort_order, notebook_id }
+}
+
+pub async fn list_wiki_pages(pool: &SqlitePool) -> AppResult<Vec<WikiPageList>> {
+    let rows = sqlx::query_as::<_, PageListRow>(
+        r#"
+        SELECT id, title, tags, notebook, section, section_id, updated_at
+        FROM wiki_pages
+        WHERE deleted_at IS NULL
+        ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(page_list_from_row).collect())
+}
+
+// Active pages with their content, oldest first
+pub async fn list_wiki_page_contents(pool: &SqlitePool) -> AppResult<Vec<WikiPage>> {
+    let rows = sqlx::query_as::<_, PageRow>(
+        r#"
+        SELECT id, title, content, tags, notebook, section, section_id, created_at, updated_at, version
+        FROM wiki_pages
+        WHERE deleted_at IS NULL
+        ORDER BY created_at ASC, id ASC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(page_from_row).collect())
+}
+
+pub async fn find_wiki_page(conn: &mut SqliteConnection, id: &str) -> AppResult<Option<WikiPage>> {
+    let row = sqlx::query_as::<_, PageRow>(
+        r#"
+        SELECT id, title, content, tags, notebook, section, section_id, created_at, updated_at, version
+        FROM wiki_pages
+        WHERE id = ? AND deleted_at IS NULL
+        "#
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(page_from_row))
+}
+
+pub async fn get_wiki_page(pool: &SqlitePool, id: &str) -> AppResult<WikiPage> {
+    let mut conn = pool.acquire().await?;
+    find_wiki_page(&mut conn, id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Page {} not found", id)))
+}
+
+pub async fn insert_wiki_page(conn: &mut SqliteConnection, page: &WikiPage) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_pages (id, title, content, tags, notebook, section, section_id, created_at, updated_at, version)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&page.id)
+    .bind(&page.title)
+    .bind(&page.content)
+    .bind(tags_to_json(&page.tags))
+    .bind(&page.notebook)
+    .bind(&page.section)
+    .bind(&page.section_id)
+    .bind(page.created_at)
+    .bind(page.updated_at)
+    .bind(page.version)
+    .execute(&mut *conn)
+    .await?;
+
+    index_wiki_links(conn, &page.id, &page.content).await
+}
+
+// The page and its link index are written together
+pub async fn create_wiki_page(pool: &SqlitePool, page: &WikiPage) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+    insert_wiki_page(&mut tx, page).await?;
+    tx.commit().await?;
+    info!("Wiki page {} created", page.id);
+    Ok(())
+}
+
+// Copies the stored state of a page into its revision history
+async fn snapshot_revision(conn: &mut SqliteConnection, page_id: &str) -> AppResult<()> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO wiki_revisions (page_id, title, content, tags, notebook, section, section_id, created_at, version)
+        SELECT id, title, content, tags, notebook, section, section_id, updated_at, version
+        FROM wiki_pages
+        WHERE id = ?
+        "#
+    )
+    .bind(page_id)
+    .execute(conn)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found(format!("Page {} not found", page_id)));
+    }
+    Ok(())
+}
+
+async fn write_wiki_page(conn: &mut SqliteConnection, page: &WikiPage) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        UPDATE wiki_pages
+        SET title = ?, content = ?, tags = ?, notebook = ?, section = ?, section_id = ?, updated_at = ?, version = ?
+        WHERE id = ?
+        "#
+    )
+    .bind(&page.title)
+    .bind(&page.content)
+    .bind(tags_to_json(&page.tags))
+    .bind(&page.notebook)
+    .bind(&page.section)
+    .bind(&page.section_id)
+    .bind(page.updated_at)
+    .bind(page.version)
+    .bind(&page.id)
+    .execute(&mut *conn)
+    .await?;
+
+    index_wiki_links(conn, &page.id, &page.content).await
+}
+
+// Saves the previous state as a revision and overwrites the page, returning
+// the page with its new version. With `expected_version` the save is refused
+// if the page was saved elsewhere after that version.
+pub async fn save_wiki_page(
+    conn: &mut SqliteConnection,
+    page: &WikiPage,
+    expected_version: Option<i64>,
+) -> AppResult<WikiPage> {
+    let current = find_wiki_page(conn, &page.id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Page {} not found", page.id)))?;
+    if expected_version.is_some_and(|expected| expected != current.version) {
+        debug!("Wiki page {} is at version {}, edit was based on {:?}", page.id, current.version, expected_version);
+        return Err(AppError::edit_conflict(current));
+    }
+
+    let page = WikiPage { version: current.version + 1, ..page.clone() };
+    snapshot_revision(conn, &page.id).await?;
+    write_wiki_page(conn, &page).await?;
+
+    debug!("Wiki page {} updated to version {}", page.id, page.version);
+    Ok(page)
+}
+
+// save_wiki_page in a transaction of its own
+pub async fn update_wiki_page(
+    pool: &SqlitePool,
+    page: &WikiPage,
+    expected_version: Option<i64>,
+) -> AppResult<WikiPage> {
+    let mut tx = pool.begin().await?;
+    let page = save_wiki_page(&mut tx, page, expected_version).await?;
+    tx.commit().await?;
+    Ok(page)
+}
+
+// All revisions of a page, oldest first, with the content of delta-encoded
+// revisions rebuilt from the chain of newer revisions they are based on
+async fn load_revisions(conn: &mut SqliteConnection, page_id: &str) -> AppResult<Vec<RevisionRow>> {
+    let mut rows = sqlx::query_as::<_, RevisionRow>(&format!(
+        "SELECT {} FROM wiki_revisions WHERE page_id = ? ORDER BY created_at ASC, id ASC",
+        REVISION_COLUMNS
+    ))
+    .bind(page_id)
+    .fetch_all(conn)
+    .await?;
+
+    let index: HashMap<i64, usize> = rows.iter().enumerate().map(|(i, row)| (row.0, i)).collect();
+    let mut resolved: Vec<bool> = rows.iter().map(|row| row.9.is_none()).collect();
+
+    for start in 0..rows.len() {
+        // Walk towards a revision with known content, then apply the deltas back
+        let mut chain = Vec::new();
+        let mut current = start;
+        while !resolved[current] {
+            if chain.len() > rows.len() {
+                return Err(AppError::internal(format!("Revision {} has a cyclic delta chain", rows[start].0)));
+            }
+            chain.push(current);
+            let base_id = rows[current].9.unwrap_or_default();
+            current = *index.get(&base_id).ok_or_else(|| {
+                AppError::internal(format!("Revision {} is based on missing revision {}", rows[current].0, base_id))
+            })?;
+        }
+
+        for &i in chain.iter().rev() {
+            let base = rows[index[&rows[i].9.unwrap_or_default()]].2.clone();
+            let delta = rows[i].10.as_deref().unwrap_or_default();
+            rows[i].2 = wiki::apply_delta(&base, delta)
+                .map_err(|e| AppError::internal(format!("Revision {}: {}", rows[i].0, e)))?;
+            resolved[i] = true;
+        }
+    }
+
+    Ok(rows)
+}
+
+// The page as it was at `version`: the page itself if it has not been saved
+// since, otherwise the revision that version was replaced by. Revisions from
+// before versions were counted cannot be found this way.
+pub async fn find_wiki_page_version(
+    pool: &SqlitePool,
+    page_id: &str,
+    version: i64,
+) -> AppResult<Option<WikiPage>> {
+    let page = get_wiki_page(pool, page_id).await?;
+    if page.version == version {
+        return Ok(Some(page));
+    }
+
+    let mut conn = pool.acquire().await?;
+    let revision = load_revisions(&mut conn, page_id)
+        .await?
+        .into_iter()
+        .filter(|row| version > 0 && row.8 == version)
+        .max_by_key(|row| row.0);
+
+    Ok(revision.map(|row| page_from_revision(&page, row).1))
+}
+
+// The page as stored in one of its revisions
+pub async fn find_wiki_revision(
+    conn: &mut SqliteConnection,
+    page: &WikiPage,
+    revision_id: &str,
+) -> AppResult<WikiPage> {
+    let not_found = || AppError::not_found(format!("Revision {} not found", revision_id));
+    let revision_id: i64 = revision_id.parse().map_err(|_| not_found())?;
+
+    let row = load_revisions(conn, &page.id)
+        .await?
+        .into_iter()
+        .find(|row| row.0 == revision_id)
+        .ok_or_else(not_found)?;
+
+    Ok(page_from_revision(page, row).1)
+}
+
+// Every revision of the page with its id, oldest first
+pub async fn list_wiki_revision_pages(pool: &SqlitePool, page: &WikiPage) -> AppResult<Vec<(String, WikiPage)>> {
+    let mut conn = pool.acquire().await?;
+    let rows = load_revisions(&mut conn, &page.id).await?;
+
+    Ok(rows.into_iter().map(|row| page_from_revision(page, row)).collect())
+}
+
+// Content of every revision of a page as (id, created_at, content,
+// delta_base_id), oldest first, with deltas already applied
+pub async fn load_wiki_revision_contents(
+    conn: &mut SqliteConnection,
+    page_id: &str,
+) -> AppResult<Vec<(i64, i64, String, Option<i64>)>> {
+    let rows = load_revisions(conn, page_id).await?;
+    Ok(rows.into_iter().map(|row| (row.0, row.7, row.2, row.9)).collect())
+}
+
+pub async fn store_wiki_revision_content(conn: &mut SqliteConnection, id: i64, content: &str) -> AppResult<()> {
+    sqlx::query("UPDATE wiki_revisions SET content = ?, delta_base_id = NULL, delta = NULL WHERE id = ?")
+        .bind(content)
+        .bind(id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn store_wiki_revision_delta(
+    conn: &mut SqliteConnection,
+    id: i64,
+    base_id: i64,
+    delta: &str,
+) -> AppResult<()> {
+    sqlx::query("UPDATE wiki_revisions SET content = '', delta_base_id = ?, delta = ? WHERE id = ?")
+        .bind(base_id)
+        .bind(delta)
+        .bind(id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+// Deletes in a single statement so revisions based on each other can go together
+pub async fn delete_wiki_revisions(conn: &mut SqliteConnection, ids: &[i64]) -> AppResult<u64> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM wiki_revisions WHERE id IN (");
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+
+    let result = query.build().execute(conn).await?;
+    Ok(result.rows_affected())
+}
+
+// Ids of existing pages that have any revisions
+pub async fn list_revised_wiki_pages(pool: &SqlitePool) -> AppResult<Vec<String>> {
+    let rows = sqlx::query_as::<_, (String,)>(
+        "SELECT DISTINCT page_id FROM wiki_revisions WHERE page_id IN (SELECT id FROM wiki_pages)"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+// Revisions left behind by pages deleted while foreign keys were not enforced
+pub async fn delete_orphaned_wiki_revisions(pool: &SqlitePool) -> AppResult<u64> {
+    let result = sqlx::query("DELETE FROM wiki_revisions WHERE page_id NOT IN (SELECT id FROM wiki_pages)")
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() > 0 {
+        info!("Deleted {} revisions of deleted wiki pages", result.rows_affected());
+    }
+    Ok(result.rows_affected())
+}
+
+// Stored size of a revision row's text columns, in bytes
+const REVISION_BYTES: &str = "length(CAST(r.title AS BLOB)) + length(CAST(r.content AS BLOB)) \
+    + length(CAST(r.tags AS BLOB)) + COALESCE(length(CAST(r.delta AS BLOB)), 0)";
+
+pub async fn get_wiki_history_usage(pool: &SqlitePool) -> AppResult<WikiHistoryUsage> {
+    let pages = sqlx::query_as::<_, (String, Option<String>, i64, i64, i64)>(&format!(
+        r#"
+        SELECT r.page_id, p.title, COUNT(*), SUM(r.delta_base_id IS NOT NULL), SUM({})
+        FROM wiki_revisions r
+        LEFT JOIN wiki_pages p ON p.id = r.page_id
+        GROUP BY r.page_id
+        ORDER BY SUM({}) DESC
+        "#,
+        REVISION_BYTES, REVISION_BYTES
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let (page_bytes,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COALESCE(SUM(length(CAST(title AS BLOB)) + length(CAST(content AS BLOB)) + length(CAST(tags AS BLOB))), 0)
+        FROM wiki_pages
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let mut usage = WikiHistoryUsage { page_bytes, ..Default::default() };
+    for (page_id, title, revisions, delta_revisions, bytes) in pages {
+        usage.revisions += revisions;
+        usage.delta_revisions += delta_revisions;
+        usage.history_bytes += bytes;
+        usage.pages.push(PageHistoryUsage { page_id, title: title.unwrap_or_default(), revisions, bytes });
+    }
+
+    Ok(usage)
+}
+
+// Moves pages to the trash, taking them out of their section; the condition on
+// which pages is appended
+const TRASH_PAGES: &str = r#"
+    UPDATE wiki_pages
+    SET deleted_at = ?,
+        trashed_section_id = section_id,
+        trashed_section_parent_id = (SELECT parent_id FROM wiki_sections WHERE id = wiki_pages.section_id),
+        section_id = NULL
+    WHERE deleted_at IS NULL AND "#;
+
+// Moves a page to the trash; it keeps its revisions until the trash is purged
+pub async fn trash_wiki_page(pool: &SqlitePool, id: &str, deleted_at: i64) -> AppResult<()> {
+    let result = sqlx::query(&format!("{} id = ?", TRASH_PAGES))
+        .bind(deleted_at)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found(format!("Page {} not found", id)));
+    }
+
+    info!("Wiki page {} moved to trash", id);
+    Ok(())
+}
+
+pub async fn list_trashed_wiki_pages(pool: &SqlitePool) -> AppResult<Vec<TrashedWikiPage>> {
+    let rows = sqlx::query_as::<_, (String, String, String, String, String, Option<String>, i64, i64)>(
+        r#"
+        SELECT id, title, tags, notebook, section, trashed_section_id, updated_at, deleted_at
+        FROM wiki_pages
+        WHERE deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, title, tags, notebook, section, section_id, updated_at, deleted_at)| TrashedWikiPage {
+            page: page_list_from_row((id, title, tags, notebook, section, section_id, updated_at)),
+            deleted_at,
+        })
+        .collect())
+}
+
+// Takes a page out of the trash and back into the section it was deleted from.
+// If that section has been deleted since, it is recreated with the same id and
+// name, under its old parent when that still exists and otherwise under the root
+// of the page's notebook, or of the default notebook if that is gone too.
+pub async fn restore_trashed_wiki_page(pool: &SqlitePool, id: &str, restored_at: i64) -> AppResult<WikiPage> {
+    let mut tx = pool.begin().await?;
+
+    let (notebook, section, section_id, parent_id) = sqlx::query_as::<_, (String, String, Option<String>, Option<String>)>(
+        r#"
+        SELECT notebook, section, trashed_section_id, trashed_section_parent_id
+        FROM wiki_pages
+        WHERE id = ? AND deleted_at IS NOT NULL
+        "#
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::not_found(format!("Page {} is not in the trash", id)))?;
+
+    if let Some(section_id) = &section_id {
+        if find_section(&mut tx, section_id).await?.is_none() {
+            let parent_exists = match &parent_id {
+                Some(parent) => find_section(&mut tx, parent).await?.is_some(),
+                None => false,
+            };
+            let parent_id = match parent_exists {
+                true => parent_id,
+                false => match find_notebook_by_name(&mut tx, &notebook).await? {
+                    Some(notebook) => Some(notebook.root_section_id),
+                    None => Some(ROOT_SECTION_ID.to_string()),
+                },
+            };
+            insert_section(&mut tx, &Section {
+                id: section_id.clone(),
+                name: section,
+                parent_id: parent_id.filter(|_| section_id != ROOT_SECTION_ID),
+                created_at: restored_at,
+                updated_at: restored_at,
+                sort_order: 0,
+                notebook_id: None,
+            })
+            .await?;
+            info!("Recreated section {} for restored wiki page {}", section_id, id);
+        }
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE wiki_pages
+        SET deleted_at = NULL, section_id = ?, trashed_section_id = NULL, trashed_section_parent_id = NULL,
+            notebook = COALESCE(
+                (SELECT n.name FROM wiki_sections s JOIN wiki_notebooks n ON n.id = s.notebook_id WHERE s.id = ?),
+                notebook
+            )
+        WHERE id = ?
+        "#
+    )
+    .bind(&section_id)
+    .bind(&section_id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+
+    let page = find_wiki_page(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Page {} not found", id)))?;
+    tx.commit().await?;
+
+    info!("Wiki page {} restored from trash", id);
+    Ok(page)
+}
+
+// Permanently deletes trashed pages, with their revisions and attachments,
+// that were deleted before `older_than`; without it the whole trash is emptied
+pub async fn purge_wiki_trash(pool: &SqlitePool, older_than: Option<i64>) -> AppResult<u64> {
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    let attachments = evidence_operations::get_trashed_page_attachments(&mut tx, older_than).await?;
+    let result = sqlx::query("DELETE FROM wiki_pages WHERE deleted_at IS NOT NULL AND (? IS NULL OR deleted_at < ?)")
+        .bind(older_than)
+        .bind(older_than)
+        .execute(&mut *tx)
+        .await?;
+    evidence_operations::release_evidence_files(&mut tx, &attachments).await;
+    tx.commit().await?;
+
+    if result.rows_affected() > 0 {
+        info!(
+            "Purged {} wiki pages from trash with {} attachments",
+            result.rows_affected(),
+            attachments.len()
+        );
+    }
+    Ok(result.rows_affected())
+}
+
+// Wrap matched words in snippets; control characters cannot clash with page text
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+// bm25 column weights for title, content and tags
+const RANK_WEIGHTS: &str = "10.0, 1.0, 5.0";
+
+type SearchRow = (String, String, String, String, String, Option<String>, i64, f64, String);
+
+// Strips the match markers from an FTS5 snippet, recording where they were
+fn split_snippet(marked: &str) -> (String, Vec<SearchHighlight>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut offset = 0;
+    let mut start = None;
+
+    for c in marked.chars() {
+        match c {
+            MATCH_START => start = Some(offset),
+            MATCH_END => {
+                if let Some(start) = start.take() {
+                    highlights.push(SearchHighlight { start, end: offset });
+                }
+            }
+            _ => {
+                snippet.push(c);
+                offset += c.len_utf16();
+            }
+        }
+    }
+
+    (snippet, highlights)
+}
+
+pub async fn search_wiki_pages(pool: &SqlitePool, query: &SearchQuery) -> AppResult<Vec<WikiSearchResult>> {
+    let match_expression = query.match_expression();
+
+    let mut sql = QueryBuilder::<Sqlite>::new(
+        "SELECT p.id, p.title, p.tags, p.notebook, p.section, p.section_id, p.updated_at, "
+    );
+    match &match_expression {
+        Some(expression) => {
+            sql.push(format!("-bm25(wiki_pages_fts, {}) AS score, snippet(wiki_pages_fts, 1, ", RANK_WEIGHTS))
+                .push_bind(MATCH_START.to_string())
+                .push(", ")
+                .push_bind(MATCH_END.to_string())
+                .push(", '…', 16) FROM wiki_pages_fts JOIN wiki_pages p ON p.doc_id = wiki_pages_fts.rowid")
+                .push(" WHERE wiki_pages_fts MATCH ")
+                .push_bind(expression.as_str());
+        }
+        None => {
+            sql.push("0.0 AS score, substr(p.content, 1, 120) FROM wiki_pages p WHERE 1 = 1");
+        }
+    }
+    sql.push(" AND p.deleted_at IS NULL");
+
+    if let Some(expression) = query.exclude_expression() {
+        sql.push(" AND p.doc_id NOT IN (SELECT rowid FROM wiki_pages_fts WHERE wiki_pages_fts MATCH ")
+            .push_bind(expression)
+            .push(")");
+    }
+
+    for (field, value, negated) in query.filters() {
+        sql.push(if negated { " AND NOT " } else { " AND " });
+        match field {
+            SearchField::Tag => {
+                sql.push("EXISTS (SELECT 1 FROM json_each(p.tags) WHERE lower(json_each.value) = lower(")
+                    .push_bind(value)
+                    .push("))");
+            }
+            SearchField::Section => {
+                sql.push("COALESCE(p.section_id, ")
+                    .push_bind(ROOT_SECTION_ID)
+                    .push(") IN (SELECT id FROM wiki_sections WHERE lower(name) = lower(")
+                    .push_bind(value)
+                    .push(") OR id = ")
+                    .push_bind(value)
+                    .push(")");
+            }
+            SearchField::Notebook => {
+                sql.push("(lower(p.notebook) = lower(")
+                    .push_bind(value)
+                    .push(") OR p.section_id IN (SELECT id FROM wiki_sections WHERE notebook_id = ")
+                    .push_bind(value)
+                    .push("))");
+            }
+        }
+    }
+
+    sql.push(if match_expression.is_some() {
+        " ORDER BY score DESC, p.updated_at DESC"
+    } else {
+        " ORDER BY p.updated_at DESC"
+    });
+
+    let rows = sql.build_query_as::<SearchRow>().fetch_all(pool).await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, title, tags, notebook, section, section_id, updated_at, score, snippet)| {
+            let (snippet, highlights) = split_snippet(&snippet);
+            WikiSearchResult {
+                page: page_list_from_row((id, title, tags, notebook, section, section_id, updated_at)),
+                score,
+                snippet,
+                highlights,
+            }
+        })
+        .collect())
+}
+
+// Pages whose tags cannot be read; they load with no tags instead of failing
+pub async fn find_unreadable_wiki_pages(pool: &SqlitePool) -> AppResult<Vec<UnreadableWikiPage>> {
+    let rows = sqlx::query_as::<_, (String, String, String)>(
+        r#"
+        SELECT id, title, problem FROM (
+            SELECT id, title,
+                   CASE
+                       WHEN json_valid(tags) = 0 THEN 'Tags are not valid JSON'
+                       WHEN json_type(tags) != 'array' THEN 'Tags are not a list'
+                       WHEN EXISTS (SELECT 1 FROM json_each(wiki_pages.tags) WHERE type != 'text')
+                           THEN 'Tags contain values that are not text'
+                   END AS problem
+            FROM wiki_pages
+        )
+        WHERE problem IS NOT NULL
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, title, problem)| UnreadableWikiPage { id, title, problem: problem.to_string() })
+        .collect())
+}
+
+// Keeps whatever text tags can be salvaged and drops the rest
+pub async fn repair_wiki_page_tags(pool: &SqlitePool, ids: &[String]) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+
+    for id in ids {
+        sqlx::query(
+            r#"
+            UPDATE wiki_pages
+            SET tags = CASE
+                WHEN json_valid(tags) AND json_type(tags) = 'array'
+                    THEN (SELECT json_group_array(value) FROM json_each(wiki_pages.tags) WHERE type = 'text')
+                ELSE '[]'
+            END
+            WHERE id = ?
+            "#
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    info!("Repaired tags of {} wiki pages", ids.len());
+    Ok(())
+}
+
+pub async fn check_wiki_index(pool: &SqlitePool) -> AppResult<bool> {
+    // A failed check leaves its statement holding the write lock, so it runs in
+    // a transaction that is always rolled back
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("INSERT INTO wiki_pages_fts (wiki_pages_fts, rank) VALUES ('integrity-check', 1)")
+        .execute(&mut *tx)
+        .await;
+    tx.rollback().await?;
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(sqlx::Error::Database(e)) => {
+            warn!("Wiki search index is inconsistent: {}", e);
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn rebuild_wiki_index(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query("INSERT INTO wiki_pages_fts (wiki_pages_fts) VALUES ('rebuild')")
+        .execute(pool)
+        .await?;
+
+    info!("Wiki search index rebuilt");
+    Ok(())
+}
+
+pub async fn list_wiki_revisions(pool: &SqlitePool, page_id: &str) -> AppResult<Vec<WikiRevisionMeta>> {
+    let rows = sqlx::query_as::<_, (i64, String, String, String, String, Option<String>, i64)>(
+        r#"
+        SELECT id, page_id, title, notebook, section, section_id, created_at
+        FROM wiki_revisions
+        WHERE page_id = ?
+        ORDER BY created_at DESC, id DESC
+        "#
+    )
+    .bind(page_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, page_id, title, notebook, section, section_id, created_at)| WikiRevisionMeta {
+            id: id.to_string(),
+            page_id,
+            title,
+            notebook,
+            section,
+            section_id,
+            created_at,
+        })
+        .collect())
+}
+
+// Revisions inserted directly (by the legacy import) have no known page version
+pub async fn insert_wiki_revision(conn: &mut SqliteConnection, page: &WikiPage) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_revisions (page_id, title, content, tags, notebook, section, section_id, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&page.id)
+    .bind(&page.title)
+    .bind(&page.content)
+    .bind(tags_to_json(&page.tags))
+    .bind(&page.notebook)
+    .bind(&page.section)
+    .bind(&page.section_id)
+    .bind(page.updated_at)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+// Replaces the page with a stored revision, keeping the current state as a new revision
+pub async fn restore_wiki_revision(
+    pool: &SqlitePool,
+    page_id: &str,
+    revision_id: &str,
+    restored_at: i64,
+) -> AppResult<WikiPage> {
+    let mut tx = pool.begin().await?;
+
+    let page = find_wiki_page(&mut tx, page_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Page {} not found", page_id)))?;
+    let WikiPage { title, content, tags, section, section_id, .. } =
+        find_wiki_revision(&mut tx, &page, revision_id).await?;
+
+    // Stay in the current section if the revision predates sections or its
+    // section has since been deleted
+    let section_exists = match &section_id {
+        Some(id) => find_section(&mut tx, id).await?.is_some(),
+        None => false,
+    };
+    let (section, section_id) = if section_exists {
+        (section, section_id)
+    } else {
+        (page.section.clone(), page.section_id.clone())
+    };
+    let notebook = section_notebook(&mut tx, section_id.as_deref()).await?.name;
+
+    let restored = WikiPage {
+        tags,
+        title,
+        content,
+        notebook,
+        section,
+        section_id,
+        updated_at: restored_at,
+        version: page.version + 1,
+        ..page
+    };
+
+    snapshot_revision(&mut tx, page_id).await?;
+    write_wiki_page(&mut tx, &restored).await?;
+    tx.commit().await?;
+
+    info!("Wiki page {} restored to revision {}", page_id, revision_id);
+    Ok(restored)
+}
+
+// Replaces the links recorded for a page with the [[links]] in its content
+async fn index_wiki_links(conn: &mut SqliteConnection, page_id: &str, content: &str) -> AppResult<()> {
+    sqlx::query("DELETE FROM wiki_links WHERE source_id = ?")
+        .bind(page_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for link in wiki::parse_links(content) {
+        sqlx::query("INSERT INTO wiki_links (source_id, target, alias) VALUES (?, ?, ?)")
+            .bind(page_id)
+            .bind(&link.target)
+            .bind(&link.alias)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Indexes the links of every page, for pages written before links were indexed
+pub async fn reindex_wiki_links(pool: &SqlitePool) -> AppResult<usize> {
+    let pages = sqlx::query_as::<_, (String, String)>("SELECT id, content FROM wiki_pages")
+        .fetch_all(pool)
+        .await?;
+
+    let mut tx = pool.begin().await?;
+    for (id, content) in &pages {
+        index_wiki_links(&mut tx, id, content).await?;
+    }
+    tx.commit().await?;
+
+    info!("Indexed links of {} wiki pages", pages.len());
+    Ok(pages.len())
+}
+
+// (id, title, updated_at) of every page outside the trash
+pub async fn list_wiki_page_titles(conn: &mut SqliteConnection) -> AppResult<Vec<(String, String, i64)>> {
+    let rows = sqlx::query_as::<_, (String, String, i64)>(
+        "SELECT id, title, updated_at FROM wiki_pages WHERE deleted_at IS NULL"
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows)
+}
+
+// Titles of the pages filed directly under a section, outside the trash
+pub async fn list_section_page_titles(conn: &mut SqliteConnection, section_id: &str) -> AppResult<Vec<String>> {
+    let rows = sqlx::query_as::<_, (String,)>(
+        "SELECT title FROM wiki_pages WHERE section_id = ? AND deleted_at IS NULL"
+    )
+    .bind(section_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|(title,)| title).collect())
+}
+
+// Links from pages outside the trash as (source_id, target, alias)
+pub async fn list_wiki_links(conn: &mut SqliteConnection) -> AppResult<Vec<(String, String, Option<String>)>> {
+    let rows = sqlx::query_as::<_, (String, String, Option<String>)>(
+        r#"
+        SELECT l.source_id, l.target, l.alias
+        FROM wiki_links l
+        JOIN wiki_pages p ON p.id = l.source_id
+        WHERE p.deleted_at IS NULL
+        ORDER BY l.id
+        "#
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn list_sections(pool: &SqlitePool) -> AppResult<Vec<Section>> {
+    let rows = sqlx::query_as::<_, SectionRow>(&format!(
+        "SELECT {} FROM wiki_sections ORDER BY sort_order ASC, created_at ASC, rowid ASC",
+        SECTION_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(section_from_row).collect())
+}
+
+pub async fn find_section(conn: &mut SqliteConnection, id: &str) -> AppResult<Option<Section>> {
+    let row = sqlx::query_as::<_, SectionRow>(&format!("SELECT {} FROM wiki_sections WHERE id = ?", SECTION_COLUMNS))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(row.map(section_from_row))
+}
+
+// New sections go after their siblings; `section.sort_order` is ignored. A
+// section belongs to its parent's notebook, so `section.notebook_id` is only
+// used for the root section of a notebook.
+pub async fn insert_section(conn: &mut SqliteConnection, section: &Section) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_sections (id, name, parent_id, created_at, updated_at, sort_order, notebook_id)
+        VALUES (?, ?, ?, ?, ?,
+                (SELECT COALESCE(MAX(sort_order) + 1, 0) FROM wiki_sections WHERE parent_id IS ?),
+                COALESCE((SELECT notebook_id FROM wiki_sections WHERE id = ?), ?))
+        "#
+    )
+    .bind(&section.id)
+    .bind(&section.name)
+    .bind(&section.parent_id)
+    .bind(section.created_at)
+    .bind(section.updated_at)
+    .bind(&section.parent_id)
+    .bind(&section.parent_id)
+    .bind(&section.notebook_id)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn create_section(pool: &SqlitePool, section: &Section) -> AppResult<Section> {
+    let mut conn = pool.acquire().await?;
+    let parent_id = section.parent_id.as_deref().unwrap_or(ROOT_SECTION_ID);
+    if find_section(&mut conn, parent_id).await?.is_none() {
+        return Err(AppError::not_found("Parent section not found"));
+    }
+    insert_section(&mut conn, section).await?;
+    find_section(&mut conn, &section.id)
+        .await?
+        .ok_or_else(|| AppError::internal(format!("Section {} was not saved", section.id)))
+}
+
+// Returns the section to file a page under, creating a missing one below the root
+pub async fn ensure_section(pool: &SqlitePool, section_id: Option<String>, now: i64) -> AppResult<Section> {
+    let mut conn = pool.acquire().await?;
+    let id = section_id.unwrap_or_else(|| ROOT_SECTION_ID.to_string());
+
+    if let Some(section) = find_section(&mut conn, &id).await? {
+        return Ok(section);
+    }
+
+    let section = Section {
+        id: id.clone(),
+        name: id,
+        parent_id: Some(ROOT_SECTION_ID.to_string()),
+        created_at: now,
+        updated_at: now,
+        sort_order: 0,
+        notebook_id: None,
+    };
+    insert_section(&mut conn, &section).await?;
+    info!("Created missing section {}", section.id);
+    find_section(&mut conn, &section.id)
+        .await?
+        .ok_or_else(|| AppError::internal(format!("Section {} was not saved", section.id)))
+}
+
+pub async fn rename_section(pool: &SqlitePool, id: &str, name: &str, now: i64) -> AppResult<Section> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("UPDATE wiki_sections SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(name)
+        .bind(now)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found("Section not found"));
+    }
+
+    let section = find_section(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Section not found"))?;
+    tx.commit().await?;
+    Ok(section)
+}
+
+// The section and its ancestors, the root of its notebook first
+pub async fn section_path(conn: &mut SqliteConnection, id: &str) -> AppResult<Vec<Section>> {
+    let rows = sqlx::query_as::<_, SectionRow>(&format!(
+        r#"
+        WITH RECURSIVE path(id, depth) AS (
+            SELECT ?, 0
+            UNION ALL
+            SELECT s.parent_id, path.depth + 1 FROM wiki_sections s JOIN path ON s.id = path.id
+            WHERE s.parent_id IS NOT NULL
+        )
+        SELECT {} FROM wiki_sections JOIN path USING (id) ORDER BY path.depth DESC
+        "#,
+        SECTION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows.into_iter().map(section_from_row).collect())
+}
+
+pub async fn find_child_section(
+    conn: &mut SqliteConnection,
+    parent_id: &str,
+    name: &str,
+) -> AppResult<Option<Section>> {
+    let row = sqlx::query_as::<_, SectionRow>(&format!(
+        "SELECT {} FROM wiki_sections WHERE parent_id = ? AND name = ? ORDER BY sort_order ASC LIMIT 1",
+        SECTION_COLUMNS
+    ))
+    .bind(parent_id)
+    .bind(name)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(section_from_row))
+}
+
+// Ids of the sections under `parent_id`, in order
+async fn list_child_sections(conn: &mut SqliteConnection, parent_id: &str) -> AppResult<Vec<String>> {
+    let rows = sqlx::query_as::<_, (String,)>(
+        "SELECT id FROM wiki_sections WHERE parent_id = ? ORDER BY sort_order ASC, created_at ASC, rowid ASC"
+    )
+    .bind(parent_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+// Numbers the given sections 0, 1, 2... under `parent_id`
+async fn write_section_order(
+    conn: &mut SqliteConnection,
+    parent_id: &str,
+    ids: &[String],
+    now: i64,
+) -> AppResult<()> {
+    for (position, id) in ids.iter().enumerate() {
+        sqlx::query(
+            r#"
+            UPDATE wiki_sections
+            SET parent_id = ?, sort_order = ?,
+                updated_at = CASE WHEN parent_id IS ? AND sort_order = ? THEN updated_at ELSE ? END
+            WHERE id = ?
+            "#
+        )
+        .bind(parent_id)
+        .bind(position as i64)
+        .bind(parent_id)
+        .bind(position as i64)
+        .bind(now)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// The section and every section below it, the section itself first
+async fn section_subtree(conn: &mut SqliteConnection, id: &str) -> AppResult<Vec<String>> {
+    let rows = sqlx::query_as::<_, (String,)>(
+        r#"
+        WITH RECURSIVE tree(id, depth) AS (
+            SELECT ?, 0
+            UNION
+            SELECT s.id, tree.depth + 1 FROM wiki_sections s JOIN tree ON s.parent_id = tree.id
+        )
+        SELECT id FROM tree ORDER BY depth
+        "#
+    )
+    .bind(id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+// Moves a section under `new_parent_id` at `position` among its new siblings
+// (at the end without one). A section cannot move into its own subtree; moved
+// into another notebook, it takes its subsections and pages along.
+pub async fn move_section(
+    pool: &SqlitePool,
+    id: &str,
+    new_parent_id: &str,
+    position: Option<usize>,
+    now: i64,
+) -> AppResult<Section> {
+    let mut tx = pool.begin().await?;
+
+    let section = find_section(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Section not found"))?;
+    if section.parent_id.is_none() {
+        return Err(AppError::validation("The root section of a notebook cannot be moved"));
+    }
+    let parent = find_section(&mut tx, new_parent_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Parent section not found"))?;
+    let subtree = section_subtree(&mut tx, id).await?;
+    if subtree.iter().any(|s| s == new_parent_id) {
+        return Err(AppError::conflict("A section cannot be moved into itself or one of its subsections"));
+    }
+
+    let mut siblings = list_child_sections(&mut tx, new_parent_id).await?;
+    siblings.retain(|s| s != id);
+    siblings.insert(position.unwrap_or(siblings.len()).min(siblings.len()), id.to_string());
+    write_section_order(&mut tx, new_parent_id, &siblings, now).await?;
+
+    // Close the gap left under the old parent
+    if let Some(old_parent_id) = section.parent_id.as_deref().filter(|p| *p != new_parent_id) {
+        let remaining = list_child_sections(&mut tx, old_parent_id).await?;
+        write_section_order(&mut tx, old_parent_id, &remaining, now).await?;
+    }
+
+    if parent.notebook_id != section.notebook_id {
+        let notebook = section_notebook(&mut tx, Some(new_parent_id)).await?;
+        for section_id in &subtree {
+            sqlx::query("UPDATE wiki_sections SET notebook_id = ? WHERE id = ?")
+                .bind(&notebook.id)
+                .bind(section_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE wiki_pages SET notebook = ? WHERE section_id = ? OR trashed_section_id = ?")
+                .bind(&notebook.name)
+                .bind(section_id)
+                .bind(section_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        info!("Section {} and {} subsections moved to notebook {}", id, subtree.len() - 1, notebook.id);
+    }
+
+    let section = find_section(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Section not found"))?;
+    tx.commit().await?;
+
+    info!("Section {} moved under {}", id, new_parent_id);
+    Ok(section)
+}
+
+// Files pages under another section, each as a new version of the page
+async fn move_pages_to(
+    conn: &mut SqliteConnection,
+    page_ids: &[String],
+    section: &Section,
+    now: i64,
+) -> AppResult<Vec<WikiPage>> {
+    let notebook = section_notebook(conn, Some(&section.id)).await?;
+    let mut moved = Vec::with_capacity(page_ids.len());
+    for page_id in page_ids {
+        let page = find_wiki_page(conn, page_id)
+            .await?
+            .ok_or_else(|| AppError::not_found(format!("Page {} not found", page_id)))?;
+        if page.section_id.as_deref() == Some(section.id.as_str()) {
+            moved.push(page);
+            continue;
+        }
+
+        let page = WikiPage {
+            notebook: notebook.name.clone(),
+            section: section.name.clone(),
+            section_id: Some(section.id.clone()),
+            updated_at: now,
+            ..page
+        };
+        moved.push(save_wiki_page(conn, &page, None).await?);
+    }
+
+    Ok(moved)
+}
+
+// Moves all the pages into one section; nothing moves if any page is missing
+pub async fn move_pages(
+    pool: &SqlitePool,
+    page_ids: &[String],
+    section_id: &str,
+    now: i64,
+) -> AppResult<Vec<WikiPage>> {
+    let mut tx = pool.begin().await?;
+
+    let section = find_section(&mut tx, section_id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Section not found"))?;
+    let moved = move_pages_to(&mut tx, page_ids, &section, now).await?;
+    tx.commit().await?;
+
+    info!("Moved {} wiki pages to section {}", moved.len(), section_id);
+    Ok(moved)
+}
+
+pub async fn delete_section(pool: &SqlitePool, id: &str, mode: SectionDeleteMode, now: i64) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+
+    let section = find_section(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("Section not found"))?;
+    if section.parent_id.is_none() {
+        return Err(AppError::validation("The root section of a notebook cannot be deleted; delete the notebook instead"));
+    }
+    remove_section(&mut tx, &section, mode, now).await?;
+
+    tx.commit().await?;
+    info!("Section {} deleted ({:?})", id, mode);
+    Ok(())
+}
+
+// Deletes a section, dealing with its contents according to `mode`
+pub(super) async fn remove_section(
+    conn: &mut SqliteConnection,
+    section: &Section,
+    mode: SectionDeleteMode,
+    now: i64,
+) -> AppResult<()> {
+    let id = section.id.as_str();
+    let children = list_child_sections(conn, id).await?;
+    let page_ids: Vec<String> = sqlx::query_as::<_, (String,)>(
+        "SELECT id FROM wiki_pages WHERE section_id = ? AND deleted_at IS NULL"
+    )
+    .bind(id)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|(id,)| id)
+    .collect();
+
+    let subtree = match mode {
+        SectionDeleteMode::Empty => {
+            if !children.is_empty() {
+                return Err(AppError::conflict("Section has child sections"));
+            }
+            if !page_ids.is_empty() {
+                return Err(AppError::conflict("Section has pages; move or delete them first"));
+            }
+            vec![id.to_string()]
+        }
+        SectionDeleteMode::Cascade => {
+            let subtree = section_subtree(conn, id).await?;
+            let mut trashed = 0;
+            for section_id in &subtree {
+                trashed += sqlx::query(&format!("{} section_id = ?", TRASH_PAGES))
+                    .bind(now)
+                    .bind(section_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+            }
+            info!("Moved {} wiki pages to trash with section {}", trashed, id);
+            subtree
+        }
+        SectionDeleteMode::Reparent => {
+            let parent_id = section
+                .parent_id
+                .clone()
+                .ok_or_else(|| AppError::validation("A root section has no parent to hand its contents to"))?;
+            let parent = find_section(conn, &parent_id)
+                .await?
+                .ok_or_else(|| AppError::not_found("Parent section not found"))?;
+
+            let mut siblings = list_child_sections(conn, &parent_id).await?;
+            // Children take the deleted section's place among its siblings
+            match siblings.iter().position(|s| s == id) {
+                Some(position) => drop(siblings.splice(position..=position, children)),
+                None => siblings.extend(children),
+            }
+            write_section_order(conn, &parent_id, &siblings, now).await?;
+
+            move_pages_to(conn, &page_ids, &parent, now).await?;
+            vec![id.to_string()]
+        }
+    };
+
+    // One statement, so sections may reference each other until all are gone
+    let mut delete = QueryBuilder::<Sqlite>::new("DELETE FROM wiki_sections WHERE id IN (");
+    let mut separated = delete.separated(", ");
+    for section_id in &subtree {
+        separated.push_bind(section_id);
+    }
+    separated.push_unseparated(")");
+    delete.build().execute(&mut *conn).await?;
+
+    if let Some(parent_id) = section.parent_id.as_deref().filter(|_| mode != SectionDeleteMode::Reparent) {
+        let remaining = list_child_sections(conn, parent_id).await?;
+        write_section_order(conn, parent_id, &remaining, now).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marked(text: &str) -> String {
+        text.replace('[', &MATCH_START.to_string()).replace(']', &MATCH_END.to_string())
+    }
+
+    // The highlighted text, sliced the way the frontend does: by UTF-16 code units
+    fn highlighted(snippet: &str, highlights: &[SearchHighlight]) -> Vec<String> {
+        let units: Vec<u16> = snippet.encode_utf16().collect();
+        highlights.iter().map(|h| String::from_utf16(&units[h.start..h.end]).unwrap()).collect()
+    }
+
+    #[test]
+    fn snippet_markers_become_highlights() {
+        let (snippet, highlights) = split_snippet(&marked("a [plan] for [plans]"));
+        assert_eq!(snippet, "a plan for plans");
+        assert_eq!(highlights.iter().map(|h| (h.start, h.end)).collect::<Vec<_>>(), [(2, 6), (11, 16)]);
+    }
+
+    #[test]
+    fn highlight_offsets_count_utf16_units() {
+        let (snippet, highlights) = split_snippet(&marked("…café [naïve] 😀 [über]"));
+        assert_eq!(snippet, "…café naïve 😀 über");
+        assert_eq!(highlights.iter().map(|h| (h.start, h.end)).collect::<Vec<_>>(), [(6, 11), (15, 19)]);
+        assert_eq!(highlighted(&snippet, &highlights), ["naïve", "über"]);
+    }
+
+    #[test]
+    fn an_unclosed_marker_is_not_a_highlight() {
+        let (snippet, highlights) = split_snippet(&marked("[cut off…"));
+        assert_eq!(snippet, "cut off…");
+        assert!(highlights.is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::io;
+use crate::models::WikiPage;
+
+/// Error returned by every command. Serialized for the frontend as
+/// `{ code, message, details }`, where `code` is one of the stable strings
+/// from [`AppError::code`] and `details` carries the underlying cause, if any.
+/// An edit conflict additionally carries the saved page as `current`.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Validation(String),
+    Conflict(String),
+    EditConflict { message: String, current: Box<WikiPage> },
+    Io { message: String, source: io::Error },
+    Database { message: String, source: sqlx::Error },
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound(message.into())
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation(message.into())
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        AppError::Conflict(message.into())
+    }
+
+    pub fn edit_conflict(current: WikiPage) -> Self {
+        AppError::EditConflict {
+            message: format!("Page \"{}\" was changed elsewhere", current.title),
+            current: Box::new(current),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::Internal(message.into())
+    }
+
+    pub fn io(message: impl Into<String>, source: io::Error) -> Self {
+        AppError::Io { message: message.into(), source }
+    }
+
+    pub fn database(message: impl Into<String>, source: sqlx::Error) -> Self {
+        AppError::Database { message: message.into(), source }
+    }
+
+    /// Stable machine-readable code the UI switches on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Validation(_) => "validation",
+            AppError::Conflict(_) => "conflict",
+            AppError::EditConflict { .. } => "edit_conflict",
+            AppError::Io { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => "not_found",
+                io::ErrorKind::PermissionDenied => "permission_denied",
+                io::ErrorKind::StorageFull => "storage_full",
+                io::ErrorKind::AlreadyExists => "conflict",
+                _ => "io",
+            },
+            AppError::Database { source, .. } => {
+                if is_busy(source) {
+                    "busy"
+                } else if is_constraint_violation(source) {
+                    "conflict"
+                } else {
+                    "database"
+                }
+            }
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Validation(message)
+            | AppError::Conflict(message)
+            | AppError::Internal(message)
+            | AppError::EditConflict { message, .. }
+            | AppError::Io { message, .. }
+            | AppError::Database { message, .. } => message,
+        }
+    }
+
+    fn details(&self) -> Option<String> {
+        match self {
+            AppError::Io { source, .. } => Some(source.to_string()),
+            AppError::Database { source, .. } => Some(source.to_string()),
+            _ => None,
+        }
+    }
+}
+
+// The database was locked by another connection or the pool was exhausted;
+// retrying shortly usually succeeds
+fn is_busy(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(e) => {
+            // SQLITE_BUSY (5) and SQLITE_LOCKED (6), including extended codes
+            let primary = e.code().and_then(|c| c.parse::<i32>().ok()).map(|c| c & 0xff);
+            matches!(primary, Some(5) | Some(6))
+        }
+        _ => false,
+    }
+}
+
+fn is_constraint_violation(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Database(e) => e.is_unique_violation() || e.is_foreign_key_violation(),
+        _ => false,
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.details() {
+            Some(details) => write!(f, "{}: {}", self.message(), details),
+            None => f.write_str(self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Io { source, .. } => Some(source),
+            AppError::Database { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("details", &self.details())?;
+        match self {
+            AppError::EditConflict { current, .. } => state.serialize_field("current", current)?,
+            _ => state.skip_field("current")?,
+        }
+        state.end()
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::RowNotFound => AppError::NotFound("Record not found".into()),
+            _ if is_busy(&error) => AppError::database("Database is busy", error),
+            _ if is_constraint_violation(&error) => AppError::database("Conflicting change", error),
+            _ => AppError::database("Database error", error),
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        AppError::io("File operation failed", error)
+    }
+}
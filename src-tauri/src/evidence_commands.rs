@@ -1,11 +1,17 @@
 use tauri::{command, AppHandle, Manager};
 use sqlx::SqlitePool;
-use std::path::PathBuf;
-use log::info;
-use crate::models::{Evidence, EvidenceInput};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::{info, warn};
+use crate::error::{AppError, AppResult};
+use crate::attachments::{self, ATTACHMENTS_DIR};
+use crate::models::{AttachmentGcReport, Evidence, EvidenceInput, EvidenceOrphanReport, EvidenceStoreReport, PageAttachment};
 use crate::database;
+use crate::wiki;
 
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_evidence_to_reminder(
     app: AppHandle,
     reminder_id: i64,
@@ -17,13 +23,19 @@ pub async fn add_evidence_to_reminder(
     thumbnail_path: Option<String>,
     description: Option<String>,
     metadata: Option<String>,
-) -> Result<Evidence, String> {
+) -> AppResult<Evidence> {
     info!("Adding evidence to reminder {}", reminder_id);
     
     let pool = app.state::<SqlitePool>();
     
+    let content_hash = if file_path.starts_with("http") {
+        None
+    } else {
+        attachments::hash_file(Path::new(&file_path)).ok()
+    };
     let input = EvidenceInput {
-        reminder_id,
+        reminder_id: Some(reminder_id),
+        page_id: None,
         file_type,
         file_path,
         file_name,
@@ -32,6 +44,7 @@ pub async fn add_evidence_to_reminder(
         thumbnail_path,
         description,
         metadata,
+        content_hash,
     };
     
     database::add_evidence(&pool, input).await
@@ -41,13 +54,13 @@ pub async fn add_evidence_to_reminder(
 pub async fn get_reminder_evidence(
     app: AppHandle,
     reminder_id: i64,
-) -> Result<Vec<Evidence>, String> {
+) -> AppResult<Vec<Evidence>> {
     let pool = app.state::<SqlitePool>();
     database::get_evidence_by_reminder(&pool, reminder_id).await
 }
 
 #[command]
-pub async fn get_all_evidence_items(app: AppHandle) -> Result<Vec<Evidence>, String> {
+pub async fn get_all_evidence_items(app: AppHandle) -> AppResult<Vec<Evidence>> {
     let pool = app.state::<SqlitePool>();
     database::get_all_evidence(&pool).await
 }
@@ -57,7 +70,7 @@ pub async fn update_evidence_desc(
     app: AppHandle,
     evidence_id: i64,
     description: Option<String>,
-) -> Result<(), String> {
+) -> AppResult<()> {
     let pool = app.state::<SqlitePool>();
     database::update_evidence_description(&pool, evidence_id, description).await
 }
@@ -66,7 +79,7 @@ pub async fn update_evidence_desc(
 pub async fn delete_evidence_item(
     app: AppHandle,
     evidence_id: i64,
-) -> Result<(), String> {
+) -> AppResult<()> {
     let pool = app.state::<SqlitePool>();
     database::delete_evidence(&pool, evidence_id).await
 }
@@ -76,43 +89,233 @@ pub async fn save_uploaded_file(
     app: AppHandle,
     file_name: String,
     file_data: Vec<u8>,
-) -> Result<String, String> {
+) -> AppResult<String> {
     info!("Saving uploaded file: {}", file_name);
     
     // Get app data directory
     let app_dir = app.path()
         .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    
-    // Create evidence directory
-    let evidence_dir = app_dir.join("evidence");
-    std::fs::create_dir_all(&evidence_dir)
-        .map_err(|e| format!("Failed to create evidence dir: {}", e))?;
+        .map_err(|e| AppError::internal(format!("Failed to get app data dir: {}", e)))?;
     
-    // Generate unique filename with timestamp
-    let timestamp = chrono::Utc::now().timestamp();
     let extension = std::path::Path::new(&file_name)
         .extension()
         .and_then(|e| e.to_str())
         .unwrap_or("bin");
-    let unique_name = format!("{}_{}.{}", timestamp, uuid::Uuid::new_v4(), extension);
+    let stored = attachments::store_attachment(&app_dir.join(ATTACHMENTS_DIR), extension, &file_data)?;
     
-    let file_path = evidence_dir.join(&unique_name);
+    info!("File saved successfully: {:?}", stored.path);
+    
+    Ok(stored.path.to_string_lossy().to_string())
+}
+
+// Files newer than this may belong to an upload whose evidence row isn't written yet
+const UNREFERENCED_FILE_GRACE: Duration = Duration::from_secs(10 * 60);
+
+// Finds evidence rows pointing to deleted reminders or missing files, and files
+// in the evidence directory that nothing references. With `repair` the rows and
+// stray files are removed.
+#[command]
+pub async fn scan_evidence_orphans(
+    app: AppHandle,
+    repair: Option<bool>,
+) -> AppResult<EvidenceOrphanReport> {
+    let repair = repair.unwrap_or(false);
+    info!("Scanning evidence for orphans (repair: {})", repair);
+    
+    let pool = app.state::<SqlitePool>();
+    
+    let missing_reminder = database::get_orphaned_evidence(&pool).await?;
+    let all_evidence = database::get_all_evidence(&pool).await?;
+    
+    let orphan_ids: HashSet<i64> = missing_reminder.iter().map(|e| e.id).collect();
+    let missing_file: Vec<Evidence> = all_evidence
+        .iter()
+        .filter(|e| !orphan_ids.contains(&e.id))
+        .filter(|e| !e.file_path.starts_with("http") && !PathBuf::from(&e.file_path).exists())
+        .cloned()
+        .collect();
+    
+    let referenced: HashSet<PathBuf> = all_evidence
+        .iter()
+        .flat_map(|e| std::iter::once(&e.file_path).chain(e.thumbnail_path.as_ref()))
+        .map(PathBuf::from)
+        .collect();
+    
+    let evidence_dir = app.path()
+        .app_data_dir()
+        .map_err(|e| AppError::internal(format!("Failed to get app data dir: {}", e)))?
+        .join(ATTACHMENTS_DIR);
+    
+    let mut unreferenced_files = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(&evidence_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else { continue };
+            let settled = metadata
+                .modified()
+                .ok()
+                .and_then(|m| m.elapsed().ok())
+                .is_some_and(|age| age >= UNREFERENCED_FILE_GRACE);
+            if metadata.is_file() && settled && !referenced.contains(&path) {
+                unreferenced_files.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    
+    info!(
+        "Evidence scan found {} rows without reminder, {} rows without file, {} unreferenced files",
+        missing_reminder.len(),
+        missing_file.len(),
+        unreferenced_files.len()
+    );
+    
+    if repair {
+        let broken: Vec<Evidence> = missing_reminder.iter().chain(&missing_file).cloned().collect();
+        database::purge_evidence(&pool, &broken).await?;
+        
+        for path in &unreferenced_files {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove unreferenced evidence file {}: {}", path, e);
+            }
+        }
+    }
     
-    // Write file
-    std::fs::write(&file_path, file_data)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+    Ok(EvidenceOrphanReport {
+        missing_reminder,
+        missing_file,
+        unreferenced_files,
+        repaired: repair,
+    })
+}
+
+// Re-hashes every stored file and reports rows whose file is missing or no
+// longer has the hash recorded for it. Rows stored before files were hashed
+// get the hash of their file recorded as it reads now.
+#[command]
+pub async fn verify_evidence_store(app: AppHandle) -> AppResult<EvidenceStoreReport> {
+    verify_store(&app.state::<SqlitePool>()).await
+}
+
+async fn verify_store(pool: &SqlitePool) -> AppResult<EvidenceStoreReport> {
+    info!("Verifying evidence store");
+    
+    let evidence: Vec<Evidence> = database::get_all_evidence(pool)
+        .await?
+        .into_iter()
+        .filter(|e| !e.file_path.starts_with("http"))
+        .collect();
+    
+    // Rows with the same content share a file, which is read only once
+    let paths: HashSet<String> = evidence.iter().map(|e| e.file_path.clone()).collect();
+    let hashes: HashMap<String, Option<String>> = tauri::async_runtime::spawn_blocking(move || {
+        paths
+            .into_iter()
+            .map(|path| {
+                let hash = attachments::hash_file(Path::new(&path))
+                    .inspect_err(|e| {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            warn!("Failed to read evidence file {}: {}", path, e);
+                        }
+                    })
+                    .ok();
+                (path, hash)
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Evidence verification failed: {}", e)))?;
+    
+    let mut report = EvidenceStoreReport { checked: hashes.len(), ..Default::default() };
+    let mut unhashed = HashSet::new();
+    for item in evidence {
+        match (&hashes[&item.file_path], &item.content_hash) {
+            (None, _) => report.missing.push(item),
+            (Some(actual), Some(recorded)) if actual != recorded => report.corrupt.push(item),
+            (Some(_), None) => {
+                unhashed.insert(item.file_path);
+            }
+            (Some(_), Some(_)) => {}
+        }
+    }
+    for path in unhashed {
+        if let Some(hash) = &hashes[&path] {
+            report.hashed += database::set_evidence_hash(pool, &path, hash).await? as usize;
+        }
+    }
+    
+    if report.missing.is_empty() && report.corrupt.is_empty() {
+        info!("Evidence store verified: {} files intact, {} hashes recorded", report.checked, report.hashed);
+    } else {
+        warn!(
+            "Evidence store verification found {} rows without file and {} corrupt rows in {} files",
+            report.missing.len(),
+            report.corrupt.len(),
+            report.checked
+        );
+    }
+    Ok(report)
+}
+
+// Stores a file as an attachment of a wiki page. Its `url` can be embedded in
+// the page content, as ![name](url) for images.
+#[command]
+pub async fn upload_page_attachment(
+    app: AppHandle,
+    page_id: String,
+    file_name: String,
+    file_data: Vec<u8>,
+    description: Option<String>,
+) -> AppResult<PageAttachment> {
+    info!("Attaching {} to wiki page {}", file_name, page_id);
     
-    info!("File saved successfully: {:?}", file_path);
+    let pool = app.state::<SqlitePool>();
+    let app_dir = app.path()
+        .app_data_dir()
+        .map_err(|e| AppError::internal(format!("Failed to get app data dir: {}", e)))?;
     
-    Ok(file_path.to_string_lossy().to_string())
+    wiki::add_page_attachment(&pool, &app_dir.join(ATTACHMENTS_DIR), &page_id, &file_name, &file_data, description).await
+}
+
+#[command]
+pub async fn list_page_attachments(
+    app: AppHandle,
+    page_id: String,
+) -> AppResult<Vec<PageAttachment>> {
+    let pool = app.state::<SqlitePool>();
+    let attachments = database::get_evidence_by_page(&pool, &page_id).await?;
+    Ok(attachments.into_iter().map(wiki::attachment_with_url).collect())
+}
+
+#[command]
+pub async fn delete_page_attachment(
+    app: AppHandle,
+    page_id: String,
+    evidence_id: i64,
+) -> AppResult<()> {
+    let pool = app.state::<SqlitePool>();
+    let evidence = database::get_evidence_by_id(&pool, evidence_id).await?;
+    if evidence.page_id.as_deref() != Some(page_id.as_str()) {
+        return Err(AppError::not_found(format!("Attachment {} not found on page {}", evidence_id, page_id)));
+    }
+    database::delete_evidence(&pool, evidence_id).await
+}
+
+// Deletes page attachments that no page or revision refers to any more; with
+// `dry_run` they are only listed
+#[command]
+pub async fn collect_page_attachments(
+    app: AppHandle,
+    dry_run: Option<bool>,
+) -> AppResult<AttachmentGcReport> {
+    let pool = app.state::<SqlitePool>();
+    wiki::collect_attachment_garbage(&pool, dry_run.unwrap_or(false), chrono::Utc::now()).await
 }
 
 #[command]
 pub async fn get_evidence_file_path(
     app: AppHandle,
     evidence_id: i64,
-) -> Result<String, String> {
+) -> AppResult<String> {
     let pool = app.state::<SqlitePool>();
     let evidence = database::get_evidence_by_id(&pool, evidence_id).await?;
     Ok(evidence.file_path)
@@ -122,40 +325,19 @@ pub async fn get_evidence_file_path(
 pub async fn open_evidence_file(
     app: AppHandle,
     file_path: String,
-) -> Result<(), String> {
+) -> AppResult<()> {
     use tauri_plugin_opener::OpenerExt;
     
     app.opener()
         .open_path(&file_path, None::<&str>)
-        .map_err(|e| format!("Failed to open file: {}", e))?;
+        .map_err(|e| AppError::internal(format!("Failed to open file: {}", e)))?;
     
     Ok(())
 }
 
 #[command]
 pub fn get_mime_type(file_path: String) -> String {
-    let path = PathBuf::from(&file_path);
-    
-    match path.extension().and_then(|e| e.to_str()) {
-        Some("jpg") | Some("jpeg") => "image/jpeg".to_string(),
-        Some("png") => "image/png".to_string(),
-        Some("gif") => "image/gif".to_string(),
-        Some("webp") => "image/webp".to_string(),
-        Some("svg") => "image/svg+xml".to_string(),
-        Some("mp4") => "video/mp4".to_string(),
-        Some("webm") => "video/webm".to_string(),
-        Some("mov") => "video/quicktime".to_string(),
-        Some("mp3") => "audio/mpeg".to_string(),
-        Some("wav") => "audio/wav".to_string(),
-        Some("ogg") => "audio/ogg".to_string(),
-        Some("pdf") => "application/pdf".to_string(),
-        Some("doc") | Some("docx") => "application/msword".to_string(),
-        Some("xls") | Some("xlsx") => "application/vnd.ms-excel".to_string(),
-        Some("txt") => "text/plain".to_string(),
-        Some("json") => "application/json".to_string(),
-        Some("zip") => "application/zip".to_string(),
-        _ => "application/octet-stream".to_string(),
-    }
+    attachments::mime_type(&PathBuf::from(&file_path)).to_string()
 }
 
 #[command]
@@ -178,3 +360,82 @@ pub fn format_file_size(bytes: i64) -> String {
         format!("{:.2} {}", size, UNITS[exp])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::{page_attachment, store_attachment};
+
+    async fn attach(pool: &SqlitePool, dir: &Path, data: &[u8], hashed: bool) -> Evidence {
+        let stored = store_attachment(dir, "txt", data).unwrap();
+        let mut input = page_attachment("page", &stored, "file.txt", None);
+        if !hashed {
+            input.content_hash = None;
+        }
+        database::add_evidence(pool, input).await.unwrap()
+    }
+
+    fn ids(evidence: &[Evidence]) -> Vec<i64> {
+        let mut ids: Vec<i64> = evidence.iter().map(|e| e.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn intact_store_reports_nothing() {
+        let pool = database::pool_with_page().await;
+        let dir = tempfile::tempdir().unwrap();
+        attach(&pool, dir.path(), b"one", true).await;
+        attach(&pool, dir.path(), b"one", true).await;
+        attach(&pool, dir.path(), b"two", true).await;
+
+        let report = verify_store(&pool).await.unwrap();
+        assert_eq!(report.checked, 2);
+        assert!(report.missing.is_empty() && report.corrupt.is_empty());
+        assert_eq!(report.hashed, 0);
+    }
+
+    #[tokio::test]
+    async fn missing_and_corrupt_files_are_reported() {
+        let pool = database::pool_with_page().await;
+        let dir = tempfile::tempdir().unwrap();
+        let missing = attach(&pool, dir.path(), b"missing", true).await;
+        let corrupt = attach(&pool, dir.path(), b"corrupt", true).await;
+        let sharing = attach(&pool, dir.path(), b"corrupt", true).await;
+        attach(&pool, dir.path(), b"intact", true).await;
+
+        std::fs::remove_file(&missing.file_path).unwrap();
+        std::fs::write(&corrupt.file_path, b"CORRUPT").unwrap();
+
+        let report = verify_store(&pool).await.unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(ids(&report.missing), vec![missing.id]);
+        assert_eq!(ids(&report.corrupt), vec![corrupt.id, sharing.id]);
+        assert_eq!(report.hashed, 0);
+    }
+
+    #[tokio::test]
+    async fn rows_from_before_hashing_get_their_hash_recorded() {
+        let pool = database::pool_with_page().await;
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = attach(&pool, dir.path(), b"legacy", false).await;
+        let also = attach(&pool, dir.path(), b"legacy", false).await;
+        let gone = attach(&pool, dir.path(), b"gone", false).await;
+        std::fs::remove_file(&gone.file_path).unwrap();
+
+        let report = verify_store(&pool).await.unwrap();
+        assert_eq!(report.hashed, 2);
+        assert_eq!(ids(&report.missing), vec![gone.id]);
+
+        let expected = Some(attachments::content_hash(b"legacy"));
+        for id in [legacy.id, also.id] {
+            assert_eq!(database::get_evidence_by_id(&pool, id).await.unwrap().content_hash, expected);
+        }
+        assert_eq!(database::get_evidence_by_id(&pool, gone.id).await.unwrap().content_hash, None);
+
+        // Once recorded, a later change to the file shows as corruption
+        std::fs::write(&legacy.file_path, b"LEGACY").unwrap();
+        let report = verify_store(&pool).await.unwrap();
+        assert_eq!((report.hashed, ids(&report.corrupt)), (0, vec![legacy.id, also.id]));
+    }
+}
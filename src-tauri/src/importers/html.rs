@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use base64::Engine;
+use crate::error::{AppError, AppResult};
+use super::markdown::{render, Block, Inline};
+use super::{ImportedDocument, Progress};
+
+// Elements left out along with everything inside them. Nothing of the HTML
+// is kept but the text and the structure Markdown can express, so scripts,
+// styles, forms and embedded content go here and everything else is text.
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "object", "embed", "svg", "math", "canvas", "select",
+    "button", "textarea",
+];
+
+// Elements whose content is text up to the end tag, not markup
+const RAW_TEXT: &[&str] = &["script", "style", "title", "textarea"];
+
+const BLOCKS: &[&str] = &[
+    "p", "div", "section", "article", "header", "footer", "main", "aside", "nav", "figure", "figcaption", "dl", "dt",
+    "dd", "address", "center", "body", "form", "fieldset", "details", "summary", "caption",
+];
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg", "bmp"];
+
+#[derive(Debug)]
+enum Token {
+    Start { name: String, attributes: HashMap<String, String> },
+    End(String),
+    Text(String),
+}
+
+/// Converts HTML to Markdown. Images are taken from data: URLs and from
+/// files next to the page in `base_dir`; images on the web stay links.
+/// Markup that is not well formed is read as far as it goes, but binary
+/// data is refused.
+pub fn convert(html: &str, base_dir: Option<&Path>, progress: Progress) -> AppResult<ImportedDocument> {
+    if html.contains('\0') {
+        return Err(AppError::validation("Not an HTML file"));
+    }
+
+    let tokens = tokenize(html);
+    let total = tokens.len().max(1);
+    let mut converter = Converter { base_dir, ..Default::default() };
+
+    for (i, token) in tokens.into_iter().enumerate() {
+        converter.token(token);
+        if i % 2000 == 0 {
+            progress(0.1 + 0.8 * i as f64 / total as f64);
+        }
+    }
+    converter.flush();
+    converter.end_table();
+
+    let title = converter.title.take().filter(|t| !t.trim().is_empty()).or_else(|| {
+        converter.blocks.iter().find_map(|block| match block {
+            Block::Heading(1, text) => Some(unescape(text)),
+            _ => None,
+        })
+    });
+    Ok(ImportedDocument {
+        title: title.unwrap_or_default(),
+        markdown: render(&converter.blocks),
+        ..converter.document
+    })
+}
+
+#[derive(Default)]
+struct Converter<'a> {
+    base_dir: Option<&'a Path>,
+    document: ImportedDocument,
+    blocks: Vec<Block>,
+    inline: Inline,
+    title: Option<String>,
+    in_title: bool,
+    skipping: Option<(String, usize)>,
+    heading: Option<usize>,
+    lists: Vec<bool>,  // Whether each open list is ordered
+    quotes: usize,
+    links: Vec<Option<String>>,
+    tables: usize,
+    rows: Vec<Vec<String>>,
+    in_cell: bool,
+    pre: Option<String>,
+}
+
+impl Converter<'_> {
+    fn token(&mut self, token: Token) {
+        if let Some((name, depth)) = &mut self.skipping {
+            match &token {
+                Token::Start { name: start, .. } if start == name => *depth += 1,
+                Token::End(end) if end == name && *depth > 1 => *depth -= 1,
+                Token::End(end) if end == name => self.skipping = None,
+                _ => {}
+            }
+            return;
+        }
+
+        match token {
+            Token::Start { name, attributes } => self.start(name, &attributes),
+            Token::End(name) => self.end(&name),
+            Token::Text(text) if self.in_title => self.title.get_or_insert_with(String::new).push_str(&text),
+            Token::Text(text) => match &mut self.pre {
+                Some(code) => code.push_str(&text),
+                None => self.inline.push_text(&text),
+            },
+        }
+    }
+
+    fn start(&mut self, name: String, attributes: &HashMap<String, String>) {
+        if self.pre.is_some() {
+            if name == "br" {
+                self.pre.get_or_insert_with(String::new).push('\n');
+            }
+            return;
+        }
+
+        match name.as_str() {
+            skipped if SKIPPED.contains(&skipped) => self.skipping = Some((name, 1)),
+            "title" => self.in_title = true,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.heading = name[1..].parse().ok();
+            }
+            block if BLOCKS.contains(&block) => self.flush(),
+            "br" if self.in_cell => self.inline.push_text(" "),
+            "br" => self.inline.line_break(),
+            "hr" => {
+                self.flush();
+                self.blocks.push(Block::Rule);
+            }
+            "strong" | "b" => self.inline.open("**"),
+            "em" | "i" | "cite" => self.inline.open("*"),
+            "del" | "s" | "strike" => self.inline.open("~~"),
+            "code" | "kbd" | "samp" | "tt" => {
+                self.inline.open("`");
+                self.inline.verbatim = true;
+            }
+            "a" => {
+                let href = attributes.get("href").map(|h| h.trim()).filter(|h| safe_url(h)).map(str::to_string);
+                if href.is_some() {
+                    self.inline.open("[");
+                }
+                self.links.push(href);
+            }
+            "img" => self.image(attributes),
+            "ul" | "ol" => {
+                self.flush();
+                self.lists.push(name == "ol");
+            }
+            "li" => self.flush(),
+            "blockquote" => {
+                self.flush();
+                self.quotes += 1;
+            }
+            "pre" => {
+                self.flush();
+                self.pre = Some(String::new());
+            }
+            "table" => {
+                self.flush();
+                self.tables += 1;
+            }
+            "tr" if self.tables == 1 => self.rows.push(Vec::new()),
+            "td" | "th" if self.tables == 1 => {
+                self.flush();
+                if self.rows.is_empty() {
+                    self.rows.push(Vec::new());
+                }
+                if let Some(row) = self.rows.last_mut() {
+                    row.push(String::new());
+                }
+                self.in_cell = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        if let Some(code) = &self.pre {
+            if name == "pre" {
+                let code = code.trim_matches('\n').to_string();
+                self.pre = None;
+                if !code.trim().is_empty() {
+                    self.blocks.push(Block::Code(code));
+                }
+            }
+            return;
+        }
+
+        match name {
+            "title" => self.in_title = false,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.heading = None;
+            }
+            block if BLOCKS.contains(&block) => self.flush(),
+            "strong" | "b" => self.inline.close("**", "**"),
+            "em" | "i" | "cite" => self.inline.close("*", "*"),
+            "del" | "s" | "strike" => self.inline.close("~~", "~~"),
+            "code" | "kbd" | "samp" | "tt" => {
+                self.inline.close("`", "`");
+                self.inline.verbatim = false;
+            }
+            "a" => {
+                if let Some(Some(href)) = self.links.pop() {
+                    self.inline.close("[", &format!("]({})", link_target(&href)));
+                }
+            }
+            "ul" | "ol" => {
+                self.flush();
+                self.lists.pop();
+            }
+            "li" => self.flush(),
+            "blockquote" => {
+                self.flush();
+                self.quotes = self.quotes.saturating_sub(1);
+            }
+            "td" | "th" if self.tables == 1 => {
+                self.flush();
+                self.in_cell = false;
+            }
+            "table" => {
+                self.flush();
+                self.tables = self.tables.saturating_sub(1);
+                if self.tables == 0 {
+                    self.end_table();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Ends the block whose inline text has been collected
+    fn flush(&mut self) {
+        if self.inline.is_empty() {
+            self.inline.take();
+            return;
+        }
+        let text = self.inline.take();
+
+        if self.tables > 0 && self.in_cell {
+            if let Some(cell) = self.rows.last_mut().and_then(|row| row.last_mut()) {
+                if !cell.is_empty() {
+                    cell.push(' ');
+                }
+                cell.push_str(&text);
+                return;
+            }
+        }
+
+        let block = if let Some(level) = self.heading {
+            Block::Heading(level, text)
+        } else if let Some(&ordered) = self.lists.last() {
+            Block::ListItem { depth: self.lists.len() - 1, ordered, text }
+        } else if self.quotes > 0 {
+            Block::Quote(text)
+        } else {
+            Block::Paragraph(text)
+        };
+        self.blocks.push(block);
+    }
+
+    fn end_table(&mut self) {
+        let rows: Vec<Vec<String>> = std::mem::take(&mut self.rows).into_iter().filter(|row| !row.is_empty()).collect();
+        if !rows.is_empty() {
+            self.blocks.push(Block::Table(rows));
+        }
+    }
+
+    fn image(&mut self, attributes: &HashMap<String, String>) {
+        let alt = attributes.get("alt").map(|alt| super::markdown::escape(alt.trim())).unwrap_or_default();
+        let src = attributes.get("src").map(|src| src.trim()).unwrap_or_default();
+
+        let url = if let Some(data) = src.strip_prefix("data:") {
+            data_url_image(data).map(|(extension, bytes)| self.document.add_image(extension, bytes))
+        } else if src.starts_with("http://") || src.starts_with("https://") {
+            Some(link_target(src))
+        } else {
+            self.local_image(src)
+        };
+
+        match url {
+            Some(url) => self.inline.push_markup(&format!("![{}]({})", alt, url)),
+            None => self.inline.push_text(&alt),
+        }
+    }
+
+    // An image file the page refers to by a path relative to itself
+    fn local_image(&mut self, src: &str) -> Option<String> {
+        let src = urlencoding::decode(src).ok()?;
+        let src = src.split(['?', '#']).next()?;
+        let path = self.base_dir?.join(src);
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+        let data = fs::read(&path).ok()?;
+        Some(self.document.add_image(&extension, data))
+    }
+}
+
+// Links that could run code when clicked are dropped
+fn safe_url(url: &str) -> bool {
+    let scheme = url.split(':').next().unwrap_or_default().to_lowercase();
+    !url.contains(':') || ["http", "https", "mailto"].contains(&scheme.as_str())
+}
+
+fn link_target(url: &str) -> String {
+    url.replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+// The image in a data: URL, which must be base64-encoded
+fn data_url_image(data: &str) -> Option<(&'static str, Vec<u8>)> {
+    let (header, payload) = data.split_once(',')?;
+    let header = header.to_lowercase();
+    if !header.ends_with(";base64") {
+        return None;
+    }
+    let extension = match header.trim_end_matches(";base64") {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "image/bmp" => "bmp",
+        _ => return None,
+    };
+    let payload: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(payload).ok()?;
+    Some((extension, bytes)).filter(|(_, bytes)| !bytes.is_empty())
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// A forgiving tokenizer: anything that is not a well-formed tag is text
+fn tokenize(html: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(end_tag) = rest.strip_prefix("</") {
+            let end = end_tag.find('>').unwrap_or(end_tag.len());
+            let name = end_tag[..end].trim().to_lowercase();
+            tokens.push(Token::End(name));
+            rest = end_tag.get(end + 1..).unwrap_or("");
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (name, attributes, self_closing, after) = parse_tag(&rest[1..]);
+            rest = after;
+            if RAW_TEXT.contains(&name.as_str()) && !self_closing {
+                let end = find_ignore_case(rest, &format!("</{}", name)).unwrap_or(rest.len());
+                let text = decode_entities(&rest[..end]);
+                rest = rest[end..].find('>').map_or("", |close| &rest[end + close + 1..]);
+                tokens.push(Token::Start { name: name.clone(), attributes });
+                tokens.push(Token::Text(text));
+                tokens.push(Token::End(name));
+            } else {
+                tokens.push(Token::Start { name: name.clone(), attributes });
+                if self_closing {
+                    tokens.push(Token::End(name));
+                }
+            }
+        } else {
+            let end = rest.char_indices().skip(1).find(|&(_, c)| c == '<').map_or(rest.len(), |(i, _)| i);
+            tokens.push(Token::Text(decode_entities(&rest[..end])));
+            rest = &rest[end..];
+        }
+    }
+
+    tokens
+}
+
+// Parses a start tag after its "<", returning the rest of the input after it
+fn parse_tag(input: &str) -> (String, HashMap<String, String>, bool, &str) {
+    let name_end = input.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(input.len());
+    let name = input[..name_end].to_lowercase();
+    let mut attributes = HashMap::new();
+    let mut rest = &input[name_end..];
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return (name, attributes, true, after);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (name, attributes, false, after);
+        }
+        if rest.is_empty() {
+            return (name, attributes, false, rest);
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            continue;
+        }
+
+        let key_end = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/').unwrap_or(rest.len());
+        let key = rest[..key_end].to_lowercase();
+        rest = rest[key_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = remaining;
+        }
+        if !key.is_empty() {
+            attributes.entry(key).or_insert(value);
+        } else if let Some(c) = rest.chars().next() {
+            // A stray character that starts no attribute
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+}
+
+// Lowercasing only ASCII keeps byte offsets as they are
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(&needle.to_ascii_lowercase())
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end + 1];
+            let c = match entity.strip_prefix('#') {
+                Some(number) => match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                    None => number.parse().ok().and_then(char::from_u32),
+                },
+                None => named_entity(entity),
+            }?;
+            Some((c, end + 2))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "euro" => '€',
+        "times" => '×',
+        "deg" => '°',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importers::image_placeholder;
+
+    fn markdown(html: &str) -> String {
+        convert(html, None, &|_| {}).unwrap().markdown
+    }
+
+    #[test]
+    fn headings_and_title() {
+        let document = convert("<h1>Trip &amp; plans</h1><h3>Day <em>one</em></h3><p>Text</p>", None, &|_| {}).unwrap();
+        assert_eq!(document.markdown, "# Trip & plans\n\n### Day *one*\n\nText\n");
+        assert_eq!(document.title, "Trip & plans");
+
+        let document = convert("<title>Page title</title><h1>Heading</h1>", None, &|_| {}).unwrap();
+        assert_eq!(document.title, "Page title");
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            markdown("<ul><li>One</li><li>Two<ol><li>First</li><li>Second</li></ol></li></ul><p>After</p>"),
+            "- One\n- Two\n    1. First\n    2. Second\n\nAfter\n"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            markdown("<table><tr><th>Name</th><th>Qty</th></tr><tr><td>Tea | green</td><td>2</td></tr></table>"),
+            "| Name | Qty |\n| --- | --- |\n| Tea \\| green | 2 |\n"
+        );
+    }
+
+    #[test]
+    fn embedded_images() {
+        let document = convert(
+            r#"<p><img alt="dot" src="data:image/png;base64,iVBORw0KGgo="><img src="https://example.com/a b.png"><img src="data:text/plain;base64,aGk=" alt="text"></p>"#,
+            None,
+            &|_| {},
+        )
+        .unwrap();
+        assert_eq!(document.images.len(), 1);
+        assert_eq!(document.images[0].extension, "png");
+        assert_eq!(document.images[0].data, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(
+            document.markdown,
+            format!("![dot]({})![](https://example.com/a%20b.png)text\n", image_placeholder(0))
+        );
+    }
+
+    #[test]
+    fn unsafe_links_and_scripts_are_dropped() {
+        assert_eq!(
+            markdown(r#"<p><a href="javascript:alert(1)">click</a> <a href="https://x.org">site</a><script>alert(1)</script></p>"#),
+            "click [site](https://x.org)\n"
+        );
+    }
+
+    #[test]
+    fn truncated_markup_is_read_as_far_as_it_goes() {
+        assert_eq!(markdown("<h2>Half"), "## Half\n");
+        assert_eq!(markdown("<p>Some <b>bold"), "Some **bold\n");
+        assert_eq!(markdown("<table><tr><td>a<td>b"), "| a | b |\n| --- | --- |\n");
+        assert_eq!(markdown("<ul><li>item</ul></ul></li>"), "- item\n");
+        assert_eq!(markdown("text <img src=\"data:image/png;base64,"), "text\n");
+        assert_eq!(markdown("a < b &amp c &#xFFFFFFFF; <!-- open"), "a \\< b &amp c &#xFFFFFFFF;\n");
+        assert_eq!(markdown("<p title='unclosed>é"), "");
+        assert_eq!(markdown(""), "");
+    }
+
+    #[test]
+    fn binary_data_is_refused() {
+        assert!(matches!(convert("\u{0}\u{1}PK", None, &|_| {}), Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,183 @@
+/// Blocks of a converted document. The converters produce these and the
+/// Markdown is written in one place, so headings, lists and tables come out
+/// the same whatever the source format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    ListItem { depth: usize, ordered: bool, text: String },
+    Table(Vec<Vec<String>>),  // The first row is the header
+    Code(String),
+    Quote(String),
+    Rule,
+}
+
+pub fn render(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    // Numbers of the ordered list items so far, by depth
+    let mut numbers: Vec<usize> = Vec::new();
+    let mut after_item = false;
+
+    for block in blocks {
+        let is_item = matches!(block, Block::ListItem { .. });
+        if !out.is_empty() {
+            out.push_str(if is_item && after_item { "\n" } else { "\n\n" });
+        }
+        if !is_item {
+            numbers.clear();
+        }
+
+        match block {
+            Block::Heading(level, text) => {
+                out.push_str(&"#".repeat((*level).clamp(1, 6)));
+                out.push(' ');
+                out.push_str(text);
+            }
+            Block::Paragraph(text) => out.push_str(text),
+            Block::ListItem { depth, ordered, text } => {
+                numbers.resize(depth + 1, 0);
+                out.push_str(&"    ".repeat(*depth));
+                match ordered {
+                    true => {
+                        numbers[*depth] += 1;
+                        out.push_str(&format!("{}. ", numbers[*depth]));
+                    }
+                    // A numbered list after a bulleted one starts again from 1
+                    false => {
+                        numbers[*depth] = 0;
+                        out.push_str("- ");
+                    }
+                }
+                out.push_str(text);
+            }
+            Block::Table(rows) => render_table(rows, &mut out),
+            Block::Code(text) => {
+                let fence = if text.contains("```") { "~~~~" } else { "```" };
+                out.push_str(&format!("{}\n{}\n{}", fence, text.trim_end_matches('\n'), fence));
+            }
+            Block::Quote(text) => {
+                let quoted: Vec<String> = text.lines().map(|line| format!("> {}", line).trim_end().to_string()).collect();
+                out.push_str(&quoted.join("\n"));
+            }
+            Block::Rule => out.push_str("---"),
+        }
+        after_item = is_item;
+    }
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+fn render_table(rows: &[Vec<String>], out: &mut String) {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0).max(1);
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = (0..columns)
+            .map(|i| cells.get(i).map_or(String::new(), |cell| cell.replace('|', "\\|").replace('\n', " ")))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![line(rows.first().map_or(&[][..], Vec::as_slice))];
+    lines.push(format!("|{}", " --- |".repeat(columns)));
+    lines.extend(rows.iter().skip(1).map(|row| line(row)));
+    out.push_str(&lines.join("\n"));
+}
+
+/// Escapes the characters Markdown would read as formatting.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Inline text of one block as it is being converted. Runs of whitespace
+/// collapse to one space, and emphasis and link markers are only written
+/// once they enclose some text, with spaces kept outside them, since
+/// "** bold**" is not bold in Markdown.
+#[derive(Default)]
+pub struct Inline {
+    text: String,
+    pending: String,
+    /// Text is written as is, for code spans
+    pub verbatim: bool,
+}
+
+impl Inline {
+    pub fn push_text(&mut self, text: &str) {
+        let mut collapsed = String::with_capacity(text.len());
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                collapsed.push(' ');
+            }
+            match self.verbatim {
+                true => collapsed.push_str(word),
+                false => collapsed.push_str(&escape(word)),
+            }
+        }
+        let leading = text.starts_with(char::is_whitespace);
+        let trailing = text.ends_with(char::is_whitespace) && !collapsed.is_empty();
+
+        if leading && !self.text.is_empty() && !self.text.ends_with([' ', '\n']) {
+            self.text.push(' ');
+        }
+        if collapsed.is_empty() {
+            return;
+        }
+        self.text.push_str(&std::mem::take(&mut self.pending));
+        self.text.push_str(&collapsed);
+        if trailing {
+            self.text.push(' ');
+        }
+    }
+
+    /// Markup that counts as content, such as an image.
+    pub fn push_markup(&mut self, markup: &str) {
+        self.text.push_str(&std::mem::take(&mut self.pending));
+        self.text.push_str(markup);
+    }
+
+    pub fn line_break(&mut self) {
+        let trimmed = self.text.trim_end_matches(' ').len();
+        self.text.truncate(trimmed);
+        if !self.text.is_empty() {
+            self.text.push_str("  \n");
+        }
+    }
+
+    pub fn open(&mut self, marker: &str) {
+        self.pending.push_str(marker);
+    }
+
+    /// Closes what `open(opening)` started with `closing`; nothing is written
+    /// if no text came in between.
+    pub fn close(&mut self, opening: &str, closing: &str) {
+        if self.pending.ends_with(opening) {
+            self.pending.truncate(self.pending.len() - opening.len());
+            return;
+        }
+        let had_space = self.text.ends_with(' ');
+        let trimmed = self.text.trim_end_matches(' ').len();
+        self.text.truncate(trimmed);
+        self.text.push_str(closing);
+        if had_space {
+            self.text.push(' ');
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    pub fn take(&mut self) -> String {
+        self.pending.clear();
+        let text = std::mem::take(&mut self.text);
+        text.trim().to_string()
+    }
+}
@@ -0,0 +1,91 @@
+mod html;
+mod markdown;
+mod odt;
+mod pdf;
+mod rtf;
+mod text;
+
+use std::fs;
+use std::path::Path;
+use crate::error::{AppError, AppResult};
+
+/// Reports how far a conversion is, from 0.0 to 1.0.
+pub type Progress<'a> = &'a dyn Fn(f64);
+
+/// A document converted to Markdown. Images it embeds are referenced in
+/// `markdown` by [`image_placeholder`] URLs, in the order of `images`.
+#[derive(Debug, Default)]
+pub struct ImportedDocument {
+    pub title: String,
+    pub markdown: String,
+    pub images: Vec<ImportedImage>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ImportedImage {
+    pub extension: String,
+    pub data: Vec<u8>,
+}
+
+impl ImportedDocument {
+    // Keeps an image and returns the URL standing in for it until it is stored
+    fn add_image(&mut self, extension: &str, data: Vec<u8>) -> String {
+        self.images.push(ImportedImage { extension: extension.to_lowercase(), data });
+        image_placeholder(self.images.len() - 1)
+    }
+}
+
+/// The URL the Markdown of an imported document uses for its `index`th image.
+pub fn image_placeholder(index: usize) -> String {
+    format!("import-image:{}", index)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Pdf,
+    Html,
+    Odt,
+    Text,
+    Rtf,
+}
+
+impl DocumentKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "pdf" => Some(DocumentKind::Pdf),
+            "html" | "htm" | "xhtml" => Some(DocumentKind::Html),
+            "odt" => Some(DocumentKind::Odt),
+            "txt" | "text" => Some(DocumentKind::Text),
+            "rtf" => Some(DocumentKind::Rtf),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the document at `path` to Markdown, keeping headings, lists and
+/// tables where the format records them. PDF files have no such structure,
+/// so only their text and JPEG images come through. This reads and parses
+/// the whole file, so call it off the async runtime.
+pub fn convert(path: &Path, progress: Progress) -> AppResult<ImportedDocument> {
+    let kind = DocumentKind::from_path(path)
+        .ok_or_else(|| AppError::validation(format!("Unsupported document type: {}", path.display())))?;
+    let data = fs::read(path).map_err(|e| AppError::io(format!("Failed to read {}", path.display()), e))?;
+    progress(0.05);
+
+    let mut document = match kind {
+        DocumentKind::Pdf => pdf::convert(&data, progress)?,
+        DocumentKind::Html => html::convert(&String::from_utf8_lossy(&data), path.parent(), progress)?,
+        DocumentKind::Odt => odt::convert(&data, progress)?,
+        DocumentKind::Text => text::convert(&data),
+        DocumentKind::Rtf => rtf::convert(&data, progress)?,
+    };
+
+    if document.title.trim().is_empty() {
+        document.title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    }
+    document.title = document.title.split_whitespace().collect::<Vec<_>>().join(" ");
+    progress(1.0);
+    Ok(document)
+}
@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::ZipArchive;
+use zip::result::ZipError;
+use crate::error::{AppError, AppResult};
+use super::markdown::{render, Block, Inline};
+use super::{ImportedDocument, Progress};
+
+// Content that is not part of the running text: notes, comments, tracked
+// deletions and the generated table of contents
+const SKIPPED: &[&[u8]] = &[
+    b"text:note",
+    b"office:annotation",
+    b"text:tracked-changes",
+    b"text:table-of-content",
+    b"office:forms",
+];
+
+// Repeated empty cells fill rows out to the width of the sheet; beyond this
+// many repeats a cell is taken once
+const MAX_REPEATED_CELLS: usize = 64;
+
+/// Converts an OpenDocument text file. Bold and italics come from the
+/// automatic styles of spans, and whether a list is numbered from its list
+/// style.
+pub fn convert(data: &[u8], progress: Progress) -> AppResult<ImportedDocument> {
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| AppError::validation(format!("Not an OpenDocument file: {}", e)))?;
+    let content = read_entry(&mut archive, "content.xml")?
+        .ok_or_else(|| AppError::validation("Not an OpenDocument file: content.xml is missing"))?;
+
+    let mut styles = Styles::default();
+    if let Some(shared) = read_entry(&mut archive, "styles.xml")? {
+        styles.read(&shared)?;
+    }
+    styles.read(&content)?;
+    progress(0.2);
+
+    let title = match read_entry(&mut archive, "meta.xml")? {
+        Some(meta) => meta_title(&meta)?,
+        None => None,
+    };
+    let pictures = read_pictures(&mut archive)?;
+
+    let mut converter = Converter { styles, pictures, ..Default::default() };
+    let mut reader = Reader::from_str(&content);
+    let mut open = 0;
+    for events in 1.. {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) => {
+                open += 1;
+                converter.start(&e);
+            }
+            Event::Empty(e) => {
+                converter.start(&e);
+                converter.end(e.name().as_ref());
+            }
+            Event::End(e) => {
+                open -= 1;
+                converter.end(e.name().as_ref());
+            }
+            Event::Text(t) if converter.skipping == 0 => converter.inline.push_text(&t.unescape().map_err(xml_error)?),
+            // The end tags matched so far, but some were never reached
+            Event::Eof if open > 0 => return Err(AppError::validation("Invalid OpenDocument XML: content.xml is cut short")),
+            Event::Eof => break,
+            _ => {}
+        }
+        if events % 2000 == 0 {
+            progress(0.2 + 0.7 * reader.buffer_position() as f64 / content.len().max(1) as f64);
+        }
+    }
+    converter.flush();
+
+    let title = title.filter(|title| !title.is_empty()).or_else(|| {
+        converter.blocks.iter().find_map(|block| match block {
+            Block::Heading(1, text) => Some(text.replace('\\', "")),
+            _ => None,
+        })
+    });
+
+    Ok(ImportedDocument {
+        title: title.unwrap_or_default(),
+        markdown: render(&converter.blocks),
+        ..converter.document
+    })
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> AppResult<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(AppError::validation(format!("Failed to read {} from the document: {}", name, e))),
+    };
+    let mut text = String::new();
+    entry
+        .read_to_string(&mut text)
+        .map_err(|e| AppError::validation(format!("Failed to read {} from the document: {}", name, e)))?;
+    Ok(Some(text))
+}
+
+// Embedded images are kept under Pictures/ in the archive
+fn read_pictures(archive: &mut ZipArchive<Cursor<&[u8]>>) -> AppResult<HashMap<String, Vec<u8>>> {
+    let mut pictures = HashMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| AppError::validation(format!("Failed to read the document: {}", e)))?;
+        if !entry.is_file() || !entry.name().starts_with("Pictures/") {
+            continue;
+        }
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| AppError::validation(format!("Failed to read {} from the document: {}", entry.name(), e)))?;
+        pictures.insert(entry.name().to_string(), data);
+    }
+    Ok(pictures)
+}
+
+fn xml_error(error: quick_xml::Error) -> AppError {
+    AppError::validation(format!("Invalid OpenDocument XML: {}", error))
+}
+
+fn attribute(element: &BytesStart, key: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == key)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+fn meta_title(meta: &str) -> AppResult<Option<String>> {
+    let mut reader = Reader::from_str(meta);
+    let mut in_title = false;
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) if e.name().as_ref() == b"dc:title" => in_title = true,
+            Event::Text(t) if in_title => return Ok(Some(t.unescape().map_err(xml_error)?.trim().to_string())),
+            Event::End(_) => in_title = false,
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+// The styles that matter for Markdown: which text styles are bold or italic,
+// and which list styles are numbered
+#[derive(Default)]
+struct Styles {
+    bold: HashSet<String>,
+    italic: HashSet<String>,
+    numbered: HashSet<String>,
+}
+
+impl Styles {
+    fn read(&mut self, xml: &str) -> AppResult<()> {
+        let mut reader = Reader::from_str(xml);
+        let mut style: Option<String> = None;
+        let mut list_style: Option<String> = None;
+
+        loop {
+            match reader.read_event().map_err(xml_error)? {
+                Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                    b"style:style" => style = attribute(&e, b"style:name"),
+                    b"text:list-style" => list_style = attribute(&e, b"style:name"),
+                    b"style:text-properties" => {
+                        let Some(name) = &style else { continue };
+                        if attribute(&e, b"fo:font-weight").is_some_and(|w| w == "bold" || w.parse::<u32>().is_ok_and(|w| w >= 600)) {
+                            self.bold.insert(name.clone());
+                        }
+                        if attribute(&e, b"fo:font-style").is_some_and(|s| s == "italic" || s == "oblique") {
+                            self.italic.insert(name.clone());
+                        }
+                    }
+                    // The first level decides for the whole list
+                    b"text:list-level-style-number" => {
+                        if let Some(name) = list_style.take() {
+                            self.numbered.insert(name);
+                        }
+                    }
+                    b"text:list-level-style-bullet" => list_style = None,
+                    _ => {}
+                },
+                Event::End(e) if e.name().as_ref() == b"style:style" => style = None,
+                Event::Eof => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    fn emphasis(&self, style: &str) -> &'static str {
+        match (self.bold.contains(style), self.italic.contains(style)) {
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Converter {
+    styles: Styles,
+    pictures: HashMap<String, Vec<u8>>,
+    document: ImportedDocument,
+    blocks: Vec<Block>,
+    inline: Inline,
+    skipping: usize,
+    heading: Option<usize>,
+    lists: Vec<bool>,  // Whether each open list is numbered
+    spans: Vec<&'static str>,
+    links: Vec<Option<String>>,
+    tables: usize,
+    rows: Vec<Vec<String>>,
+    in_cell: bool,
+    repeated: usize,
+}
+
+impl Converter {
+    fn start(&mut self, element: &BytesStart) {
+        let name = element.name();
+        let name = name.as_ref();
+        if self.skipping > 0 || SKIPPED.contains(&name) {
+            self.skipping += 1;
+            return;
+        }
+
+        match name {
+            b"text:h" => {
+                self.flush();
+                let level = attribute(element, b"text:outline-level").and_then(|l| l.parse().ok());
+                self.heading = Some(level.unwrap_or(1));
+            }
+            b"text:list" => {
+                self.flush();
+                let numbered = match attribute(element, b"text:style-name") {
+                    Some(style) => self.styles.numbered.contains(&style),
+                    None => self.lists.last().copied().unwrap_or(false),
+                };
+                self.lists.push(numbered);
+            }
+            b"text:span" => {
+                let marker = attribute(element, b"text:style-name").map_or("", |style| self.styles.emphasis(&style));
+                self.inline.open(marker);
+                self.spans.push(marker);
+            }
+            b"text:a" => {
+                let href = attribute(element, b"xlink:href").filter(|href| !href.trim().is_empty());
+                if href.is_some() {
+                    self.inline.open("[");
+                }
+                self.links.push(href);
+            }
+            b"text:s" | b"text:tab" => self.inline.push_text(" "),
+            b"text:line-break" if self.in_cell => self.inline.push_text(" "),
+            b"text:line-break" => self.inline.line_break(),
+            b"draw:image" => self.image(element),
+            b"table:table" => {
+                self.flush();
+                self.tables += 1;
+            }
+            b"table:table-row" if self.tables == 1 => self.rows.push(Vec::new()),
+            b"table:table-cell" | b"table:covered-table-cell" if self.tables == 1 => {
+                self.flush();
+                self.repeated = attribute(element, b"table:number-columns-repeated")
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|&n| n <= MAX_REPEATED_CELLS)
+                    .unwrap_or(1);
+                if self.rows.is_empty() {
+                    self.rows.push(Vec::new());
+                }
+                if let Some(row) = self.rows.last_mut() {
+                    row.extend(std::iter::repeat_n(String::new(), self.repeated));
+                }
+                self.in_cell = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        if self.skipping > 0 {
+            self.skipping -= 1;
+            return;
+        }
+
+        match name {
+            b"text:p" => self.flush(),
+            b"text:h" => {
+                self.flush();
+                self.heading = None;
+            }
+            b"text:list" => {
+                self.flush();
+                self.lists.pop();
+            }
+            b"text:span" => {
+                let marker = self.spans.pop().unwrap_or_default();
+                self.inline.close(marker, marker);
+            }
+            b"text:a" => {
+                if let Some(Some(href)) = self.links.pop() {
+                    self.inline.close("[", &format!("]({})", href.replace(' ', "%20")));
+                }
+            }
+            b"table:table-cell" | b"table:covered-table-cell" if self.tables == 1 => {
+                self.flush();
+                self.in_cell = false;
+                // A repeated cell holds the same content each time
+                if let Some(row) = self.rows.last_mut() {
+                    let from = row.len().saturating_sub(self.repeated);
+                    if let Some(content) = row.last().cloned() {
+                        row[from..].fill(content);
+                    }
+                }
+            }
+            b"table:table" => {
+                self.flush();
+                self.tables = self.tables.saturating_sub(1);
+                if self.tables == 0 {
+                    self.end_table();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Ends the paragraph or heading whose text has been collected
+    fn flush(&mut self) {
+        if self.inline.is_empty() {
+            self.inline.take();
+            return;
+        }
+        let text = self.inline.take();
+
+        if self.tables > 0 && self.in_cell {
+            if let Some(cell) = self.rows.last_mut().and_then(|row| row.last_mut()) {
+                if !cell.is_empty() {
+                    cell.push(' ');
+                }
+                cell.push_str(&text);
+                return;
+            }
+        }
+
+        let block = if let Some(level) = self.heading {
+            Block::Heading(level, text)
+        } else if let Some(&ordered) = self.lists.last() {
+            Block::ListItem { depth: self.lists.len() - 1, ordered, text }
+        } else {
+            Block::Paragraph(text)
+        };
+        self.blocks.push(block);
+    }
+
+    // Columns that are empty in every row, as repeated cells leave at the end, are dropped
+    fn end_table(&mut self) {
+        let mut rows: Vec<Vec<String>> = std::mem::take(&mut self.rows)
+            .into_iter()
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        let columns = rows
+            .iter()
+            .map(|row| row.iter().rposition(|cell| !cell.is_empty()).map_or(0, |i| i + 1))
+            .max()
+            .unwrap_or(0);
+        for row in &mut rows {
+            row.truncate(columns);
+        }
+        if !rows.is_empty() {
+            self.blocks.push(Block::Table(rows));
+        }
+    }
+
+    fn image(&mut self, element: &BytesStart) {
+        let Some(href) = attribute(element, b"xlink:href") else { return };
+        let Some(data) = self.pictures.get(&href).cloned() else { return };
+        let extension = href.rsplit_once('.').map_or("png", |(_, extension)| extension).to_string();
+
+        let url = self.document.add_image(&extension, data);
+        self.inline.push_markup(&format!("![]({})", url));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+    use crate::importers::image_placeholder;
+
+    const STYLES: &str = r#"<office:automatic-styles>
+        <style:style style:name="B"><style:text-properties fo:font-weight="bold"/></style:style>
+        <style:style style:name="I"><style:text-properties fo:font-style="italic"/></style:style>
+        <text:list-style style:name="N"><text:list-level-style-number text:level="1"/></text:list-style>
+        <text:list-style style:name="L"><text:list-level-style-bullet text:level="1"/></text:list-style>
+    </office:automatic-styles>"#;
+
+    fn odt(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn content(body: &str) -> String {
+        format!("<office:document-content>{}<office:body><office:text>{}</office:text></office:body></office:document-content>", STYLES, body)
+    }
+
+    fn convert_body(body: &str) -> ImportedDocument {
+        convert(&odt(&[("content.xml", content(body).as_bytes())]), &|_| {}).unwrap()
+    }
+
+    #[test]
+    fn headings_and_emphasis() {
+        let document = convert_body(concat!(
+            r#"<text:h text:outline-level="1">Trip &amp; plans</text:h>"#,
+            r#"<text:h text:outline-level="2">Day one</text:h>"#,
+            r#"<text:p>Some <text:span text:style-name="B">bold</text:span> and <text:span text:style-name="I">italic</text:span>"#,
+            r#"<text:note><text:note-body><text:p>A footnote</text:p></text:note-body></text:note> text</text:p>"#,
+        ));
+        assert_eq!(document.markdown, "# Trip & plans\n\n## Day one\n\nSome **bold** and *italic* text\n");
+        assert_eq!(document.title, "Trip & plans");
+
+        let meta = br#"<office:document-meta><office:meta><dc:title>From meta</dc:title></office:meta></office:document-meta>"#;
+        let data = odt(&[("content.xml", content("<text:h>Heading</text:h>").as_bytes()), ("meta.xml", meta)]);
+        assert_eq!(convert(&data, &|_| {}).unwrap().title, "From meta");
+    }
+
+    #[test]
+    fn lists() {
+        let document = convert_body(concat!(
+            r#"<text:list text:style-name="L"><text:list-item><text:p>One</text:p></text:list-item>"#,
+            r#"<text:list-item><text:p>Two</text:p><text:list><text:list-item><text:p>Nested</text:p></text:list-item></text:list></text:list-item></text:list>"#,
+            r#"<text:list text:style-name="N"><text:list-item><text:p>First</text:p></text:list-item><text:list-item><text:p>Second</text:p></text:list-item></text:list>"#,
+        ));
+        assert_eq!(document.markdown, "- One\n- Two\n    - Nested\n1. First\n2. Second\n");
+    }
+
+    #[test]
+    fn tables() {
+        let document = convert_body(concat!(
+            "<table:table><table:table-row>",
+            "<table:table-cell><text:p>Name</text:p></table:table-cell><table:table-cell><text:p>Qty</text:p></table:table-cell>",
+            r#"<table:table-cell table:number-columns-repeated="1000"/></table:table-row><table:table-row>"#,
+            r#"<table:table-cell><text:p>Tea</text:p></table:table-cell><table:table-cell table:number-columns-repeated="2"><text:p>2</text:p></table:table-cell>"#,
+            "</table:table-row></table:table>",
+        ));
+        assert_eq!(document.markdown, "| Name | Qty |  |\n| --- | --- | --- |\n| Tea | 2 | 2 |\n");
+    }
+
+    #[test]
+    fn embedded_images() {
+        let body = concat!(
+            r#"<text:p>Logo: <draw:frame><draw:image xlink:href="Pictures/logo.png"/></draw:frame>"#,
+            r#"<draw:frame><draw:image xlink:href="Pictures/missing.png"/></draw:frame></text:p>"#,
+        );
+        let data = odt(&[("content.xml", content(body).as_bytes()), ("Pictures/logo.png", b"\x89PNG")]);
+        let document = convert(&data, &|_| {}).unwrap();
+        assert_eq!(document.images.len(), 1);
+        assert_eq!((document.images[0].extension.as_str(), document.images[0].data.as_slice()), ("png", &b"\x89PNG"[..]));
+        assert_eq!(document.markdown, format!("Logo: ![]({})\n", image_placeholder(0)));
+    }
+
+    #[test]
+    fn damaged_files_are_refused() {
+        let is_invalid = |data: &[u8]| matches!(convert(data, &|_| {}), Err(AppError::Validation(_)));
+        let valid = odt(&[("content.xml", content("<text:p>Text</text:p>").as_bytes())]);
+
+        assert!(is_invalid(b""));
+        assert!(is_invalid(b"PK\x03\x04 not really a zip"));
+        assert!(is_invalid(&valid[..valid.len() / 2]));
+        assert!(is_invalid(&odt(&[("other.xml", b"<a/>")])));
+        assert!(is_invalid(&odt(&[("content.xml", b"<text:p>Text</text:span>")])));
+        assert!(is_invalid(&odt(&[("content.xml", b"\xff\xfe not utf-8")])));
+
+        // Corrupt the compressed content.xml but keep the archive directory intact
+        let mut corrupt = valid.clone();
+        let at = corrupt.windows(11).position(|w| w == b"content.xml").unwrap() + 11;
+        corrupt[at..at + 16].fill(0xAA);
+        assert!(is_invalid(&corrupt));
+    }
+
+    #[test]
+    fn truncated_content_is_refused() {
+        let xml = content("<text:h>Cut short</text:h>");
+        let data = odt(&[("content.xml", &xml.as_bytes()[..xml.len() - 40])]);
+        assert!(matches!(convert(&data, &|_| {}), Err(AppError::Validation(_))));
+    }
+}
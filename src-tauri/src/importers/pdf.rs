@@ -0,0 +1,616 @@
+use std::io::Read;
+use flate2::read::ZlibDecoder;
+use crate::error::{AppError, AppResult};
+use super::markdown::{escape, render, Block};
+use super::text::windows_1252;
+use super::{ImportedDocument, Progress};
+
+// Decompressed streams beyond this are cut off rather than read into memory
+const MAX_STREAM_SIZE: u64 = 64 * 1024 * 1024;
+
+// Readers accept a file whose end marker is this close to its end
+const EOF_MARKER_WINDOW: usize = 1024;
+
+// Streams that hold no page text: cross references, compressed objects,
+// XMP metadata, embedded fonts and character maps
+const SKIPPED_TYPES: &[&[u8]] = &[b"XRef", b"ObjStm", b"Metadata", b"EmbeddedFile"];
+const FONT_KEYS: &[&[u8]] = &[b"/Length1", b"/Length2", b"/Length3"];
+
+/// Extracts the text and JPEG images of a PDF file. PDF only records where
+/// glyphs go on the page, so lines are joined into paragraphs by their
+/// spacing, and text in fonts with their own encodings cannot be read
+/// without the font, which is reported as a warning.
+pub fn convert(data: &[u8], progress: Progress) -> AppResult<ImportedDocument> {
+    if !data.starts_with(b"%PDF") {
+        return Err(AppError::validation("Not a PDF file"));
+    }
+    if find(data, b"%%EOF", data.len().saturating_sub(EOF_MARKER_WINDOW)).is_none() {
+        return Err(AppError::validation("The PDF file is damaged or cut short"));
+    }
+    if find(data, b"/Encrypt", 0).is_some() {
+        return Err(AppError::validation("Encrypted PDF files cannot be imported"));
+    }
+
+    let streams = streams(data);
+    if streams.is_empty() {
+        return Err(AppError::validation("The PDF file has no content; it may be damaged"));
+    }
+    let total = streams.len().max(1);
+    let mut document = ImportedDocument::default();
+    let mut blocks = Vec::new();
+    let mut unreadable = 0;
+    let mut skipped_images = 0;
+
+    for (i, stream) in streams.into_iter().enumerate() {
+        let content = match has_filter(stream.dict, b"FlateDecode") {
+            true => inflate(stream.data),
+            false => stream.data.to_vec(),
+        };
+
+        if name_value(stream.dict, b"/Subtype") == Some(b"Image") {
+            match has_filter(stream.dict, b"DCTDecode") {
+                true => {
+                    let url = document.add_image("jpg", content);
+                    blocks.push(Block::Paragraph(format!("![]({})", url)));
+                }
+                false => skipped_images += 1,
+            }
+        } else if !is_skipped(stream.dict) && !has_filter(stream.dict, b"DCTDecode") {
+            let page = Page::read(&content);
+            unreadable += page.unreadable;
+            blocks.extend(page.paragraphs().into_iter().map(Block::Paragraph));
+        }
+        progress(0.05 + 0.9 * (i + 1) as f64 / total as f64);
+    }
+
+    if unreadable > 0 {
+        document.warnings.push(format!(
+            "Text in {} places uses a font encoding that cannot be read and was left out",
+            unreadable
+        ));
+    }
+    if skipped_images > 0 {
+        document.warnings.push(format!("{} images that are not JPEG were left out", skipped_images));
+    }
+    if blocks.is_empty() {
+        document.warnings.push("No text was found; the PDF may be scanned images".to_string());
+    }
+
+    document.title = info_title(data).unwrap_or_default();
+    document.markdown = render(&blocks);
+    Ok(document)
+}
+
+struct Stream<'a> {
+    dict: &'a [u8],
+    data: &'a [u8],
+}
+
+// Finds the streams of the file in order, which for the files PDF writers
+// produce is also the order of the pages
+fn streams(data: &[u8]) -> Vec<Stream<'_>> {
+    let mut streams = Vec::new();
+    let mut from = 0;
+
+    while let Some(at) = find(data, b"stream", from) {
+        let mut start = at + b"stream".len();
+        let is_keyword = !data[..at].ends_with(b"end");
+        match data.get(start..start + 2) {
+            Some(b"\r\n") => start += 2,
+            Some([b'\n', _]) | Some([b'\r', _]) => start += 1,
+            _ => {
+                from = start;
+                continue;
+            }
+        }
+        if !is_keyword {
+            from = start;
+            continue;
+        }
+
+        let dict_start = data[from..at].windows(3).rposition(|w| w == b"obj").map_or(from, |i| from + i + 3);
+        let dict = &data[dict_start..at];
+        let end = direct_length(dict)
+            .and_then(|length| start.checked_add(length))
+            .filter(|&end| end <= data.len() && find(&data[end..data.len().min(end + 32)], b"endstream", 0).is_some())
+            .or_else(|| find(data, b"endstream", start))
+            .unwrap_or(data.len());
+
+        streams.push(Stream { dict, data: &data[start..end] });
+        from = end;
+    }
+    streams
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|i| from + i)
+}
+
+// The /Length of a stream when it is a number rather than a reference
+fn direct_length(dict: &[u8]) -> Option<usize> {
+    let at = find(dict, b"/Length", 0)? + b"/Length".len();
+    let rest = &dict[at..];
+    let digits: Vec<u8> = rest.iter().copied().skip_while(u8::is_ascii_whitespace).take_while(u8::is_ascii_digit).collect();
+    let after = rest.iter().skip_while(|b| b.is_ascii_whitespace()).skip(digits.len());
+    let after: Vec<u8> = after.take(8).copied().collect();
+    // "/Length 12 0 R" is a reference to another object
+    let is_reference = after.iter().copied().filter(|b| !b.is_ascii_whitespace()).take(2).eq(*b"0R");
+    match is_reference {
+        true => None,
+        false => std::str::from_utf8(&digits).ok()?.parse().ok(),
+    }
+}
+
+// The name a key has in a dictionary, as in "/Subtype /Image"
+fn name_value<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut from = 0;
+    while let Some(at) = find(dict, key, from) {
+        from = at + key.len();
+        // "/Type" also starts "/TypeX"
+        if dict.get(from).is_some_and(|b| b.is_ascii_alphanumeric()) {
+            continue;
+        }
+        let rest = &dict[from..];
+        let start = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+        if rest[start] != b'/' {
+            return None;
+        }
+        let name = &rest[start + 1..];
+        let end = name.iter().position(|&b| is_delimiter(b) || b.is_ascii_whitespace()).unwrap_or(name.len());
+        return Some(&name[..end]);
+    }
+    None
+}
+
+fn has_filter(dict: &[u8], filter: &[u8]) -> bool {
+    let Some(at) = find(dict, b"/Filter", 0) else { return false };
+    let rest = &dict[at..];
+    let end = rest.iter().position(|&b| b == b']').unwrap_or(rest.len()).min(128);
+    find(&rest[..end.min(rest.len())], filter, 0).is_some()
+}
+
+fn is_skipped(dict: &[u8]) -> bool {
+    let type_name = name_value(dict, b"/Type");
+    type_name.is_some_and(|name| SKIPPED_TYPES.contains(&name))
+        || FONT_KEYS.iter().any(|key| find(dict, key, 0).is_some())
+        || name_value(dict, b"/Subtype").is_some_and(|name| name.ends_with(b"C") || name == b"OpenType")
+        || find(dict, b"/CMapName", 0).is_some()
+}
+
+// A damaged stream still yields the text before the damage
+fn inflate(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = ZlibDecoder::new(data).take(MAX_STREAM_SIZE).read_to_end(&mut out);
+    out
+}
+
+fn info_title(data: &[u8]) -> Option<String> {
+    let mut from = 0;
+    while let Some(at) = find(data, b"/Title", from) {
+        from = at + b"/Title".len();
+        let rest = &data[from..];
+        let start = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+        let mut lexer = Lexer { data: &rest[start..], at: 0 };
+        if let Some(Token::Operand(Operand::String(bytes))) = lexer.next() {
+            let title = decode(&bytes)?;
+            if !title.trim().is_empty() {
+                return Some(title);
+            }
+        }
+    }
+    None
+}
+
+// Text strings are UTF-16 when they start with a byte order mark and in a
+// single-byte encoding otherwise. Bytes that are control characters mean
+// the font has its own encoding, which is not readable without it.
+fn decode(bytes: &[u8]) -> Option<String> {
+    if let [0xFE, 0xFF, rest @ ..] = bytes {
+        let units: Vec<u16> = rest.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        return Some(String::from_utf16_lossy(&units));
+    }
+    let controls = bytes.iter().filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')).count();
+    if controls * 4 > bytes.len() {
+        return None;
+    }
+    Some(bytes.iter().filter(|&&b| b >= 0x20 || b == b'\t').map(|&b| windows_1252(b)).collect())
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+#[derive(Debug)]
+enum Operand {
+    Number(f64),
+    String(Vec<u8>),
+    Array(Vec<Operand>),
+    Other,
+}
+
+#[derive(Debug)]
+enum Token {
+    Operand(Operand),
+    ArrayStart,
+    ArrayEnd,
+    Operator(Vec<u8>),
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Lexer<'_> {
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            let byte = *self.data.get(self.at)?;
+            match byte {
+                b if b.is_ascii_whitespace() || b == 0 => self.at += 1,
+                b'%' => self.skip_while(|b| b != b'\n' && b != b'\r'),
+                _ => break,
+            }
+        }
+
+        let byte = self.data[self.at];
+        self.at += 1;
+        let token = match byte {
+            b'(' => Token::Operand(Operand::String(self.literal())),
+            b'<' if self.data.get(self.at) == Some(&b'<') => {
+                self.at += 1;
+                Token::Operand(Operand::Other)
+            }
+            b'>' if self.data.get(self.at) == Some(&b'>') => {
+                self.at += 1;
+                Token::Operand(Operand::Other)
+            }
+            b'<' => Token::Operand(Operand::String(self.hex())),
+            b'[' => Token::ArrayStart,
+            b']' => Token::ArrayEnd,
+            b'/' => {
+                self.skip_while(|b| !b.is_ascii_whitespace() && !is_delimiter(b));
+                Token::Operand(Operand::Other)
+            }
+            b'+' | b'-' | b'.' | b'0'..=b'9' => {
+                let start = self.at - 1;
+                self.skip_while(|b| b.is_ascii_digit() || b == b'.');
+                let text = std::str::from_utf8(&self.data[start..self.at]).unwrap_or("0");
+                Token::Operand(Operand::Number(text.parse().unwrap_or(0.0)))
+            }
+            _ => {
+                let start = self.at - 1;
+                self.skip_while(|b| !b.is_ascii_whitespace() && !is_delimiter(b));
+                Token::Operator(self.data[start..self.at].to_vec())
+            }
+        };
+        Some(token)
+    }
+
+    fn skip_while(&mut self, keep: impl Fn(u8) -> bool) {
+        while self.data.get(self.at).is_some_and(|&b| keep(b)) {
+            self.at += 1;
+        }
+    }
+
+    fn literal(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut depth = 0;
+        while let Some(&byte) = self.data.get(self.at) {
+            self.at += 1;
+            match byte {
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                b'\\' => {
+                    let Some(&escaped) = self.data.get(self.at) else { break };
+                    self.at += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0C),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.data.get(self.at) {
+                                    Some(&digit @ b'0'..=b'7') => {
+                                        value = value * 8 + (digit - b'0') as u32;
+                                        self.at += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        // A backslash at the end of a line continues the string
+                        b'\r' => {
+                            if self.data.get(self.at) == Some(&b'\n') {
+                                self.at += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => out.push(other),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(byte);
+        }
+        out
+    }
+
+    fn hex(&mut self) -> Vec<u8> {
+        let start = self.at;
+        self.skip_while(|b| b != b'>');
+        let digits: Vec<u8> = self.data[start..self.at]
+            .iter()
+            .filter_map(|&b| (b as char).to_digit(16).map(|d| d as u8))
+            .collect();
+        self.at += 1;
+        digits.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect()
+    }
+}
+
+// The text of one content stream, as lines with the vertical gap before each
+#[derive(Default)]
+struct Page {
+    lines: Vec<(f64, String)>,
+    line: String,
+    gap: f64,
+    y: f64,
+    leading: f64,
+    unreadable: usize,
+}
+
+impl Page {
+    fn read(content: &[u8]) -> Page {
+        let mut page = Page::default();
+        let mut lexer = Lexer { data: content, at: 0 };
+        let mut operands: Vec<Operand> = Vec::new();
+        let mut arrays: Vec<Vec<Operand>> = Vec::new();
+        let mut in_text = false;
+
+        while let Some(token) = lexer.next() {
+            match token {
+                Token::Operand(operand) => match arrays.last_mut() {
+                    Some(array) => array.push(operand),
+                    None => operands.push(operand),
+                },
+                Token::ArrayStart => arrays.push(Vec::new()),
+                Token::ArrayEnd => {
+                    let array = Operand::Array(arrays.pop().unwrap_or_default());
+                    match arrays.last_mut() {
+                        Some(outer) => outer.push(array),
+                        None => operands.push(array),
+                    }
+                }
+                Token::Operator(operator) => {
+                    // Inline image data runs up to "EI"
+                    if operator == b"ID" {
+                        lexer.at = find(content, b"EI", lexer.at).map_or(content.len(), |at| at + 2);
+                    }
+                    match operator.as_slice() {
+                        b"BT" => in_text = true,
+                        b"ET" => in_text = false,
+                        _ if in_text => page.operator(&operator, &operands),
+                        _ => {}
+                    }
+                    operands.clear();
+                    arrays.clear();
+                }
+            }
+        }
+        page.new_line(0.0);
+        page
+    }
+
+    fn operator(&mut self, operator: &[u8], operands: &[Operand]) {
+        let number = |i: usize| match operands.get(i) {
+            Some(Operand::Number(n)) => *n,
+            _ => 0.0,
+        };
+        match operator {
+            b"Tj" => self.show(operands.last()),
+            b"'" => {
+                self.new_line(self.leading);
+                self.show(operands.last());
+            }
+            b"\"" => {
+                self.new_line(self.leading);
+                self.show(operands.get(2));
+            }
+            b"TJ" => {
+                let Some(Operand::Array(items)) = operands.last() else { return };
+                for item in items {
+                    match item {
+                        // Moving on by more than a fraction of a glyph is a word gap
+                        Operand::Number(n) if *n < -200.0 => self.space(),
+                        Operand::String(_) => self.show(Some(item)),
+                        _ => {}
+                    }
+                }
+            }
+            b"TL" => self.leading = number(0),
+            b"Td" | b"TD" => {
+                let (tx, ty) = (number(0), number(1));
+                if operator == b"TD" {
+                    self.leading = -ty;
+                }
+                if ty != 0.0 {
+                    self.new_line(ty.abs());
+                    self.y += ty;
+                } else if tx > 0.0 {
+                    self.space();
+                }
+            }
+            b"T*" => self.new_line(self.leading.abs()),
+            b"Tm" => {
+                let y = number(5);
+                if (y - self.y).abs() > 0.01 {
+                    self.new_line((y - self.y).abs());
+                    self.y = y;
+                } else {
+                    self.space();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn show(&mut self, operand: Option<&Operand>) {
+        let Some(Operand::String(bytes)) = operand else { return };
+        match decode(bytes) {
+            Some(text) => self.line.push_str(&text),
+            None => self.unreadable += 1,
+        }
+    }
+
+    fn space(&mut self) {
+        if !self.line.is_empty() && !self.line.ends_with(' ') {
+            self.line.push(' ');
+        }
+    }
+
+    fn new_line(&mut self, gap: f64) {
+        let line = self.line.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.line.clear();
+        if line.is_empty() {
+            self.gap += gap;
+            return;
+        }
+        self.lines.push((self.gap, line));
+        self.gap = gap;
+    }
+
+    // A gap well beyond the usual line spacing starts a paragraph, and a
+    // word hyphenated at the end of a line is joined up again
+    fn paragraphs(&self) -> Vec<String> {
+        let mut paragraphs = Vec::new();
+        let mut current = String::new();
+        let mut spacing: Option<f64> = None;
+
+        for (gap, line) in &self.lines {
+            let breaks = spacing.is_some_and(|spacing| *gap > spacing * 1.5);
+            if breaks && !current.is_empty() {
+                paragraphs.push(escape(&std::mem::take(&mut current)));
+            }
+            if *gap > 0.0 && (spacing.is_none() || !breaks) {
+                spacing = Some(spacing.map_or(*gap, |s: f64| s.min(*gap)));
+            }
+
+            if current.ends_with('-') && line.starts_with(char::is_lowercase) {
+                current.pop();
+            } else if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(line);
+        }
+        if !current.is_empty() {
+            paragraphs.push(escape(&current));
+        }
+        paragraphs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use crate::importers::image_placeholder;
+
+    // A file with the given objects, each a dictionary and optionally a stream
+    fn pdf(objects: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+        let mut data = b"%PDF-1.4\n".to_vec();
+        for (i, (dict, stream)) in objects.iter().enumerate() {
+            match stream {
+                Some(stream) => {
+                    write!(data, "{} 0 obj\n<< {} /Length {} >>\nstream\n", i + 1, dict, stream.len()).unwrap();
+                    data.extend_from_slice(stream);
+                    data.extend_from_slice(b"\nendstream\nendobj\n");
+                }
+                None => write!(data, "{} 0 obj\n<< {} >>\nendobj\n", i + 1, dict).unwrap(),
+            }
+        }
+        data.extend_from_slice(b"trailer\n<< /Root 1 0 R >>\n%%EOF\n");
+        data
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    const PAGE: &[u8] = b"BT /F1 12 Tf 14 TL 72 720 Td (First line of a para-) Tj T* (graph that wraps.) Tj \
+        0 -40 Td [(Sec) 20 (ond) -300 (\\[paragraph\\]) ] TJ ET";
+
+    #[test]
+    fn text_is_joined_into_paragraphs() {
+        let data = pdf(&[("/Type /Catalog", None), ("/Title (Quarterly report)", None), ("", Some(PAGE))]);
+        let document = convert(&data, &|_| {}).unwrap();
+        assert_eq!(document.title, "Quarterly report");
+        assert_eq!(document.markdown, "First line of a paragraph that wraps.\n\nSecond \\[paragraph\\]\n");
+        assert!(document.warnings.is_empty());
+    }
+
+    #[test]
+    fn compressed_streams_and_unicode_titles() {
+        let title = "/Title <FEFF00440069006100720079002003B1>";
+        let data = pdf(&[(title, None), ("/Filter /FlateDecode", Some(&zlib(b"BT (Caf\\351 \\200) Tj ET")))]);
+        let document = convert(&data, &|_| {}).unwrap();
+        assert_eq!(document.title, "Diary α");
+        assert_eq!(document.markdown, "Café €\n");
+    }
+
+    #[test]
+    fn jpeg_images_are_kept() {
+        let data = pdf(&[
+            ("/Type /XObject /Subtype /Image /Filter /DCTDecode", Some(b"\xff\xd8\xff\xd9")),
+            ("/Type /XObject /Subtype /Image /Filter /FlateDecode", Some(&zlib(b"\0\0\0"))),
+            ("/Length1 10", Some(b"font data")),
+            ("", Some(b"BT (Caption) Tj ET")),
+        ]);
+        let document = convert(&data, &|_| {}).unwrap();
+        assert_eq!(document.images.len(), 1);
+        assert_eq!((document.images[0].extension.as_str(), document.images[0].data.as_slice()), ("jpg", &b"\xff\xd8\xff\xd9"[..]));
+        assert_eq!(document.markdown, format!("![]({})\n\nCaption\n", image_placeholder(0)));
+        assert_eq!(document.warnings, vec!["1 images that are not JPEG were left out".to_string()]);
+    }
+
+    #[test]
+    fn unreadable_fonts_are_reported() {
+        let data = pdf(&[("", Some(b"BT <00010203> Tj ET"))]);
+        let document = convert(&data, &|_| {}).unwrap();
+        assert_eq!(document.markdown, "");
+        assert_eq!(document.warnings.len(), 2);
+    }
+
+    #[test]
+    fn damaged_files_are_refused() {
+        let is_invalid = |data: &[u8]| matches!(convert(data, &|_| {}), Err(AppError::Validation(_)));
+        let valid = pdf(&[("", Some(PAGE))]);
+
+        assert!(is_invalid(b""));
+        assert!(is_invalid(b"<html>not a pdf</html>"));
+        assert!(is_invalid(&valid[..valid.len() / 2]));
+        assert!(is_invalid(b"%PDF-1.7\n1 0 obj << /Type /Catalog >> endobj\n%%EOF\n"));
+        assert!(is_invalid(&pdf(&[("/Encrypt 5 0 R", None), ("", Some(PAGE))])));
+    }
+
+    #[test]
+    fn damaged_streams_do_not_stop_the_import() {
+        let bad_length = b"%PDF-1.4\n1 0 obj << /Length 18446744073709551615 >>\nstream\nBT (Kept) Tj ET\nendstream\nendobj\n%%EOF";
+        assert_eq!(convert(bad_length, &|_| {}).unwrap().markdown, "Kept\n");
+
+        let garbage = pdf(&[("/Filter /FlateDecode", Some(b"\x78\x9c not zlib at all")), ("", Some(b"BT (After) Tj ET"))]);
+        assert_eq!(convert(&garbage, &|_| {}).unwrap().markdown, "After\n");
+
+        for page in [&b"BT (unterminated"[..], b"BT <414", b"BT [(a) [(b) TJ", b"BT (x) Tj ID \xff\xfe", b"BT 1.2.3.4 Td \\ ( ET"] {
+            assert!(convert(&pdf(&[("", Some(page))]), &|_| {}).is_ok(), "{:?}", page);
+        }
+    }
+}
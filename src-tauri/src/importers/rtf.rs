@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use crate::error::{AppError, AppResult};
+use super::markdown::{render, Block, Inline};
+use super::text::windows_1252;
+use super::{ImportedDocument, Progress};
+
+// Destinations whose text is not part of the document. Any other group
+// marked with \* is skipped too, as the format asks of unknown ones.
+const SKIPPED: &[&str] = &[
+    "fonttbl", "colortbl", "listtable", "listoverridetable", "revtbl", "rsidtbl", "generator",
+    "header", "headerl", "headerr", "headerf", "footer", "footerl", "footerr", "footerf",
+    "footnote", "annotation", "object", "nonshppict", "xmlnstbl", "themedata", "colorschememapping",
+    "latentstyles", "datastore", "pnseclvl", "bkmkstart", "bkmkend", "txe", "xe", "tc",
+];
+
+// Starred destinations that do hold text or pictures
+const STARRED: &[&str] = &["shppict", "fldinst"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Destination {
+    #[default]
+    Text,
+    Skipped,
+    Info,
+    Title,
+    StyleSheet,
+    Style,
+    ListText,
+    Picture,
+    FieldInstruction,
+}
+
+// Character formatting and the destination, which groups save and restore
+#[derive(Debug, Clone, Default)]
+struct Group {
+    destination: Destination,
+    bold: bool,
+    italic: bool,
+    unicode_skip: usize,
+    link: Option<String>,
+}
+
+// Paragraph formatting, which lasts until \pard
+#[derive(Default)]
+struct Paragraph {
+    outline: Option<usize>,
+    style: Option<i32>,
+    list_level: Option<usize>,
+    in_table: bool,
+}
+
+/// Converts RTF. Headings come from outline levels and the "heading" styles
+/// of the style sheet, list items from list numbering, and PNG and JPEG
+/// pictures are kept; other picture formats are reported as warnings.
+pub fn convert(data: &[u8], progress: Progress) -> AppResult<ImportedDocument> {
+    if !data.trim_ascii_start().starts_with(b"{\\rtf") {
+        return Err(AppError::validation("Not an RTF file"));
+    }
+
+    let mut converter = Converter {
+        groups: vec![Group { unicode_skip: 1, ..Default::default() }],
+        ..Default::default()
+    };
+    let mut at = 0;
+    let mut reported = 0;
+
+    while at < data.len() {
+        match data[at] {
+            b'{' => {
+                converter.open_group();
+                at += 1;
+            }
+            b'}' => {
+                converter.close_group();
+                at += 1;
+            }
+            b'\\' => at = converter.control(data, at + 1),
+            b'\r' | b'\n' => at += 1,
+            byte => {
+                converter.character(windows_1252(byte));
+                at += 1;
+            }
+        }
+        if at - reported > 64 * 1024 {
+            reported = at;
+            progress(0.05 + 0.9 * at as f64 / data.len() as f64);
+        }
+    }
+    converter.flush();
+    converter.end_table();
+
+    if converter.unsupported_pictures > 0 {
+        converter.document.warnings.push(format!(
+            "{} pictures that are not PNG or JPEG were left out",
+            converter.unsupported_pictures
+        ));
+    }
+    let title = Some(converter.title.trim().to_string()).filter(|title| !title.is_empty()).or_else(|| {
+        converter.blocks.iter().find_map(|block| match block {
+            Block::Heading(1, text) => Some(text.replace('\\', "")),
+            _ => None,
+        })
+    });
+
+    Ok(ImportedDocument {
+        title: title.unwrap_or_default(),
+        markdown: render(&converter.blocks),
+        ..converter.document
+    })
+}
+
+#[derive(Default)]
+struct Converter {
+    groups: Vec<Group>,
+    document: ImportedDocument,
+    blocks: Vec<Block>,
+    inline: Inline,
+    paragraph: Paragraph,
+    open_bold: bool,
+    open_italic: bool,
+    // Characters still to drop after \u, which are its fallback
+    skip_characters: usize,
+    high_surrogate: Option<u16>,
+    title: String,
+    headings: HashMap<i32, usize>,
+    style: (Option<i32>, String),
+    list_text: String,
+    picture: (Option<&'static str>, String),
+    field_instruction: String,
+    rows: Vec<Vec<String>>,
+    row: Vec<String>,
+    cell: String,
+    unsupported_pictures: usize,
+}
+
+impl Converter {
+    fn group(&self) -> &Group {
+        self.groups.last().expect("the document group is never closed")
+    }
+
+    fn group_mut(&mut self) -> &mut Group {
+        self.groups.last_mut().expect("the document group is never closed")
+    }
+
+    fn set_destination(&mut self, destination: Destination) {
+        self.group_mut().destination = destination;
+    }
+
+    fn open_group(&mut self) {
+        let mut group = Group { link: None, ..self.group().clone() };
+        if group.destination == Destination::StyleSheet {
+            group.destination = Destination::Style;
+            self.style = (None, String::new());
+        }
+        self.groups.push(group);
+    }
+
+    fn close_group(&mut self) {
+        // The unbalanced closing brace of a damaged file
+        if self.groups.len() == 1 {
+            return;
+        }
+        let group = self.groups.pop().unwrap_or_default();
+        let outer = self.group().destination;
+
+        match group.destination {
+            Destination::Style if outer == Destination::StyleSheet => {
+                let (number, name) = std::mem::take(&mut self.style);
+                let name = name.trim_end_matches(';').trim().to_lowercase();
+                let level = name.strip_prefix("heading ").and_then(|level| level.parse().ok());
+                if let (Some(level), Some(number)) = (level, number) {
+                    self.headings.insert(number, level);
+                }
+            }
+            Destination::Picture if outer != Destination::Picture => self.picture(),
+            _ => {}
+        }
+        if let Some(href) = group.link {
+            self.format(false, false);
+            self.inline.close("[", &format!("]({})", href));
+        }
+    }
+
+    // Reads the control word or symbol after a backslash and returns where
+    // the text after it starts
+    fn control(&mut self, data: &[u8], start: usize) -> usize {
+        let Some(&first) = data.get(start) else { return start };
+        if !first.is_ascii_alphabetic() {
+            return self.symbol(data, start);
+        }
+
+        let mut at = start;
+        while data.get(at).is_some_and(u8::is_ascii_alphabetic) {
+            at += 1;
+        }
+        let word = String::from_utf8_lossy(&data[start..at]).to_string();
+        let number_start = at;
+        if data.get(at) == Some(&b'-') {
+            at += 1;
+        }
+        while data.get(at).is_some_and(u8::is_ascii_digit) {
+            at += 1;
+        }
+        let parameter: Option<i32> = std::str::from_utf8(&data[number_start..at]).ok().and_then(|n| n.parse().ok());
+        if data.get(at) == Some(&b' ') {
+            at += 1;
+        }
+
+        // Binary data runs for the given number of bytes
+        if word == "bin" {
+            let length = parameter.unwrap_or(0).max(0) as usize;
+            return (at + length).min(data.len());
+        }
+        self.word(&word, parameter);
+        at
+    }
+
+    fn symbol(&mut self, data: &[u8], start: usize) -> usize {
+        match data[start] {
+            b'\'' => {
+                let hex = data.get(start + 1..start + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+                if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    self.character(windows_1252(byte));
+                }
+                start + 3
+            }
+            b'*' => {
+                // Only the control word after \* says whether the destination is known
+                let mut at = start + 1;
+                while data.get(at).is_some_and(u8::is_ascii_whitespace) {
+                    at += 1;
+                }
+                let word: String = data[at..]
+                    .iter()
+                    .skip(1)
+                    .take_while(|b| b.is_ascii_alphabetic())
+                    .map(|&b| b as char)
+                    .collect();
+                if !STARRED.contains(&word.as_str()) {
+                    self.set_destination(Destination::Skipped);
+                }
+                start + 1
+            }
+            b'~' => {
+                self.character(' ');
+                start + 1
+            }
+            b'_' => {
+                self.character('-');
+                start + 1
+            }
+            // An optional hyphen, and the line breaks some writers escape
+            b'-' | b'\r' | b'\n' => start + 1,
+            other => {
+                self.character(other as char);
+                start + 1
+            }
+        }
+    }
+
+    fn word(&mut self, word: &str, parameter: Option<i32>) {
+        let destination = self.group().destination;
+        if destination == Destination::Skipped {
+            return;
+        }
+        if SKIPPED.contains(&word) {
+            self.set_destination(Destination::Skipped);
+            return;
+        }
+        let on = parameter != Some(0);
+
+        match word {
+            "info" => self.set_destination(Destination::Info),
+            "title" if destination == Destination::Info => self.set_destination(Destination::Title),
+            // Other document properties
+            _ if destination == Destination::Info => self.set_destination(Destination::Skipped),
+            "stylesheet" => self.set_destination(Destination::StyleSheet),
+            "s" if destination == Destination::Style => self.style.0 = parameter,
+            "s" => self.paragraph.style = parameter,
+            "listtext" | "pntext" => {
+                self.list_text.clear();
+                self.paragraph.list_level.get_or_insert(0);
+                self.set_destination(Destination::ListText);
+            }
+            "ls" => {
+                self.paragraph.list_level.get_or_insert(0);
+            }
+            "ilvl" => self.paragraph.list_level = Some(parameter.unwrap_or(0).max(0) as usize),
+            "outlinelevel" => self.paragraph.outline = parameter.map(|level| level.max(0) as usize + 1),
+            "pard" => self.paragraph = Paragraph::default(),
+            "intbl" => self.paragraph.in_table = true,
+            "pict" => {
+                self.picture = (None, String::new());
+                self.set_destination(Destination::Picture);
+            }
+            "pngblip" => self.picture.0 = Some("png"),
+            "jpegblip" => self.picture.0 = Some("jpg"),
+            "fldinst" => {
+                self.field_instruction.clear();
+                self.set_destination(Destination::FieldInstruction);
+            }
+            "fldrslt" => {
+                let href = hyperlink(&self.field_instruction);
+                if href.is_some() {
+                    self.inline.open("[");
+                }
+                self.group_mut().link = href;
+            }
+            "b" => self.group_mut().bold = on,
+            "i" => self.group_mut().italic = on,
+            "plain" => {
+                let group = self.group_mut();
+                group.bold = false;
+                group.italic = false;
+            }
+            "uc" => self.group_mut().unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+            "u" => {
+                let unit = parameter.unwrap_or(0).rem_euclid(65536) as u16;
+                self.unicode(unit);
+                self.skip_characters = self.group().unicode_skip;
+            }
+            "par" | "sect" | "page" => self.flush(),
+            "line" if self.paragraph.in_table => self.character(' '),
+            "line" => self.inline.line_break(),
+            "tab" => self.character(' '),
+            "cell" => {
+                self.flush();
+                self.row.push(std::mem::take(&mut self.cell));
+            }
+            "row" => {
+                self.flush();
+                let row = std::mem::take(&mut self.row);
+                self.rows.push(row);
+            }
+            "emdash" => self.character('—'),
+            "endash" => self.character('–'),
+            "bullet" => self.character('•'),
+            "lquote" => self.character('‘'),
+            "rquote" => self.character('’'),
+            "ldblquote" => self.character('“'),
+            "rdblquote" => self.character('”'),
+            _ => {}
+        }
+    }
+
+    fn unicode(&mut self, unit: u16) {
+        match unit {
+            0xD800..=0xDBFF => self.high_surrogate = Some(unit),
+            0xDC00..=0xDFFF => {
+                if let Some(high) = self.high_surrogate.take() {
+                    let text = String::from_utf16_lossy(&[high, unit]);
+                    text.chars().for_each(|c| self.push(c));
+                }
+            }
+            _ => {
+                self.high_surrogate = None;
+                self.push(char::from_u32(unit as u32).unwrap_or('\u{FFFD}'));
+            }
+        }
+    }
+
+    // A character of the text, unless it is the fallback of a \u
+    fn character(&mut self, c: char) {
+        if self.skip_characters > 0 {
+            self.skip_characters -= 1;
+            return;
+        }
+        self.push(c);
+    }
+
+    fn push(&mut self, c: char) {
+        match self.group().destination {
+            Destination::Text => {
+                let (bold, italic) = (self.group().bold, self.group().italic);
+                if !c.is_whitespace() {
+                    self.format(bold, italic);
+                }
+                self.inline.push_text(c.encode_utf8(&mut [0; 4]));
+            }
+            Destination::Title => self.title.push(c),
+            Destination::Style => self.style.1.push(c),
+            Destination::ListText => self.list_text.push(c),
+            Destination::Picture => self.picture.1.push(c),
+            Destination::FieldInstruction => self.field_instruction.push(c),
+            Destination::Skipped | Destination::Info | Destination::StyleSheet => {}
+        }
+    }
+
+    // Opens and closes emphasis so that the markers nest, italics inside bold
+    fn format(&mut self, bold: bool, italic: bool) {
+        if self.open_italic && (!italic || self.open_bold != bold) {
+            self.inline.close("*", "*");
+            self.open_italic = false;
+        }
+        if self.open_bold != bold {
+            match bold {
+                true => self.inline.open("**"),
+                false => self.inline.close("**", "**"),
+            }
+            self.open_bold = bold;
+        }
+        if italic && !self.open_italic {
+            self.inline.open("*");
+            self.open_italic = true;
+        }
+    }
+
+    // Ends the paragraph whose text has been collected
+    fn flush(&mut self) {
+        self.format(false, false);
+        let list_text = std::mem::take(&mut self.list_text);
+        if self.inline.is_empty() {
+            self.inline.take();
+            return;
+        }
+        let text = self.inline.take();
+
+        if self.paragraph.in_table {
+            if !self.cell.is_empty() {
+                self.cell.push(' ');
+            }
+            self.cell.push_str(&text);
+            return;
+        }
+        self.end_table();
+
+        let heading = self.paragraph.outline.or_else(|| self.paragraph.style.and_then(|s| self.headings.get(&s).copied()));
+        let block = if let Some(level) = heading {
+            Block::Heading(level, text)
+        } else if let Some(depth) = self.paragraph.list_level {
+            let ordered = list_text.trim_start().starts_with(|c: char| c.is_ascii_alphanumeric());
+            Block::ListItem { depth, ordered, text }
+        } else {
+            Block::Paragraph(text)
+        };
+        self.blocks.push(block);
+    }
+
+    fn end_table(&mut self) {
+        if !self.row.is_empty() {
+            let row = std::mem::take(&mut self.row);
+            self.rows.push(row);
+        }
+        let rows: Vec<Vec<String>> = std::mem::take(&mut self.rows)
+            .into_iter()
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .collect();
+        if !rows.is_empty() {
+            self.blocks.push(Block::Table(rows));
+        }
+    }
+
+    fn picture(&mut self) {
+        let (extension, hex) = std::mem::take(&mut self.picture);
+        let Some(extension) = extension else {
+            self.unsupported_pictures += 1;
+            return;
+        };
+        let digits: Vec<u8> = hex.chars().filter_map(|c| c.to_digit(16).map(|d| d as u8)).collect();
+        let data: Vec<u8> = digits.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+        if data.is_empty() {
+            return;
+        }
+        let url = self.document.add_image(extension, data);
+        self.inline.push_markup(&format!("![]({})", url));
+    }
+}
+
+// The target of a HYPERLINK field, if it is a web or mail address
+fn hyperlink(instruction: &str) -> Option<String> {
+    let rest = instruction.trim().strip_prefix("HYPERLINK")?;
+    let href = rest.split('"').nth(1).unwrap_or(rest).trim();
+    let safe = ["http://", "https://", "mailto:"].iter().any(|scheme| href.to_lowercase().starts_with(scheme));
+    safe.then(|| href.replace(' ', "%20"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importers::image_placeholder;
+
+    const HEADER: &str = r"{\rtf1\ansi\deff0{\fonttbl{\f0 Arial;}}{\stylesheet{\s0 Normal;}{\s1 heading 1;}{\s2 Heading 2;}}";
+
+    fn convert_rtf(body: &str) -> ImportedDocument {
+        convert(format!("{}{}}}", HEADER, body).as_bytes(), &|_| {}).unwrap()
+    }
+
+    #[test]
+    fn headings_from_styles_and_outline_levels() {
+        let document = convert_rtf(r"{\info{\title Report}{\author Me}}\pard\s1 Results\par\pard\s2 Details\par\pard\outlinelevel2 Deeper\par\pard Body \b bold\b0  and \i italic\i0\par");
+        assert_eq!(document.title, "Report");
+        assert_eq!(document.markdown, "# Results\n\n## Details\n\n### Deeper\n\nBody **bold** and *italic*\n");
+
+        assert_eq!(convert_rtf(r"\pard\s1 Caf\'e9 \u8364?\par").title, "Café €");
+    }
+
+    #[test]
+    fn lists() {
+        let markdown = convert_rtf(concat!(
+            r"\pard{\listtext\'95\tab}\ls1 One\par",
+            r"\pard{\listtext\'95\tab}\ls1\ilvl1 Nested\par",
+            r"\pard{\listtext 1.\tab}\ls2 First\par",
+            r"\pard{\listtext 2.\tab}\ls2 Second\par",
+            r"\pard After\par",
+        ))
+        .markdown;
+        assert_eq!(markdown, "- One\n    - Nested\n1. First\n2. Second\n\nAfter\n");
+    }
+
+    #[test]
+    fn tables() {
+        let markdown = convert_rtf(concat!(
+            r"\trowd\cellx1000\cellx2000\pard\intbl Name\cell Qty\cell\row",
+            r"\trowd\cellx1000\cellx2000\pard\intbl Tea\cell 2\cell\row",
+            r"\pard After\par",
+        ))
+        .markdown;
+        assert_eq!(markdown, "| Name | Qty |\n| --- | --- |\n| Tea | 2 |\n\nAfter\n");
+    }
+
+    #[test]
+    fn embedded_pictures() {
+        let document = convert_rtf(r"\pard Logo: {\pict\pngblip\picw1\pich1 89504e47 0d0a1a0a}{\pict\wmetafile8 0100}\par");
+        assert_eq!(document.images.len(), 1);
+        assert_eq!(document.images[0].extension, "png");
+        assert_eq!(document.images[0].data, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(document.markdown, format!("Logo: ![]({})\n", image_placeholder(0)));
+        assert_eq!(document.warnings.len(), 1);
+    }
+
+    #[test]
+    fn damaged_files_are_read_as_far_as_they_go() {
+        let read = |rtf: &str| convert(rtf.as_bytes(), &|_| {}).unwrap().markdown;
+        assert_eq!(read(r"{\rtf1 Cut \b short"), "Cut **short**\n");
+        assert_eq!(read(r"{\rtf1 Extra}}} braces\par}"), "Extra braces\n");
+        assert_eq!(read(r"{\rtf1 Hex \'4"), "Hex\n");
+        assert_eq!(read(r"{\rtf1 Too \bin99999999999 long"), "Too long\n");
+        assert_eq!(read(r"{\rtf1 Bin \bin2147483647"), "Bin\n");
+        assert_eq!(read(r"{\rtf1 \u-10179?\u-8638? end"), "🙂 end\n");
+        assert_eq!(read(r"{\rtf1 {\pict\jpegblip abc"), "");
+        assert_eq!(read(r"{\rtf1\"), "");
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        for data in [&b""[..], b"plain text", b"%PDF-1.4", b"{\\rtx1}"] {
+            assert!(matches!(convert(data, &|_| {}), Err(AppError::Validation(_))));
+        }
+        assert!(convert(b"\r\n {\\rtf1 ok}", &|_| {}).is_ok());
+    }
+}
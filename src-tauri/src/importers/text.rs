@@ -0,0 +1,34 @@
+use super::ImportedDocument;
+
+// Plain text is kept as written. Files that are not UTF-8 are read as UTF-16
+// when they start with a byte order mark, and as Windows-1252 otherwise.
+pub fn convert(data: &[u8]) -> ImportedDocument {
+    let text = match data {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => match std::str::from_utf8(data) {
+            Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+            Err(_) => data.iter().map(|&b| windows_1252(b)).collect(),
+        },
+    };
+
+    let markdown = text.replace("\r\n", "\n").replace('\r', "\n");
+    ImportedDocument { markdown: format!("{}\n", markdown.trim_end()), ..Default::default() }
+}
+
+fn utf16(data: &[u8], decode: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = data.chunks_exact(2).map(|pair| decode([pair[0], pair[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+// Windows-1252 is Latin-1 apart from printable characters in 0x80..0xA0
+pub(super) fn windows_1252(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+        '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9F => HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
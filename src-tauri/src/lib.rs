@@ -1,14 +1,19 @@
+mod error;
 mod models;
 mod commands;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 mod tray;
 mod database;
 mod notifications;
+mod recurrence;
+mod attachments;
 mod evidence_commands;
+mod wiki;
 mod wiki_commands;
+mod importers;
 
 use log::{info, error};
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -31,14 +36,28 @@ pub fn run() {
             commands::update_reminder,
             commands::set_debug_mode,
             commands::get_debug_mode,
+            commands::get_database_status,
             commands::broadcast_reminders,
+            commands::preview_occurrences,
+            commands::link_reminder_to_page,
+            commands::unlink_reminder_from_page,
+            commands::get_reminder_pages,
+            commands::get_page_reminders,
             notifications::dismiss_notification,
             notifications::snooze_reminder,
+            notifications::get_notification_channel,
+            notifications::set_notification_channel,
             evidence_commands::add_evidence_to_reminder,
             evidence_commands::get_reminder_evidence,
             evidence_commands::get_all_evidence_items,
             evidence_commands::update_evidence_desc,
             evidence_commands::delete_evidence_item,
+            evidence_commands::scan_evidence_orphans,
+            evidence_commands::verify_evidence_store,
+            evidence_commands::upload_page_attachment,
+            evidence_commands::list_page_attachments,
+            evidence_commands::delete_page_attachment,
+            evidence_commands::collect_page_attachments,
             evidence_commands::save_uploaded_file,
             evidence_commands::get_evidence_file_path,
             evidence_commands::open_evidence_file,
@@ -46,20 +65,58 @@ pub fn run() {
             evidence_commands::format_file_size,
             wiki_commands::create_wiki_page,
             wiki_commands::update_wiki_page,
+            wiki_commands::merge_wiki_page,
             wiki_commands::get_wiki_page,
+            wiki_commands::get_backlinks,
+            wiki_commands::find_broken_links,
+            wiki_commands::get_link_graph,
             wiki_commands::list_wiki_pages,
             wiki_commands::delete_wiki_page,
+            wiki_commands::list_trashed_pages,
+            wiki_commands::restore_trashed_page,
+            wiki_commands::purge_trash,
+            wiki_commands::get_trash_retention_days,
+            wiki_commands::set_trash_retention_days,
             wiki_commands::search_wiki_pages,
             wiki_commands::list_wiki_revisions,
             wiki_commands::restore_wiki_revision,
+            wiki_commands::diff_wiki_revisions,
+            wiki_commands::blame_wiki_page,
+            wiki_commands::scan_wiki_pages,
+            wiki_commands::import_markdown_directory,
+            wiki_commands::import_document,
+            wiki_commands::export_wiki,
+            wiki_commands::get_revision_retention,
+            wiki_commands::set_revision_retention,
+            wiki_commands::compact_wiki_history,
+            wiki_commands::get_wiki_history_usage,
             wiki_commands::list_sections,
             wiki_commands::create_section,
             wiki_commands::update_section,
             wiki_commands::delete_section,
+            wiki_commands::move_section,
+            wiki_commands::move_pages,
+            wiki_commands::list_notebooks,
+            wiki_commands::create_notebook,
+            wiki_commands::rename_notebook,
+            wiki_commands::delete_notebook,
+            wiki_commands::list_notebook_pages,
+            wiki_commands::move_pages_to_notebook,
+            wiki_commands::list_templates,
+            wiki_commands::get_template,
+            wiki_commands::create_template,
+            wiki_commands::update_template,
+            wiki_commands::delete_template,
+            wiki_commands::list_template_revisions,
+            wiki_commands::restore_template_revision,
+            wiki_commands::get_template_variables,
+            wiki_commands::create_wiki_page_from_template,
         ])
         .setup(|app| {
             info!("Setting up application...");
             
+            app.manage(commands::DatabaseStatus::default());
+
             let app_handle = app.app_handle().clone();
             
             // Initialize database asynchronously to avoid blocking on mobile
@@ -80,12 +137,39 @@ pub fn run() {
                     Ok(pool) => {
                         info!("Database initialized successfully");
                         app_handle.manage(pool.clone());
+
+                        // Pages used to be stored as JSON files under wiki/
+                        if let Err(e) = wiki::import_legacy_wiki(&pool, &app_dir.join("wiki")).await {
+                            let message = format!("Failed to import wiki pages: {}", e);
+                            error!("{}", message);
+                            app_handle.state::<commands::DatabaseStatus>().set_error(message.clone());
+                            let _ = app_handle.emit("database-error", message);
+                        }
+
+                        if let Err(e) = wiki::assign_notebooks(&pool, chrono::Utc::now().timestamp()).await {
+                            error!("Failed to move wiki pages into notebooks: {}", e);
+                        }
+                        if let Err(e) = wiki::index_existing_links(&pool).await {
+                            error!("Failed to index wiki links: {}", e);
+                        }
+
+                        tauri::async_runtime::spawn(wiki::purge_expired_trash_periodically(pool.clone()));
+
+                        // Trim page history once per start; failures only cost disk space
+                        let now = chrono::Utc::now().timestamp();
+                        let retention = wiki::load_retention(&pool).await;
+                        if let Err(e) = wiki::compact_history(&pool, &retention, now).await {
+                            error!("Failed to compact wiki history: {}", e);
+                        }
                         
                         // Start notification service
                         notifications::start_notification_service(pool, app_handle.clone()).await;
                     }
                     Err(e) => {
-                        error!("Failed to initialize database: {}", e);
+                        let message = format!("Failed to initialize database: {}", e);
+                        error!("{}", message);
+                        app_handle.state::<commands::DatabaseStatus>().set_error(message.clone());
+                        let _ = app_handle.emit("database-error", message);
                     }
                 }
             });
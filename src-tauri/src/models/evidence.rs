@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+// A file in the attachment store, owned by either a reminder or a wiki page
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Evidence {
     pub id: i64,
-    pub reminder_id: i64,
+    pub reminder_id: Option<i64>,
+    pub page_id: Option<String>,
     pub file_type: String,  // image, video, audio, document, link
     pub file_path: String,  // Local path or URL
     pub file_name: String,
@@ -13,11 +15,13 @@ pub struct Evidence {
     pub description: Option<String>,
     pub created_at: String,
     pub metadata: Option<String>, // JSON string for additional metadata
+    pub content_hash: Option<String>, // SHA-256 of the file, hex; None for links
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EvidenceInput {
-    pub reminder_id: i64,
+    pub reminder_id: Option<i64>,
+    pub page_id: Option<String>,
     pub file_type: String,
     pub file_path: String,
     pub file_name: String,
@@ -26,4 +30,37 @@ pub struct EvidenceInput {
     pub thumbnail_path: Option<String>,
     pub description: Option<String>,
     pub metadata: Option<String>,
+    pub content_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvidenceOrphanReport {
+    pub missing_reminder: Vec<Evidence>,  // Rows of reminders that were deleted
+    pub missing_file: Vec<Evidence>,      // Local rows whose file is gone from disk
+    pub unreferenced_files: Vec<String>,  // Files in the evidence dir no row points to
+    pub repaired: bool,
+}
+
+// An attachment of a wiki page with the URL its content embeds it by
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageAttachment {
+    #[serde(flatten)]
+    pub evidence: Evidence,
+    pub url: String,
+}
+
+// Page attachments that no page or revision refers to any more
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AttachmentGcReport {
+    pub unreferenced: Vec<Evidence>,
+    pub removed: bool,  // False on a dry run
+}
+
+// Result of re-hashing the files of the evidence store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvidenceStoreReport {
+    pub checked: usize,          // Files read, each once however many rows share it
+    pub missing: Vec<Evidence>,  // Rows whose file is gone from disk
+    pub corrupt: Vec<Evidence>,  // Rows whose file no longer has the recorded hash
+    pub hashed: usize,           // Rows from before hashing whose hash was recorded now
 }
@@ -1,5 +1,45 @@
 mod reminder;
 mod evidence;
+mod wiki;
 
-pub use reminder::Reminder;
-pub use evidence::{Evidence, EvidenceInput};
+pub use reminder::{LinkedPage, Reminder};
+pub use evidence::{AttachmentGcReport, Evidence, EvidenceInput, EvidenceOrphanReport, EvidenceStoreReport, PageAttachment};
+pub use wiki::{
+    BlameLine,
+    BrokenWikiLink,
+    DiffHunk,
+    DiffKind,
+    DiffLine,
+    DiffSegment,
+    DocumentImportProgress,
+    FieldChange,
+    MarkdownImportEntry,
+    MarkdownImportReport,
+    Notebook,
+    PageHistoryUsage,
+    RevisionRetention,
+    SearchHighlight,
+    Section,
+    SectionDeleteMode,
+    TrashedWikiPage,
+    UnreadableWikiPage,
+    WikiBacklink,
+    WikiCompactionReport,
+    WikiExportFormat,
+    WikiExportReport,
+    WikiGraphEdge,
+    WikiGraphNode,
+    WikiHistoryUsage,
+    WikiImportReport,
+    WikiLinkGraph,
+    WikiMergeResult,
+    WikiPage,
+    WikiPageList,
+    WikiRevisionDiff,
+    WikiRevisionMeta,
+    WikiScanReport,
+    WikiSearchResult,
+    WikiTemplate,
+    WikiTemplateDraft,
+    WikiTemplateRevision,
+};
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::recurrence::Recurrence;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reminder {
@@ -8,5 +9,13 @@ pub struct Reminder {
     pub time: String,
     pub completed: bool,
     pub category: String,
-    pub frequency: String,
+    pub frequency: Recurrence,
+    #[serde(default)]
+    pub linked_pages: Vec<LinkedPage>,  // Wiki pages linked to the reminder
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkedPage {
+    pub id: String,
+    pub title: String,
 }
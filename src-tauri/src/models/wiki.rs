@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WikiPage {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    #[serde(default = "default_notebook")]
+    pub notebook: String,
+    #[serde(default = "default_section")]
+    pub section: String,
+    pub section_id: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default = "first_version")]
+    pub version: i64,  // Incremented on every save; edits name the version they started from
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WikiPageList {
+    pub id: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub notebook: String,
+    pub section: String,
+    pub section_id: Option<String>,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WikiSearchResult {
+    #[serde(flatten)]
+    pub page: WikiPageList,
+    pub score: f64,               // Higher is better; 0 when the query has no words to rank by
+    pub snippet: String,          // Excerpt of the content around the best match
+    pub highlights: Vec<SearchHighlight>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedWikiPage {
+    #[serde(flatten)]
+    pub page: WikiPageList,       // section_id is the section the page was deleted from
+    pub deleted_at: i64,
+}
+
+// Matched text within a snippet, in UTF-16 code units so the frontend can slice the string directly
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SearchHighlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WikiRevisionMeta {
+    pub id: String,
+    pub page_id: String,
+    pub title: String,
+    pub notebook: String,
+    pub section: String,
+    pub section_id: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Section {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default)]
+    pub sort_order: i64,  // Position among the sections sharing the parent
+    #[serde(default)]
+    pub notebook_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WikiTemplate {
+    pub id: String,
+    pub name: String,
+    pub title: String,               // Title of the pages created from it; may hold {{placeholders}} too
+    pub content: String,
+    pub tags: Vec<String>,
+    pub section_id: Option<String>,  // Where new pages go; the default notebook without one
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub version: i64,
+}
+
+// The fields of a template that are edited
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WikiTemplateDraft {
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub section_id: Option<String>,
+}
+
+// A template as it was before a save
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WikiTemplateRevision {
+    pub id: i64,
+    pub template_id: String,
+    pub version: i64,
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub section_id: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notebook {
+    pub id: String,
+    pub name: String,
+    pub root_section_id: String,  // Top of the notebook's section tree
+    pub sort_order: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// What happens to the contents of a deleted section
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionDeleteMode {
+    /// Refuse unless the section has no child sections and no pages
+    #[default]
+    Empty,
+    /// Delete child sections too and move all their pages to the trash
+    Cascade,
+    /// Hand child sections and pages to the parent section
+    Reparent,
+}
+
+// How an export is written: a directory tree, or the same tree in one zip file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WikiExportFormat {
+    #[default]
+    Folder,
+    Zip,
+}
+
+// Result of exporting the wiki as Markdown files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WikiExportReport {
+    pub path: String,
+    pub pages: usize,
+    pub sections: usize,
+    pub links: usize,             // Internal links rewritten to relative paths
+    pub broken_links: usize,      // Links to no existing page, left as written
+}
+
+// A file met while importing a folder of Markdown files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarkdownImportEntry {
+    pub path: String,            // Relative to the imported folder
+    pub title: String,
+    pub section: String,         // Section path below the target section, "" for the target itself
+    pub reason: Option<String>,  // Why the file was skipped or conflicts
+}
+
+// What importing a folder of Markdown files creates, or would create on a dry run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarkdownImportReport {
+    pub dry_run: bool,
+    pub sections: Vec<String>,            // Paths of the sections created below the target section
+    pub pages: Vec<MarkdownImportEntry>,
+    pub conflicts: Vec<MarkdownImportEntry>,  // Not imported: a page with the same title is already there
+    pub skipped: Vec<MarkdownImportEntry>,
+    pub images: usize,                    // Image files copied into local storage
+    pub missing_images: Vec<String>,      // Image references that name no file in the folder
+}
+
+// Progress of importing a document, sent as "document-import-progress" events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentImportProgress {
+    pub path: String,
+    pub stage: String,   // "converting", "saving" or "done"
+    pub progress: f64,   // 0.0 to 1.0
+}
+
+// Result of moving a legacy wiki/ directory of JSON files into the database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WikiImportReport {
+    pub pages: usize,
+    pub sections: usize,
+    pub revisions: usize,
+    pub skipped: Vec<String>,  // Files that could not be read or parsed, or revisions of missing pages
+}
+
+// Result of checking stored pages and the legacy import backup for data that cannot be read
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WikiScanReport {
+    pub unreadable_pages: Vec<UnreadableWikiPage>,
+    pub unreadable_files: Vec<String>,  // Legacy page files the import had to leave behind
+    pub index_ok: bool,
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnreadableWikiPage {
+    pub id: String,
+    pub title: String,
+    pub problem: String,
+}
+
+// Outcome of merging an edit based on an older version into the saved page.
+// When `conflicts` is 0 the merge can be saved as is, using the version of
+// `current`; otherwise the conflicting lines are wrapped in markers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WikiMergeResult {
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub conflicts: usize,
+    pub current: WikiPage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+// Changes between two versions of a page; `from` and `to` are revision ids or "current"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WikiRevisionDiff {
+    pub page_id: String,
+    pub from: String,
+    pub to: String,
+    pub title: Option<FieldChange>,
+    pub section: Option<FieldChange>,
+    pub tags_added: Vec<String>,
+    pub tags_removed: Vec<String>,
+    pub hunks: Vec<DiffHunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub old: String,
+    pub new: String,
+}
+
+// A run of consecutive content lines that were all kept, inserted or deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    pub kind: DiffKind,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub old_line: Option<usize>,  // 1-based; None for inserted lines
+    pub new_line: Option<usize>,  // 1-based; None for deleted lines
+    pub segments: Vec<DiffSegment>,
+}
+
+// Part of a line; `changed` marks the words that differ within a changed line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffSegment {
+    pub text: String,
+    pub changed: bool,
+}
+
+// A line of the current page and the revision that introduced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameLine {
+    pub line: usize,
+    pub text: String,
+    pub revision_id: Option<String>,  // None when the line is new in the current version
+    pub changed_at: i64,
+}
+
+// A page linking to another, with how many [[links]] to it the page has
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WikiBacklink {
+    #[serde(flatten)]
+    pub page: WikiPageList,
+    pub count: usize,
+}
+
+// A [[link]] that names no existing page
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BrokenWikiLink {
+    pub source_id: String,
+    pub source_title: String,
+    pub target: String,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WikiLinkGraph {
+    pub nodes: Vec<WikiGraphNode>,
+    pub edges: Vec<WikiGraphEdge>,  // Broken links are left out
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WikiGraphNode {
+    pub id: String,
+    pub title: String,
+    pub section_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WikiGraphEdge {
+    pub source: String,
+    pub target: String,
+    pub count: usize,
+}
+
+// How long revisions are kept. Everything from the last `keep_all_hours` is
+// kept; older revisions are thinned to the newest one per hour, then per day,
+// per week and finally per month, each tier ending at the given age. Monthly
+// revisions are kept until `max_age_days`, or forever when it is unset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RevisionRetention {
+    pub keep_all_hours: i64,
+    pub hourly_days: i64,
+    pub daily_days: i64,
+    pub weekly_days: i64,
+    pub max_age_days: Option<i64>,
+    pub compress_after_days: i64,  // Older revisions are stored as deltas
+}
+
+impl Default for RevisionRetention {
+    fn default() -> Self {
+        RevisionRetention {
+            keep_all_hours: 24,
+            hourly_days: 7,
+            daily_days: 30,
+            weekly_days: 365,
+            max_age_days: None,
+            compress_after_days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WikiCompactionReport {
+    pub pruned: usize,           // Revisions dropped by the retention policy
+    pub compressed: usize,       // Revisions newly stored as deltas
+    pub orphans_removed: usize,  // Revisions of pages that no longer exist
+}
+
+// Space taken by page history, in bytes of stored text
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WikiHistoryUsage {
+    pub revisions: i64,
+    pub delta_revisions: i64,
+    pub history_bytes: i64,
+    pub page_bytes: i64,
+    pub pages: Vec<PageHistoryUsage>,  // Largest histories first
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageHistoryUsage {
+    pub page_id: String,
+    pub title: String,
+    pub revisions: i64,
+    pub bytes: i64,
+}
+
+pub fn default_notebook() -> String {
+    "Notebook".to_string()
+}
+
+fn default_section() -> String {
+    "Section".to_string()
+}
+
+fn first_version() -> i64 {
+    1
+}
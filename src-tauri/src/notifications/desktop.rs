@@ -0,0 +1,124 @@
+use log::{info, debug, error};
+use notify_rust::{ActionResponse, Notification, NotificationHandle, Timeout};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use crate::models::Reminder;
+use super::sink::NotificationSink;
+
+const SNOOZE_MINUTES: i64 = 10;
+
+// Notifications still on screen, by reminder id, so they can be taken down
+#[derive(Default)]
+struct Shown(Mutex<HashMap<u32, NotificationHandle>>);
+
+fn shown(app: &AppHandle) -> &Shown {
+    // Does nothing once managed
+    app.manage(Shown::default());
+    app.state::<Shown>().inner()
+}
+
+/// Native notifications through the freedesktop notification service
+/// (D-Bus `org.freedesktop.Notifications`) with Complete / Snooze / Open actions.
+pub struct DesktopSink(pub AppHandle);
+
+impl NotificationSink for DesktopSink {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn notify(&self, reminders: &[Reminder]) -> Result<(), String> {
+        let app = &self.0;
+        for reminder in reminders {
+            let handle = Notification::new()
+                .appname(&app.package_info().name)
+                .summary(&reminder.title)
+                .body(&reminder.description)
+                .action("complete", "Complete")
+                .action("snooze", "Snooze 10m")
+                .action("open", "Open")
+                .timeout(Timeout::Never)
+                .show()
+                .map_err(|e| format!("Failed to show desktop notification: {}", e))?;
+            debug!("Desktop notification shown for reminder id={}", reminder.id);
+
+            let id = reminder.id;
+            let notification_id = handle.id();
+            let replaced = shown(app).0.lock().unwrap().insert(id, handle);
+            if let Some(replaced) = replaced {
+                replaced.close();
+            }
+
+            // Waiting for the user's response blocks, so it gets its own thread
+            let app = app.clone();
+            std::thread::spawn(move || {
+                let waited = notify_rust::handle_action(notification_id, |response| match response {
+                    ActionResponse::Custom(action) => handle_action(&app, id, action),
+                    ActionResponse::Closed(_) => handle_action(&app, id, "__closed"),
+                });
+                if let Err(e) = waited {
+                    error!("Failed to wait for desktop notification of reminder id={}: {}", id, e);
+                }
+
+                // Unless a newer notification for the reminder took its place
+                let mut open = shown(&app).0.lock().unwrap();
+                if open.get(&id).is_some_and(|handle| handle.id() == notification_id) {
+                    open.remove(&id);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    // Nothing is due anymore, so notifications still up were dealt with elsewhere
+    fn clear(&self) {
+        let handles: Vec<NotificationHandle> = shown(&self.0).0.lock().unwrap().drain().map(|(_, h)| h).collect();
+        if !handles.is_empty() {
+            debug!("Closing {} desktop notifications", handles.len());
+        }
+        for handle in handles {
+            handle.close();
+        }
+    }
+}
+
+fn handle_action(app: &AppHandle, id: u32, action: &str) {
+    debug!("Desktop notification action '{}' for reminder id={}", action, id);
+
+    let Some(pool) = app.try_state::<SqlitePool>() else {
+        return;
+    };
+    let pool = pool.inner().clone();
+
+    let result = match action {
+        "complete" => tauri::async_runtime::block_on(crate::database::complete_reminder(&pool, id)),
+        "snooze" => tauri::async_runtime::block_on(
+            crate::database::snooze_reminder(&pool, id as i64, SNOOZE_MINUTES),
+        ),
+        "open" | "default" => {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+                info!("Main window shown from desktop notification");
+            }
+            return;
+        }
+        // "__closed": dismissed without an action
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        error!("Failed to apply '{}' to reminder id={}: {}", action, id, e);
+        return;
+    }
+
+    match tauri::async_runtime::block_on(crate::database::get_all_reminders(&pool)) {
+        Ok(reminders) => {
+            let _ = app.emit("reminders-updated", &reminders);
+        }
+        Err(e) => error!("Failed to broadcast reminders: {}", e),
+    }
+    super::rearm(app);
+}
@@ -1,137 +1,240 @@
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod popup;
+#[cfg(target_os = "linux")]
+mod desktop;
+mod sink;
+
 use sqlx::SqlitePool;
-use log::{info, debug, error};
-use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindowBuilder};
-use std::time::Duration;
+use log::{info, debug, error, warn};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+use crate::error::AppResult;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::models::Reminder;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::error::AppError;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use std::time::{Duration, Instant};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
 use tokio::time::sleep;
 
-const CHECK_INTERVAL_SECONDS: u64 = 30;
+// Longest single sleep. Tokio's timer doesn't advance while the machine is
+// suspended, so the wall clock is re-checked at least this often.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+const MAX_SLEEP_SECONDS: u64 = 15;
+
+// Difference between wall-clock and monotonic elapsed time treated as a clock jump or resume
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+const CLOCK_JUMP_TOLERANCE_SECONDS: i64 = 5;
+
+// Delay before retrying after the database could not be read
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+const RETRY_DELAY_SECONDS: u64 = 30;
+
+/// Managed handle that wakes the notification scheduler so it recomputes the
+/// next due reminder.
+#[derive(Clone)]
+pub struct NotificationScheduler {
+    tx: mpsc::UnboundedSender<()>,
+}
+
+impl NotificationScheduler {
+    pub fn rearm(&self) {
+        let _ = self.tx.send(());
+    }
+}
+
+/// Re-arms the scheduler after reminders were added, changed or removed.
+pub fn rearm(app: &AppHandle) {
+    if let Some(scheduler) = app.try_state::<NotificationScheduler>() {
+        debug!("Re-arming notification scheduler");
+        scheduler.rearm();
+    }
+}
+
+pub use sink::{NotificationChannel, NotificationSink};
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+enum Wake {
+    Due,
+    Changed,
+    ClockJump,
+    Closed,
+}
 
 // Notification service is only available on desktop platforms
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub async fn start_notification_service(pool: SqlitePool, app: AppHandle) {
     info!("Starting notification service");
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    app.manage(NotificationScheduler { tx });
     
     tokio::spawn(async move {
         loop {
-            sleep(Duration::from_secs(CHECK_INTERVAL_SECONDS)).await;
-            
-            match check_due_reminders(&pool).await {
-                Ok(count) => {
-                    if count > 0 {
-                        debug!("Found {} incomplete reminders, showing notification", count);
-                        if let Err(e) = show_notification_list(&app).await {
-                            error!("Failed to show notification list: {}", e);
-                        }
-                    } else {
-                        debug!("No incomplete reminders, closing notification if open");
-                        // Close notification window if no incomplete reminders
-                        if let Some(window) = app.get_webview_window("notification-list") {
-                            let _ = window.destroy();
-                        }
+            let sinks = sinks_for(load_channel(&pool).await, &app);
+            let next = match check_due_reminders(&pool, &app, &sinks).await {
+                Ok(()) => crate::database::get_next_pending_time(&pool).await,
+                Err(e) => Err(e),
+            };
+
+            let wake = match next {
+                Ok(Some(due)) => {
+                    debug!("Next reminder due at {}", due);
+                    wait_until(due, &mut rx).await
+                }
+                Ok(None) => {
+                    debug!("No pending reminders, waiting for changes");
+                    match rx.recv().await {
+                        Some(()) => Wake::Changed,
+                        None => Wake::Closed,
                     }
                 }
                 Err(e) => {
                     error!("Failed to check due reminders: {}", e);
+                    tokio::select! {
+                        msg = rx.recv() => if msg.is_some() { Wake::Changed } else { Wake::Closed },
+                        _ = sleep(Duration::from_secs(RETRY_DELAY_SECONDS)) => Wake::Due,
+                    }
+                }
+            };
+
+            match wake {
+                Wake::Due => debug!("Scheduled reminder time reached"),
+                Wake::Changed => {
+                    // Several mutations in a row only need one re-evaluation
+                    while rx.try_recv().is_ok() {}
+                    debug!("Reminders changed, rescheduling");
+                }
+                Wake::ClockJump => info!("System clock changed or resumed from sleep, rescheduling"),
+                Wake::Closed => {
+                    info!("Notification scheduler stopped");
+                    break;
                 }
             }
         }
     });
 }
 
+// Sleeps until the local wall-clock time reaches `due`, in bounded steps so
+// suspend/resume and clock changes are noticed
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+async fn wait_until(due: chrono::NaiveDateTime, rx: &mut mpsc::UnboundedReceiver<()>) -> Wake {
+    loop {
+        let now = chrono::Local::now().naive_local();
+        let remaining = match (due - now).to_std() {
+            Ok(remaining) if !remaining.is_zero() => remaining,
+            _ => return Wake::Due,
+        };
+        let step = remaining.min(Duration::from_secs(MAX_SLEEP_SECONDS));
+
+        let started = Instant::now();
+        tokio::select! {
+            msg = rx.recv() => return if msg.is_some() { Wake::Changed } else { Wake::Closed },
+            _ = sleep(step) => {}
+        }
+
+        let wall_elapsed = chrono::Local::now().naive_local() - now;
+        let monotonic_elapsed = chrono::Duration::from_std(started.elapsed()).unwrap_or_default();
+        if (wall_elapsed - monotonic_elapsed).num_seconds().abs() > CLOCK_JUMP_TOLERANCE_SECONDS {
+            return Wake::ClockJump;
+        }
+    }
+}
+
 // Mobile stub - notifications handled differently on mobile
 #[cfg(any(target_os = "android", target_os = "ios"))]
 pub async fn start_notification_service(_pool: SqlitePool, _app: AppHandle) {
     info!("Notification service not available on mobile platforms");
 }
 
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
-async fn check_due_reminders(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
-    debug!("Checking for incomplete reminders");
-    
-    let count: (i64,) = sqlx::query_as(
-        r#"
-        SELECT COUNT(*)
-        FROM reminders
-        WHERE completed = 0
-        "#
-    )
-    .fetch_one(pool)
-    .await?;
-    
-    Ok(count.0 as usize)
+async fn load_channel(pool: &SqlitePool) -> NotificationChannel {
+    match crate::database::get_setting(pool, sink::CHANNEL_SETTING).await {
+        Ok(Some(value)) => value.parse().unwrap_or_else(|e| {
+            warn!("{}, using default", e);
+            NotificationChannel::default()
+        }),
+        Ok(None) => NotificationChannel::default(),
+        Err(e) => {
+            error!("Failed to load notification channel: {}", e);
+            NotificationChannel::default()
+        }
+    }
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-async fn show_notification_list(app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
-    let window_label = "notification-list";
-    
-    // Check if window already exists and is visible
-    if let Some(window) = app.get_webview_window(window_label) {
-        if window.is_visible().unwrap_or(false) {
-            debug!("Notification window already visible, doing nothing");
-            return Ok(());
+fn sinks_for(channel: NotificationChannel, app: &AppHandle) -> Vec<Box<dyn NotificationSink>> {
+    let popup = || -> Box<dyn NotificationSink> { Box::new(popup::PopupSink(app.clone())) };
+    #[cfg(target_os = "linux")]
+    let native: Option<Box<dyn NotificationSink>> = Some(Box::new(desktop::DesktopSink(app.clone())));
+    #[cfg(not(target_os = "linux"))]
+    let native: Option<Box<dyn NotificationSink>> = None;
+
+    match (channel, native) {
+        (NotificationChannel::Popup, _) => vec![popup()],
+        (NotificationChannel::Native, Some(native)) => vec![native],
+        (NotificationChannel::Both, Some(native)) => vec![popup(), native],
+        (_, None) => {
+            debug!("Native notifications unavailable on this platform, using popup");
+            vec![popup()]
         }
-        // Window exists but hidden, show it - not available on mobile
-        debug!("Showing existing notification window");
-        return Ok(());
     }
-    
-    // Get screen dimensions
-    let (screen_width, _screen_height) = get_screen_size(app)?;
-    
-    // Notification list dimensions
-    let width = 320.0;
-    let height = 400.0;
-    let margin = 20.0;
-    
-    // Position in top-right corner
-    let x = screen_width - width - margin;
-    let y = margin;
-    
-    // Create notification window
-    let _window = WebviewWindowBuilder::new(
-        app,
-        window_label,
-        WebviewUrl::App("notification.html".into())
-    )
-    .inner_size(width, height)
-    .position(x, y)
-    .resizable(false)
-    .minimizable(false)
-    .maximizable(false)
-    .skip_taskbar(true)
-    .always_on_top(true)
-    .decorations(false)
-    .visible(true)
-    .build()?;
-    
-    info!("Notification list window created");
-    
+}
+
+// Announces reminders that became due since the last check to the windows
+// and the sinks
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+async fn check_due_reminders(
+    pool: &SqlitePool,
+    app: &AppHandle,
+    sinks: &[Box<dyn NotificationSink>],
+) -> Result<(), sqlx::Error> {
+    debug!("Checking for due reminders");
+
+    for reminder in dispatch_due_reminders(pool, sinks).await? {
+        let _ = app.emit("reminder-due", &reminder);
+    }
     Ok(())
 }
 
+// Hands reminders that became due since the last check to every sink and
+// returns them; clears the sinks once nothing is due anymore
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-fn get_screen_size(app: &AppHandle) -> Result<(f64, f64), Box<dyn std::error::Error>> {
-    // Get primary monitor
-    if let Some(monitor) = app.primary_monitor()? {
-        let size = monitor.size();
-        let scale = monitor.scale_factor();
-        Ok((size.width as f64 / scale, size.height as f64 / scale))
-    } else {
-        // Default fallback
-        Ok((1920.0, 1080.0))
+async fn dispatch_due_reminders(
+    pool: &SqlitePool,
+    sinks: &[Box<dyn NotificationSink>],
+) -> Result<Vec<Reminder>, sqlx::Error> {
+    let newly_due = crate::database::get_unnotified_due_reminders(pool).await?;
+    if !newly_due.is_empty() {
+        debug!("Found {} newly due reminders, notifying", newly_due.len());
+        for sink in sinks {
+            if let Err(e) = sink.notify(&newly_due) {
+                error!("Failed to deliver {} notification: {}", sink.name(), e);
+            }
+        }
+        crate::database::mark_reminders_notified(pool, &newly_due).await?;
+        return Ok(newly_due);
     }
+
+    if crate::database::get_due_reminders(pool).await?.is_empty() {
+        debug!("No due reminders, clearing notifications");
+        for sink in sinks {
+            sink.clear();
+        }
+    }
+
+    Ok(newly_due)
 }
 
 #[tauri::command]
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub async fn dismiss_notification(
     app: AppHandle,
-) -> Result<(), String> {
-    let window_label = "notification-list";
-    
-    if let Some(window) = app.get_webview_window(window_label) {
-        window.destroy().map_err(|e| e.to_string())?;
+) -> AppResult<()> {
+    if let Some(window) = app.get_webview_window(popup::WINDOW_LABEL) {
+        window
+            .destroy()
+            .map_err(|e| AppError::internal(format!("Failed to close notification list: {}", e)))?;
         info!("Dismissed notification list");
     }
     
@@ -140,32 +243,140 @@ pub async fn dismiss_notification(
 
 #[tauri::command]
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub async fn dismiss_notification(_app: AppHandle) -> Result<(), String> {
+pub async fn dismiss_notification(_app: AppHandle) -> AppResult<()> {
     Ok(())
 }
 
 #[tauri::command]
 pub async fn snooze_reminder(
     pool: tauri::State<'_, SqlitePool>,
+    app: AppHandle,
     reminder_id: i64,
     minutes: i64,
-) -> Result<(), String> {
+) -> AppResult<()> {
     info!("Snoozing reminder {} for {} minutes", reminder_id, minutes);
     
-    // Update reminder time
-    sqlx::query(
-        r#"
-        UPDATE reminders
-        SET time = datetime(time, ? || ' minutes'),
-            updated_at = CURRENT_TIMESTAMP
-        WHERE id = ?
-        "#
-    )
-    .bind(format!("+{}", minutes))
-    .bind(reminder_id)
-    .execute(pool.inner())
-    .await
-    .map_err(|e| e.to_string())?;
-    
+    crate::database::snooze_reminder(&pool, reminder_id, minutes)
+        .await?;
+
+    rearm(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_notification_channel(
+    pool: tauri::State<'_, SqlitePool>,
+) -> AppResult<NotificationChannel> {
+    Ok(load_channel(&pool).await)
+}
+
+#[tauri::command]
+pub async fn set_notification_channel(
+    pool: tauri::State<'_, SqlitePool>,
+    app: AppHandle,
+    channel: NotificationChannel,
+) -> AppResult<()> {
+    info!("Setting notification channel to {}", channel.as_str());
+
+    crate::database::set_setting(&pool, sink::CHANNEL_SETTING, channel.as_str()).await?;
+
+    // The scheduler picks its sinks when it wakes, so wake it to use the new ones
+    rearm(&app);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use crate::database::test_pool;
+    use crate::recurrence::Recurrence;
+    use super::sink::RecordingSink;
+
+    fn recording(fail: bool) -> (RecordingSink, Box<dyn NotificationSink>) {
+        let sink = RecordingSink { fail, ..Default::default() };
+        (sink.clone(), Box::new(sink))
+    }
+
+    async fn add(pool: &SqlitePool, title: &str, time: &str) -> u32 {
+        crate::database::add_reminder(pool, title, "", time, "", &Recurrence::default()).await.unwrap() as u32
+    }
+
+    #[tokio::test]
+    async fn due_reminders_are_delivered_once() {
+        let pool = test_pool().await;
+        let overdue = add(&pool, "Overdue", "2020-01-01T08:00").await;
+        add(&pool, "Later", "2999-01-01T08:00").await;
+        let (sink, boxed) = recording(false);
+        let sinks = vec![boxed];
+
+        let due = dispatch_due_reminders(&pool, &sinks).await.unwrap();
+        assert_eq!(due.iter().map(|r| r.id).collect::<Vec<_>>(), [overdue]);
+        assert_eq!(*sink.notified.lock().unwrap(), [vec![overdue]]);
+
+        assert!(dispatch_due_reminders(&pool, &sinks).await.unwrap().is_empty());
+        assert_eq!(sink.notified.lock().unwrap().len(), 1);
+        // Still due, so the notification stays up
+        assert_eq!(sink.clears.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn rescheduled_reminder_is_delivered_again() {
+        let pool = test_pool().await;
+        let id = add(&pool, "Stretch", "2020-01-01T08:00").await;
+        let (sink, boxed) = recording(false);
+        let sinks = vec![boxed];
+        dispatch_due_reminders(&pool, &sinks).await.unwrap();
+
+        sqlx::query("UPDATE reminders SET time = '2020-01-02T08:00' WHERE id = ?")
+            .bind(id as i64)
+            .execute(&pool)
+            .await
+            .unwrap();
+        dispatch_due_reminders(&pool, &sinks).await.unwrap();
+
+        assert_eq!(*sink.notified.lock().unwrap(), [vec![id], vec![id]]);
+    }
+
+    #[tokio::test]
+    async fn failing_sink_does_not_stop_the_others() {
+        let pool = test_pool().await;
+        let id = add(&pool, "Pay rent", "2020-01-01T08:00").await;
+        let (failing, failing_box) = recording(true);
+        let (working, working_box) = recording(false);
+        let sinks = vec![failing_box, working_box];
+
+        dispatch_due_reminders(&pool, &sinks).await.unwrap();
+
+        assert_eq!(*failing.notified.lock().unwrap(), [vec![id]]);
+        assert_eq!(*working.notified.lock().unwrap(), [vec![id]]);
+        assert!(dispatch_due_reminders(&pool, &sinks).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sinks_are_cleared_once_nothing_is_due() {
+        let pool = test_pool().await;
+        let id = add(&pool, "Stand up", "2020-01-01T08:00").await;
+        let (sink, boxed) = recording(false);
+        let sinks = vec![boxed];
+        dispatch_due_reminders(&pool, &sinks).await.unwrap();
+
+        crate::database::toggle_reminder(&pool, id).await.unwrap();
+        dispatch_due_reminders(&pool, &sinks).await.unwrap();
+
+        assert_eq!(sink.clears.load(Ordering::SeqCst), 1);
+        assert_eq!(sink.notified.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn channel_setting_falls_back_to_popup() {
+        let pool = test_pool().await;
+        assert_eq!(load_channel(&pool).await, NotificationChannel::Popup);
+
+        crate::database::set_setting(&pool, sink::CHANNEL_SETTING, "both").await.unwrap();
+        assert_eq!(load_channel(&pool).await, NotificationChannel::Both);
+
+        crate::database::set_setting(&pool, sink::CHANNEL_SETTING, "pigeon").await.unwrap();
+        assert_eq!(load_channel(&pool).await, NotificationChannel::Popup);
+    }
+}
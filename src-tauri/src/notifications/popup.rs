@@ -0,0 +1,84 @@
+use log::{info, debug};
+use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindowBuilder};
+use crate::models::Reminder;
+use super::sink::NotificationSink;
+
+pub const WINDOW_LABEL: &str = "notification-list";
+
+/// The borderless always-on-top notification list window.
+pub struct PopupSink(pub AppHandle);
+
+impl NotificationSink for PopupSink {
+    fn name(&self) -> &'static str {
+        "popup"
+    }
+
+    fn notify(&self, _reminders: &[Reminder]) -> Result<(), String> {
+        show_notification_list(&self.0).map_err(|e| e.to_string())
+    }
+
+    fn clear(&self) {
+        if let Some(window) = self.0.get_webview_window(WINDOW_LABEL) {
+            debug!("Closing notification list");
+            let _ = window.destroy();
+        }
+    }
+}
+
+fn show_notification_list(app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+    // Check if window already exists and is visible
+    if let Some(window) = app.get_webview_window(WINDOW_LABEL) {
+        if window.is_visible().unwrap_or(false) {
+            debug!("Notification window already visible, doing nothing");
+            return Ok(());
+        }
+        // Window exists but hidden, show it - not available on mobile
+        debug!("Showing existing notification window");
+        return Ok(());
+    }
+    
+    // Get screen dimensions
+    let (screen_width, _screen_height) = get_screen_size(app)?;
+    
+    // Notification list dimensions
+    let width = 320.0;
+    let height = 400.0;
+    let margin = 20.0;
+    
+    // Position in top-right corner
+    let x = screen_width - width - margin;
+    let y = margin;
+    
+    // Create notification window
+    let _window = WebviewWindowBuilder::new(
+        app,
+        WINDOW_LABEL,
+        WebviewUrl::App("notification.html".into())
+    )
+    .inner_size(width, height)
+    .position(x, y)
+    .resizable(false)
+    .minimizable(false)
+    .maximizable(false)
+    .skip_taskbar(true)
+    .always_on_top(true)
+    .decorations(false)
+    .visible(true)
+    .build()?;
+    
+    info!("Notification list window created");
+    
+    Ok(())
+}
+
+fn get_screen_size(app: &AppHandle) -> Result<(f64, f64), Box<dyn std::error::Error>> {
+    // Get primary monitor
+    if let Some(monitor) = app.primary_monitor()? {
+        let size = monitor.size();
+        let scale = monitor.scale_factor();
+        Ok((size.width as f64 / scale, size.height as f64 / scale))
+    } else {
+        // Default fallback
+        Ok((1920.0, 1080.0))
+    }
+}
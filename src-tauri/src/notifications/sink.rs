@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use crate::models::Reminder;
+
+// Key in app_settings holding the selected NotificationChannel
+pub const CHANNEL_SETTING: &str = "notification_channel";
+
+/// A way of telling the user that reminders are due. The scheduler hands
+/// every newly due batch to each configured sink.
+pub trait NotificationSink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Announces reminders that just became due.
+    fn notify(&self, reminders: &[Reminder]) -> Result<(), String>;
+
+    /// Called when nothing is due anymore.
+    fn clear(&self) {}
+}
+
+/// Which sinks deliver reminders, selectable from the UI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationChannel {
+    /// The borderless notification-list webview
+    #[default]
+    Popup,
+    /// Native OS notifications
+    Native,
+    Both,
+}
+
+impl NotificationChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::Popup => "popup",
+            NotificationChannel::Native => "native",
+            NotificationChannel::Both => "both",
+        }
+    }
+}
+
+impl FromStr for NotificationChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "popup" => Ok(NotificationChannel::Popup),
+            "native" => Ok(NotificationChannel::Native),
+            "both" => Ok(NotificationChannel::Both),
+            _ => Err(format!("Unknown notification channel: '{}'", s)),
+        }
+    }
+}
+
+/// Keeps what it was asked to deliver instead of showing anything. Clones
+/// share what they recorded.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    pub notified: std::sync::Arc<std::sync::Mutex<Vec<Vec<u32>>>>,  // Reminder ids of each batch
+    pub clears: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    pub fail: bool,
+}
+
+#[cfg(test)]
+impl NotificationSink for RecordingSink {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn notify(&self, reminders: &[Reminder]) -> Result<(), String> {
+        self.notified.lock().unwrap().push(reminders.iter().map(|r| r.id).collect());
+        if self.fail {
+            return Err("delivery failed".to_string());
+        }
+        Ok(())
+    }
+
+    fn clear(&self) {
+        self.clears.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,376 @@
+mod rrule;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub use rrule::RRule;
+
+// Formats accepted for `reminders.time`. The frontend sends datetime-local
+// values, while SQLite's datetime() (used by snooze) writes the space form.
+const TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+];
+
+pub fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    TIME_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+}
+
+pub fn format_time(time: NaiveDateTime) -> String {
+    if time.second() == 0 {
+        time.format("%Y-%m-%dT%H:%M").to_string()
+    } else {
+        time.format("%Y-%m-%dT%H:%M:%S").to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFrequencyError(String);
+
+impl fmt::Display for ParseFrequencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown frequency: '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseFrequencyError {}
+
+impl FromStr for Frequency {
+    type Err = ParseFrequencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "once" => Ok(Frequency::Once),
+            "daily" => Ok(Frequency::Daily),
+            "weekly" => Ok(Frequency::Weekly),
+            "monthly" => Ok(Frequency::Monthly),
+            "yearly" => Ok(Frequency::Yearly),
+            _ => Err(ParseFrequencyError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Frequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Once => "once",
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+            Frequency::Yearly => "yearly",
+        }
+    }
+
+    pub fn is_recurring(&self) -> bool {
+        *self != Frequency::Once
+    }
+
+    /// Returns the `index`-th occurrence (0 = `anchor`) of a series starting at `anchor`.
+    ///
+    /// Monthly and yearly rules are computed from the anchor rather than from the
+    /// previous occurrence, so a series on the 31st clamps to the last day of
+    /// shorter months and returns to the 31st afterwards (likewise Feb 29).
+    pub fn nth_occurrence(&self, anchor: NaiveDateTime, index: u32) -> Option<NaiveDateTime> {
+        match self {
+            Frequency::Once => (index == 0).then_some(anchor),
+            Frequency::Daily => anchor.checked_add_signed(Duration::days(index as i64)),
+            Frequency::Weekly => anchor.checked_add_signed(Duration::weeks(index as i64)),
+            Frequency::Monthly => add_months(anchor, index),
+            Frequency::Yearly => add_months(anchor, index.checked_mul(12)?),
+        }
+    }
+
+    /// Returns the first occurrence strictly after `after` of the series starting at `anchor`.
+    pub fn next_after(&self, anchor: NaiveDateTime, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        if anchor > after {
+            return Some(anchor);
+        }
+
+        let mut index = match self {
+            Frequency::Once => return None,
+            Frequency::Daily => (after - anchor).num_days(),
+            Frequency::Weekly => (after - anchor).num_weeks(),
+            Frequency::Monthly => months_between(anchor, after),
+            Frequency::Yearly => months_between(anchor, after) / 12,
+        }
+        .max(0) as u32;
+
+        // The estimate never overshoots, so at most a couple of steps are needed.
+        loop {
+            let candidate = self.nth_occurrence(anchor, index)?;
+            if candidate > after {
+                return Some(candidate);
+            }
+            index = index.checked_add(1)?;
+        }
+    }
+
+    /// Whether `time` is one of the occurrences of the series starting at `anchor`.
+    pub fn is_occurrence(&self, anchor: NaiveDateTime, time: NaiveDateTime) -> bool {
+        if time == anchor {
+            return true;
+        }
+        time > anchor && self.next_after(anchor, time - Duration::seconds(1)) == Some(time)
+    }
+}
+
+/// The schedule stored in `reminders.frequency`: one of the presets
+/// ("once", "daily", ...) or an iCalendar RRULE (optionally followed by an
+/// EXDATE line). Serialized as that same string for the frontend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Recurrence {
+    Preset(Frequency),
+    Rule(RRule),
+}
+
+impl Default for Recurrence {
+    fn default() -> Self {
+        Recurrence::Preset(Frequency::Once)
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(frequency) = s.parse::<Frequency>() {
+            return Ok(Recurrence::Preset(frequency));
+        }
+        let upper = s.to_ascii_uppercase();
+        if upper.contains("FREQ=") || upper.contains("RRULE:") {
+            return s.parse::<RRule>().map(Recurrence::Rule).map_err(|e| e.to_string());
+        }
+        Err(ParseFrequencyError(s.to_string()).to_string())
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Preset(frequency) => frequency.fmt(f),
+            Recurrence::Rule(rule) => rule.fmt(f),
+        }
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(value: Recurrence) -> Self {
+        value.to_string()
+    }
+}
+
+impl Recurrence {
+    pub fn is_recurring(&self) -> bool {
+        match self {
+            Recurrence::Preset(frequency) => frequency.is_recurring(),
+            Recurrence::Rule(_) => true,
+        }
+    }
+
+    /// Returns the first occurrence strictly after `after` of the series starting at `anchor`.
+    pub fn next_after(&self, anchor: NaiveDateTime, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Recurrence::Preset(frequency) => frequency.next_after(anchor, after),
+            Recurrence::Rule(rule) => rule.occurrences(anchor).find(|t| *t > after),
+        }
+    }
+
+    /// Whether `time` is one of the occurrences of the series starting at `anchor`.
+    pub fn is_occurrence(&self, anchor: NaiveDateTime, time: NaiveDateTime) -> bool {
+        match self {
+            Recurrence::Preset(frequency) => frequency.is_occurrence(anchor, time),
+            Recurrence::Rule(rule) => rule
+                .occurrences(anchor)
+                .take_while(|t| *t <= time)
+                .any(|t| t == time),
+        }
+    }
+
+    /// The first `limit` fire times of the series starting at `start`.
+    pub fn preview(&self, start: NaiveDateTime, limit: usize) -> Vec<NaiveDateTime> {
+        match self {
+            Recurrence::Preset(frequency) => (0..)
+                .map_while(|i| frequency.nth_occurrence(start, i))
+                .take(limit)
+                .collect(),
+            Recurrence::Rule(rule) => rule.occurrences(start).take(limit).collect(),
+        }
+    }
+
+    /// Moves a start time that the rule itself would never produce (e.g. a
+    /// Saturday for a weekday rule) onto the rule's first occurrence.
+    pub fn align_start(&self, start: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Recurrence::Preset(_) => Some(start),
+            Recurrence::Rule(rule) => rule.occurrences(start).next(),
+        }
+    }
+}
+
+fn months_between(from: NaiveDateTime, to: NaiveDateTime) -> i64 {
+    (to.year() as i64 - from.year() as i64) * 12 + (to.month() as i64 - from.month() as i64)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|d| d.pred_opt())
+        .map(|d| d.day())
+        .unwrap_or(28)
+}
+
+/// Adds whole months, clamping the day to the end of the target month.
+fn add_months(time: NaiveDateTime, months: u32) -> Option<NaiveDateTime> {
+    let total = time.year() as i64 * 12 + (time.month0() as i64) + months as i64;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    let day = time.day().min(days_in_month(year, month));
+    NaiveDate::from_ymd_opt(year, month, day).map(|d| d.and_time(time.time()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        parse_time(value).unwrap()
+    }
+
+    fn series(frequency: Frequency, anchor: &str, count: u32) -> Vec<String> {
+        (0..count)
+            .map(|i| format_time(frequency.nth_occurrence(at(anchor), i).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn daily_advances_one_day() {
+        assert_eq!(
+            series(Frequency::Daily, "2024-02-28T09:30", 3),
+            ["2024-02-28T09:30", "2024-02-29T09:30", "2024-03-01T09:30"]
+        );
+    }
+
+    #[test]
+    fn weekly_advances_seven_days() {
+        assert_eq!(
+            series(Frequency::Weekly, "2024-12-25T18:00", 3),
+            ["2024-12-25T18:00", "2025-01-01T18:00", "2025-01-08T18:00"]
+        );
+    }
+
+    #[test]
+    fn monthly_keeps_day_of_month() {
+        assert_eq!(
+            series(Frequency::Monthly, "2024-11-15T08:00", 3),
+            ["2024-11-15T08:00", "2024-12-15T08:00", "2025-01-15T08:00"]
+        );
+    }
+
+    #[test]
+    fn yearly_keeps_date() {
+        assert_eq!(
+            series(Frequency::Yearly, "2023-07-04T12:00", 3),
+            ["2023-07-04T12:00", "2024-07-04T12:00", "2025-07-04T12:00"]
+        );
+    }
+
+    #[test]
+    fn once_has_no_next_occurrence() {
+        let anchor = at("2024-01-01T10:00");
+        assert_eq!(Frequency::Once.nth_occurrence(anchor, 0), Some(anchor));
+        assert_eq!(Frequency::Once.nth_occurrence(anchor, 1), None);
+        assert_eq!(Frequency::Once.next_after(anchor, anchor), None);
+        assert_eq!(Frequency::Once.next_after(anchor, at("2023-12-31T10:00")), Some(anchor));
+    }
+
+    #[test]
+    fn month_end_clamps_and_returns_to_the_31st() {
+        assert_eq!(
+            series(Frequency::Monthly, "2023-01-31T07:00", 4),
+            ["2023-01-31T07:00", "2023-02-28T07:00", "2023-03-31T07:00", "2023-04-30T07:00"]
+        );
+        assert_eq!(
+            series(Frequency::Monthly, "2024-01-31T07:00", 3),
+            ["2024-01-31T07:00", "2024-02-29T07:00", "2024-03-31T07:00"]
+        );
+    }
+
+    #[test]
+    fn next_after_a_clamped_occurrence_uses_the_anchor() {
+        let anchor = at("2023-01-31T07:00");
+        let february = at("2023-02-28T07:00");
+        assert_eq!(Frequency::Monthly.next_after(anchor, february), Some(at("2023-03-31T07:00")));
+        assert!(Frequency::Monthly.is_occurrence(anchor, february));
+        assert!(!Frequency::Monthly.is_occurrence(anchor, at("2023-03-28T07:00")));
+    }
+
+    #[test]
+    fn february_29_yearly_falls_back_outside_leap_years() {
+        assert_eq!(
+            series(Frequency::Yearly, "2024-02-29T09:00", 5),
+            [
+                "2024-02-29T09:00",
+                "2025-02-28T09:00",
+                "2026-02-28T09:00",
+                "2027-02-28T09:00",
+                "2028-02-29T09:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn next_after_skips_missed_occurrences() {
+        let anchor = at("2020-01-01T08:00");
+        let now = at("2024-06-15T12:00");
+        assert_eq!(Frequency::Daily.next_after(anchor, now), Some(at("2024-06-16T08:00")));
+        assert_eq!(Frequency::Weekly.next_after(anchor, now), Some(at("2024-06-19T08:00")));
+        assert_eq!(Frequency::Monthly.next_after(anchor, now), Some(at("2024-07-01T08:00")));
+        assert_eq!(Frequency::Yearly.next_after(anchor, now), Some(at("2025-01-01T08:00")));
+    }
+
+    #[test]
+    fn parses_presets_and_rejects_unknown() {
+        assert_eq!("".parse::<Frequency>(), Ok(Frequency::Once));
+        assert_eq!(" Weekly ".parse::<Frequency>(), Ok(Frequency::Weekly));
+        assert!("fortnightly".parse::<Frequency>().is_err());
+        assert!("fortnightly".parse::<Recurrence>().is_err());
+        assert_eq!("monthly".parse::<Recurrence>(), Ok(Recurrence::Preset(Frequency::Monthly)));
+    }
+
+    #[test]
+    fn accepts_sqlite_time_format() {
+        assert_eq!(parse_time("2024-03-01 10:15:00"), Some(at("2024-03-01T10:15")));
+        assert_eq!(format_time(at("2024-03-01T10:15:30")), "2024-03-01T10:15:30");
+    }
+}
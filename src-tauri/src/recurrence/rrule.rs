@@ -0,0 +1,706 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::fmt;
+use std::str::FromStr;
+
+use super::Frequency;
+
+// Give up on rules that stop producing occurrences (e.g. BYMONTH=2;BYMONTHDAY=30)
+const MAX_EMPTY_PERIODS: u32 = 4000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRuleError(String);
+
+impl fmt::Display for RRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid RRULE: {}", self.0)
+    }
+}
+
+impl std::error::Error for RRuleError {}
+
+fn invalid<T>(msg: impl Into<String>) -> Result<T, RRuleError> {
+    Err(RRuleError(msg.into()))
+}
+
+/// A BYDAY entry such as `TU`, `2TU` or `-1FR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekdayNum {
+    pub ordinal: Option<i32>,
+    pub weekday: Weekday,
+}
+
+/// An EXDATE value; date-only values exclude every occurrence on that day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExDate {
+    At(NaiveDateTime),
+    Day(NaiveDate),
+}
+
+/// An RFC 5545 recurrence rule evaluated in local wall-clock time.
+///
+/// Supports FREQ (DAILY to YEARLY), INTERVAL, BYDAY, BYMONTHDAY, BYMONTH,
+/// BYSETPOS, COUNT, UNTIL and WKST, plus EXDATE given on its own line.
+/// The start of the series (DTSTART) is the reminder's `time`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<WeekdayNum>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDateTime>,
+    pub week_start: Weekday,
+    pub exdates: Vec<ExDate>,
+}
+
+impl RRule {
+    fn new(freq: Frequency) -> Self {
+        RRule {
+            freq,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            count: None,
+            until: None,
+            week_start: Weekday::Mon,
+            exdates: Vec::new(),
+        }
+    }
+
+    /// Iterates over the occurrences of the series starting at `dtstart`.
+    pub fn occurrences(&self, dtstart: NaiveDateTime) -> Occurrences<'_> {
+        Occurrences {
+            rule: self,
+            dtstart,
+            period: 0,
+            empty_periods: 0,
+            emitted: 0,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    fn is_excluded(&self, time: NaiveDateTime) -> bool {
+        self.exdates.iter().any(|ex| match ex {
+            ExDate::At(at) => *at == time,
+            ExDate::Day(day) => *day == time.date(),
+        })
+    }
+
+    fn matches_by_day(&self, date: NaiveDate, scope_first: NaiveDate, scope_last: NaiveDate) -> bool {
+        self.by_day.iter().any(|wd| {
+            if wd.weekday != date.weekday() {
+                return false;
+            }
+            match wd.ordinal {
+                None => true,
+                Some(n) if n > 0 => (date - scope_first).num_days() / 7 + 1 == n as i64,
+                Some(n) => (scope_last - date).num_days() / 7 + 1 == -n as i64,
+            }
+        })
+    }
+
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        let len = days_in_month(date.year(), date.month()) as i32;
+        let day = date.day() as i32;
+        self.by_month_day
+            .iter()
+            .any(|&md| if md > 0 { md == day } else { len + md + 1 == day })
+    }
+
+    fn matches_month(&self, date: NaiveDate) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&date.month())
+    }
+
+    /// Candidate dates for the `period`-th period of the series, before BYSETPOS.
+    fn period_dates(&self, dtstart: NaiveDate, period: u32) -> Option<Vec<NaiveDate>> {
+        let step = period.checked_mul(self.interval)?;
+        let mut dates = match self.freq {
+            Frequency::Once => return None,
+            Frequency::Daily => {
+                let day = dtstart.checked_add_signed(Duration::days(step as i64))?;
+                let (first, last) = month_bounds(day.year(), day.month())?;
+                let keep = self.matches_month(day)
+                    && (self.by_month_day.is_empty() || self.matches_month_day(day))
+                    && (self.by_day.is_empty() || self.matches_by_day(day, first, last));
+                if keep { vec![day] } else { Vec::new() }
+            }
+            Frequency::Weekly => {
+                let offset = days_from(self.week_start, dtstart.weekday());
+                let week = dtstart.checked_sub_signed(Duration::days(offset as i64))?
+                    .checked_add_signed(Duration::weeks(step as i64))?;
+                week.iter_days()
+                    .take(7)
+                    .filter(|d| {
+                        if self.by_day.is_empty() {
+                            d.weekday() == dtstart.weekday()
+                        } else {
+                            self.by_day.iter().any(|wd| wd.weekday == d.weekday())
+                        }
+                    })
+                    .filter(|d| self.matches_month(*d))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let total = dtstart.year() as i64 * 12 + dtstart.month0() as i64 + step as i64;
+                let year = i32::try_from(total.div_euclid(12)).ok()?;
+                let month = total.rem_euclid(12) as u32 + 1;
+                if self.by_month.is_empty() || self.by_month.contains(&month) {
+                    self.month_dates(dtstart, year, month)?
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Yearly => {
+                let year = dtstart.year().checked_add(i32::try_from(step).ok()?)?;
+                if self.by_month.is_empty() && self.by_month_day.is_empty() && !self.by_day.is_empty() {
+                    // BYDAY ordinals without BYMONTH count within the whole year
+                    let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+                    let last = NaiveDate::from_ymd_opt(year, 12, 31)?;
+                    first.iter_days()
+                        .take_while(|d| *d <= last)
+                        .filter(|d| self.matches_by_day(*d, first, last))
+                        .collect()
+                } else if self.by_month.is_empty() && self.by_month_day.is_empty() {
+                    NaiveDate::from_ymd_opt(year, dtstart.month(), dtstart.day())
+                        .into_iter()
+                        .collect()
+                } else {
+                    let months: Vec<u32> = if self.by_month.is_empty() {
+                        (1..=12).collect()
+                    } else {
+                        self.by_month.clone()
+                    };
+                    let mut dates = Vec::new();
+                    for month in months {
+                        dates.extend(self.month_dates(dtstart, year, month)?);
+                    }
+                    dates
+                }
+            }
+        };
+
+        dates.sort();
+        dates.dedup();
+        Some(dates)
+    }
+
+    fn month_dates(&self, dtstart: NaiveDate, year: i32, month: u32) -> Option<Vec<NaiveDate>> {
+        let (first, last) = month_bounds(year, month)?;
+        if self.by_month_day.is_empty() && self.by_day.is_empty() {
+            // Months without the start day are skipped, as RFC 5545 requires
+            return Some(NaiveDate::from_ymd_opt(year, month, dtstart.day()).into_iter().collect());
+        }
+        Some(
+            first.iter_days()
+                .take_while(|d| *d <= last)
+                .filter(|d| self.by_month_day.is_empty() || self.matches_month_day(*d))
+                .filter(|d| self.by_day.is_empty() || self.matches_by_day(*d, first, last))
+                .collect(),
+        )
+    }
+
+    fn apply_set_pos(&self, dates: Vec<NaiveDate>) -> Vec<NaiveDate> {
+        if self.by_set_pos.is_empty() {
+            return dates;
+        }
+        let len = dates.len() as i32;
+        let mut selected: Vec<NaiveDate> = self
+            .by_set_pos
+            .iter()
+            .filter_map(|&pos| {
+                let index = if pos > 0 { pos - 1 } else { len + pos };
+                (0..len).contains(&index).then(|| dates[index as usize])
+            })
+            .collect();
+        selected.sort();
+        selected.dedup();
+        selected
+    }
+}
+
+pub struct Occurrences<'a> {
+    rule: &'a RRule,
+    dtstart: NaiveDateTime,
+    period: u32,
+    empty_periods: u32,
+    emitted: u32,
+    pending: Vec<NaiveDateTime>,
+    done: bool,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = NaiveDateTime;
+
+    fn next(&mut self) -> Option<NaiveDateTime> {
+        loop {
+            if self.done {
+                return None;
+            }
+
+            if let Some(time) = self.pending.pop() {
+                if self.rule.until.is_some_and(|until| time > until) {
+                    self.done = true;
+                    return None;
+                }
+                // COUNT is reached before EXDATE removes anything
+                self.emitted += 1;
+                if self.rule.count.is_some_and(|count| self.emitted >= count) {
+                    self.done = true;
+                }
+                if self.rule.is_excluded(time) {
+                    continue;
+                }
+                return Some(time);
+            }
+
+            if self.rule.count == Some(0) || self.empty_periods >= MAX_EMPTY_PERIODS {
+                self.done = true;
+                continue;
+            }
+
+            let dates = match self.rule.period_dates(self.dtstart.date(), self.period) {
+                Some(dates) => self.rule.apply_set_pos(dates),
+                None => {
+                    self.done = true;
+                    continue;
+                }
+            };
+            self.period += 1;
+
+            let time = self.dtstart.time();
+            self.pending = dates
+                .into_iter()
+                .rev()
+                .map(|d| d.and_time(time))
+                .filter(|t| *t >= self.dtstart)
+                .collect();
+
+            if self.pending.is_empty() {
+                self.empty_periods += 1;
+            } else {
+                self.empty_periods = 0;
+            }
+        }
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    month_bounds(year, month).map(|(_, last)| last.day()).unwrap_or(28)
+}
+
+fn month_bounds(year: i32, month: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    let last = NaiveDate::from_ymd_opt(next_year, next_month, 1)?.pred_opt()?;
+    Some((first, last))
+}
+
+// Days from `start` forward to `day` within a week (0..=6)
+fn days_from(start: Weekday, day: Weekday) -> u32 {
+    (day.num_days_from_monday() + 7 - start.num_days_from_monday()) % 7
+}
+
+fn parse_weekday(s: &str) -> Result<Weekday, RRuleError> {
+    match s {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => invalid(format!("unknown weekday '{}'", s)),
+    }
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn parse_weekday_num(s: &str) -> Result<WeekdayNum, RRuleError> {
+    let split = s.len().saturating_sub(2);
+    let (ordinal, day) = s.split_at(split);
+    let weekday = parse_weekday(day)?;
+    if ordinal.is_empty() {
+        return Ok(WeekdayNum { ordinal: None, weekday });
+    }
+    let n: i32 = ordinal
+        .trim_start_matches('+')
+        .parse()
+        .or_else(|_| invalid(format!("invalid BYDAY value '{}'", s)))?;
+    if n == 0 || n.abs() > 53 {
+        return invalid(format!("BYDAY ordinal out of range in '{}'", s));
+    }
+    Ok(WeekdayNum { ordinal: Some(n), weekday })
+}
+
+fn parse_int_list(name: &str, value: &str, min: i32, max: i32) -> Result<Vec<i32>, RRuleError> {
+    value
+        .split(',')
+        .map(|v| {
+            let n: i32 = v
+                .trim_start_matches('+')
+                .parse()
+                .or_else(|_| invalid(format!("invalid {} value '{}'", name, v)))?;
+            if n == 0 || n.abs() < min || n.abs() > max {
+                return invalid(format!("{} value {} out of range", name, n));
+            }
+            Ok(n)
+        })
+        .collect()
+}
+
+/// Parses `YYYYMMDD` or `YYYYMMDDTHHMMSS[Z]`. A trailing `Z` is accepted but
+/// the value is still treated as local time, like the rest of the reminder.
+fn parse_ical_value(value: &str) -> Result<ExDate, RRuleError> {
+    let value = value.trim().trim_end_matches('Z');
+    if let Ok(at) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Ok(ExDate::At(at));
+    }
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .map(ExDate::Day)
+        .or_else(|_| invalid(format!("invalid date '{}'", value)))
+}
+
+fn format_ical_value(value: &ExDate) -> String {
+    match value {
+        ExDate::At(at) => at.format("%Y%m%dT%H%M%S").to_string(),
+        ExDate::Day(day) => day.format("%Y%m%d").to_string(),
+    }
+}
+
+impl FromStr for RRule {
+    type Err = RRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rule_parts: Option<&str> = None;
+        let mut exdates = Vec::new();
+
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let upper = line.to_ascii_uppercase();
+            if let Some(rest) = upper.strip_prefix("RRULE:") {
+                if rule_parts.is_some() {
+                    return invalid("only one RRULE is supported");
+                }
+                rule_parts = Some(&line[line.len() - rest.len()..]);
+            } else if upper.starts_with("EXDATE") {
+                let (_, values) = line
+                    .split_once(':')
+                    .ok_or_else(|| RRuleError("EXDATE has no value".into()))?;
+                for value in values.split(',') {
+                    exdates.push(parse_ical_value(value)?);
+                }
+            } else if upper.starts_with("DTSTART") {
+                return invalid("DTSTART is taken from the reminder time");
+            } else if rule_parts.is_none() && upper.contains("FREQ=") {
+                rule_parts = Some(line);
+            } else {
+                return invalid(format!("unexpected line '{}'", line));
+            }
+        }
+
+        let parts = rule_parts.ok_or_else(|| RRuleError("missing FREQ".into()))?;
+        let mut rule: Option<RRule> = None;
+        let mut seen: Vec<String> = Vec::new();
+        let mut pending: Vec<(String, String)> = Vec::new();
+
+        for part in parts.split(';').filter(|p| !p.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| RRuleError(format!("malformed part '{}'", part)))?;
+            let name = name.trim().to_ascii_uppercase();
+            let value = value.trim().to_ascii_uppercase();
+            if seen.contains(&name) {
+                return invalid(format!("{} given more than once", name));
+            }
+            seen.push(name.clone());
+
+            if name == "FREQ" {
+                let freq = match value.as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    "SECONDLY" | "MINUTELY" | "HOURLY" => {
+                        return invalid(format!("FREQ={} is not supported", value))
+                    }
+                    _ => return invalid(format!("unknown FREQ '{}'", value)),
+                };
+                rule = Some(RRule::new(freq));
+            } else {
+                pending.push((name, value));
+            }
+        }
+
+        let mut rule = rule.ok_or_else(|| RRuleError("missing FREQ".into()))?;
+        for (name, value) in pending {
+            match name.as_str() {
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| RRuleError(format!("invalid INTERVAL '{}'", value)))?;
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .ok_or_else(|| RRuleError(format!("invalid COUNT '{}'", value)))?,
+                    );
+                }
+                "UNTIL" => {
+                    rule.until = Some(match parse_ical_value(&value)? {
+                        ExDate::At(at) => at,
+                        ExDate::Day(day) => day.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap_or_default()),
+                    });
+                }
+                "BYDAY" => {
+                    rule.by_day = value.split(',').map(parse_weekday_num).collect::<Result<_, _>>()?;
+                }
+                "BYMONTHDAY" => rule.by_month_day = parse_int_list(&name, &value, 1, 31)?,
+                "BYMONTH" => {
+                    rule.by_month = parse_int_list(&name, &value, 1, 12)?
+                        .into_iter()
+                        .map(|m| {
+                            if m < 0 {
+                                invalid("BYMONTH values must be positive")
+                            } else {
+                                Ok(m as u32)
+                            }
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                "BYSETPOS" => rule.by_set_pos = parse_int_list(&name, &value, 1, 366)?,
+                "WKST" => rule.week_start = parse_weekday(&value)?,
+                _ => return invalid(format!("{} is not supported", name)),
+            }
+        }
+
+        if rule.count.is_some() && rule.until.is_some() {
+            return invalid("COUNT and UNTIL cannot both be set");
+        }
+        if rule.by_day.iter().any(|wd| wd.ordinal.is_some())
+            && !matches!(rule.freq, Frequency::Monthly | Frequency::Yearly)
+        {
+            return invalid("numbered BYDAY values need FREQ=MONTHLY or FREQ=YEARLY");
+        }
+        if rule.freq == Frequency::Weekly && !rule.by_month_day.is_empty() {
+            return invalid("BYMONTHDAY cannot be used with FREQ=WEEKLY");
+        }
+        if !rule.by_set_pos.is_empty()
+            && rule.by_day.is_empty()
+            && rule.by_month_day.is_empty()
+            && rule.by_month.is_empty()
+        {
+            return invalid("BYSETPOS needs another BYxxx rule part");
+        }
+
+        rule.exdates = exdates;
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for RRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |values: Vec<String>| values.join(",");
+
+        write!(f, "RRULE:FREQ={}", self.freq.as_str().to_ascii_uppercase())?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%S"))?;
+        }
+        if !self.by_month.is_empty() {
+            write!(f, ";BYMONTH={}", join(self.by_month.iter().map(|m| m.to_string()).collect()))?;
+        }
+        if !self.by_month_day.is_empty() {
+            write!(f, ";BYMONTHDAY={}", join(self.by_month_day.iter().map(|d| d.to_string()).collect()))?;
+        }
+        if !self.by_day.is_empty() {
+            let days = self
+                .by_day
+                .iter()
+                .map(|wd| match wd.ordinal {
+                    Some(n) => format!("{}{}", n, weekday_code(wd.weekday)),
+                    None => weekday_code(wd.weekday).to_string(),
+                })
+                .collect();
+            write!(f, ";BYDAY={}", join(days))?;
+        }
+        if !self.by_set_pos.is_empty() {
+            write!(f, ";BYSETPOS={}", join(self.by_set_pos.iter().map(|p| p.to_string()).collect()))?;
+        }
+        if self.week_start != Weekday::Mon {
+            write!(f, ";WKST={}", weekday_code(self.week_start))?;
+        }
+        if !self.exdates.is_empty() {
+            write!(f, "\nEXDATE:{}", join(self.exdates.iter().map(format_ical_value).collect()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recurrence::{format_time, parse_time};
+
+    fn rule(value: &str) -> RRule {
+        value.parse().unwrap()
+    }
+
+    fn fire(value: &str, start: &str, limit: usize) -> Vec<String> {
+        rule(value)
+            .occurrences(parse_time(start).unwrap())
+            .take(limit)
+            .map(format_time)
+            .collect()
+    }
+
+    #[test]
+    fn every_second_tuesday() {
+        assert_eq!(
+            fire("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU", "2024-01-02T09:00", 3),
+            ["2024-01-02T09:00", "2024-01-16T09:00", "2024-01-30T09:00"]
+        );
+    }
+
+    #[test]
+    fn second_tuesday_of_the_month() {
+        assert_eq!(
+            fire("FREQ=MONTHLY;BYDAY=2TU", "2024-01-01T09:00", 3),
+            ["2024-01-09T09:00", "2024-02-13T09:00", "2024-03-12T09:00"]
+        );
+    }
+
+    #[test]
+    fn weekdays_only() {
+        let expected = [
+            "2024-01-05T08:00",
+            "2024-01-08T08:00",
+            "2024-01-09T08:00",
+            "2024-01-10T08:00",
+            "2024-01-11T08:00",
+            "2024-01-12T08:00",
+            "2024-01-15T08:00",
+        ];
+        assert_eq!(fire("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR", "2024-01-05T08:00", 7), expected);
+        assert_eq!(fire("FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR", "2024-01-05T08:00", 7), expected);
+    }
+
+    #[test]
+    fn last_business_day_of_the_month() {
+        assert_eq!(
+            fire("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1", "2024-01-01T17:00", 6),
+            [
+                "2024-01-31T17:00",
+                "2024-02-29T17:00",
+                "2024-03-29T17:00",
+                "2024-04-30T17:00",
+                "2024-05-31T17:00",
+                "2024-06-28T17:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn count_ends_the_series() {
+        assert_eq!(
+            fire("FREQ=DAILY;COUNT=3", "2024-01-01T09:00", 10),
+            ["2024-01-01T09:00", "2024-01-02T09:00", "2024-01-03T09:00"]
+        );
+    }
+
+    #[test]
+    fn until_ends_the_series() {
+        assert_eq!(
+            fire("FREQ=DAILY;UNTIL=20240103T090000", "2024-01-01T09:00", 10),
+            ["2024-01-01T09:00", "2024-01-02T09:00", "2024-01-03T09:00"]
+        );
+        // A date-only UNTIL includes that whole day
+        assert_eq!(fire("FREQ=WEEKLY;UNTIL=20240115", "2024-01-01T21:00", 10).len(), 3);
+    }
+
+    #[test]
+    fn exdate_removes_occurrences_counted_by_count() {
+        assert_eq!(
+            fire("RRULE:FREQ=DAILY;COUNT=4\nEXDATE:20240102T090000", "2024-01-01T09:00", 10),
+            ["2024-01-01T09:00", "2024-01-03T09:00", "2024-01-04T09:00"]
+        );
+        assert_eq!(
+            fire("RRULE:FREQ=DAILY;COUNT=3\nEXDATE:20240101,20240103", "2024-01-01T09:00", 10),
+            ["2024-01-02T09:00"]
+        );
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let canonical = "RRULE:FREQ=MONTHLY;INTERVAL=2;COUNT=5;BYMONTH=1,7;BYDAY=-1FR,2MO;BYSETPOS=1;WKST=SU\nEXDATE:20240105,20240301T090000";
+        let parsed = rule(canonical);
+        assert_eq!(parsed.to_string(), canonical);
+        assert_eq!(rule(&parsed.to_string()), parsed);
+
+        assert_eq!(rule("freq=weekly;byday=mo;interval=1").to_string(), "RRULE:FREQ=WEEKLY;BYDAY=MO");
+        let until = rule("FREQ=DAILY;UNTIL=20240131");
+        assert_eq!(until.to_string(), "RRULE:FREQ=DAILY;UNTIL=20240131T235959");
+        assert_eq!(rule(&until.to_string()), until);
+    }
+
+    #[test]
+    fn rule_that_never_fires_terminates() {
+        for value in [
+            "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=31",
+            "FREQ=MONTHLY;BYMONTH=2;BYMONTHDAY=31",
+            "FREQ=DAILY;BYMONTH=2;BYMONTHDAY=30",
+        ] {
+            assert_eq!(fire(value, "2024-01-01T09:00", 5), Vec::<String>::new(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn months_without_the_start_day_are_skipped() {
+        assert_eq!(
+            fire("FREQ=MONTHLY", "2024-01-31T09:00", 3),
+            ["2024-01-31T09:00", "2024-03-31T09:00", "2024-05-31T09:00"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for value in [
+            "BYDAY=MO",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;COUNT=2;UNTIL=20240101",
+            "FREQ=WEEKLY;BYDAY=2TU",
+            "FREQ=WEEKLY;BYMONTHDAY=1",
+            "FREQ=DAILY;BYSETPOS=1",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;FREQ=WEEKLY",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=DAILY;BYDAY=XX",
+            "DTSTART:20240101T090000\nRRULE:FREQ=DAILY",
+        ] {
+            assert!(value.parse::<RRule>().is_err(), "{}", value);
+        }
+    }
+}
@@ -77,6 +77,7 @@ pub fn setup_window_handlers(app: &AppHandle) -> Result<(), Box<dyn std::error::
     // Handle window close event to hide instead of quit
     if let Some(window) = app.get_webview_window("main") {
         let window_clone = window.clone();
+        #[cfg(target_os = "macos")]
         let app_handle = app.app_handle().clone();
         window.on_window_event(move |event| {
             if let tauri::WindowEvent::CloseRequested { api, .. } = event {
@@ -0,0 +1,160 @@
+use std::path::Path;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqlitePool;
+use log::info;
+use crate::attachments::{asset_url, page_attachment, store_attachment};
+use crate::database;
+use crate::error::{AppError, AppResult};
+use crate::models::{AttachmentGcReport, Evidence, PageAttachment};
+
+// An attachment is only collected once it is this old, so that one uploaded
+// while its page is being edited survives until the page is saved
+const GC_GRACE: Duration = Duration::days(1);
+
+/// Stores `data` as a file attached to a page, named `file_name` in lists.
+pub async fn add_page_attachment(
+    pool: &SqlitePool,
+    attachments_dir: &Path,
+    page_id: &str,
+    file_name: &str,
+    data: &[u8],
+    description: Option<String>,
+) -> AppResult<PageAttachment> {
+    if database::find_wiki_page(&mut *pool.acquire().await?, page_id).await?.is_none() {
+        return Err(AppError::not_found(format!("Page {} not found", page_id)));
+    }
+    let extension = Path::new(file_name).extension().and_then(|e| e.to_str()).unwrap_or("bin");
+    let stored = store_attachment(attachments_dir, extension, data)?;
+
+    let input = page_attachment(page_id, &stored, file_name, description);
+    match database::add_evidence(pool, input).await {
+        Ok(evidence) => Ok(with_url(evidence)),
+        Err(e) => {
+            // The file may hold content other rows already share
+            database::release_files(pool, std::slice::from_ref(&stored.path)).await;
+            Err(e)
+        }
+    }
+}
+
+pub fn with_url(evidence: Evidence) -> PageAttachment {
+    let url = asset_url(Path::new(&evidence.file_path));
+    PageAttachment { evidence, url }
+}
+
+/// Finds page attachments that neither a page, trashed ones included, nor any
+/// revision refers to, and unless `dry_run` deletes them with their files.
+/// Pages refer to attachments by their asset URL, which contains the stored
+/// file name, so an attachment is referenced wherever that name occurs. The
+/// write lock is held from the first check to the delete, so a page saved
+/// meanwhile cannot start referring to an attachment about to go.
+pub async fn collect_attachment_garbage(
+    pool: &SqlitePool,
+    dry_run: bool,
+    now: DateTime<Utc>,
+) -> AppResult<AttachmentGcReport> {
+    let created_before = (now - GC_GRACE).format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+    let candidates = database::get_page_attachments_before(&mut tx, &created_before).await?;
+
+    let mut unreferenced = Vec::new();
+    for attachment in candidates {
+        let Some(stored_name) = Path::new(&attachment.file_path).file_name() else { continue };
+        if !database::is_referenced_by_wiki(&mut tx, &stored_name.to_string_lossy()).await? {
+            unreferenced.push(attachment);
+        }
+    }
+
+    if !dry_run && !unreferenced.is_empty() {
+        database::delete_evidence_rows(&mut tx, &unreferenced).await?;
+        database::release_evidence_files(&mut tx, &unreferenced).await;
+        tx.commit().await?;
+        info!("Removed {} unreferenced page attachments", unreferenced.len());
+    }
+    Ok(AttachmentGcReport { unreferenced, removed: !dry_run })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_page as page;
+
+    async fn attach(pool: &SqlitePool, dir: &Path, page_id: &str, data: &[u8], age: &str) -> PageAttachment {
+        let attachment = add_page_attachment(pool, dir, page_id, "file.png", data, None).await.unwrap();
+        sqlx::query(&format!("UPDATE evidence SET created_at = datetime('now', '{}') WHERE id = ?", age))
+            .bind(attachment.evidence.id)
+            .execute(pool)
+            .await
+            .unwrap();
+        attachment
+    }
+
+    fn ids(report: &AttachmentGcReport) -> Vec<i64> {
+        let mut ids: Vec<i64> = report.unreferenced.iter().map(|e| e.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn only_old_unreferenced_attachments_are_collected() {
+        let pool = database::test_pool().await;
+        let dir = tempfile::tempdir().unwrap();
+        for id in ["notes", "trashed", "revised"] {
+            database::create_wiki_page(&pool, &page(id, "")).await.unwrap();
+        }
+
+        let referenced = attach(&pool, dir.path(), "notes", b"referenced", "-2 days").await;
+        let unreferenced = attach(&pool, dir.path(), "notes", b"unreferenced", "-2 days").await;
+        let recent = attach(&pool, dir.path(), "notes", b"recent", "-1 hours").await;
+        let in_trash = attach(&pool, dir.path(), "trashed", b"in trash", "-2 days").await;
+        let trashed_unused = attach(&pool, dir.path(), "trashed", b"trashed unused", "-2 days").await;
+        let in_revision = attach(&pool, dir.path(), "revised", b"in revision", "-2 days").await;
+
+        let notes = page("notes", &format!("![]({})", referenced.url));
+        database::update_wiki_page(&pool, &notes, None).await.unwrap();
+        let trashed = page("trashed", &format!("![]({})", in_trash.url));
+        database::update_wiki_page(&pool, &trashed, None).await.unwrap();
+        database::trash_wiki_page(&pool, "trashed", 2).await.unwrap();
+        let revised = page("revised", &format!("![]({})", in_revision.url));
+        database::update_wiki_page(&pool, &revised, None).await.unwrap();
+        database::update_wiki_page(&pool, &page("revised", "Image removed"), None).await.unwrap();
+
+        let report = collect_attachment_garbage(&pool, true, Utc::now()).await.unwrap();
+        assert_eq!(ids(&report), vec![unreferenced.evidence.id, trashed_unused.evidence.id]);
+        assert!(!report.removed);
+        assert_eq!(database::get_all_evidence(&pool).await.unwrap().len(), 6);
+        assert!(Path::new(&unreferenced.evidence.file_path).exists());
+
+        let report = collect_attachment_garbage(&pool, false, Utc::now()).await.unwrap();
+        assert_eq!(ids(&report), vec![unreferenced.evidence.id, trashed_unused.evidence.id]);
+        assert!(report.removed);
+
+        let mut left: Vec<i64> = database::get_all_evidence(&pool).await.unwrap().iter().map(|e| e.id).collect();
+        left.sort();
+        let kept = [&referenced, &recent, &in_trash, &in_revision];
+        assert_eq!(left, kept.iter().map(|a| a.evidence.id).collect::<Vec<_>>());
+        assert!(!Path::new(&unreferenced.evidence.file_path).exists());
+        assert!(!Path::new(&trashed_unused.evidence.file_path).exists());
+        assert!(kept.iter().all(|a| Path::new(&a.evidence.file_path).exists()));
+
+        // Past its grace period the recent attachment goes too
+        let report = collect_attachment_garbage(&pool, false, Utc::now() + Duration::days(2)).await.unwrap();
+        assert_eq!(ids(&report), vec![recent.evidence.id]);
+    }
+
+    #[tokio::test]
+    async fn an_attachment_sharing_a_referenced_file_is_kept() {
+        let pool = database::test_pool().await;
+        let dir = tempfile::tempdir().unwrap();
+        database::create_wiki_page(&pool, &page("one", "")).await.unwrap();
+        database::create_wiki_page(&pool, &page("two", "")).await.unwrap();
+
+        let used = attach(&pool, dir.path(), "one", b"same", "-2 days").await;
+        attach(&pool, dir.path(), "two", b"same", "-2 days").await;
+        database::update_wiki_page(&pool, &page("one", &used.url), None).await.unwrap();
+
+        let report = collect_attachment_garbage(&pool, false, Utc::now()).await.unwrap();
+        assert!(report.unreferenced.is_empty());
+        assert!(Path::new(&used.evidence.file_path).exists());
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+// Older revisions are stored as the edits that turn the next newer revision
+// back into them: runs of lines copied from that base and inserted text.
+#[derive(Debug, Serialize, Deserialize)]
+enum DeltaOp {
+    #[serde(rename = "c")]
+    Copy(usize, usize),  // Start line and number of lines in the base
+    #[serde(rename = "i")]
+    Insert(String),
+}
+
+/// Serialized delta that rebuilds `target` from `base`.
+pub fn encode(base: &str, target: &str) -> String {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let target_lines: Vec<&str> = target.split_inclusive('\n').collect();
+
+    let mut ops: Vec<DeltaOp> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, &base_lines, &target_lines) {
+        let (tag, old, new) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => ops.push(DeltaOp::Copy(old.start, old.len())),
+            DiffTag::Delete => {}
+            DiffTag::Insert | DiffTag::Replace => {
+                let text = target_lines[new].concat();
+                match ops.last_mut() {
+                    Some(DeltaOp::Insert(previous)) => previous.push_str(&text),
+                    _ => ops.push(DeltaOp::Insert(text)),
+                }
+            }
+        }
+    }
+
+    serde_json::to_string(&ops).unwrap_or_default()
+}
+
+/// Rebuilds the text a delta was encoded from.
+pub fn apply(base: &str, delta: &str) -> Result<String, String> {
+    let ops: Vec<DeltaOp> = serde_json::from_str(delta).map_err(|e| format!("invalid delta: {}", e))?;
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+
+    let mut text = String::with_capacity(base.len());
+    for op in ops {
+        match op {
+            DeltaOp::Copy(start, len) => {
+                let lines = start
+                    .checked_add(len)
+                    .and_then(|end| base_lines.get(start..end))
+                    .ok_or_else(|| format!("delta copies lines {}..+{} of a {}-line base", start, len, base_lines.len()))?;
+                lines.iter().for_each(|line| text.push_str(line));
+            }
+            DeltaOp::Insert(inserted) => text.push_str(&inserted),
+        }
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &str, target: &str) {
+        let delta = encode(base, target);
+        assert_eq!(apply(base, &delta).unwrap(), target, "delta {} from {:?}", delta, base);
+    }
+
+    #[test]
+    fn empty_texts_round_trip() {
+        round_trip("", "");
+        round_trip("", "new page\n");
+        round_trip("old page\n", "");
+    }
+
+    #[test]
+    fn identical_text_is_copied_whole() {
+        let text = "# Title\n\nfirst\nsecond\n";
+        assert_eq!(encode(text, text), r#"[{"c":[0,4]}]"#);
+        round_trip(text, text);
+    }
+
+    #[test]
+    fn completely_different_text_round_trips() {
+        round_trip("alpha\nbeta\ngamma\n", "one\ntwo\n");
+        round_trip("no trailing newline", "still none");
+    }
+
+    #[test]
+    fn edited_lines_round_trip() {
+        let base = "one\ntwo\nthree\nfour\n";
+        round_trip(base, "one\nthree\nfour\n");
+        round_trip(base, "zero\none\ntwo\n2.5\nthree\nfour\nfive");
+        round_trip(base, "one\nTWO\nthree\nfour");
+    }
+
+    #[test]
+    fn multibyte_text_round_trips() {
+        round_trip("héllo\n日本語\n", "héllo\n日本語のページ\n🙂\n");
+        round_trip("🙂\r\nçà\r\n", "çà\r\n🙂");
+    }
+
+    #[test]
+    fn apply_rejects_bad_deltas() {
+        assert!(apply("one\n", "not json").is_err());
+        assert!(apply("one\n", r#"[{"c":[0,2]}]"#).is_err());
+        assert!(apply("one\n", &format!(r#"[{{"c":[1,{}]}}]"#, usize::MAX)).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use chrono::SecondsFormat;
+use sqlx::SqlitePool;
+use log::info;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+use crate::database::{self, ROOT_SECTION_ID};
+use crate::error::{AppError, AppResult};
+use crate::models::{Section, WikiExportFormat, WikiExportReport, WikiPage};
+use super::links::{parse_links, Resolver};
+
+// Longest file or directory name written, in characters, not counting ".md"
+const MAX_NAME_CHARS: usize = 120;
+
+// Names Windows will not open as files, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Writes every page as a Markdown file with YAML front matter, in a
+/// directory per section following the section tree, the root section of
+/// each notebook becoming a top-level directory named after it. `[[links]]`
+/// to other pages become Markdown links with relative paths; links to no
+/// page are left as written. For [`WikiExportFormat::Folder`] `target` is a
+/// directory that must be empty or not exist yet; for
+/// [`WikiExportFormat::Zip`] it is the zip file, replaced if it exists.
+pub async fn export_wiki(pool: &SqlitePool, target: &Path, format: WikiExportFormat) -> AppResult<WikiExportReport> {
+    let sections = database::list_sections(pool).await?;
+    let pages = database::list_wiki_page_contents(pool).await?;
+
+    let tree = SectionTree::new(&sections);
+    let mut names = Names::default();
+    let mut dirs: Vec<Vec<String>> = Vec::new();
+    let mut section_dirs: HashMap<&str, Vec<String>> = HashMap::new();
+
+    // Parents come before their children, and siblings in their shown order
+    for section in &sections {
+        let mut dir: Vec<String> = Vec::new();
+        for step in tree.path(&section.id) {
+            match section_dirs.get(step.id.as_str()) {
+                Some(known) => dir = known.clone(),
+                None => {
+                    let name = names.claim(&dir, &step.name, "");
+                    dir.push(name);
+                    section_dirs.insert(&step.id, dir.clone());
+                    dirs.push(dir.clone());
+                }
+            }
+        }
+    }
+
+    let mut page_paths: HashMap<&str, Vec<String>> = HashMap::new();
+    for page in &pages {
+        let mut path = page_section(&tree, page)
+            .and_then(|section| section_dirs.get(section.id.as_str()).cloned())
+            .unwrap_or_default();
+        let name = names.claim(&path, &page.title, ".md");
+        path.push(name);
+        page_paths.insert(&page.id, path);
+    }
+
+    let resolver = Resolver::new(pages.iter().map(|p| (p.id.as_str(), p.title.as_str(), p.updated_at)));
+    let titles: HashMap<&str, &str> = pages.iter().map(|p| (p.id.as_str(), p.title.as_str())).collect();
+    let mut report = WikiExportReport {
+        path: target.display().to_string(),
+        pages: pages.len(),
+        sections: dirs.len(),
+        ..Default::default()
+    };
+
+    let mut files: Vec<(&[String], String)> = Vec::with_capacity(pages.len());
+    for page in &pages {
+        let path = &page_paths[page.id.as_str()];
+        let dir = &path[..path.len() - 1];
+
+        let mut content = page.content.clone();
+        for link in parse_links(&page.content).iter().rev() {
+            let Some(target_id) = resolver.resolve(&link.target) else {
+                report.broken_links += 1;
+                continue;
+            };
+            let label = match &link.alias {
+                Some(alias) => alias.as_str(),
+                None if link.target == target_id => titles[target_id],
+                None => link.target.as_str(),
+            };
+            let href = relative_path(dir, &page_paths[target_id]);
+            content.replace_range(link.range.clone(), &format!("[{}]({})", label, href));
+            report.links += 1;
+        }
+
+        let section = page_section(&tree, page).map(|section| tree.path(&section.id)).unwrap_or_default();
+        files.push((path, format!("{}\n{}", front_matter(page, &section), content)));
+    }
+
+    match format {
+        WikiExportFormat::Folder => write_folder(target, &dirs, &files)?,
+        WikiExportFormat::Zip => write_zip(target, &dirs, &files)?,
+    }
+
+    info!(
+        "Exported {} wiki pages in {} sections to {:?}; rewrote {} links, left {} broken",
+        report.pages, report.sections, target, report.links, report.broken_links
+    );
+    Ok(report)
+}
+
+struct SectionTree<'a> {
+    sections: HashMap<&'a str, &'a Section>,
+}
+
+impl<'a> SectionTree<'a> {
+    fn new(sections: &'a [Section]) -> Self {
+        SectionTree { sections: sections.iter().map(|s| (s.id.as_str(), s)).collect() }
+    }
+
+    // The section and its ancestors, the root first
+    fn path(&self, id: &str) -> Vec<&'a Section> {
+        let mut path = Vec::new();
+        let mut next = self.sections.get(id).copied();
+        while let Some(section) = next {
+            // Guards against a cycle, which moves do not allow but an old database might hold
+            if path.len() > self.sections.len() {
+                break;
+            }
+            path.push(section);
+            next = section.parent_id.as_deref().and_then(|parent| self.sections.get(parent).copied());
+        }
+        path.reverse();
+        path
+    }
+}
+
+// The section a page is filed under; pages without one go to the root of the default notebook
+fn page_section<'a>(tree: &SectionTree<'a>, page: &WikiPage) -> Option<&'a Section> {
+    page.section_id
+        .as_deref()
+        .and_then(|id| tree.sections.get(id))
+        .or_else(|| tree.sections.get(ROOT_SECTION_ID))
+        .copied()
+}
+
+// File and directory names in use, per directory, compared case-insensitively
+// since exports often end up on case-insensitive file systems
+#[derive(Default)]
+struct Names {
+    used: HashMap<Vec<String>, HashSet<String>>,
+}
+
+impl Names {
+    // A name for `wanted` in `dir` that no other entry there has, numbering repeats "Name (2)"
+    fn claim(&mut self, dir: &[String], wanted: &str, extension: &str) -> String {
+        let used = self.used.entry(dir.to_vec()).or_default();
+        let base = file_name(wanted);
+
+        let mut name = format!("{}{}", base, extension);
+        let mut n = 2;
+        while !used.insert(name.to_lowercase()) {
+            name = format!("{} ({}){}", base, n, extension);
+            n += 1;
+        }
+        name
+    }
+}
+
+// A page title or section name as a file name that is valid on every common file system
+fn file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches('.').trim_end().to_string();
+
+    if cleaned.is_empty() {
+        return "Untitled".to_string();
+    }
+    let stem = cleaned.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        return format!("_{}", cleaned);
+    }
+    cleaned
+}
+
+// A link from a file in `from_dir` to the file at `to`, with each part percent-encoded
+fn relative_path(from_dir: &[String], to: &[String]) -> String {
+    let to_dir = &to[..to.len() - 1];
+    let common = from_dir.iter().zip(to_dir).take_while(|(a, b)| a == b).count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from_dir.len() - common];
+    parts.extend(to[common..].iter().map(|part| urlencoding::encode(part).into_owned()));
+    parts.join("/")
+}
+
+// Strings are written as JSON strings, which YAML reads as double-quoted
+// scalars, so titles with colons, quotes or leading dashes stay intact
+fn front_matter(page: &WikiPage, section: &[&Section]) -> String {
+    let quote = |value: &str| serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string());
+    let timestamp = |seconds: i64| {
+        chrono::DateTime::from_timestamp(seconds, 0)
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .unwrap_or_else(|| seconds.to_string())
+    };
+    // The root of the notebook is named by `notebook`
+    let section: Vec<&str> = section.iter().skip(1).map(|s| s.name.as_str()).collect();
+
+    format!(
+        "---\nid: {}\ntitle: {}\ntags: {}\nnotebook: {}\nsection: {}\ncreated: {}\nupdated: {}\n---\n",
+        quote(&page.id),
+        quote(&page.title),
+        serde_json::to_string(&page.tags).unwrap_or_else(|_| "[]".to_string()),
+        quote(&page.notebook),
+        quote(&section.join("/")),
+        timestamp(page.created_at),
+        timestamp(page.updated_at),
+    )
+}
+
+fn write_folder(target: &Path, dirs: &[Vec<String>], files: &[(&[String], String)]) -> AppResult<()> {
+    if target.is_file() {
+        return Err(AppError::validation(format!("{} is a file, not a folder", target.display())));
+    }
+    let occupied = fs::read_dir(target).map(|mut entries| entries.next().is_some()).unwrap_or(false);
+    if occupied {
+        return Err(AppError::validation(format!("Folder {} is not empty", target.display())));
+    }
+
+    let join = |parts: &[String]| parts.iter().fold(target.to_path_buf(), |path, part| path.join(part));
+    fs::create_dir_all(target).map_err(|e| AppError::io("Failed to create export folder", e))?;
+    for dir in dirs {
+        fs::create_dir_all(join(dir)).map_err(|e| AppError::io("Failed to create export folder", e))?;
+    }
+    for (path, content) in files {
+        fs::write(join(path), content).map_err(|e| AppError::io("Failed to write exported page", e))?;
+    }
+    Ok(())
+}
+
+// Written next to the target first, so a failed export leaves an earlier zip in place
+fn write_zip(target: &Path, dirs: &[Vec<String>], files: &[(&[String], String)]) -> AppResult<()> {
+    if target.is_dir() {
+        return Err(AppError::validation(format!("{} is a folder, not a zip file", target.display())));
+    }
+
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let written = (|| -> io::Result<()> {
+        let mut zip = ZipWriter::new(File::create(&partial)?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for dir in dirs {
+            zip.add_directory(dir.join("/"), options)?;
+        }
+        for (path, content) in files {
+            zip.start_file(path.join("/"), options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?.sync_all()
+    })();
+
+    if let Err(e) = written.and_then(|()| fs::rename(&partial, target)) {
+        let _ = fs::remove_file(&partial);
+        return Err(AppError::io("Failed to write export zip", e));
+    }
+    Ok(())
+}
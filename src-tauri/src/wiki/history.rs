@@ -0,0 +1,201 @@
+use similar::{ChangeTag, DiffTag, TextDiff};
+use crate::models::{BlameLine, DiffHunk, DiffKind, DiffLine, DiffSegment, FieldChange, WikiPage, WikiRevisionDiff};
+
+/// Names the live page wherever a revision id is expected
+pub const CURRENT_REVISION: &str = "current";
+
+/// Line diff of the content, with changed words marked inside changed lines,
+/// plus changes to the title, section and tags.
+pub fn diff_pages(from_id: &str, from: &WikiPage, to_id: &str, to: &WikiPage) -> WikiRevisionDiff {
+    let title = (from.title != to.title).then(|| FieldChange { old: from.title.clone(), new: to.title.clone() });
+    let section = (from.section_id != to.section_id || from.section != to.section)
+        .then(|| FieldChange { old: from.section.clone(), new: to.section.clone() });
+
+    WikiRevisionDiff {
+        page_id: to.id.clone(),
+        from: from_id.to_string(),
+        to: to_id.to_string(),
+        title,
+        section,
+        tags_added: to.tags.iter().filter(|t| !from.tags.contains(t)).cloned().collect(),
+        tags_removed: from.tags.iter().filter(|t| !to.tags.contains(t)).cloned().collect(),
+        hunks: diff_content(&from.content, &to.content),
+    }
+}
+
+fn diff_content(old: &str, new: &str) -> Vec<DiffHunk> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+
+    for op in diff.ops() {
+        for change in diff.iter_inline_changes(op) {
+            let kind = match change.tag() {
+                ChangeTag::Equal => DiffKind::Equal,
+                ChangeTag::Insert => DiffKind::Insert,
+                ChangeTag::Delete => DiffKind::Delete,
+            };
+            let segments = change
+                .iter_strings_lossy()
+                .map(|(changed, text)| DiffSegment {
+                    text: text.trim_end_matches(['\n', '\r']).to_string(),
+                    changed: changed && kind != DiffKind::Equal,
+                })
+                .filter(|segment| !segment.text.is_empty())
+                .collect();
+            let line = DiffLine {
+                old_line: change.old_index().map(|i| i + 1),
+                new_line: change.new_index().map(|i| i + 1),
+                segments,
+            };
+
+            match hunks.last_mut() {
+                Some(hunk) if hunk.kind == kind => hunk.lines.push(line),
+                _ => hunks.push(DiffHunk { kind, lines: vec![line] }),
+            }
+        }
+    }
+
+    hunks
+}
+
+/// Attributes each line of the newest version to the version that introduced
+/// it. `versions` runs oldest first; each is paired with its revision id, or
+/// `None` for the current page.
+pub fn blame(versions: &[(Option<String>, &WikiPage)]) -> Vec<BlameLine> {
+    // Origin (revision id and time) of each line of the version last looked at
+    let mut origins: Vec<(Option<String>, i64)> = Vec::new();
+    let mut previous = "";
+
+    for (revision_id, page) in versions {
+        let diff = TextDiff::from_lines(previous, page.content.as_str());
+        let mut next = Vec::with_capacity(origins.len());
+
+        for op in diff.ops() {
+            let (tag, old, new) = op.as_tag_tuple();
+            match tag {
+                DiffTag::Equal => next.extend_from_slice(&origins[old]),
+                DiffTag::Delete => {}
+                DiffTag::Insert | DiffTag::Replace => {
+                    next.extend(new.map(|_| (revision_id.clone(), page.updated_at)));
+                }
+            }
+        }
+
+        origins = next;
+        previous = &page.content;
+    }
+
+    previous
+        .lines()
+        .zip(origins)
+        .enumerate()
+        .map(|(index, (text, (revision_id, changed_at)))| BlameLine {
+            line: index + 1,
+            text: text.to_string(),
+            revision_id,
+            changed_at,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_page;
+
+    fn version(content: &str, updated_at: i64) -> WikiPage {
+        WikiPage { updated_at, ..test_page("page", content) }
+    }
+
+    fn words(line: &DiffLine, changed: bool) -> Vec<&str> {
+        line.segments.iter().filter(|s| s.changed == changed).map(|s| s.text.as_str()).collect()
+    }
+
+    fn text(line: &DiffLine) -> String {
+        line.segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn changed_words_are_marked_inside_changed_lines() {
+        let from = version("first\nthe quick fox\nlast\n", 1);
+        let to = version("first\nthe slow fox\nlast\n", 2);
+        let diff = diff_pages("r1", &from, CURRENT_REVISION, &to);
+
+        let kinds: Vec<DiffKind> = diff.hunks.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, [DiffKind::Equal, DiffKind::Delete, DiffKind::Insert, DiffKind::Equal]);
+
+        let deleted = &diff.hunks[1].lines[0];
+        assert_eq!((deleted.old_line, deleted.new_line), (Some(2), None));
+        assert_eq!(text(deleted), "the quick fox");
+        assert_eq!(words(deleted, true), ["quick"]);
+
+        let inserted = &diff.hunks[2].lines[0];
+        assert_eq!((inserted.old_line, inserted.new_line), (None, Some(2)));
+        assert_eq!(text(inserted), "the slow fox");
+        assert_eq!(words(inserted, true), ["slow"]);
+    }
+
+    #[test]
+    fn unchanged_lines_have_no_changed_words() {
+        let page = version("one\ntwo\n", 1);
+        let diff = diff_pages("r1", &page, "r2", &page);
+        assert_eq!(diff.hunks.len(), 1);
+        assert_eq!(diff.hunks[0].kind, DiffKind::Equal);
+        assert!(diff.hunks[0].lines.iter().all(|line| words(line, true).is_empty()));
+        assert!(diff.title.is_none() && diff.section.is_none());
+        assert!(diff.tags_added.is_empty() && diff.tags_removed.is_empty());
+    }
+
+    #[test]
+    fn title_tag_and_section_changes_are_reported() {
+        let from = WikiPage { tags: vec!["kept".into(), "old".into()], ..version("", 1) };
+        let to = WikiPage {
+            title: "Renamed".into(),
+            tags: vec!["kept".into(), "new".into()],
+            section: "Work".into(),
+            section_id: Some("work".into()),
+            ..version("", 2)
+        };
+        let diff = diff_pages("r1", &from, "r2", &to);
+
+        let title = diff.title.unwrap();
+        assert_eq!((title.old.as_str(), title.new.as_str()), ("page", "Renamed"));
+        let section = diff.section.unwrap();
+        assert_eq!((section.old.as_str(), section.new.as_str()), ("", "Work"));
+        assert_eq!(diff.tags_added, ["new"]);
+        assert_eq!(diff.tags_removed, ["old"]);
+        assert_eq!((diff.from.as_str(), diff.to.as_str()), ("r1", "r2"));
+    }
+
+    #[test]
+    fn each_line_is_blamed_on_the_version_that_introduced_it() {
+        let first = version("a\nb\n", 10);
+        let second = version("a\nB\nc\n", 20);
+        let current = version("a\nc\nd", 30);
+        let versions = [(Some("r1".to_string()), &first), (Some("r2".to_string()), &second), (None, &current)];
+
+        let blamed = blame(&versions);
+        let lines: Vec<(usize, &str, Option<&str>, i64)> =
+            blamed.iter().map(|l| (l.line, l.text.as_str(), l.revision_id.as_deref(), l.changed_at)).collect();
+        assert_eq!(lines, [(1, "a", Some("r1"), 10), (2, "c", Some("r2"), 20), (3, "d", None, 30)]);
+    }
+
+    #[test]
+    fn a_line_put_back_after_deletion_is_blamed_on_the_version_that_restored_it() {
+        let first = version("a\nb\nc\n", 10);
+        let second = version("a\nc\n", 20);
+        let third = version("a\nb\nc\n", 30);
+        let versions = [(Some("r1".to_string()), &first), (Some("r2".to_string()), &second), (Some("r3".to_string()), &third)];
+
+        let origins: Vec<Option<String>> = blame(&versions).into_iter().map(|l| l.revision_id).collect();
+        assert_eq!(origins, [Some("r1".into()), Some("r3".into()), Some("r1".into())]);
+    }
+
+    #[test]
+    fn blaming_a_single_version_attributes_every_line_to_it() {
+        let only = version("x\ny\n", 5);
+        let lines = blame(&[(None, &only)]);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.revision_id.is_none() && l.changed_at == 5));
+    }
+}
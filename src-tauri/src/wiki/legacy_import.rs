@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use sqlx::{SqliteConnection, SqlitePool};
+use log::{info, debug, warn};
+use crate::database::{self, DEFAULT_NOTEBOOK_ID, ROOT_SECTION_ID};
+use crate::error::{AppError, AppResult};
+use crate::models::{Section, WikiImportReport, WikiPage};
+
+// app_settings key recording when the legacy wiki/ directory was imported
+const IMPORTED_SETTING: &str = "wiki_legacy_imported_at";
+
+const SECTIONS_FILE: &str = "sections.json";
+
+/// Moves a wiki/ directory written by earlier versions (one JSON file per page,
+/// `sections.json` and `revisions/<page_id>/<timestamp>.json`) into the
+/// database. Everything is inserted and verified in one transaction; the
+/// directory is then renamed to `wiki.imported` and kept as a backup.
+/// Returns `None` when there is nothing to import.
+pub async fn import_legacy_wiki(pool: &SqlitePool, wiki_dir: &Path) -> AppResult<Option<WikiImportReport>> {
+    if !wiki_dir.is_dir() {
+        return Ok(None);
+    }
+    if database::get_setting(pool, IMPORTED_SETTING).await?.is_some() {
+        debug!("Legacy wiki directory already imported, ignoring {:?}", wiki_dir);
+        return Ok(None);
+    }
+
+    info!("Importing legacy wiki from {:?}", wiki_dir);
+
+    let mut report = WikiImportReport::default();
+    let sections = read_sections(wiki_dir)?;
+    let pages = read_pages(wiki_dir, &mut report)?;
+    let page_ids: HashSet<&str> = pages.iter().map(|p| p.id.as_str()).collect();
+    let revisions = read_revisions(wiki_dir, &page_ids, &mut report)?;
+    let now = chrono::Utc::now().timestamp();
+
+    let mut tx = pool.begin().await?;
+
+    // sections.json may list a child before its parent
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *tx).await?;
+
+    let mut known: HashSet<String> = sections.iter().map(|s| s.id.clone()).collect();
+    known.insert(ROOT_SECTION_ID.to_string());
+
+    for section in &sections {
+        let parent_id = match section.parent_id.as_deref() {
+            _ if section.id == ROOT_SECTION_ID => None,
+            Some(parent) if known.contains(parent) => Some(parent.to_string()),
+            _ => Some(ROOT_SECTION_ID.to_string()),
+        };
+        upsert_section(&mut tx, &Section { parent_id, ..section.clone() }).await?;
+    }
+    report.sections = sections.len();
+
+    for page in &pages {
+        // Pages could name sections that were never written to sections.json
+        if let Some(section_id) = &page.section_id {
+            if known.insert(section_id.clone()) {
+                database::insert_section(&mut tx, &Section {
+                    id: section_id.clone(),
+                    name: section_id.clone(),
+                    parent_id: Some(ROOT_SECTION_ID.to_string()),
+                    created_at: now,
+                    updated_at: now,
+                    sort_order: 0,
+                    notebook_id: None,
+                })
+                .await?;
+                report.sections += 1;
+            }
+        }
+        database::insert_wiki_page(&mut tx, page).await?;
+    }
+    report.pages = pages.len();
+
+    let (revisions_before,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM wiki_revisions")
+        .fetch_one(&mut *tx)
+        .await?;
+    for revision in &revisions {
+        database::insert_wiki_revision(&mut tx, revision).await?;
+    }
+    report.revisions = revisions.len();
+
+    verify(&mut tx, &pages, revisions_before + revisions.len() as i64).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO app_settings (key, value, updated_at)
+        VALUES (?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+        "#
+    )
+    .bind(IMPORTED_SETTING)
+    .bind(now.to_string())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    info!(
+        "Imported {} wiki pages, {} sections and {} revisions; skipped {} files",
+        report.pages,
+        report.sections,
+        report.revisions,
+        report.skipped.len()
+    );
+    for skipped in &report.skipped {
+        warn!("Skipped during wiki import: {}", skipped);
+    }
+
+    let backup = backup_dir(wiki_dir, now);
+    match fs::rename(wiki_dir, &backup) {
+        Ok(()) => info!("Legacy wiki directory kept as {:?}", backup),
+        Err(e) => warn!("Failed to rename legacy wiki directory to {:?}: {}", backup, e),
+    }
+
+    Ok(Some(report))
+}
+
+/// Lists page and revision files in the legacy backups (`wiki.imported*`) that
+/// could not be parsed and were therefore left out of the import.
+pub fn find_unreadable_legacy_files(app_dir: &Path) -> Vec<String> {
+    let mut report = WikiImportReport::default();
+
+    let Ok(entries) = fs::read_dir(app_dir) else {
+        return report.skipped;
+    };
+    let backups = entries.flatten().map(|entry| entry.path()).filter(|path| {
+        path.is_dir()
+            && path.file_name().and_then(|s| s.to_str()).is_some_and(|name| name.starts_with("wiki.imported"))
+    });
+
+    for backup in backups {
+        let revision_dirs = fs::read_dir(backup.join("revisions"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path());
+        let dirs = std::iter::once(backup.clone()).chain(revision_dirs);
+
+        for dir in dirs {
+            let Ok(files) = fs::read_dir(&dir) else { continue };
+            for path in files.flatten().map(|entry| entry.path()) {
+                let is_page = path.extension().and_then(|s| s.to_str()) == Some("json")
+                    && path.file_name().and_then(|s| s.to_str()) != Some(SECTIONS_FILE);
+                if is_page {
+                    read_page_file(&path, &mut report);
+                }
+            }
+        }
+    }
+
+    report.skipped
+}
+
+fn read_sections(wiki_dir: &Path) -> AppResult<Vec<Section>> {
+    let path = wiki_dir.join(SECTIONS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let data = fs::read_to_string(&path).map_err(|e| AppError::io("Failed to read sections file", e))?;
+    serde_json::from_str(&data)
+        .map_err(|e| AppError::validation(format!("Failed to parse {:?}: {}", path, e)))
+}
+
+fn read_pages(wiki_dir: &Path, report: &mut WikiImportReport) -> AppResult<Vec<WikiPage>> {
+    let mut pages: HashMap<String, WikiPage> = HashMap::new();
+
+    let entries = fs::read_dir(wiki_dir).map_err(|e| AppError::io("Failed to read wiki directory", e))?;
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let is_page = path.extension().and_then(|s| s.to_str()) == Some("json")
+            && path.file_name().and_then(|s| s.to_str()) != Some(SECTIONS_FILE);
+        if !is_page {
+            continue;
+        }
+
+        let Some(page) = read_page_file(&path, report) else {
+            continue;
+        };
+
+        // Keep the most recently edited copy if two files claim the same id
+        match pages.get(&page.id) {
+            Some(existing) if existing.updated_at >= page.updated_at => {
+                report.skipped.push(format!("{}: duplicate page id {}", path.display(), page.id));
+            }
+            _ => {
+                pages.insert(page.id.clone(), page);
+            }
+        }
+    }
+
+    let mut pages: Vec<WikiPage> = pages.into_values().collect();
+    pages.sort_by_key(|p| p.created_at);
+    Ok(pages)
+}
+
+fn read_revisions(
+    wiki_dir: &Path,
+    page_ids: &HashSet<&str>,
+    report: &mut WikiImportReport,
+) -> AppResult<Vec<WikiPage>> {
+    let revisions_dir = wiki_dir.join("revisions");
+    let mut revisions = Vec::new();
+
+    let Ok(page_dirs) = fs::read_dir(&revisions_dir) else {
+        return Ok(revisions);
+    };
+
+    for page_dir in page_dirs.flatten().map(|entry| entry.path()).filter(|p| p.is_dir()) {
+        let page_id = page_dir.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let entries = fs::read_dir(&page_dir).map_err(|e| AppError::io("Failed to read revisions directory", e))?;
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            if !page_ids.contains(page_id.as_str()) {
+                report.skipped.push(format!("{}: page {} no longer exists", path.display(), page_id));
+                continue;
+            }
+            if let Some(revision) = read_page_file(&path, report) {
+                revisions.push(WikiPage { id: page_id.clone(), ..revision });
+            }
+        }
+    }
+
+    // Oldest first, so revision ids follow the order the edits were made in
+    revisions.sort_by(|a, b| a.id.cmp(&b.id).then(a.updated_at.cmp(&b.updated_at)));
+    Ok(revisions)
+}
+
+fn read_page_file(path: &Path, report: &mut WikiImportReport) -> Option<WikiPage> {
+    let result = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str::<WikiPage>(&json).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(page) => Some(page),
+        Err(e) => {
+            report.skipped.push(format!("{}: {}", path.display(), e));
+            None
+        }
+    }
+}
+
+// The legacy section tree becomes part of the default notebook
+async fn upsert_section(conn: &mut SqliteConnection, section: &Section) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO wiki_sections (id, name, parent_id, created_at, updated_at, sort_order, notebook_id)
+        VALUES (?, ?, ?, ?, ?, (SELECT COALESCE(MAX(sort_order) + 1, 0) FROM wiki_sections WHERE parent_id IS ?), ?)
+        ON CONFLICT(id) DO UPDATE SET
+            name = excluded.name,
+            parent_id = excluded.parent_id,
+            created_at = excluded.created_at,
+            updated_at = excluded.updated_at,
+            notebook_id = excluded.notebook_id
+        "#
+    )
+    .bind(&section.id)
+    .bind(&section.name)
+    .bind(&section.parent_id)
+    .bind(section.created_at)
+    .bind(section.updated_at)
+    .bind(&section.parent_id)
+    .bind(DEFAULT_NOTEBOOK_ID)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+// Reads every imported page back and checks the full-text index before committing
+async fn verify(conn: &mut SqliteConnection, pages: &[WikiPage], expected_revisions: i64) -> AppResult<()> {
+    for page in pages {
+        let stored = database::find_wiki_page(&mut *conn, &page.id).await?;
+        if stored.as_ref() != Some(page) {
+            return Err(AppError::internal(format!(
+                "Wiki import verification failed: page {} does not match its file",
+                page.id
+            )));
+        }
+    }
+
+    let (revisions,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM wiki_revisions")
+        .fetch_one(&mut *conn)
+        .await?;
+    if revisions != expected_revisions {
+        return Err(AppError::internal(format!(
+            "Wiki import verification failed: expected {} revisions, found {}",
+            expected_revisions, revisions
+        )));
+    }
+
+    sqlx::query("INSERT INTO wiki_pages_fts (wiki_pages_fts, rank) VALUES ('integrity-check', 1)")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+fn backup_dir(wiki_dir: &Path, now: i64) -> PathBuf {
+    let backup = wiki_dir.with_file_name("wiki.imported");
+    if backup.exists() {
+        wiki_dir.with_file_name(format!("wiki.imported-{}", now))
+    } else {
+        backup
+    }
+}
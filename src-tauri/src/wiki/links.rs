@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use sqlx::{SqliteConnection, SqlitePool};
+use log::info;
+use crate::database;
+use crate::error::AppResult;
+use crate::models::{BrokenWikiLink, WikiBacklink, WikiGraphEdge, WikiGraphNode, WikiLinkGraph, WikiPage};
+
+// app_settings key recording when links of existing pages were first indexed
+const INDEXED_SETTING: &str = "wiki_links_indexed_at";
+
+/// A `[[target]]` or `[[target|alias]]` link in page content. The target is a
+/// page id or title; `target_range` is where it sits in the content and
+/// `range` is where the whole link, brackets included, does.
+#[derive(Debug)]
+pub struct ParsedLink {
+    pub target: String,
+    pub alias: Option<String>,
+    pub target_range: Range<usize>,
+    pub range: Range<usize>,
+}
+
+/// Finds the links in Markdown content, skipping code blocks and inline code.
+pub fn parse_links(content: &str) -> Vec<ParsedLink> {
+    let mut links = Vec::new();
+    let mut in_fence = false;
+    let mut line_start = 0;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            parse_line(line, line_start, &mut links);
+        }
+        line_start += line.len();
+    }
+
+    links
+}
+
+fn parse_line(line: &str, offset: usize, links: &mut Vec<ParsedLink>) {
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        if let Some(code) = rest.strip_prefix('`') {
+            // Inline code runs to the next backtick, if there is one
+            i += code.find('`').map_or(1, |end| end + 2);
+        } else if let Some(inner) = rest.strip_prefix("[[") {
+            let Some(end) = inner.find("]]") else { break };
+            let body = &inner[..end];
+            if body.contains(['[', ']', '\n']) {
+                i += 2;
+                continue;
+            }
+
+            let (target_part, alias) = match body.split_once('|') {
+                Some((target, alias)) => (target, Some(alias.trim()).filter(|a| !a.is_empty())),
+                None => (body, None),
+            };
+            let target = target_part.trim();
+            if !target.is_empty() {
+                let start = offset + i + 2 + (target_part.len() - target_part.trim_start().len());
+                links.push(ParsedLink {
+                    target: target.to_string(),
+                    alias: alias.map(str::to_string),
+                    target_range: start..start + target.len(),
+                    range: offset + i..offset + i + 2 + end + 2,
+                });
+            }
+            i += 2 + end + 2;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+}
+
+fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+/// Resolves link targets to page ids. An id matches first; otherwise titles
+/// match case-insensitively, the most recently updated page winning when
+/// several share a title.
+pub(super) struct Resolver {
+    ids: HashMap<String, String>,
+    titles: HashMap<String, (String, i64)>,
+}
+
+impl Resolver {
+    pub(super) fn new<'a>(pages: impl IntoIterator<Item = (&'a str, &'a str, i64)>) -> Self {
+        let mut resolver = Resolver { ids: HashMap::new(), titles: HashMap::new() };
+        for (id, title, updated_at) in pages {
+            resolver.ids.insert(id.to_string(), id.to_string());
+            let entry = resolver.titles.entry(title_key(title)).or_insert_with(|| (id.to_string(), updated_at));
+            if updated_at > entry.1 {
+                *entry = (id.to_string(), updated_at);
+            }
+        }
+        resolver
+    }
+
+    pub(super) fn resolve(&self, target: &str) -> Option<&str> {
+        self.ids
+            .get(target)
+            .or_else(|| self.titles.get(&title_key(target)).map(|(id, _)| id))
+            .map(String::as_str)
+    }
+
+    // Whether `target` reaches `page_id` through its title rather than its id
+    fn resolves_by_title(&self, target: &str, page_id: &str) -> bool {
+        !self.ids.contains_key(target) && self.resolve(target) == Some(page_id)
+    }
+}
+
+/// Pages with links to `page_id`, most recently updated first.
+pub async fn get_backlinks(pool: &SqlitePool, page_id: &str) -> AppResult<Vec<WikiBacklink>> {
+    let pages = database::list_wiki_pages(pool).await?;
+    let links = database::list_wiki_links(&mut *pool.acquire().await?).await?;
+    let resolver = Resolver::new(pages.iter().map(|p| (p.id.as_str(), p.title.as_str(), p.updated_at)));
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (source_id, target, _) in &links {
+        if resolver.resolve(target) == Some(page_id) {
+            *counts.entry(source_id.as_str()).or_default() += 1;
+        }
+    }
+
+    Ok(pages
+        .into_iter()
+        .filter_map(|page| counts.get(page.id.as_str()).copied().map(|count| WikiBacklink { page, count }))
+        .collect())
+}
+
+pub async fn find_broken_links(pool: &SqlitePool) -> AppResult<Vec<BrokenWikiLink>> {
+    let pages = database::list_wiki_pages(pool).await?;
+    let links = database::list_wiki_links(&mut *pool.acquire().await?).await?;
+    let resolver = Resolver::new(pages.iter().map(|p| (p.id.as_str(), p.title.as_str(), p.updated_at)));
+    let titles: HashMap<&str, &str> = pages.iter().map(|p| (p.id.as_str(), p.title.as_str())).collect();
+
+    Ok(links
+        .into_iter()
+        .filter(|(_, target, _)| resolver.resolve(target).is_none())
+        .map(|(source_id, target, alias)| BrokenWikiLink {
+            source_title: titles.get(source_id.as_str()).unwrap_or(&"").to_string(),
+            source_id,
+            target,
+            alias,
+        })
+        .collect())
+}
+
+/// Every page as a node and every resolved link as an edge, with repeated
+/// links between the same two pages counted on one edge.
+pub async fn get_link_graph(pool: &SqlitePool) -> AppResult<WikiLinkGraph> {
+    let pages = database::list_wiki_pages(pool).await?;
+    let links = database::list_wiki_links(&mut *pool.acquire().await?).await?;
+    let resolver = Resolver::new(pages.iter().map(|p| (p.id.as_str(), p.title.as_str(), p.updated_at)));
+
+    let mut edges: Vec<WikiGraphEdge> = Vec::new();
+    let mut edge_index: HashMap<(String, String), usize> = HashMap::new();
+    for (source_id, target, _) in links {
+        let Some(target_id) = resolver.resolve(&target) else { continue };
+        let key = (source_id, target_id.to_string());
+        match edge_index.get(&key) {
+            Some(&i) => edges[i].count += 1,
+            None => {
+                edge_index.insert(key.clone(), edges.len());
+                edges.push(WikiGraphEdge { source: key.0, target: key.1, count: 1 });
+            }
+        }
+    }
+
+    let nodes = pages
+        .into_iter()
+        .map(|page| WikiGraphNode { id: page.id, title: page.title, section_id: page.section_id })
+        .collect();
+
+    Ok(WikiLinkGraph { nodes, edges })
+}
+
+// Whether `title` can stand as the target of a link and still reach its page
+fn is_link_target(title: &str) -> bool {
+    !title.trim().is_empty() && !title.contains(['[', ']', '|', '\n'])
+}
+
+/// Points links that name `page_id` by `old_title` at `new_title` instead, in
+/// every other page, saving each changed page as a new version. Links by id
+/// are left as they are. A title that cannot be written inside `[[...]]`, or
+/// that is another page's id, is replaced by the page id. Returns the pages
+/// that were rewritten.
+pub async fn rewrite_inbound_links(
+    conn: &mut SqliteConnection,
+    page_id: &str,
+    old_title: &str,
+    new_title: &str,
+    now: i64,
+) -> AppResult<Vec<WikiPage>> {
+    let pages = database::list_wiki_page_titles(conn).await?;
+    let links = database::list_wiki_links(conn).await?;
+
+    // Resolve against the titles as they were before the rename
+    let resolver = Resolver::new(pages.iter().map(|(id, title, updated_at)| {
+        let title = if id == page_id { old_title } else { title.as_str() };
+        (id.as_str(), title, *updated_at)
+    }));
+
+    let target = if is_link_target(new_title) && resolver.ids.get(new_title).is_none_or(|id| id == page_id) {
+        new_title
+    } else {
+        page_id
+    };
+
+    let mut sources: Vec<&str> = links
+        .iter()
+        .filter(|(source_id, target, _)| source_id != page_id && resolver.resolves_by_title(target, page_id))
+        .map(|(source_id, _, _)| source_id.as_str())
+        .collect();
+    sources.sort_unstable();
+    sources.dedup();
+
+    let mut rewritten = Vec::new();
+    for source_id in sources {
+        let Some(mut source) = database::find_wiki_page(conn, source_id).await? else { continue };
+
+        let mut content = source.content.clone();
+        for link in parse_links(&source.content).iter().rev() {
+            if resolver.resolves_by_title(&link.target, page_id) {
+                content.replace_range(link.target_range.clone(), target);
+            }
+        }
+        if content == source.content {
+            continue;
+        }
+
+        source.content = content;
+        source.updated_at = now;
+        rewritten.push(database::save_wiki_page(conn, &source, None).await?);
+    }
+
+    info!("Rewrote links to wiki page {} in {} pages", page_id, rewritten.len());
+    Ok(rewritten)
+}
+
+/// Indexes the links of pages saved before links were indexed. Runs once.
+pub async fn index_existing_links(pool: &SqlitePool) -> AppResult<()> {
+    if database::get_setting(pool, INDEXED_SETTING).await?.is_some() {
+        return Ok(());
+    }
+
+    database::reindex_wiki_links(pool).await?;
+    database::set_setting(pool, INDEXED_SETTING, &chrono::Utc::now().timestamp().to_string()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(content: &str) -> Vec<(String, Option<String>)> {
+        parse_links(content).into_iter().map(|link| (link.target, link.alias)).collect()
+    }
+
+    fn link(target: &str, alias: Option<&str>) -> (String, Option<String>) {
+        (target.to_string(), alias.map(str::to_string))
+    }
+
+    #[test]
+    fn links_and_aliases_are_parsed() {
+        assert_eq!(
+            targets("See [[Home]] and [[ Project plan | the plan ]], or [[notes|]].\n"),
+            vec![link("Home", None), link("Project plan", Some("the plan")), link("notes", None)]
+        );
+    }
+
+    #[test]
+    fn ranges_point_into_the_content() {
+        let content = "é [[ Café | menu ]] end";
+        let links = parse_links(content);
+        assert_eq!(links.len(), 1);
+        assert_eq!(&content[links[0].target_range.clone()], "Café");
+        assert_eq!(&content[links[0].range.clone()], "[[ Café | menu ]]");
+    }
+
+    #[test]
+    fn code_is_skipped() {
+        let content = "[[Before]] `[[inline]]` [[After]]\n```\n[[fenced]]\n```\n~~~rust\n[[tilde]]\n~~~\n  ```\n[[indented fence]]\n  ```\n[[Last]]";
+        assert_eq!(targets(content), vec![link("Before", None), link("After", None), link("Last", None)]);
+    }
+
+    #[test]
+    fn malformed_links_are_ignored() {
+        assert_eq!(targets("[[]] [[ ]] [[a[b]] [[[Nested]]] [[unclosed\n]] text"), Vec::new());
+        assert_eq!(targets("lone ` then [[Page]]"), vec![link("Page", None)]);
+    }
+
+    fn page(id: &str, title: &str, content: &str) -> WikiPage {
+        WikiPage { title: title.into(), ..database::test_page(id, content) }
+    }
+
+    async fn rename(pool: &SqlitePool, id: &str, new_title: &str) -> Vec<WikiPage> {
+        let mut tx = pool.begin().await.unwrap();
+        let mut renamed = database::find_wiki_page(&mut tx, id).await.unwrap().unwrap();
+        let old_title = std::mem::replace(&mut renamed.title, new_title.to_string());
+        database::save_wiki_page(&mut tx, &renamed, None).await.unwrap();
+        let rewritten = rewrite_inbound_links(&mut tx, id, &old_title, new_title, 2).await.unwrap();
+        tx.commit().await.unwrap();
+        rewritten
+    }
+
+    async fn content(pool: &SqlitePool, id: &str) -> String {
+        database::get_wiki_page(pool, id).await.unwrap().content
+    }
+
+    #[tokio::test]
+    async fn renaming_rewrites_links_by_title() {
+        let pool = database::test_pool().await;
+        for page in [
+            page("plan", "Plan", "Links to [[home]]"),
+            page("notes", "Notes", "[[Plan]], [[ PLAN | the plan ]], [[plan]] by id, `[[Plan]]` and [[Other]]"),
+            page("other", "Other", "Nothing here"),
+        ] {
+            database::create_wiki_page(&pool, &page).await.unwrap();
+        }
+
+        let rewritten = rename(&pool, "plan", "Roadmap").await;
+        assert_eq!(rewritten.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["notes"]);
+        assert_eq!(
+            content(&pool, "notes").await,
+            "[[Roadmap]], [[ Roadmap | the plan ]], [[plan]] by id, `[[Plan]]` and [[Other]]"
+        );
+        assert_eq!(content(&pool, "other").await, "Nothing here");
+    }
+
+    #[tokio::test]
+    async fn titles_that_cannot_be_linked_fall_back_to_the_id() {
+        let pool = database::test_pool().await;
+        database::create_wiki_page(&pool, &page("plan", "Plan", "")).await.unwrap();
+        database::create_wiki_page(&pool, &page("notes", "Notes", "[[Plan]] and [[Plan|alias]]")).await.unwrap();
+        database::create_wiki_page(&pool, &page("other", "Other", "")).await.unwrap();
+
+        rename(&pool, "plan", "A | B").await;
+        assert_eq!(content(&pool, "notes").await, "[[plan]] and [[plan|alias]]");
+
+        // Back to a title, which by-id links keep ignoring
+        rename(&pool, "plan", "Plan").await;
+        assert_eq!(content(&pool, "notes").await, "[[plan]] and [[plan|alias]]");
+        database::update_wiki_page(&pool, &page("notes", "Notes", "[[Plan]] [[PLAN]]"), None).await.unwrap();
+
+        for title in ["[[Plan]]", "Plan]] [[Other", "other"] {
+            rename(&pool, "plan", title).await;
+            assert_eq!(content(&pool, "notes").await, "[[plan]] [[plan]]", "renamed to {}", title);
+            rename(&pool, "plan", "Plan").await;
+            database::update_wiki_page(&pool, &page("notes", "Notes", "[[Plan]] [[PLAN]]"), None).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,759 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, NaiveDate};
+use sqlx::{SqliteConnection, SqlitePool};
+use log::{info, warn};
+use crate::database;
+use crate::error::{AppError, AppResult};
+use crate::models::{MarkdownImportEntry, MarkdownImportReport, Section, WikiPage};
+use crate::attachments::{asset_url, blob_path, hash_file, page_attachment, put_blob, StoredFile};
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "avif"];
+
+/// Imports a folder of Markdown files, as written by Obsidian, Jekyll or
+/// [`export_wiki`](super::export_wiki), below `target_section_id`. Each
+/// subdirectory holding Markdown files becomes a section, reusing a section
+/// of the same name where there is one. YAML front matter gives the title
+/// (the file name otherwise), tags and dates. Images the pages embed are
+/// copied into `attachments_dir`, attached to the first page embedding them,
+/// and the references pointed at the copies. Files
+/// titled like a page already in their section are reported as conflicts and
+/// not imported. With `dry_run` nothing is written and the report says what
+/// an import would do.
+pub async fn import_markdown_directory(
+    pool: &SqlitePool,
+    dir: &Path,
+    target_section_id: &str,
+    attachments_dir: &Path,
+    dry_run: bool,
+    now: i64,
+) -> AppResult<MarkdownImportReport> {
+    let root = dir
+        .canonicalize()
+        .map_err(|e| AppError::io(format!("Failed to open {}", dir.display()), e))?;
+    if !root.is_dir() {
+        return Err(AppError::validation(format!("{} is not a folder", dir.display())));
+    }
+
+    let mut conn = pool.acquire().await?;
+    let target = database::find_section(&mut conn, target_section_id)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Section {} not found", target_section_id)))?;
+
+    let mut report = MarkdownImportReport { dry_run, ..Default::default() };
+    let mut scan = Scan::default();
+    scan_dir(&root, Path::new(""), &mut scan, &mut report);
+
+    let mut plan = Plan::new(&target);
+    let mut pages: Vec<WikiPage> = Vec::new();
+    let markdown = std::mem::take(&mut scan.markdown);
+    for path in &markdown {
+        let text = match fs::read_to_string(root.join(path)) {
+            Ok(text) => text,
+            Err(e) => {
+                report.skipped.push(skipped(path, format!("Could not be read: {}", e)));
+                continue;
+            }
+        };
+
+        let (front_matter, body) = split_front_matter(&text);
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let title = front_matter.text("title").filter(|t| !t.is_empty()).unwrap_or(stem);
+        let slot = plan.section(&mut conn, path.parent().unwrap_or(Path::new("")), now).await?;
+
+        let entry = MarkdownImportEntry {
+            path: path.display().to_string(),
+            title: title.clone(),
+            section: slot.label.clone(),
+            reason: None,
+        };
+        if let Some(reason) = plan.claim_title(&mut conn, &slot, &title).await? {
+            report.conflicts.push(MarkdownImportEntry { reason: Some(reason), ..entry });
+            continue;
+        }
+
+        let id = super::new_page_id();
+        let content = scan.embed_images(&root, path, &id, body, attachments_dir, &mut report);
+        let created_at = front_matter.timestamp("created").or_else(|| front_matter.timestamp("date")).unwrap_or(now);
+        let page = WikiPage {
+            id,
+            title,
+            content,
+            tags: front_matter.tags(),
+            notebook: String::new(),
+            section: slot.name.clone(),
+            section_id: Some(slot.id.clone()),
+            created_at,
+            updated_at: front_matter.timestamp("updated").unwrap_or(now),
+            version: 1,
+        };
+        pages.push(page);
+        report.pages.push(entry);
+    }
+    drop(conn);
+
+    for image in &scan.images {
+        if !scan.assets.contains_key(image) {
+            report.skipped.push(skipped(image, "Image not embedded in any page".to_string()));
+        }
+    }
+    report.images = scan.assets.len();
+    report.sections = plan.created.iter().map(|(_, label)| label.clone()).collect();
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    let saved = match copy_assets(&root, &scan.assets, attachments_dir) {
+        Ok(()) => save(pool, &plan.created, &pages, &scan.assets).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = saved {
+        let copies: Vec<PathBuf> = scan.assets.values().map(|(copy, _)| copy.path.clone()).collect();
+        database::release_files(pool, &copies).await;
+        return Err(e);
+    }
+
+    info!(
+        "Imported {} Markdown pages and {} images from {:?}, created {} sections; {} conflicts, {} skipped",
+        report.pages.len(),
+        report.images,
+        root,
+        report.sections.len(),
+        report.conflicts.len(),
+        report.skipped.len()
+    );
+    for missing in &report.missing_images {
+        warn!("Image not found during Markdown import: {}", missing);
+    }
+    Ok(report)
+}
+
+async fn save(
+    pool: &SqlitePool,
+    sections: &[(Section, String)],
+    pages: &[WikiPage],
+    assets: &HashMap<PathBuf, (StoredFile, String)>,
+) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+    for (section, _) in sections {
+        database::insert_section(&mut tx, section).await?;
+    }
+    for page in pages {
+        let notebook = database::section_notebook(&mut tx, page.section_id.as_deref()).await?;
+        database::insert_wiki_page(&mut tx, &WikiPage { notebook: notebook.name, ..page.clone() }).await?;
+    }
+    for (source, (copy, page_id)) in assets {
+        let file_name = source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        database::insert_evidence(&mut tx, &page_attachment(page_id, copy, &file_name, None)).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn copy_assets(root: &Path, assets: &HashMap<PathBuf, (StoredFile, String)>, attachments_dir: &Path) -> AppResult<()> {
+    if assets.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(attachments_dir).map_err(|e| AppError::io("Failed to create evidence dir", e))?;
+    for (source, (copy, _)) in assets {
+        let data = fs::read(root.join(source))
+            .map_err(|e| AppError::io(format!("Failed to copy image {}", source.display()), e))?;
+        put_blob(&copy.path, &copy.hash, &data)?;
+    }
+    Ok(())
+}
+
+fn skipped(path: &Path, reason: String) -> MarkdownImportEntry {
+    MarkdownImportEntry { path: path.display().to_string(), reason: Some(reason), ..Default::default() }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| extensions.contains(&e.as_str()))
+}
+
+// Files found in the imported folder, relative to it
+#[derive(Default)]
+struct Scan {
+    markdown: Vec<PathBuf>,
+    images: Vec<PathBuf>,
+    // Embedded images, where their copies go and the page they are attached to
+    assets: HashMap<PathBuf, (StoredFile, String)>,
+}
+
+// Hidden files and folders, such as .obsidian or .git, are left out
+fn scan_dir(root: &Path, dir: &Path, scan: &mut Scan, report: &mut MarkdownImportReport) {
+    let entries = match fs::read_dir(root.join(dir)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.skipped.push(skipped(dir, format!("Could not be read: {}", e)));
+            return;
+        }
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = dir.join(&name);
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            scan_dir(root, &path, scan, report);
+        } else if has_extension(&path, MARKDOWN_EXTENSIONS) {
+            scan.markdown.push(path);
+        } else if has_extension(&path, IMAGE_EXTENSIONS) {
+            scan.images.push(path);
+        } else {
+            report.skipped.push(skipped(&path, "Not a Markdown file".to_string()));
+        }
+    }
+}
+
+impl Scan {
+    // Points the images a page embeds, as ![alt](path) or ![[name]], at their
+    // copies in `attachments_dir`. References to other sites are left alone.
+    fn embed_images(
+        &mut self,
+        root: &Path,
+        page: &Path,
+        page_id: &str,
+        body: &str,
+        attachments_dir: &Path,
+        report: &mut MarkdownImportReport,
+    ) -> String {
+        let page_dir = page.parent().unwrap_or(Path::new(""));
+        let mut content = body.to_string();
+
+        for image in parse_images(body).into_iter().rev() {
+            let found = if image.wiki_style {
+                self.find_image(root, page_dir, &image.target)
+            } else {
+                resolve(root, page_dir, &image.target)
+            };
+            let Some(source) = found else {
+                report.missing_images.push(format!("{}: {}", page.display(), image.target));
+                continue;
+            };
+
+            // The copy is named by the image's content, so it is known before copying
+            if !self.assets.contains_key(&source) {
+                let Ok(hash) = hash_file(&root.join(&source)) else {
+                    report.missing_images.push(format!("{}: {}", page.display(), image.target));
+                    continue;
+                };
+                let extension = source.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+                let copy = StoredFile { path: blob_path(attachments_dir, &hash, &extension), hash };
+                self.assets.insert(source.clone(), (copy, page_id.to_string()));
+            }
+            let (copy, _) = &self.assets[&source];
+            content.replace_range(image.range, &format!("![{}]({})", image.alt, asset_url(&copy.path)));
+        }
+
+        content
+    }
+
+    // Obsidian resolves ![[name]] by file name anywhere in the vault
+    fn find_image(&self, root: &Path, page_dir: &Path, name: &str) -> Option<PathBuf> {
+        resolve(root, page_dir, name).or_else(|| {
+            let wanted = Path::new(name).file_name()?.to_string_lossy().to_lowercase();
+            self.images
+                .iter()
+                .find(|image| image.file_name().is_some_and(|n| n.to_string_lossy().to_lowercase() == wanted))
+                .cloned()
+        })
+    }
+}
+
+// An image file inside the imported folder named by `target`, relative to the
+// page, or to the folder when it starts with "/"
+fn resolve(root: &Path, page_dir: &Path, target: &str) -> Option<PathBuf> {
+    let base = if target.starts_with('/') { root.to_path_buf() } else { root.join(page_dir) };
+    let path = base.join(target.trim_start_matches('/')).canonicalize().ok()?;
+    if !path.is_file() || !has_extension(&path, IMAGE_EXTENSIONS) {
+        return None;
+    }
+    path.strip_prefix(root).ok().map(Path::to_path_buf)
+}
+
+struct ImageRef {
+    alt: String,
+    target: String,
+    wiki_style: bool,
+    range: Range<usize>,
+}
+
+fn parse_images(content: &str) -> Vec<ImageRef> {
+    let mut images = Vec::new();
+    let mut from = 0;
+
+    while let Some(found) = content[from..].find("![") {
+        let start = from + found;
+        let rest = &content[start + 2..];
+        from = start + 2;
+
+        if let Some(inner) = rest.strip_prefix('[') {
+            // ![[name.png]] or ![[name.png|300]]
+            let Some(end) = inner.find("]]") else { continue };
+            let body = &inner[..end];
+            if body.contains('\n') {
+                continue;
+            }
+            let target = body.split('|').next().unwrap_or_default().trim();
+            if has_extension(Path::new(target), IMAGE_EXTENSIONS) {
+                images.push(ImageRef {
+                    alt: String::new(),
+                    target: target.to_string(),
+                    wiki_style: true,
+                    range: start..start + 3 + end + 2,
+                });
+            }
+            continue;
+        }
+
+        // ![alt](path "title") or ![alt](<path with spaces>)
+        let Some(alt_end) = rest.find("](") else { continue };
+        let alt = &rest[..alt_end];
+        if alt.contains(['\n', '[', ']']) {
+            continue;
+        }
+        let link = &rest[alt_end + 2..];
+        let Some(link_end) = link.find(')') else { continue };
+        let raw = link[..link_end].trim();
+        let raw = match raw.strip_prefix('<') {
+            Some(bracketed) => bracketed.split('>').next().unwrap_or_default(),
+            None => raw.split_whitespace().next().unwrap_or_default(),
+        };
+        if raw.is_empty() || raw.contains("://") || raw.starts_with("data:") || raw.starts_with('#') {
+            continue;
+        }
+
+        let target = urlencoding::decode(raw).map(|t| t.into_owned()).unwrap_or_else(|_| raw.to_string());
+        images.push(ImageRef {
+            alt: alt.to_string(),
+            target,
+            wiki_style: false,
+            range: start..start + 2 + alt_end + 2 + link_end + 1,
+        });
+        from = start + 2 + alt_end + 2 + link_end + 1;
+    }
+
+    images
+}
+
+#[derive(Clone)]
+struct SectionSlot {
+    id: String,
+    name: String,
+    label: String,  // Path below the target section
+    created: bool,
+}
+
+// Sections the import puts pages in, and the page titles already taken in each
+struct Plan {
+    target: SectionSlot,
+    slots: HashMap<PathBuf, SectionSlot>,
+    created: Vec<(Section, String)>,
+    titles: HashMap<String, HashSet<String>>,
+    imported: HashSet<(String, String)>,
+}
+
+impl Plan {
+    fn new(target: &Section) -> Self {
+        Plan {
+            target: SectionSlot { id: target.id.clone(), name: target.name.clone(), label: String::new(), created: false },
+            slots: HashMap::new(),
+            created: Vec::new(),
+            titles: HashMap::new(),
+            imported: HashSet::new(),
+        }
+    }
+
+    // The section for a folder, found by name below the section of its parent
+    // folder, or planned as a new one
+    async fn section(&mut self, conn: &mut SqliteConnection, dir: &Path, now: i64) -> AppResult<SectionSlot> {
+        let mut slot = self.target.clone();
+        let mut prefix = PathBuf::new();
+
+        for component in dir.components() {
+            let name = component.as_os_str().to_string_lossy().to_string();
+            prefix.push(&name);
+            if let Some(known) = self.slots.get(&prefix) {
+                slot = known.clone();
+                continue;
+            }
+
+            let label = if slot.label.is_empty() { name.clone() } else { format!("{}/{}", slot.label, name) };
+            let existing = match slot.created {
+                true => None,
+                false => database::find_child_section(conn, &slot.id, &name).await?,
+            };
+            slot = match existing {
+                Some(section) => SectionSlot { id: section.id, name: section.name, label, created: false },
+                None => {
+                    let section = Section {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        name,
+                        parent_id: Some(slot.id.clone()),
+                        created_at: now,
+                        updated_at: now,
+                        sort_order: 0,
+                        notebook_id: None,
+                    };
+                    let slot = SectionSlot { id: section.id.clone(), name: section.name.clone(), label, created: true };
+                    self.created.push((section, slot.label.clone()));
+                    slot
+                }
+            };
+            self.slots.insert(prefix.clone(), slot.clone());
+        }
+
+        Ok(slot)
+    }
+
+    // Takes `title` in the section, or says why it cannot be. Titles compare
+    // case-insensitively, as links resolve them.
+    async fn claim_title(
+        &mut self,
+        conn: &mut SqliteConnection,
+        slot: &SectionSlot,
+        title: &str,
+    ) -> AppResult<Option<String>> {
+        if !self.titles.contains_key(&slot.id) {
+            let existing = match slot.created {
+                true => Vec::new(),
+                false => database::list_section_page_titles(conn, &slot.id).await?,
+            };
+            self.titles.insert(slot.id.clone(), existing.iter().map(|t| t.trim().to_lowercase()).collect());
+        }
+
+        let key = title.trim().to_lowercase();
+        if self.imported.contains(&(slot.id.clone(), key.clone())) {
+            return Ok(Some("Another file in this import has the same title".to_string()));
+        }
+        if self.titles.get(&slot.id).is_some_and(|titles| titles.contains(&key)) {
+            return Ok(Some("A page with this title already exists in the section".to_string()));
+        }
+        self.imported.insert((slot.id.clone(), key));
+        Ok(None)
+    }
+}
+
+enum FrontMatterValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+// As much YAML as front matter uses in practice: `key: value` scalars,
+// quoted or not, and lists in [a, b] or "- item" form
+#[derive(Default)]
+struct FrontMatter {
+    values: HashMap<String, FrontMatterValue>,
+}
+
+impl FrontMatter {
+    fn text(&self, key: &str) -> Option<String> {
+        match self.values.get(key)? {
+            FrontMatterValue::Text(text) => Some(text.trim().to_string()),
+            FrontMatterValue::List(_) => None,
+        }
+    }
+
+    // Tags as a list, or as one string separated by commas or, as Jekyll
+    // writes them, by spaces
+    fn tags(&self) -> Vec<String> {
+        let raw: Vec<String> = match self.values.get("tags").or_else(|| self.values.get("tag")) {
+            Some(FrontMatterValue::List(items)) => items.clone(),
+            Some(FrontMatterValue::Text(text)) if text.contains(',') => text.split(',').map(str::to_string).collect(),
+            Some(FrontMatterValue::Text(text)) => text.split_whitespace().map(str::to_string).collect(),
+            None => Vec::new(),
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in raw.iter().map(|t| t.trim().trim_start_matches('#').trim()) {
+            if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+        tags
+    }
+
+    // An RFC 3339 timestamp or a plain date, taken as midnight UTC
+    fn timestamp(&self, key: &str) -> Option<i64> {
+        let text = self.text(key)?;
+        DateTime::parse_from_rfc3339(&text)
+            .map(|t| t.timestamp())
+            .ok()
+            .or_else(|| {
+                let date = NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d").ok()?;
+                Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+            })
+    }
+}
+
+// Front matter runs from a "---" first line to the next "---" or "..." line.
+// Without a closing line the whole file is content.
+fn split_front_matter(text: &str) -> (FrontMatter, &str) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (FrontMatter::default(), text);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            let body = &rest[offset + line.len()..];
+            return (parse_front_matter(&rest[..offset]), body.strip_prefix('\n').unwrap_or(body));
+        }
+        offset += line.len();
+    }
+
+    (FrontMatter::default(), text)
+}
+
+fn parse_front_matter(yaml: &str) -> FrontMatter {
+    let mut front_matter = FrontMatter::default();
+    let mut list_key: Option<String> = None;
+
+    for line in yaml.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(item) = trimmed.strip_prefix("- ").or_else(|| (trimmed == "-").then_some("")) {
+            if let Some(FrontMatterValue::List(items)) = list_key.as_ref().and_then(|k| front_matter.values.get_mut(k)) {
+                items.push(unquote(item));
+            }
+            continue;
+        }
+
+        // Nested keys belong to a value this parser does not read
+        if line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else { continue };
+        let key = key.trim().to_string();
+        let value = value.trim();
+
+        let value = if value.is_empty() {
+            list_key = Some(key.clone());
+            FrontMatterValue::List(Vec::new())
+        } else if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            list_key = None;
+            FrontMatterValue::List(split_flow_list(items).iter().map(|item| unquote(item)).collect())
+        } else {
+            list_key = None;
+            FrontMatterValue::Text(unquote(value))
+        };
+        front_matter.values.insert(key, value);
+    }
+
+    front_matter
+}
+
+// Splits the items of a [a, "b, c"] list on the commas outside quotes
+fn split_flow_list(items: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for c in items.chars() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ',' => {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+
+    parts.into_iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect()
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        // Double-quoted YAML escapes are, for what front matter holds, JSON's
+        return serde_json::from_str(value).unwrap_or_else(|_| value[1..value.len() - 1].to_string());
+    }
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return value[1..value.len() - 1].replace("''", "'");
+    }
+    // A comment after a plain value
+    match value.find(" #") {
+        Some(comment) => value[..comment].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn titles(entries: &[MarkdownImportEntry]) -> Vec<(&str, &str)> {
+        entries.iter().map(|e| (e.path.as_str(), e.title.as_str())).collect()
+    }
+
+    #[test]
+    fn front_matter_is_split_from_the_body() {
+        let (front_matter, body) = split_front_matter("---\ntitle: Plan\n---\n\n# Plan\n");
+        assert_eq!(front_matter.text("title").as_deref(), Some("Plan"));
+        assert_eq!(body, "# Plan\n");
+    }
+
+    #[test]
+    fn front_matter_may_end_with_dots_and_use_crlf_and_a_bom() {
+        let (front_matter, body) = split_front_matter("\u{feff}---\r\ntitle: Plan\r\n...\r\nBody");
+        assert_eq!(front_matter.text("title").as_deref(), Some("Plan"));
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn without_a_closing_line_everything_is_content() {
+        let text = "---\ntitle: Plan\n# Plan\n";
+        let (front_matter, body) = split_front_matter(text);
+        assert!(front_matter.values.is_empty());
+        assert_eq!(body, text);
+
+        let (front_matter, body) = split_front_matter("# Plan\n---\n");
+        assert!(front_matter.values.is_empty());
+        assert_eq!(body, "# Plan\n---\n");
+    }
+
+    #[test]
+    fn inline_lists_split_on_commas_outside_quotes() {
+        let front_matter = parse_front_matter("tags: [rust, \"a, b\", 'it''s', #web, rust]");
+        assert_eq!(front_matter.tags(), ["rust", "a, b", "it's", "web"]);
+    }
+
+    #[test]
+    fn block_lists_collect_their_items() {
+        let front_matter = parse_front_matter("tags:\n  - one\n  - \"two\"\n- 'three'\n-\ntitle: After\n");
+        assert_eq!(front_matter.tags(), ["one", "two", "three"]);
+        assert_eq!(front_matter.text("title").as_deref(), Some("After"));
+    }
+
+    #[test]
+    fn tags_can_be_one_string() {
+        assert_eq!(parse_front_matter("tags: rust web").tags(), ["rust", "web"]);
+        assert_eq!(parse_front_matter("tag: project notes, ideas, #later").tags(), ["project notes", "ideas"]);
+    }
+
+    #[test]
+    fn quoted_values_are_unquoted_and_plain_ones_lose_comments() {
+        let front_matter = parse_front_matter(
+            "double: \"Say \\\"hi\\\" # here\"\nsingle: 'It''s: fine'\nplain: Plan # draft\n# comment: ignored\n",
+        );
+        assert_eq!(front_matter.text("double").as_deref(), Some("Say \"hi\" # here"));
+        assert_eq!(front_matter.text("single").as_deref(), Some("It's: fine"));
+        assert_eq!(front_matter.text("plain").as_deref(), Some("Plan"));
+        assert_eq!(front_matter.text("comment"), None);
+    }
+
+    #[test]
+    fn nested_values_are_not_read() {
+        let front_matter = parse_front_matter("author:\n  name: Someone\ntitle: Plan\n");
+        assert_eq!(front_matter.text("author"), None);
+        assert_eq!(front_matter.text("name"), None);
+        assert_eq!(front_matter.text("title").as_deref(), Some("Plan"));
+    }
+
+    #[test]
+    fn dates_and_timestamps_are_read() {
+        let front_matter = parse_front_matter("date: 2024-03-01\nupdated: \"2024-03-01T12:00:00+02:00\"\nbad: soon\n");
+        assert_eq!(front_matter.timestamp("date"), Some(1_709_251_200));
+        assert_eq!(front_matter.timestamp("updated"), Some(1_709_287_200));
+        assert_eq!(front_matter.timestamp("bad"), None);
+    }
+
+    #[test]
+    fn local_image_references_are_found() {
+        let content = "![alt](img/a.png \"Title\") and ![[b.png|300]] then ![s](<my image.png>) ![t](/assets/pic%20one.png)";
+        let images = parse_images(content);
+
+        let found: Vec<(&str, &str, bool)> =
+            images.iter().map(|i| (i.alt.as_str(), i.target.as_str(), i.wiki_style)).collect();
+        assert_eq!(
+            found,
+            [
+                ("alt", "img/a.png", false),
+                ("", "b.png", true),
+                ("s", "my image.png", false),
+                ("t", "/assets/pic one.png", false),
+            ]
+        );
+        assert_eq!(&content[images[0].range.clone()], "![alt](img/a.png \"Title\")");
+        assert_eq!(&content[images[1].range.clone()], "![[b.png|300]]");
+        assert_eq!(&content[images[2].range.clone()], "![s](<my image.png>)");
+    }
+
+    #[test]
+    fn remote_and_non_image_references_are_left_alone() {
+        let content = "![r](https://example.com/a.png) ![d](data:image/png;base64,AAAA) ![h](#top) ![[Note]] ![](  ) ![a\nb](c.png)";
+        assert!(parse_images(content).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_dry_run_reports_conflicts_without_writing() {
+        let pool = database::test_pool().await;
+        let existing = WikiPage { section_id: Some(database::ROOT_SECTION_ID.into()), ..database::test_page("existing", "") };
+        database::create_wiki_page(&pool, &WikiPage { title: "Existing".into(), ..existing }).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("vault");
+        write(&source, "a.md", "---\ntitle: Dup\ntags: [one]\n---\n![photo](photo.png) ![[missing.png]] ![r](https://example.com/r.png)\n");
+        write(&source, "b.md", "---\ntitle: dup\n---\nSame title as a.md\n");
+        write(&source, "existing.md", "Clashes with a stored page\n");
+        write(&source, "notes/c.md", "![again](../photo.png)\n");
+        write(&source, "notes/readme.txt", "");
+        write(&source, "photo.png", "png");
+        write(&source, "unused.png", "png");
+        write(&source, ".obsidian/app.md", "");
+        let attachments = dir.path().join("attachments");
+
+        let report = import_markdown_directory(&pool, &source, database::ROOT_SECTION_ID, &attachments, true, 100)
+            .await
+            .unwrap();
+
+        assert!(report.dry_run);
+        assert_eq!(titles(&report.pages), [("a.md", "Dup"), ("notes/c.md", "c")]);
+        assert_eq!(titles(&report.conflicts), [("b.md", "dup"), ("existing.md", "existing")]);
+        assert_eq!(
+            report.conflicts.iter().map(|e| e.reason.as_deref().unwrap()).collect::<Vec<_>>(),
+            ["Another file in this import has the same title", "A page with this title already exists in the section"]
+        );
+        assert_eq!(report.sections, ["notes"]);
+        assert_eq!(report.images, 1);
+        assert_eq!(report.missing_images, ["a.md: missing.png"]);
+        assert_eq!(
+            report.skipped.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(),
+            ["notes/readme.txt", "unused.png"]
+        );
+
+        let pages: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM wiki_pages").fetch_one(&pool).await.unwrap();
+        let sections: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM wiki_sections").fetch_one(&pool).await.unwrap();
+        assert_eq!((pages, sections), (1, 1));
+        assert!(!attachments.exists());
+    }
+}
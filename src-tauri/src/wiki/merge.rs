@@ -0,0 +1,256 @@
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+const OURS_MARKER: &str = "<<<<<<< Your changes";
+const SEPARATOR: &str = "=======";
+const THEIRS_MARKER: &str = ">>>>>>> Saved version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+// A run of base lines one side replaced
+struct Hunk<'a> {
+    side: Side,
+    start: usize,
+    end: usize,
+    lines: &'a [&'a str],
+}
+
+/// Line-based three-way merge of two edits of `base`. Changes to different
+/// parts of the text are combined; where both sides changed the same lines
+/// differently, both versions are kept between conflict markers. Returns the
+/// merged text and the number of conflicts.
+pub fn merge_text(base: &str, ours: &str, theirs: &str) -> (String, usize) {
+    if ours == theirs || theirs == base {
+        return (ours.to_string(), 0);
+    }
+    if ours == base {
+        return (theirs.to_string(), 0);
+    }
+
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let mut hunks = changes(Side::Ours, &base_lines, &our_lines);
+    hunks.extend(changes(Side::Theirs, &base_lines, &their_lines));
+    hunks.sort_by_key(|h| (h.start, h.end));
+
+    let mut merged: Vec<&str> = Vec::new();
+    let mut conflicts = 0;
+    let mut position = 0;
+    let mut index = 0;
+
+    while index < hunks.len() {
+        // Group hunks that touch or overlap, as they change the same region
+        let start = hunks[index].start;
+        let mut end = hunks[index].end;
+        let mut next = index + 1;
+        while next < hunks.len() && hunks[next].start <= end {
+            end = end.max(hunks[next].end);
+            next += 1;
+        }
+        let group = &hunks[index..next];
+        index = next;
+
+        merged.extend_from_slice(&base_lines[position..start]);
+        position = end;
+
+        let ours = apply(Side::Ours, group, &base_lines, start, end);
+        let theirs = apply(Side::Theirs, group, &base_lines, start, end);
+        let ours_changed = group.iter().any(|h| h.side == Side::Ours);
+        let theirs_changed = group.iter().any(|h| h.side == Side::Theirs);
+
+        if !theirs_changed || ours == theirs {
+            merged.extend(ours);
+        } else if !ours_changed {
+            merged.extend(theirs);
+        } else {
+            conflicts += 1;
+            push_marker(&mut merged, OURS_MARKER);
+            push_section(&mut merged, ours);
+            push_marker(&mut merged, SEPARATOR);
+            push_section(&mut merged, theirs);
+            push_marker(&mut merged, THEIRS_MARKER);
+        }
+    }
+    merged.extend_from_slice(&base_lines[position..]);
+
+    (merged.concat(), conflicts)
+}
+
+/// Three-way merge of a single value that cannot hold conflict markers: a
+/// conflicting change keeps `ours` and counts as a conflict.
+pub fn merge_value<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> (T, usize) {
+    if ours == base {
+        (theirs.clone(), 0)
+    } else if theirs == base || ours == theirs {
+        (ours.clone(), 0)
+    } else {
+        (ours.clone(), 1)
+    }
+}
+
+/// Tags added on either side are kept and tags removed on either side are dropped.
+pub fn merge_tags(base: &[String], ours: &[String], theirs: &[String]) -> Vec<String> {
+    let mut merged: Vec<String> = theirs.iter().filter(|t| !base.contains(t) || ours.contains(t)).cloned().collect();
+    for tag in ours {
+        if !base.contains(tag) && !merged.contains(tag) {
+            merged.push(tag.clone());
+        }
+    }
+    merged
+}
+
+fn changes<'a>(side: Side, base: &[&str], edited: &'a [&'a str]) -> Vec<Hunk<'a>> {
+    capture_diff_slices(Algorithm::Myers, base, edited)
+        .iter()
+        .map(|op| op.as_tag_tuple())
+        .filter(|(tag, _, _)| *tag != DiffTag::Equal)
+        .map(|(_, old, new)| Hunk { side, start: old.start, end: old.end, lines: &edited[new] })
+        .collect()
+}
+
+// The lines `side` turned base[start..end] into
+fn apply<'a>(side: Side, group: &[Hunk<'a>], base: &[&'a str], start: usize, end: usize) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    let mut position = start;
+    for hunk in group.iter().filter(|h| h.side == side) {
+        lines.extend_from_slice(&base[position..hunk.start]);
+        lines.extend_from_slice(hunk.lines);
+        position = hunk.end;
+    }
+    lines.extend_from_slice(&base[position..end]);
+    lines
+}
+
+fn push_marker(merged: &mut Vec<&str>, marker: &'static str) {
+    merged.push(marker);
+    merged.push("\n");
+}
+
+// Conflict markers have to start on a line of their own
+fn push_section<'a>(merged: &mut Vec<&'a str>, lines: Vec<&'a str>) {
+    let needs_newline = lines.last().is_some_and(|line| !line.ends_with('\n'));
+    merged.extend(lines);
+    if needs_newline {
+        merged.push("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(ours: &str, theirs: &str) -> String {
+        format!("{}\n{}{}\n{}{}\n", OURS_MARKER, ours, SEPARATOR, theirs, THEIRS_MARKER)
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn edits_to_different_lines_are_combined() {
+        let base = "a\nb\nc\nd\n";
+        let merged = merge_text(base, "A\nb\nc\nd\n", "a\nb\nc\nD\n");
+        assert_eq!(merged, ("A\nb\nc\nD\n".to_string(), 0));
+    }
+
+    #[test]
+    fn an_unchanged_side_takes_the_other() {
+        let base = "a\nb\n";
+        assert_eq!(merge_text(base, base, "a\nB\n"), ("a\nB\n".to_string(), 0));
+        assert_eq!(merge_text(base, "A\nb\n", base), ("A\nb\n".to_string(), 0));
+    }
+
+    #[test]
+    fn the_same_edit_on_both_sides_is_not_a_conflict() {
+        let base = "a\nb\nc\n";
+        assert_eq!(merge_text(base, "a\nB\nc\nd\n", "a\nB\nc\n"), ("a\nB\nc\nd\n".to_string(), 0));
+    }
+
+    #[test]
+    fn conflicting_edits_keep_both_between_markers() {
+        let (merged, conflicts) = merge_text("a\nb\nc\n", "a\nmine\nc\n", "a\nsaved\nc\n");
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, format!("a\n{}c\n", conflict("mine\n", "saved\n")));
+    }
+
+    #[test]
+    fn each_conflicting_region_is_counted() {
+        let base = "a\nb\nc\nd\ne\n";
+        let (merged, conflicts) = merge_text(base, "1\nb\nc\nd\n2\n", "x\nb\nc\nd\ny\n");
+        assert_eq!(conflicts, 2);
+        assert_eq!(merged, format!("{}b\nc\nd\n{}", conflict("1\n", "x\n"), conflict("2\n", "y\n")));
+    }
+
+    #[test]
+    fn insertions_at_the_same_point_conflict() {
+        let (merged, conflicts) = merge_text("a\nc\n", "a\nmine\nc\n", "a\nsaved\nc\n");
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, format!("a\n{}c\n", conflict("mine\n", "saved\n")));
+    }
+
+    #[test]
+    fn the_same_insertion_on_both_sides_is_kept_once() {
+        let base = "a\nc\n";
+        let edited = "a\nb\nc\n";
+        assert_eq!(merge_text(base, edited, "a\nb\nc\nd\n"), ("a\nb\nc\nd\n".to_string(), 0));
+    }
+
+    #[test]
+    fn deleting_lines_the_other_side_edited_conflicts() {
+        let (merged, conflicts) = merge_text("a\nb\nc\n", "a\nc\n", "a\nB\nc\n");
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, format!("a\n{}c\n", conflict("", "B\n")));
+    }
+
+    #[test]
+    fn a_deletion_away_from_the_other_edit_merges() {
+        let merged = merge_text("a\nb\nc\nd\n", "a\nb\nc\n", "A\nb\nc\nd\n");
+        assert_eq!(merged, ("A\nb\nc\n".to_string(), 0));
+    }
+
+    #[test]
+    fn text_without_a_trailing_newline_merges() {
+        let merged = merge_text("a\nb\nc", "A\nb\nc", "a\nb\nC");
+        assert_eq!(merged, ("A\nb\nC".to_string(), 0));
+    }
+
+    #[test]
+    fn a_conflict_on_an_unterminated_last_line_puts_markers_on_their_own_lines() {
+        let (merged, conflicts) = merge_text("a\nb", "a\nmine", "a\nsaved");
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, format!("a\n{}", conflict("mine\n", "saved\n")));
+    }
+
+    #[test]
+    fn tags_added_or_removed_on_either_side_are_kept_or_dropped() {
+        let base = tags(&["a", "b"]);
+        let ours = tags(&["b", "mine"]);
+        let theirs = tags(&["a", "saved"]);
+        assert_eq!(merge_tags(&base, &ours, &theirs), tags(&["saved", "mine"]));
+    }
+
+    #[test]
+    fn a_tag_added_on_both_sides_appears_once() {
+        let base = tags(&["a"]);
+        let both = tags(&["a", "new"]);
+        assert_eq!(merge_tags(&base, &both, &both), both);
+    }
+
+    #[test]
+    fn values_take_whichever_side_changed() {
+        assert_eq!(merge_value(&"base", &"base", &"saved"), ("saved", 0));
+        assert_eq!(merge_value(&"base", &"mine", &"base"), ("mine", 0));
+        assert_eq!(merge_value(&"base", &"same", &"same"), ("same", 0));
+    }
+
+    #[test]
+    fn conflicting_values_keep_ours() {
+        assert_eq!(merge_value(&"base", &"mine", &"saved"), ("mine", 1));
+    }
+}
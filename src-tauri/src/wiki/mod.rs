@@ -0,0 +1,36 @@
+mod attachments;
+mod delta;
+mod export;
+mod history;
+mod legacy_import;
+mod links;
+mod markdown_import;
+mod merge;
+mod notebooks;
+mod retention;
+mod search;
+mod templates;
+mod trash;
+
+pub use attachments::{add_page_attachment, collect_attachment_garbage, with_url as attachment_with_url};
+pub use delta::apply as apply_delta;
+pub use export::export_wiki;
+pub use history::{blame, diff_pages, CURRENT_REVISION};
+pub use legacy_import::{find_unreadable_legacy_files, import_legacy_wiki};
+pub use links::{
+    find_broken_links, get_backlinks, get_link_graph, index_existing_links, parse_links, rewrite_inbound_links,
+};
+pub use markdown_import::import_markdown_directory;
+pub use merge::{merge_tags, merge_text, merge_value};
+pub use notebooks::assign_notebooks;
+pub use retention::{compact_history, load_retention, validate_retention, RETENTION_SETTING};
+pub use search::{SearchField, SearchQuery};
+pub use templates::{instantiate as instantiate_template, template_variables};
+pub use trash::{load_trash_days, purge_expired_trash, purge_expired_trash_periodically, TRASH_DAYS_SETTING};
+
+/// Ids of new pages are time-ordered UUIDs. Pages created by earlier versions
+/// keep their Unix-timestamp ids; ids are opaque strings everywhere else, so
+/// both forms keep working side by side.
+pub fn new_page_id() -> String {
+    uuid::Uuid::now_v7().to_string()
+}
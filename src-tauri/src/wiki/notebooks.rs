@@ -0,0 +1,75 @@
+use sqlx::{SqliteConnection, SqlitePool};
+use log::info;
+use crate::database::{self, DEFAULT_NOTEBOOK_ID};
+use crate::error::{AppError, AppResult};
+use crate::models::{Notebook, Section};
+
+/// Moves every page into the notebook its `notebook` field names, creating
+/// notebooks that do not exist yet. The page keeps its place in the section
+/// tree: the sections on its path are found, or created, by name under the
+/// root of the new notebook. Pages already in the right notebook are left
+/// alone, so after the first run this only finds pages written by a legacy
+/// import. Returns how many pages were moved.
+pub async fn assign_notebooks(pool: &SqlitePool, now: i64) -> AppResult<usize> {
+    let mut tx = pool.begin().await?;
+    let pages = database::list_misplaced_wiki_pages(&mut tx).await?;
+
+    for (page_id, name, section_id) in &pages {
+        let notebook = notebook_named(&mut tx, name, now).await?;
+        let path = match section_id {
+            Some(section_id) => database::section_path(&mut tx, section_id).await?,
+            None => Vec::new(),
+        };
+
+        let mut section = database::find_section(&mut tx, &notebook.root_section_id)
+            .await?
+            .ok_or_else(|| AppError::internal(format!("Root section of notebook {} is missing", notebook.id)))?;
+        // The first section on the path is the root of the old notebook
+        for ancestor in path.iter().skip(1) {
+            section = child_section(&mut tx, &section, &ancestor.name, now).await?;
+        }
+
+        database::place_wiki_page(&mut tx, page_id, &section, &notebook).await?;
+    }
+
+    tx.commit().await?;
+    if !pages.is_empty() {
+        info!("Moved {} wiki pages into their notebooks", pages.len());
+    }
+    Ok(pages.len())
+}
+
+async fn notebook_named(conn: &mut SqliteConnection, name: &str, now: i64) -> AppResult<Notebook> {
+    if name.trim().is_empty() {
+        return database::find_notebook(conn, DEFAULT_NOTEBOOK_ID)
+            .await?
+            .ok_or_else(|| AppError::internal("The default notebook is missing"));
+    }
+
+    match database::find_notebook_by_name(conn, name).await? {
+        Some(notebook) => Ok(notebook),
+        None => database::insert_notebook(conn, &uuid::Uuid::new_v4().to_string(), name, now).await,
+    }
+}
+
+async fn child_section(conn: &mut SqliteConnection, parent: &Section, name: &str, now: i64) -> AppResult<Section> {
+    if let Some(section) = database::find_child_section(conn, &parent.id, name).await? {
+        return Ok(section);
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    database::insert_section(conn, &Section {
+        id: id.clone(),
+        name: name.to_string(),
+        parent_id: Some(parent.id.clone()),
+        created_at: now,
+        updated_at: now,
+        sort_order: 0,
+        notebook_id: None,
+    })
+    .await?;
+
+    database::find_section(conn, &id)
+        .await?
+        .ok_or_else(|| AppError::internal(format!("Section {} was not saved", id)))
+}
@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+use sqlx::SqlitePool;
+use log::{info, debug, warn};
+use crate::database;
+use crate::error::{AppError, AppResult};
+use crate::models::{RevisionRetention, WikiCompactionReport};
+use super::delta;
+
+// app_settings key holding the RevisionRetention as JSON
+pub const RETENTION_SETTING: &str = "wiki_revision_retention";
+
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+
+pub async fn load_retention(pool: &SqlitePool) -> RevisionRetention {
+    match database::get_setting(pool, RETENTION_SETTING).await {
+        Ok(Some(value)) => serde_json::from_str(&value).unwrap_or_else(|e| {
+            warn!("Invalid wiki revision retention {}: {}", value, e);
+            RevisionRetention::default()
+        }),
+        Ok(None) => RevisionRetention::default(),
+        Err(e) => {
+            warn!("Failed to read wiki revision retention: {}", e);
+            RevisionRetention::default()
+        }
+    }
+}
+
+pub fn validate_retention(policy: &RevisionRetention) -> AppResult<()> {
+    let periods = [
+        ("keep_all_hours", policy.keep_all_hours),
+        ("hourly_days", policy.hourly_days),
+        ("daily_days", policy.daily_days),
+        ("weekly_days", policy.weekly_days),
+        ("max_age_days", policy.max_age_days.unwrap_or_default()),
+        ("compress_after_days", policy.compress_after_days),
+    ];
+    match periods.iter().find(|(_, value)| *value < 0) {
+        Some((name, value)) => Err(AppError::validation(format!("{} cannot be negative (got {})", name, value))),
+        None => Ok(()),
+    }
+}
+
+/// Ids of the revisions the policy keeps, given (id, created_at) pairs. Within
+/// each hour, day, week or month the newest revision is the one kept.
+pub fn select_kept(policy: &RevisionRetention, now: i64, revisions: &[(i64, i64)]) -> HashSet<i64> {
+    let mut newest_first = revisions.to_vec();
+    newest_first.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+
+    let mut buckets = HashSet::new();
+    newest_first
+        .into_iter()
+        .filter(|&(id, created_at)| {
+            let age = now - created_at;
+            let bucket = if age < policy.keep_all_hours * HOUR {
+                ('r', id)
+            } else if age < policy.hourly_days * DAY {
+                ('h', created_at.div_euclid(HOUR))
+            } else if age < policy.daily_days * DAY {
+                ('d', created_at.div_euclid(DAY))
+            } else if age < policy.weekly_days * DAY {
+                ('w', created_at.div_euclid(7 * DAY))
+            } else if policy.max_age_days.is_some_and(|max| age >= max * DAY) {
+                return false;
+            } else {
+                ('m', created_at.div_euclid(30 * DAY))
+            };
+            buckets.insert(bucket)
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Applies the retention policy to every page's history: drops revisions the
+/// policy no longer keeps and stores kept revisions older than
+/// `compress_after_days` as deltas against the next newer kept revision. The
+/// newest revision of a page always keeps its full content. Revisions whose
+/// page no longer exists are removed as well.
+pub async fn compact_history(
+    pool: &SqlitePool,
+    policy: &RevisionRetention,
+    now: i64,
+) -> AppResult<WikiCompactionReport> {
+    let mut report = WikiCompactionReport {
+        orphans_removed: database::delete_orphaned_wiki_revisions(pool).await? as usize,
+        ..Default::default()
+    };
+
+    for page_id in database::list_revised_wiki_pages(pool).await? {
+        let mut tx = pool.begin().await?;
+        let revisions = database::load_wiki_revision_contents(&mut tx, &page_id).await?;
+
+        let times: Vec<(i64, i64)> = revisions.iter().map(|r| (r.0, r.1)).collect();
+        let kept_ids = select_kept(policy, now, &times);
+        let (kept, pruned): (Vec<_>, Vec<_>) = revisions.iter().partition(|r| kept_ids.contains(&r.0));
+
+        // Re-encode first so no kept revision is based on one about to be deleted
+        for (i, &(id, created_at, content, base_id)) in kept.iter().enumerate() {
+            let base = kept.get(i + 1).filter(|_| now - created_at >= policy.compress_after_days * DAY);
+            let delta = base
+                .map(|&(base, _, base_content, _)| (*base, delta::encode(base_content, content)))
+                .filter(|(_, delta)| delta.len() < content.len());
+
+            match delta {
+                Some((new_base, _)) if *base_id == Some(new_base) => {}
+                Some((new_base, delta)) => {
+                    database::store_wiki_revision_delta(&mut tx, *id, new_base, &delta).await?;
+                    if base_id.is_none() {
+                        report.compressed += 1;
+                    }
+                }
+                None if base_id.is_some() => database::store_wiki_revision_content(&mut tx, *id, content).await?,
+                None => {}
+            }
+        }
+
+        let pruned_ids: Vec<i64> = pruned.iter().map(|r| r.0).collect();
+        report.pruned += database::delete_wiki_revisions(&mut tx, &pruned_ids).await? as usize;
+        tx.commit().await?;
+
+        debug!("Wiki page {}: kept {} revisions, pruned {}", page_id, kept.len(), pruned_ids.len());
+    }
+
+    info!(
+        "Wiki history compacted: {} revisions pruned, {} compressed, {} orphans removed",
+        report.pruned, report.compressed, report.orphans_removed
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WikiPage;
+
+    const NOW: i64 = 1_705_536_000;  // A multiple of 30 and of 7 days, so buckets line up with it
+
+    fn kept(policy: &RevisionRetention, revisions: &[(i64, i64)]) -> Vec<i64> {
+        let mut ids: Vec<i64> = select_kept(policy, NOW, revisions).into_iter().collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn recent_revisions_are_all_kept() {
+        let revisions: Vec<(i64, i64)> = (1..=5).map(|id| (id, NOW - id * 60)).collect();
+        assert_eq!(kept(&RevisionRetention::default(), &revisions), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn newest_revision_of_each_bucket_is_kept() {
+        let policy = RevisionRetention::default();
+        let hour_ago = NOW - 2 * DAY;
+        let day_ago = NOW - 10 * DAY;
+        let week_ago = NOW - 98 * DAY;
+        let month_ago = NOW - 390 * DAY;
+        let revisions = [
+            (1, hour_ago + 10),
+            (2, hour_ago + 20),
+            (3, hour_ago + HOUR),
+            (4, day_ago + HOUR),
+            (5, day_ago + 2 * HOUR),
+            (6, week_ago + DAY),
+            (7, week_ago + 2 * DAY),
+            (8, month_ago + DAY),
+            (9, month_ago + 10 * DAY),
+        ];
+        assert_eq!(kept(&policy, &revisions), vec![2, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    fn revisions_saved_together_keep_the_highest_id() {
+        let policy = RevisionRetention::default();
+        let created_at = NOW - 3 * DAY;
+        assert_eq!(kept(&policy, &[(4, created_at), (7, created_at), (5, created_at)]), vec![7]);
+    }
+
+    #[test]
+    fn revisions_past_the_maximum_age_are_dropped() {
+        let policy = RevisionRetention { max_age_days: Some(400), ..Default::default() };
+        let revisions = [(1, NOW - 500 * DAY), (2, NOW - 399 * DAY), (3, NOW - HOUR)];
+        assert_eq!(kept(&policy, &revisions), vec![2, 3]);
+        assert_eq!(kept(&RevisionRetention::default(), &revisions), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn negative_periods_are_rejected() {
+        assert!(validate_retention(&RevisionRetention::default()).is_ok());
+        let policy = RevisionRetention { daily_days: -1, ..Default::default() };
+        assert!(matches!(validate_retention(&policy), Err(AppError::Validation(_))));
+        let policy = RevisionRetention { max_age_days: Some(-30), ..Default::default() };
+        assert!(matches!(validate_retention(&policy), Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn compacted_history_reads_back_unchanged() {
+        let pool = database::test_pool().await;
+        let mut page = WikiPage { created_at: NOW - 20 * DAY, updated_at: NOW - 20 * DAY, ..database::test_page("page", "") };
+        database::create_wiki_page(&pool, &page).await.unwrap();
+
+        let mut contents = Vec::new();
+        for day in 0..12 {
+            let body: String = (0..40).map(|line| format!("line {} of día {} 🙂\n", line, day.min(line))).collect();
+            page.content = format!("# Day {}\n{}", day, body);
+            page.updated_at = NOW - (19 - day) * DAY;
+            contents.push(page.content.clone());
+            page = database::update_wiki_page(&pool, &page, None).await.unwrap();
+        }
+
+        let policy = RevisionRetention { compress_after_days: 0, ..Default::default() };
+        let report = compact_history(&pool, &policy, NOW).await.unwrap();
+        assert_eq!(report.pruned, 0);
+        assert!(report.compressed > 0);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let revisions = database::load_wiki_revision_contents(&mut conn, "page").await.unwrap();
+        assert!(revisions.iter().any(|r| r.3.is_some()));
+        assert_eq!(revisions.last().unwrap().3, None);
+
+        // Revision n holds the page as it was before save n
+        let stored: Vec<String> = revisions.into_iter().map(|r| r.2).collect();
+        drop(conn);
+        let mut expected = vec![String::new()];
+        expected.extend(contents[..11].iter().cloned());
+        assert_eq!(stored, expected);
+
+        // A second pass leaves the chain as it is
+        let report = compact_history(&pool, &policy, NOW).await.unwrap();
+        assert_eq!((report.pruned, report.compressed), (0, 0));
+    }
+}
@@ -0,0 +1,212 @@
+// Search box syntax: bare words match as prefixes, "quoted text" as a phrase,
+// tag:/section:/notebook: restrict which pages are searched (values with
+// spaces can be quoted, as in section:"Project notes") and a leading `-`
+// excludes pages matching any of them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchField {
+    Tag,
+    Section,
+    Notebook,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchTerm {
+    Word(String),
+    Phrase(String),
+    Field(SearchField, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchClause {
+    pub term: SearchTerm,
+    pub negated: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub clauses: Vec<SearchClause>,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut clauses = Vec::new();
+        let mut chars = input.chars().peekable();
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            // A lone "-" is an ordinary (and unsearchable) word, not a negation
+            let mut negated = false;
+            if chars.peek() == Some(&'-') {
+                chars.next();
+                match chars.peek() {
+                    Some(c) if !c.is_whitespace() => negated = true,
+                    _ => continue,
+                }
+            }
+
+            let quoted = chars.peek() == Some(&'"');
+            let mut raw = String::new();
+            let mut in_quotes = false;
+            while let Some(c) = chars.next_if(|c| in_quotes || !c.is_whitespace()) {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                } else {
+                    raw.push(c);
+                }
+            }
+
+            if let Some(term) = parse_term(raw, quoted) {
+                clauses.push(SearchClause { term, negated });
+            }
+        }
+
+        SearchQuery { clauses }
+    }
+
+    /// FTS5 expression every result has to match, or `None` if the query only
+    /// filters or excludes
+    pub fn match_expression(&self) -> Option<String> {
+        self.text_expression(false, " ")
+    }
+
+    /// FTS5 expression for the negated words and phrases; pages matching it are dropped
+    pub fn exclude_expression(&self) -> Option<String> {
+        self.text_expression(true, " OR ")
+    }
+
+    pub fn filters(&self) -> impl Iterator<Item = (SearchField, &str, bool)> {
+        self.clauses.iter().filter_map(|clause| match &clause.term {
+            SearchTerm::Field(field, value) => Some((*field, value.as_str(), clause.negated)),
+            _ => None,
+        })
+    }
+
+    fn text_expression(&self, negated: bool, separator: &str) -> Option<String> {
+        let terms: Vec<String> = self
+            .clauses
+            .iter()
+            .filter(|clause| clause.negated == negated)
+            .filter_map(|clause| match &clause.term {
+                SearchTerm::Word(word) => Some(format!("{}*", fts_string(word))),
+                SearchTerm::Phrase(phrase) => Some(fts_string(phrase)),
+                SearchTerm::Field(..) => None,
+            })
+            .collect();
+        (!terms.is_empty()).then(|| terms.join(separator))
+    }
+}
+
+fn parse_term(raw: String, quoted: bool) -> Option<SearchTerm> {
+    if !quoted {
+        if let Some((name, value)) = raw.split_once(':') {
+            let field = match name.to_lowercase().as_str() {
+                "tag" => Some(SearchField::Tag),
+                "section" => Some(SearchField::Section),
+                "notebook" => Some(SearchField::Notebook),
+                _ => None,
+            };
+            if let Some(field) = field {
+                let value = value.trim();
+                return (!value.is_empty()).then(|| SearchTerm::Field(field, value.to_string()));
+            }
+        }
+    }
+
+    // The FTS tokenizer drops punctuation, so a term without any letters or
+    // digits could never match
+    if !raw.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+
+    Some(if quoted { SearchTerm::Phrase(raw) } else { SearchTerm::Word(raw) })
+}
+
+fn fts_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clause(term: SearchTerm, negated: bool) -> SearchClause {
+        SearchClause { term, negated }
+    }
+
+    fn word(text: &str) -> SearchTerm {
+        SearchTerm::Word(text.into())
+    }
+
+    #[test]
+    fn words_phrases_and_negations_are_split_apart() {
+        let query = SearchQuery::parse("  rust \"exact phrase\" -draft  -\"old notes\" ");
+        assert_eq!(
+            query.clauses,
+            [
+                clause(word("rust"), false),
+                clause(SearchTerm::Phrase("exact phrase".into()), false),
+                clause(word("draft"), true),
+                clause(SearchTerm::Phrase("old notes".into()), true),
+            ]
+        );
+        assert_eq!(query.match_expression().as_deref(), Some("\"rust\"* \"exact phrase\""));
+        assert_eq!(query.exclude_expression().as_deref(), Some("\"draft\"* OR \"old notes\""));
+    }
+
+    #[test]
+    fn field_filters_take_quoted_values_and_negation() {
+        let query = SearchQuery::parse("Tag:work -section:\"Project notes\" notebook:Home");
+        let filters: Vec<_> = query.filters().collect();
+        assert_eq!(
+            filters,
+            [
+                (SearchField::Tag, "work", false),
+                (SearchField::Section, "Project notes", true),
+                (SearchField::Notebook, "Home", false),
+            ]
+        );
+        assert_eq!(query.match_expression(), None);
+        assert_eq!(query.exclude_expression(), None);
+    }
+
+    #[test]
+    fn unknown_fields_and_empty_values_are_not_filters() {
+        let query = SearchQuery::parse("title:plan tag: http://example.com");
+        assert_eq!(query.filters().count(), 0);
+        assert_eq!(
+            query.clauses,
+            [clause(word("title:plan"), false), clause(word("http://example.com"), false)]
+        );
+    }
+
+    #[test]
+    fn a_lone_dash_and_bare_punctuation_are_dropped() {
+        let query = SearchQuery::parse("- -- ... \"\" plan");
+        assert_eq!(query.clauses, [clause(word("plan"), false)]);
+    }
+
+    #[test]
+    fn fts5_syntax_in_terms_is_quoted() {
+        let query = SearchQuery::parse("NEAR(a OR title:x* ^start");
+        assert_eq!(
+            query.match_expression().as_deref(),
+            Some("\"NEAR(a\"* \"OR\"* \"title:x*\"* \"^start\"*")
+        );
+    }
+
+    #[test]
+    fn double_quotes_inside_a_term_are_escaped() {
+        assert_eq!(fts_string("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn an_unclosed_quote_runs_to_the_end() {
+        let query = SearchQuery::parse("\"open phrase");
+        assert_eq!(query.clauses, [clause(SearchTerm::Phrase("open phrase".into()), false)]);
+    }
+}
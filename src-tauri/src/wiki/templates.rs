@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Datelike, Local};
+use crate::models::WikiTemplate;
+
+// Placeholders filled in without being passed, besides {{title}}
+const BUILTIN_VARIABLES: &[&str] = &["date", "time", "datetime", "year", "month", "day", "weekday", "week"];
+
+/// Values of the built-in placeholders at `now`, in local time. `week` is the
+/// ISO week, e.g. `2026-W42`.
+pub fn builtin_variables(now: DateTime<Local>) -> HashMap<String, String> {
+    let iso = now.iso_week();
+    let values = [
+        ("date", now.format("%Y-%m-%d").to_string()),
+        ("time", now.format("%H:%M").to_string()),
+        ("datetime", now.format("%Y-%m-%d %H:%M").to_string()),
+        ("year", now.format("%Y").to_string()),
+        ("month", now.format("%m").to_string()),
+        ("day", now.format("%d").to_string()),
+        ("weekday", now.format("%A").to_string()),
+        ("week", format!("{}-W{:02}", iso.year(), iso.week())),
+    ];
+    values.into_iter().map(|(name, value)| (name.to_string(), value)).collect()
+}
+
+/// Replaces every `{{name}}` (whitespace inside the braces is ignored) with its
+/// value. Placeholders without a value are left as written.
+pub fn expand(text: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+
+        match vars.get(after[..end].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Names of the placeholders in a template that need a value from the caller:
+/// everything but the built-ins and `title`, in order of first appearance.
+pub fn template_variables(template: &WikiTemplate) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let texts = std::iter::once(&template.title)
+        .chain(std::iter::once(&template.content))
+        .chain(template.tags.iter());
+
+    for text in texts {
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("{{") {
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else { break };
+            let name = after[..end].trim();
+            let custom = !name.is_empty() && name != "title" && !BUILTIN_VARIABLES.contains(&name);
+            if custom && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+            rest = &after[end + 2..];
+        }
+    }
+
+    names
+}
+
+/// Title, content and tags of a new page from a template. `vars` may override
+/// the built-ins. The title comes from the template's title, or from a `title`
+/// variable when the template has none, and is what {{title}} expands to in
+/// the content and tags.
+pub fn instantiate(
+    template: &WikiTemplate,
+    vars: &HashMap<String, String>,
+    now: DateTime<Local>,
+) -> (String, String, Vec<String>) {
+    let mut values = builtin_variables(now);
+    values.extend(vars.iter().map(|(name, value)| (name.trim().to_string(), value.clone())));
+
+    let title = match template.title.trim() {
+        "" => values.get("title").cloned().unwrap_or_else(|| template.name.clone()),
+        pattern => expand(pattern, &values),
+    };
+    values.insert("title".to_string(), title.clone());
+
+    let content = expand(&template.content, &values);
+    let mut tags: Vec<String> = Vec::new();
+    for tag in template.tags.iter().map(|tag| expand(tag, &values)) {
+        if !tag.trim().is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    (title, content, tags)
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+use sqlx::SqlitePool;
+use log::{error, warn};
+use crate::database;
+use crate::error::AppResult;
+
+// app_settings key holding how many days deleted pages stay in the trash
+pub const TRASH_DAYS_SETTING: &str = "wiki_trash_days";
+
+const DEFAULT_TRASH_DAYS: i64 = 30;
+
+// How often a running app looks for expired pages in the trash
+const PURGE_INTERVAL_SECONDS: u64 = 3600;
+
+pub async fn load_trash_days(pool: &SqlitePool) -> i64 {
+    match database::get_setting(pool, TRASH_DAYS_SETTING).await {
+        Ok(Some(value)) => value.parse().unwrap_or_else(|_| {
+            warn!("Invalid wiki trash days: {}", value);
+            DEFAULT_TRASH_DAYS
+        }),
+        Ok(None) => DEFAULT_TRASH_DAYS,
+        Err(e) => {
+            warn!("Failed to read wiki trash days: {}", e);
+            DEFAULT_TRASH_DAYS
+        }
+    }
+}
+
+/// Permanently deletes pages that have been in the trash longer than the
+/// configured number of days.
+pub async fn purge_expired_trash(pool: &SqlitePool, now: i64) -> AppResult<u64> {
+    let days = load_trash_days(pool).await;
+    database::purge_wiki_trash(pool, Some(now - days * 24 * 3600)).await
+}
+
+/// Purges expired pages right away and then every hour, for as long as the app
+/// runs. Failures only cost disk space, so they are logged and retried.
+pub async fn purge_expired_trash_periodically(pool: SqlitePool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(PURGE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge_expired_trash(&pool, chrono::Utc::now().timestamp()).await {
+            error!("Failed to purge wiki trash: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600;
+    const NOW: i64 = 1_000 * DAY;
+
+    #[tokio::test]
+    async fn only_pages_trashed_longer_than_the_setting_are_purged() {
+        let pool = database::test_pool().await;
+        database::set_setting(&pool, TRASH_DAYS_SETTING, "7").await.unwrap();
+        for (id, trashed_days_ago) in [("old", 8), ("recent", 6)] {
+            database::create_wiki_page(&pool, &database::test_page(id, "")).await.unwrap();
+            database::trash_wiki_page(&pool, id, NOW - trashed_days_ago * DAY).await.unwrap();
+        }
+
+        assert_eq!(purge_expired_trash(&pool, NOW).await.unwrap(), 1);
+
+        let left: Vec<String> = database::list_trashed_wiki_pages(&pool).await.unwrap().into_iter().map(|p| p.page.id).collect();
+        assert_eq!(left, ["recent"]);
+    }
+}
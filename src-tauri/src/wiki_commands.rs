@@ -1,552 +1,747 @@
-use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
-use tauri::Manager;
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct WikiPage {
-    pub id: String,
-    pub title: String,
-    pub content: String,
-    pub tags: Vec<String>,
-    #[serde(default = "default_notebook")]
-    pub notebook: String,
-    #[serde(default = "default_section")]
-    pub section: String,
-    pub section_id: Option<String>,
-    pub created_at: i64,
-    pub updated_at: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WikiPageList {
-    pub id: String,
-    pub title: String,
-    pub tags: Vec<String>,
-    pub notebook: String,
-    pub section: String,
-    pub section_id: Option<String>,
-    pub updated_at: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct WikiRevisionMeta {
-    pub id: String,
-    pub page_id: String,
-    pub title: String,
-    pub notebook: String,
-    pub section: String,
-    pub section_id: Option<String>,
-    pub created_at: i64,
-}
-
-fn default_notebook() -> String {
-    "Notebook".to_string()
-}
-
-fn default_section() -> String {
-    "Section".to_string()
-}
-
-fn get_wiki_dir(app_handle: tauri::AppHandle) -> Result<PathBuf, String> {
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    
-    let wiki_dir = app_dir.join("wiki");
-    
-    if !wiki_dir.exists() {
-        fs::create_dir_all(&wiki_dir)
-            .map_err(|e| format!("Failed to create wiki directory: {}", e))?;
-    }
-
-    Ok(wiki_dir)
-}
-
-fn get_revision_dir(app_handle: &tauri::AppHandle, page_id: &str) -> Result<PathBuf, String> {
-    let wiki_dir = get_wiki_dir(app_handle.clone())?;
-    let revisions_dir = wiki_dir.join("revisions").join(page_id);
-    if !revisions_dir.exists() {
-        fs::create_dir_all(&revisions_dir)
-            .map_err(|e| format!("Failed to create revisions directory: {}", e))?;
-    }
-    Ok(revisions_dir)
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Section {
-    pub id: String,
-    pub name: String,
-    pub parent_id: Option<String>,
-    pub created_at: i64,
-    pub updated_at: i64,
-}
-
-fn sections_file(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
-    let wiki_dir = get_wiki_dir(app_handle.clone())?;
-    Ok(wiki_dir.join("sections.json"))
-}
-
-fn load_sections(app_handle: &tauri::AppHandle) -> Result<Vec<Section>, String> {
-    let path = sections_file(app_handle)?;
-    if !path.exists() {
-        let root = Section {
-            id: "root".to_string(),
-            name: "Notebook".to_string(),
-            parent_id: None,
-            created_at: chrono::Utc::now().timestamp(),
-            updated_at: chrono::Utc::now().timestamp(),
-        };
-        let json = serde_json::to_string_pretty(&vec![root])
-            .map_err(|e| format!("Failed to serialize sections: {}", e))?;
-        fs::write(&path, json)
-            .map_err(|e| format!("Failed to write sections file: {}", e))?;
-    }
-
-    let data = fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read sections file: {}", e))?;
-    let sections: Vec<Section> = serde_json::from_str(&data)
-        .map_err(|e| format!("Failed to parse sections file: {}", e))?;
-    Ok(sections)
-}
-
-fn save_sections(app_handle: &tauri::AppHandle, sections: &[Section]) -> Result<(), String> {
-    let path = sections_file(app_handle)?;
-    let json = serde_json::to_string_pretty(sections)
-        .map_err(|e| format!("Failed to serialize sections: {}", e))?;
-    fs::write(path, json)
-        .map_err(|e| format!("Failed to write sections file: {}", e))?
-    ;
-    Ok(())
-}
-
-fn ensure_section(app_handle: &tauri::AppHandle, section_id: Option<String>) -> Result<String, String> {
-    let mut sections = load_sections(app_handle)?;
-    let default_id = "root".to_string();
-    if section_id.is_none() {
-        return Ok(default_id);
-    }
-    let target = section_id.unwrap();
-    if sections.iter().any(|s| s.id == target) {
-        return Ok(target);
-    }
-    // If section missing, create under root
-    let now = chrono::Utc::now().timestamp();
-    let sec = Section {
-        id: target.clone(),
-        name: target.clone(),
-        parent_id: Some(default_id.clone()),
-        created_at: now,
-        updated_at: now,
-    };
-    sections.push(sec);
-    save_sections(app_handle, &sections)?;
-    Ok(target)
-}
-
-fn section_name(app_handle: &tauri::AppHandle, section_id: &str) -> Result<String, String> {
-    let sections = load_sections(app_handle)?;
-    if let Some(sec) = sections.iter().find(|s| s.id == section_id) {
-        return Ok(sec.name.clone());
-    }
-    Ok(default_section())
-}
-
+use std::collections::HashMap;
+use sqlx::SqlitePool;
+use log::{info, warn};
+use tauri::{AppHandle, Emitter, Manager};
+use crate::attachments::{asset_url, page_attachment, store_attachment, StoredFile, ATTACHMENTS_DIR};
+use crate::database::{self, DEFAULT_NOTEBOOK_ID, ROOT_SECTION_ID};
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    BlameLine, BrokenWikiLink, DocumentImportProgress, MarkdownImportReport, Notebook, RevisionRetention, Section, SectionDeleteMode,
+    TrashedWikiPage, WikiBacklink, WikiCompactionReport, WikiExportFormat, WikiExportReport, WikiHistoryUsage,
+    WikiLinkGraph, WikiMergeResult, WikiPage, WikiPageList, WikiRevisionDiff, WikiRevisionMeta, WikiScanReport,
+    WikiSearchResult, WikiTemplate, WikiTemplateDraft, WikiTemplateRevision,
+};
+use crate::importers::{self, DocumentKind};
+use crate::wiki::{self, SearchQuery};
+
+// Without a section the page goes to the top of the notebook, the default
+// notebook without one; with a section, the page is in that section's notebook.
 #[tauri::command]
-pub fn create_wiki_page(
-    app_handle: tauri::AppHandle,
+pub async fn create_wiki_page(
+    pool: tauri::State<'_, SqlitePool>,
     title: String,
     content: String,
     tags: Vec<String>,
-    notebook: Option<String>,
+    notebook_id: Option<String>,
     section: Option<String>,
     section_id: Option<String>,
-) -> Result<WikiPage, String> {
-    let wiki_dir = get_wiki_dir(app_handle.clone())?;
-    let section_id = ensure_section(&app_handle, section_id)?;
-    let section_name = section_name(&app_handle, &section_id)?;
-    
+) -> AppResult<WikiPage> {
     let timestamp = chrono::Utc::now().timestamp();
-    let id = format!("{}", timestamp);
-    
+    let section_id = match section_id {
+        Some(section_id) => section_id,
+        None => notebook_root(&pool, notebook_id).await?,
+    };
+    let target_section = database::ensure_section(&pool, Some(section_id), timestamp).await?;
+    let notebook = database::section_notebook(&mut *pool.acquire().await?, Some(&target_section.id)).await?;
+
     let page = WikiPage {
-        id: id.clone(),
+        id: wiki::new_page_id(),
         title,
         content,
         tags,
-        notebook: notebook.unwrap_or_else(default_notebook),
-        section: section.unwrap_or(section_name.clone()),
-        section_id: Some(section_id),
+        notebook: notebook.name,
+        section: section.unwrap_or(target_section.name),
+        section_id: Some(target_section.id),
         created_at: timestamp,
         updated_at: timestamp,
+        version: 1,
     };
-    
-    let file_path = wiki_dir.join(format!("{}.json", id));
-    let json = serde_json::to_string_pretty(&page)
-        .map_err(|e| format!("Failed to serialize page: {}", e))?;
-    
-    fs::write(file_path, json)
-        .map_err(|e| format!("Failed to write page: {}", e))?;
-    
-    Ok(page)
-}
 
-fn save_revision(
-    app_handle: &tauri::AppHandle,
-    page: &WikiPage,
-) -> Result<WikiRevisionMeta, String> {
-    let revisions_dir = get_revision_dir(app_handle, &page.id)?;
-    let revision_id = chrono::Utc::now().timestamp();
-    let file_path = revisions_dir.join(format!("{}.json", revision_id));
-
-    let json = serde_json::to_string_pretty(page)
-        .map_err(|e| format!("Failed to serialize revision: {}", e))?;
-
-    fs::write(&file_path, json)
-        .map_err(|e| format!("Failed to write revision: {}", e))?;
-
-    Ok(WikiRevisionMeta {
-        id: revision_id.to_string(),
-        page_id: page.id.clone(),
-        title: page.title.clone(),
-        notebook: page.notebook.clone(),
-        section: page.section.clone(),
-        section_id: page.section_id.clone(),
-        created_at: revision_id,
-    })
+    database::create_wiki_page(&pool, &page).await?;
+    Ok(page)
 }
 
+// `expected_version` is the version the edit started from; if the page has been
+// saved since, an edit_conflict error carrying the saved page is returned and
+// nothing is written. Without it the save always goes through. With
+// `rewrite_links`, a new title is also written into [[links]] to the page that
+// used the old title, in the same transaction; a title that cannot sit in a
+// link is written as the page id instead. The page's notebook follows
+// from its section.
 #[tauri::command]
-pub fn update_wiki_page(
-    app_handle: tauri::AppHandle,
+#[allow(clippy::too_many_arguments)]
+pub async fn update_wiki_page(
+    pool: tauri::State<'_, SqlitePool>,
     id: String,
     title: String,
     content: String,
     tags: Vec<String>,
-    notebook: Option<String>,
     section: Option<String>,
     section_id: Option<String>,
-) -> Result<WikiPage, String> {
-    let wiki_dir = get_wiki_dir(app_handle.clone())?;
-    let file_path = wiki_dir.join(format!("{}.json", id));
-    
-    let json = fs::read_to_string(&file_path)
-        .map_err(|e| format!("Failed to read page: {}", e))?;
-    
-    let mut page: WikiPage = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to parse page: {}", e))?;
-
-    // Save current state as a revision before updating
-    let _ = save_revision(&app_handle, &page);
-    
+    expected_version: Option<i64>,
+    rewrite_links: Option<bool>,
+) -> AppResult<WikiPage> {
+    let mut page = database::get_wiki_page(&pool, &id).await?;
+    let old_title = page.title.clone();
+    let now = chrono::Utc::now().timestamp();
+
+    let target_section_id = section_id
+        .or_else(|| page.section_id.clone())
+        .unwrap_or_else(|| ROOT_SECTION_ID.to_string());
+    let target_section = database::ensure_section(&pool, Some(target_section_id), now).await?;
+    let notebook = database::section_notebook(&mut *pool.acquire().await?, Some(&target_section.id)).await?;
+
     page.title = title;
     page.content = content;
     page.tags = tags;
-    page.notebook = notebook.unwrap_or_else(|| page.notebook.clone());
-
-    let target_section_id = section_id.unwrap_or_else(|| page.section_id.clone().unwrap_or_else(|| "root".to_string()));
-    let ensured_section_id = ensure_section(&app_handle, Some(target_section_id))?;
-    let section_name = section.unwrap_or_else(|| section_name(&app_handle, &ensured_section_id).unwrap_or_else(|_| default_section()));
-
-    page.section_id = Some(ensured_section_id);
-    page.section = section_name;
-    page.updated_at = chrono::Utc::now().timestamp();
-    
-    let json = serde_json::to_string_pretty(&page)
-        .map_err(|e| format!("Failed to serialize page: {}", e))?;
-    
-    fs::write(file_path, json)
-        .map_err(|e| format!("Failed to write page: {}", e))?;
-    
-    Ok(page)
+    page.notebook = notebook.name;
+    page.section = section.unwrap_or(target_section.name);
+    page.section_id = Some(target_section.id);
+    page.updated_at = now;
+
+    if !rewrite_links.unwrap_or(false) || page.title == old_title {
+        // The previous state is kept as a revision
+        return database::update_wiki_page(&pool, &page, expected_version).await;
+    }
+
+    let mut tx = pool.begin().await?;
+    let saved = database::save_wiki_page(&mut tx, &page, expected_version).await?;
+    wiki::rewrite_inbound_links(&mut tx, &id, &old_title, &saved.title, now).await?;
+    tx.commit().await?;
+    Ok(saved)
 }
 
+// Merges an edit that started from `base_version` with the saved page, using
+// the page as it was at that version as the common ancestor. Nothing is saved;
+// a clean result is saved with update_wiki_page and the version of `current`.
 #[tauri::command]
-pub fn get_wiki_page(app_handle: tauri::AppHandle, id: String) -> Result<WikiPage, String> {
-    let wiki_dir = get_wiki_dir(app_handle)?;
-    let file_path = wiki_dir.join(format!("{}.json", id));
-    
-    let json = fs::read_to_string(file_path)
-        .map_err(|e| format!("Failed to read page: {}", e))?;
-    
-    let page: WikiPage = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to parse page: {}", e))?;
-    
-    Ok(page)
+pub async fn merge_wiki_page(
+    pool: tauri::State<'_, SqlitePool>,
+    id: String,
+    base_version: i64,
+    title: String,
+    content: String,
+    tags: Vec<String>,
+) -> AppResult<WikiMergeResult> {
+    let current = database::get_wiki_page(&pool, &id).await?;
+    let base = database::find_wiki_page_version(&pool, &id, base_version)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("Version {} of page {} is no longer available", base_version, id)))?;
+
+    let (title, title_conflicts) = wiki::merge_value(&base.title, &title, &current.title);
+    let (content, content_conflicts) = wiki::merge_text(&base.content, &content, &current.content);
+    let tags = wiki::merge_tags(&base.tags, &tags, &current.tags);
+
+    Ok(WikiMergeResult {
+        title,
+        content,
+        tags,
+        conflicts: title_conflicts + content_conflicts,
+        current,
+    })
 }
 
 #[tauri::command]
-pub fn list_wiki_pages(app_handle: tauri::AppHandle) -> Result<Vec<WikiPageList>, String> {
-    let wiki_dir = get_wiki_dir(app_handle)?;
-    
-    let mut pages = Vec::new();
-    
-    let entries = fs::read_dir(wiki_dir)
-        .map_err(|e| format!("Failed to read wiki directory: {}", e))?;
-    
-    for entry in entries {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(json) = fs::read_to_string(&path) {
-                    if let Ok(page) = serde_json::from_str::<WikiPage>(&json) {
-                        pages.push(WikiPageList {
-                            id: page.id,
-                            title: page.title,
-                            tags: page.tags,
-                            notebook: page.notebook,
-                            section: page.section,
-                            section_id: page.section_id,
-                            updated_at: page.updated_at,
-                        });
-                    }
-                }
-            }
-        }
+pub async fn get_wiki_page(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<WikiPage> {
+    database::get_wiki_page(&pool, &id).await
+}
+
+#[tauri::command]
+pub async fn list_wiki_pages(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<WikiPageList>> {
+    database::list_wiki_pages(&pool).await
+}
+
+#[tauri::command]
+pub async fn get_backlinks(
+    pool: tauri::State<'_, SqlitePool>,
+    page_id: String,
+) -> AppResult<Vec<WikiBacklink>> {
+    wiki::get_backlinks(&pool, &page_id).await
+}
+
+#[tauri::command]
+pub async fn find_broken_links(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<BrokenWikiLink>> {
+    wiki::find_broken_links(&pool).await
+}
+
+#[tauri::command]
+pub async fn get_link_graph(pool: tauri::State<'_, SqlitePool>) -> AppResult<WikiLinkGraph> {
+    wiki::get_link_graph(&pool).await
+}
+
+// Deleted pages go to the trash and can be restored until it is purged
+#[tauri::command]
+pub async fn delete_wiki_page(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<()> {
+    info!("Deleting wiki page {}", id);
+    database::trash_wiki_page(&pool, &id, chrono::Utc::now().timestamp()).await
+}
+
+#[tauri::command]
+pub async fn list_trashed_pages(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<TrashedWikiPage>> {
+    // Pages can expire between the periodic purges; none should be listed
+    if let Err(e) = wiki::purge_expired_trash(&pool, chrono::Utc::now().timestamp()).await {
+        warn!("Failed to purge expired wiki trash: {}", e);
     }
-    
-    pages.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-    
-    Ok(pages)
-}
-
-#[tauri::command]
-pub fn delete_wiki_page(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
-    println!("[WIKI] Deleting page with id: {}", id);
-    let wiki_dir = get_wiki_dir(app_handle)?;
-    let file_path = wiki_dir.join(format!("{}.json", id));
-    
-    println!("[WIKI] Deleting file: {:?}", file_path);
-    
-    if !file_path.exists() {
-        return Err(format!("Page file not found: {:?}", file_path));
+    database::list_trashed_wiki_pages(&pool).await
+}
+
+#[tauri::command]
+pub async fn restore_trashed_page(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<WikiPage> {
+    info!("Restoring wiki page {} from trash", id);
+    database::restore_trashed_wiki_page(&pool, &id, chrono::Utc::now().timestamp()).await
+}
+
+// Permanently deletes pages trashed before `older_than` (Unix seconds), or
+// everything in the trash without it. Returns how many pages were purged.
+#[tauri::command]
+pub async fn purge_trash(pool: tauri::State<'_, SqlitePool>, older_than: Option<i64>) -> AppResult<u64> {
+    info!("Purging wiki trash (older than {:?})", older_than);
+    database::purge_wiki_trash(&pool, older_than).await
+}
+
+#[tauri::command]
+pub async fn get_trash_retention_days(pool: tauri::State<'_, SqlitePool>) -> AppResult<i64> {
+    Ok(wiki::load_trash_days(&pool).await)
+}
+
+#[tauri::command]
+pub async fn set_trash_retention_days(pool: tauri::State<'_, SqlitePool>, days: i64) -> AppResult<()> {
+    if days < 0 {
+        return Err(AppError::validation("Trash retention cannot be negative"));
     }
-    
-    fs::remove_file(&file_path)
-        .map_err(|e| format!("Failed to delete page: {}", e))?;
-    
-    println!("[WIKI] Page deleted successfully");
+    info!("Setting wiki trash retention to {} days", days);
+
+    database::set_setting(&pool, wiki::TRASH_DAYS_SETTING, &days.to_string()).await?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn list_wiki_revisions(
-    app_handle: tauri::AppHandle,
+pub async fn list_wiki_revisions(
+    pool: tauri::State<'_, SqlitePool>,
     page_id: String,
-) -> Result<Vec<WikiRevisionMeta>, String> {
-    let revisions_dir = get_revision_dir(&app_handle, &page_id)?;
-    let mut revisions = Vec::new();
-
-    if revisions_dir.exists() {
-        let entries = fs::read_dir(revisions_dir)
-            .map_err(|e| format!("Failed to read revisions directory: {}", e))?;
-
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                    if let Ok(json) = fs::read_to_string(&path) {
-                        if let Ok(page) = serde_json::from_str::<WikiPage>(&json) {
-                            revisions.push(WikiRevisionMeta {
-                                id: entry
-                                    .file_name()
-                                    .to_string_lossy()
-                                    .replace(".json", ""),
-                                page_id: page.id.clone(),
-                                title: page.title,
-                                notebook: page.notebook,
-                                section: page.section,
-                                section_id: page.section_id,
-                                created_at: page.updated_at,
-                            });
-                        }
-                    }
-                }
-        }
-    }
-
-    revisions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-    Ok(revisions)
+) -> AppResult<Vec<WikiRevisionMeta>> {
+    database::list_wiki_revisions(&pool, &page_id).await
 }
 
 #[tauri::command]
-pub fn restore_wiki_revision(
-    app_handle: tauri::AppHandle,
+pub async fn restore_wiki_revision(
+    pool: tauri::State<'_, SqlitePool>,
     page_id: String,
     revision_id: String,
-) -> Result<WikiPage, String> {
-    let revisions_dir = get_revision_dir(&app_handle, &page_id)?;
-    let revision_path = revisions_dir.join(format!("{}.json", revision_id));
+) -> AppResult<WikiPage> {
+    let now = chrono::Utc::now().timestamp();
+    database::restore_wiki_revision(&pool, &page_id, &revision_id, now).await
+}
 
-    if !revision_path.exists() {
-        return Err("Revision not found".into());
+// Resolves a revision id, or "current" for the live page
+async fn load_page_version(pool: &SqlitePool, page: &WikiPage, revision_id: &str) -> AppResult<WikiPage> {
+    if revision_id == wiki::CURRENT_REVISION {
+        return Ok(page.clone());
     }
+    let mut conn = pool.acquire().await?;
+    database::find_wiki_revision(&mut conn, page, revision_id).await
+}
 
-    // Load current page to keep a backup revision before restoring
-    let current_page = get_wiki_page(app_handle.clone(), page_id.clone())?;
-    let _ = save_revision(&app_handle, &current_page);
+// Changes from one revision to another; `to` defaults to the current page
+#[tauri::command]
+pub async fn diff_wiki_revisions(
+    pool: tauri::State<'_, SqlitePool>,
+    page_id: String,
+    from: String,
+    to: Option<String>,
+) -> AppResult<WikiRevisionDiff> {
+    let to = to.unwrap_or_else(|| wiki::CURRENT_REVISION.to_string());
+    let page = database::get_wiki_page(&pool, &page_id).await?;
+    let old = load_page_version(&pool, &page, &from).await?;
+    let new = load_page_version(&pool, &page, &to).await?;
+
+    Ok(wiki::diff_pages(&from, &old, &to, &new))
+}
 
-    let json = fs::read_to_string(&revision_path)
-        .map_err(|e| format!("Failed to read revision: {}", e))?;
+#[tauri::command]
+pub async fn blame_wiki_page(pool: tauri::State<'_, SqlitePool>, page_id: String) -> AppResult<Vec<BlameLine>> {
+    let page = database::get_wiki_page(&pool, &page_id).await?;
+    let revisions = database::list_wiki_revision_pages(&pool, &page).await?;
 
-    let mut revision_page: WikiPage = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to parse revision: {}", e))?;
+    let versions: Vec<(Option<String>, &WikiPage)> = revisions
+        .iter()
+        .map(|(id, revision)| (Some(id.clone()), revision))
+        .chain(std::iter::once((None, &page)))
+        .collect();
 
-    // Apply restore (update timestamp)
-    revision_page.updated_at = chrono::Utc::now().timestamp();
+    Ok(wiki::blame(&versions))
+}
 
-    let wiki_dir = get_wiki_dir(app_handle)?;
-    let page_path = wiki_dir.join(format!("{}.json", page_id));
+#[tauri::command]
+pub async fn search_wiki_pages(
+    pool: tauri::State<'_, SqlitePool>,
+    query: String,
+) -> AppResult<Vec<WikiSearchResult>> {
+    database::search_wiki_pages(&pool, &SearchQuery::parse(&query)).await
+}
 
-    let page_json = serde_json::to_string_pretty(&revision_page)
-        .map_err(|e| format!("Failed to serialize restored page: {}", e))?;
+#[tauri::command]
+pub async fn get_revision_retention(
+    pool: tauri::State<'_, SqlitePool>,
+) -> AppResult<RevisionRetention> {
+    Ok(wiki::load_retention(&pool).await)
+}
 
-    fs::write(page_path, page_json)
-        .map_err(|e| format!("Failed to write restored page: {}", e))?;
+#[tauri::command]
+pub async fn set_revision_retention(
+    pool: tauri::State<'_, SqlitePool>,
+    retention: RevisionRetention,
+) -> AppResult<()> {
+    wiki::validate_retention(&retention)?;
+    info!("Setting wiki revision retention to {:?}", retention);
+
+    let value = serde_json::to_string(&retention)
+        .map_err(|e| AppError::internal(format!("Failed to serialize retention: {}", e)))?;
+    database::set_setting(&pool, wiki::RETENTION_SETTING, &value).await?;
+    Ok(())
+}
 
-    Ok(revision_page)
+// Prunes and delta-compresses page history according to the saved retention
+#[tauri::command]
+pub async fn compact_wiki_history(
+    pool: tauri::State<'_, SqlitePool>,
+) -> AppResult<WikiCompactionReport> {
+    let retention = wiki::load_retention(&pool).await;
+    wiki::compact_history(&pool, &retention, chrono::Utc::now().timestamp()).await
 }
 
 #[tauri::command]
-pub fn search_wiki_pages(
-    app_handle: tauri::AppHandle,
-    query: String,
-) -> Result<Vec<WikiPageList>, String> {
-    let wiki_dir = get_wiki_dir(app_handle)?;
-    let query_lower = query.to_lowercase();
-    
-    let mut pages = Vec::new();
-    
-    let entries = fs::read_dir(wiki_dir)
-        .map_err(|e| format!("Failed to read wiki directory: {}", e))?;
-    
-    for entry in entries {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(json) = fs::read_to_string(&path) {
-                    if let Ok(page) = serde_json::from_str::<WikiPage>(&json) {
-                        let title_match = page.title.to_lowercase().contains(&query_lower);
-                        let content_match = page.content.to_lowercase().contains(&query_lower);
-                        let tag_match = page.tags.iter().any(|t| t.to_lowercase().contains(&query_lower));
-                        let notebook_match = page.notebook.to_lowercase().contains(&query_lower);
-                        let section_match = page.section.to_lowercase().contains(&query_lower);
-                        
-                        if title_match || content_match || tag_match || notebook_match || section_match {
-                            pages.push(WikiPageList {
-                                id: page.id,
-                                title: page.title,
-                                tags: page.tags,
-                                notebook: page.notebook,
-                                section: page.section,
-                                section_id: page.section_id,
-                                updated_at: page.updated_at,
-                            });
-                        }
-                    }
-                }
-            }
+pub async fn get_wiki_history_usage(
+    pool: tauri::State<'_, SqlitePool>,
+) -> AppResult<WikiHistoryUsage> {
+    database::get_wiki_history_usage(&pool).await
+}
+
+// Reports pages whose stored data cannot be read, legacy page files the import
+// could not parse and whether the search index matches the pages. With
+// `repair` salvageable tags are kept and the index is rebuilt; legacy files are
+// only reported, as they need fixing by hand.
+#[tauri::command]
+pub async fn scan_wiki_pages(
+    app: AppHandle,
+    pool: tauri::State<'_, SqlitePool>,
+    repair: Option<bool>,
+) -> AppResult<WikiScanReport> {
+    let repair = repair.unwrap_or(false);
+    info!("Scanning wiki pages (repair: {})", repair);
+
+    let app_dir = app.path()
+        .app_data_dir()
+        .map_err(|e| AppError::internal(format!("Failed to get app data dir: {}", e)))?;
+
+    let unreadable_pages = database::find_unreadable_wiki_pages(&pool).await?;
+    let unreadable_files = wiki::find_unreadable_legacy_files(&app_dir);
+    let index_ok = database::check_wiki_index(&pool).await?;
+
+    info!(
+        "Wiki scan found {} unreadable pages, {} unreadable legacy files; index ok: {}",
+        unreadable_pages.len(),
+        unreadable_files.len(),
+        index_ok
+    );
+    for file in &unreadable_files {
+        warn!("Unreadable legacy wiki file: {}", file);
+    }
+
+    if repair {
+        let ids: Vec<String> = unreadable_pages.iter().map(|p| p.id.clone()).collect();
+        database::repair_wiki_page_tags(&pool, &ids).await?;
+        if !index_ok {
+            database::rebuild_wiki_index(&pool).await?;
+        }
+    }
+
+    Ok(WikiScanReport {
+        unreadable_pages,
+        unreadable_files,
+        index_ok,
+        repaired: repair,
+    })
+}
+
+// Imports a folder of Markdown files below a section. Run with `dry_run` first
+// to show what would be created, skipped or in conflict; embedded images are
+// copied into the attachment store and attached to the pages.
+#[tauri::command]
+pub async fn import_markdown_directory(
+    app: AppHandle,
+    pool: tauri::State<'_, SqlitePool>,
+    path: String,
+    target_section_id: String,
+    dry_run: bool,
+) -> AppResult<MarkdownImportReport> {
+    let app_dir = app.path()
+        .app_data_dir()
+        .map_err(|e| AppError::internal(format!("Failed to get app data dir: {}", e)))?;
+
+    wiki::import_markdown_directory(
+        &pool,
+        std::path::Path::new(&path),
+        &target_section_id,
+        &app_dir.join(ATTACHMENTS_DIR),
+        dry_run,
+        chrono::Utc::now().timestamp(),
+    )
+    .await
+}
+
+// Imports a PDF, HTML, ODT, RTF or text file as a new page in `section_id`,
+// the default notebook without one. The document is converted on a blocking
+// thread and progress is sent as "document-import-progress" events; images it
+// embeds are stored as attachments of the page.
+#[tauri::command]
+pub async fn import_document(
+    app: AppHandle,
+    pool: tauri::State<'_, SqlitePool>,
+    path: String,
+    section_id: Option<String>,
+) -> AppResult<WikiPage> {
+    let source = std::path::PathBuf::from(&path);
+    if DocumentKind::from_path(&source).is_none() {
+        return Err(AppError::validation(format!("Unsupported document type: {}", path)));
+    }
+    let app_dir = app.path()
+        .app_data_dir()
+        .map_err(|e| AppError::internal(format!("Failed to get app data dir: {}", e)))?;
+
+    let emit_progress = {
+        let app = app.clone();
+        let path = path.clone();
+        move |stage: &str, progress: f64| {
+            let event = DocumentImportProgress { path: path.clone(), stage: stage.to_string(), progress };
+            let _ = app.emit("document-import-progress", event);
         }
+    };
+
+    let document = {
+        let emit_progress = emit_progress.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            importers::convert(&source, &|progress| emit_progress("converting", progress))
+        })
+        .await
+        .map_err(|e| AppError::internal(format!("Document import failed: {}", e)))??
+    };
+    emit_progress("saving", 1.0);
+    for warning in &document.warnings {
+        warn!("Importing {}: {}", path, warning);
     }
-    
-    pages.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-    
-    Ok(pages)
-}
-
-fn count_pages_in_section(app_handle: &tauri::AppHandle, section_id: &str) -> Result<usize, String> {
-    let wiki_dir = get_wiki_dir(app_handle.clone())?;
-    let mut count = 0;
-    let entries = fs::read_dir(wiki_dir)
-        .map_err(|e| format!("Failed to read wiki directory: {}", e))?;
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.extension().and_then(|s| s.to_str()) == Some("json") {
-            if let Ok(json) = fs::read_to_string(&path) {
-                if let Ok(page) = serde_json::from_str::<WikiPage>(&json) {
-                    if let Some(sec_id) = &page.section_id {
-                        if sec_id == section_id {
-                            count += 1;
-                        }
-                    }
-                }
+
+    let attachments_dir = app_dir.join(ATTACHMENTS_DIR);
+    let mut content = document.markdown;
+    let mut stored: Vec<StoredFile> = Vec::new();
+    for (index, image) in document.images.iter().enumerate() {
+        let stored_image = match store_attachment(&attachments_dir, &image.extension, &image.data) {
+            Ok(stored_image) => stored_image,
+            Err(e) => {
+                let stored_paths: Vec<_> = stored.iter().map(|s| s.path.clone()).collect();
+                database::release_files(&pool, &stored_paths).await;
+                return Err(e);
             }
+        };
+        let placeholder = format!("({})", importers::image_placeholder(index));
+        content = content.replace(&placeholder, &format!("({})", asset_url(&stored_image.path)));
+        stored.push(stored_image);
+    }
+
+    let timestamp = chrono::Utc::now().timestamp();
+    let page = async {
+        let section_id = match section_id {
+            Some(section_id) => section_id,
+            None => notebook_root(&pool, None).await?,
+        };
+        let target_section = database::ensure_section(&pool, Some(section_id), timestamp).await?;
+        let notebook = database::section_notebook(&mut *pool.acquire().await?, Some(&target_section.id)).await?;
+
+        let page = WikiPage {
+            id: wiki::new_page_id(),
+            title: document.title,
+            content,
+            tags: Vec::new(),
+            notebook: notebook.name,
+            section: target_section.name,
+            section_id: Some(target_section.id),
+            created_at: timestamp,
+            updated_at: timestamp,
+            version: 1,
+        };
+
+        let mut tx = pool.begin().await?;
+        database::insert_wiki_page(&mut tx, &page).await?;
+        for (index, stored_image) in stored.iter().enumerate() {
+            let file_name = format!("image-{}.{}", index + 1, document.images[index].extension);
+            database::insert_evidence(&mut tx, &page_attachment(&page.id, stored_image, &file_name, None)).await?;
         }
+        tx.commit().await?;
+        Ok::<_, AppError>(page)
+    }
+    .await;
+
+    // Stored images are only kept when the page referencing them is saved,
+    // unless other rows already share their content
+    if page.is_err() {
+        let stored_paths: Vec<_> = stored.iter().map(|s| s.path.clone()).collect();
+        database::release_files(&pool, &stored_paths).await;
+    }
+    let page = page?;
+    emit_progress("done", 1.0);
+    info!("Imported {} as wiki page {} with {} images", path, page.id, stored.len());
+    Ok(page)
+}
+
+// Writes the whole wiki as Markdown files to `target`, a folder by default
+#[tauri::command]
+pub async fn export_wiki(
+    pool: tauri::State<'_, SqlitePool>,
+    target: String,
+    format: Option<WikiExportFormat>,
+) -> AppResult<WikiExportReport> {
+    if target.trim().is_empty() {
+        return Err(AppError::validation("Export target cannot be empty"));
     }
-    Ok(count)
+    wiki::export_wiki(&pool, std::path::Path::new(&target), format.unwrap_or_default()).await
 }
 
 #[tauri::command]
-pub fn list_sections(app_handle: tauri::AppHandle) -> Result<Vec<Section>, String> {
-    load_sections(&app_handle)
+pub async fn list_sections(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<Section>> {
+    database::list_sections(&pool).await
 }
 
+// Without a parent the section goes to the top of the notebook, the default
+// notebook without one
 #[tauri::command]
-pub fn create_section(
-    app_handle: tauri::AppHandle,
+pub async fn create_section(
+    pool: tauri::State<'_, SqlitePool>,
     name: String,
     parent_id: Option<String>,
-) -> Result<Section, String> {
-    let mut sections = load_sections(&app_handle)?;
-    let parent = parent_id.unwrap_or_else(|| "root".to_string());
-    if !sections.iter().any(|s| s.id == parent) {
-        return Err("Parent section not found".into());
-    }
-    let id = uuid::Uuid::new_v4().to_string();
+    notebook_id: Option<String>,
+) -> AppResult<Section> {
     let now = chrono::Utc::now().timestamp();
+    let parent_id = match parent_id {
+        Some(parent_id) => parent_id,
+        None => notebook_root(&pool, notebook_id).await?,
+    };
     let section = Section {
-        id: id.clone(),
+        id: uuid::Uuid::new_v4().to_string(),
         name: if name.is_empty() { "Untitled Section".to_string() } else { name },
-        parent_id: Some(parent),
+        parent_id: Some(parent_id),
         created_at: now,
         updated_at: now,
+        sort_order: 0,
+        notebook_id: None,
     };
-    sections.push(section.clone());
-    save_sections(&app_handle, &sections)?;
-    Ok(section)
+    database::create_section(&pool, &section).await
 }
 
 #[tauri::command]
-pub fn update_section(
-    app_handle: tauri::AppHandle,
+pub async fn update_section(
+    pool: tauri::State<'_, SqlitePool>,
     id: String,
     name: String,
-) -> Result<Section, String> {
-    let mut sections = load_sections(&app_handle)?;
+) -> AppResult<Section> {
+    let name = if name.is_empty() { "Untitled Section".to_string() } else { name };
     let now = chrono::Utc::now().timestamp();
-    let mut updated: Option<Section> = None;
-    if let Some(sec) = sections.iter_mut().find(|s| s.id == id) {
-        sec.name = if name.is_empty() { "Untitled Section".to_string() } else { name };
-        sec.updated_at = now;
-        updated = Some(sec.clone());
-    }
+    database::rename_section(&pool, &id, &name, now).await
+}
 
-    if updated.is_none() {
-        return Err("Section not found".into());
-    }
+#[tauri::command]
+pub async fn delete_section(
+    pool: tauri::State<'_, SqlitePool>,
+    id: String,
+    mode: Option<SectionDeleteMode>,
+) -> AppResult<()> {
+    let now = chrono::Utc::now().timestamp();
+    database::delete_section(&pool, &id, mode.unwrap_or_default(), now).await
+}
 
-    save_sections(&app_handle, &sections)?;
-    Ok(updated.unwrap())
+#[tauri::command]
+pub async fn move_section(
+    pool: tauri::State<'_, SqlitePool>,
+    id: String,
+    new_parent_id: String,
+    position: Option<usize>,
+) -> AppResult<Section> {
+    let now = chrono::Utc::now().timestamp();
+    database::move_section(&pool, &id, &new_parent_id, position, now).await
 }
 
 #[tauri::command]
-pub fn delete_section(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
-    if id == "root" {
-        return Err("Cannot delete root section".into());
-    }
-    let mut sections = load_sections(&app_handle)?;
-    if sections.iter().any(|s| s.parent_id.as_deref() == Some(&id)) {
-        return Err("Section has child sections".into());
-    }
-    let pages_in = count_pages_in_section(&app_handle, &id)?;
-    if pages_in > 0 {
-        return Err("Section has pages; move or delete them first".into());
-    }
-    let before = sections.len();
-    sections.retain(|s| s.id != id);
-    if sections.len() == before {
-        return Err("Section not found".into());
+pub async fn move_pages(
+    pool: tauri::State<'_, SqlitePool>,
+    page_ids: Vec<String>,
+    section_id: String,
+) -> AppResult<Vec<WikiPage>> {
+    let now = chrono::Utc::now().timestamp();
+    database::move_pages(&pool, &page_ids, &section_id, now).await
+}
+
+#[tauri::command]
+pub async fn list_notebooks(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<Notebook>> {
+    database::list_notebooks(&pool).await
+}
+
+#[tauri::command]
+pub async fn create_notebook(pool: tauri::State<'_, SqlitePool>, name: String) -> AppResult<Notebook> {
+    let name = if name.trim().is_empty() { "Untitled Notebook".to_string() } else { name };
+    let now = chrono::Utc::now().timestamp();
+    database::create_notebook(&pool, &uuid::Uuid::new_v4().to_string(), &name, now).await
+}
+
+#[tauri::command]
+pub async fn rename_notebook(pool: tauri::State<'_, SqlitePool>, id: String, name: String) -> AppResult<Notebook> {
+    if name.trim().is_empty() {
+        return Err(AppError::validation("Notebook name cannot be empty"));
     }
-    save_sections(&app_handle, &sections)?;
-    Ok(())
+    let now = chrono::Utc::now().timestamp();
+    database::rename_notebook(&pool, &id, &name, now).await
+}
+
+// Deletes the notebook's sections and moves its pages to the trash
+#[tauri::command]
+pub async fn delete_notebook(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<()> {
+    info!("Deleting notebook {}", id);
+    database::delete_notebook(&pool, &id, chrono::Utc::now().timestamp()).await
+}
+
+#[tauri::command]
+pub async fn list_notebook_pages(
+    pool: tauri::State<'_, SqlitePool>,
+    notebook_id: String,
+) -> AppResult<Vec<WikiPageList>> {
+    database::list_notebook_pages(&pool, &notebook_id).await
+}
+
+#[tauri::command]
+pub async fn move_pages_to_notebook(
+    pool: tauri::State<'_, SqlitePool>,
+    page_ids: Vec<String>,
+    notebook_id: String,
+) -> AppResult<Vec<WikiPage>> {
+    let now = chrono::Utc::now().timestamp();
+    database::move_pages_to_notebook(&pool, &page_ids, &notebook_id, now).await
+}
+
+#[tauri::command]
+pub async fn list_templates(pool: tauri::State<'_, SqlitePool>) -> AppResult<Vec<WikiTemplate>> {
+    database::list_templates(&pool).await
+}
+
+#[tauri::command]
+pub async fn get_template(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<WikiTemplate> {
+    database::get_template(&pool, &id).await
+}
+
+#[tauri::command]
+pub async fn create_template(
+    pool: tauri::State<'_, SqlitePool>,
+    template: WikiTemplateDraft,
+) -> AppResult<WikiTemplate> {
+    let now = chrono::Utc::now().timestamp();
+    let template = WikiTemplate {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: if template.name.trim().is_empty() { "Untitled Template".to_string() } else { template.name },
+        title: template.title,
+        content: template.content,
+        tags: template.tags,
+        section_id: template.section_id,
+        created_at: now,
+        updated_at: now,
+        version: 1,
+    };
+    database::create_template(&pool, &template).await?;
+    Ok(template)
+}
+
+// Saves the template as a new version; `expected_version` works as for pages
+#[tauri::command]
+pub async fn update_template(
+    pool: tauri::State<'_, SqlitePool>,
+    id: String,
+    template: WikiTemplateDraft,
+    expected_version: Option<i64>,
+) -> AppResult<WikiTemplate> {
+    let current = database::get_template(&pool, &id).await?;
+    let template = WikiTemplate {
+        name: if template.name.trim().is_empty() { current.name.clone() } else { template.name },
+        title: template.title,
+        content: template.content,
+        tags: template.tags,
+        section_id: template.section_id,
+        updated_at: chrono::Utc::now().timestamp(),
+        ..current
+    };
+    database::update_template(&pool, &template, expected_version).await
+}
+
+#[tauri::command]
+pub async fn delete_template(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<()> {
+    database::delete_template(&pool, &id).await
+}
+
+#[tauri::command]
+pub async fn list_template_revisions(
+    pool: tauri::State<'_, SqlitePool>,
+    template_id: String,
+) -> AppResult<Vec<WikiTemplateRevision>> {
+    database::list_template_revisions(&pool, &template_id).await
+}
+
+#[tauri::command]
+pub async fn restore_template_revision(
+    pool: tauri::State<'_, SqlitePool>,
+    template_id: String,
+    revision_id: i64,
+) -> AppResult<WikiTemplate> {
+    let now = chrono::Utc::now().timestamp();
+    database::restore_template_revision(&pool, &template_id, revision_id, now).await
+}
+
+// Placeholders in the template the caller has to supply, besides the built-in
+// ones ({{date}}, {{time}}, {{week}}...) and {{title}}
+#[tauri::command]
+pub async fn get_template_variables(pool: tauri::State<'_, SqlitePool>, id: String) -> AppResult<Vec<String>> {
+    let template = database::get_template(&pool, &id).await?;
+    Ok(wiki::template_variables(&template))
+}
+
+// Creates a page from a template, expanding {{placeholders}} in its title,
+// content and tags with `vars` and the built-in values. The page goes to
+// `section_id`, else to the template's section, else to the default notebook.
+#[tauri::command]
+pub async fn create_wiki_page_from_template(
+    pool: tauri::State<'_, SqlitePool>,
+    template_id: String,
+    vars: Option<HashMap<String, String>>,
+    section_id: Option<String>,
+) -> AppResult<WikiPage> {
+    let template = database::get_template(&pool, &template_id).await?;
+    let (title, content, tags) = wiki::instantiate_template(&template, &vars.unwrap_or_default(), chrono::Local::now());
+
+    let timestamp = chrono::Utc::now().timestamp();
+    let section_id = match section_id.or_else(|| template.section_id.clone()) {
+        Some(section_id) => section_id,
+        None => notebook_root(&pool, None).await?,
+    };
+    let target_section = database::ensure_section(&pool, Some(section_id), timestamp).await?;
+    let notebook = database::section_notebook(&mut *pool.acquire().await?, Some(&target_section.id)).await?;
+
+    let page = WikiPage {
+        id: wiki::new_page_id(),
+        title,
+        content,
+        tags,
+        notebook: notebook.name,
+        section: target_section.name,
+        section_id: Some(target_section.id),
+        created_at: timestamp,
+        updated_at: timestamp,
+        version: 1,
+    };
+
+    database::create_wiki_page(&pool, &page).await?;
+    info!("Created wiki page {} from template {}", page.id, template_id);
+    Ok(page)
+}
+
+// Root section of a notebook, of the default notebook without one
+async fn notebook_root(pool: &SqlitePool, notebook_id: Option<String>) -> AppResult<String> {
+    let id = notebook_id.unwrap_or_else(|| DEFAULT_NOTEBOOK_ID.to_string());
+    database::find_notebook(&mut *pool.acquire().await?, &id)
+        .await?
+        .map(|notebook| notebook.root_section_id)
+        .ok_or_else(|| AppError::not_found(format!("Notebook {} not found", id)))
 }
@@ -1,5 +1,6 @@
 import { ref } from 'vue';
 import { invoke } from '@tauri-apps/api/core';
+import { errorMessage } from '../utils/errors';
 
 export interface WikiPage {
   id: string;
@@ -11,6 +12,7 @@ export interface WikiPage {
   section_id?: string | null;
   created_at: number;
   updated_at: number;
+  version: number;
 }
 
 export interface WikiPageList {
@@ -23,6 +25,66 @@ export interface WikiPageList {
   updated_at: number;
 }
 
+export interface SearchHighlight {
+  start: number;
+  end: number;
+}
+
+export interface WikiSearchResult extends WikiPageList {
+  score: number;
+  snippet: string;
+  highlights: SearchHighlight[];
+}
+
+export interface TrashedWikiPage extends WikiPageList {
+  deleted_at: number;
+}
+
+export interface WikiBacklink extends WikiPageList {
+  count: number;
+}
+
+export interface BrokenWikiLink {
+  source_id: string;
+  source_title: string;
+  target: string;
+  alias: string | null;
+}
+
+export interface WikiLinkGraph {
+  nodes: { id: string; title: string; section_id: string | null }[];
+  edges: { source: string; target: string; count: number }[];
+}
+
+// The fields of a reminder the wiki shows
+export interface LinkedReminder {
+  id: number;
+  title: string;
+  time: string;
+  completed: boolean;
+}
+
+// A file attached to a page; `url` is what the page content embeds
+export interface PageAttachment {
+  id: number;
+  page_id: string;
+  file_type: string;
+  file_name: string;
+  file_size: number;
+  mime_type: string;
+  description: string | null;
+  created_at: string;
+  url: string;
+}
+
+export interface WikiMergeResult {
+  title: string;
+  content: string;
+  tags: string[];
+  conflicts: number;
+  current: WikiPage;
+}
+
 export interface WikiRevisionMeta {
   id: string;
   page_id: string;
@@ -33,12 +95,132 @@ export interface WikiRevisionMeta {
   created_at: number;
 }
 
+export type DiffKind = 'equal' | 'insert' | 'delete';
+
+export interface DiffSegment {
+  text: string;
+  changed: boolean;
+}
+
+export interface DiffLine {
+  old_line: number | null;
+  new_line: number | null;
+  segments: DiffSegment[];
+}
+
+export interface DiffHunk {
+  kind: DiffKind;
+  lines: DiffLine[];
+}
+
+export interface FieldChange {
+  old: string;
+  new: string;
+}
+
+export interface WikiRevisionDiff {
+  page_id: string;
+  from: string;
+  to: string;
+  title: FieldChange | null;
+  section: FieldChange | null;
+  tags_added: string[];
+  tags_removed: string[];
+  hunks: DiffHunk[];
+}
+
+export interface BlameLine {
+  line: number;
+  text: string;
+  revision_id: string | null;
+  changed_at: number;
+}
+
 export interface Section {
   id: string;
   name: string;
   parent_id?: string | null;
   created_at: number;
   updated_at: number;
+  sort_order: number;
+  notebook_id?: string | null;
+}
+
+// Title, content and tags may hold {{placeholders}}: {{title}}, {{date}},
+// {{time}}, {{datetime}}, {{year}}, {{month}}, {{day}}, {{weekday}}, {{week}}
+// and any custom name, filled in when a page is created from the template
+export interface WikiTemplate {
+  id: string;
+  name: string;
+  title: string;
+  content: string;
+  tags: string[];
+  section_id: string | null;
+  created_at: number;
+  updated_at: number;
+  version: number;
+}
+
+export interface WikiTemplateDraft {
+  name: string;
+  title: string;
+  content: string;
+  tags: string[];
+  section_id?: string | null;
+}
+
+export interface WikiTemplateRevision extends WikiTemplateDraft {
+  id: number;
+  template_id: string;
+  version: number;
+  created_at: number;
+}
+
+// Each notebook owns a section tree; its root section has no parent
+export interface Notebook {
+  id: string;
+  name: string;
+  root_section_id: string;
+  sort_order: number;
+  created_at: number;
+  updated_at: number;
+}
+
+// empty: refuse unless empty; cascade: delete subsections and trash their pages;
+// reparent: hand subsections and pages to the parent section
+export type SectionDeleteMode = 'empty' | 'cascade' | 'reparent';
+
+export type WikiExportFormat = 'folder' | 'zip';
+
+export interface MarkdownImportEntry {
+  path: string;
+  title: string;
+  section: string;
+  reason?: string | null;
+}
+
+export interface MarkdownImportReport {
+  dry_run: boolean;
+  sections: string[];
+  pages: MarkdownImportEntry[];
+  conflicts: MarkdownImportEntry[];
+  skipped: MarkdownImportEntry[];
+  images: number;
+  missing_images: string[];
+}
+
+export interface WikiExportReport {
+  path: string;
+  pages: number;
+  sections: number;
+  links: number;
+  broken_links: number;
+}
+
+export interface DocumentImportProgress {
+  path: string;
+  stage: 'converting' | 'saving' | 'done';
+  progress: number;
 }
 
 const pages = ref<WikiPageList[]>([]);
@@ -46,6 +228,7 @@ const currentPage = ref<WikiPage | null>(null);
 const isLoading = ref(false);
 const error = ref<string | null>(null);
 const sections = ref<Section[]>([]);
+const notebooks = ref<Notebook[]>([]);
 
 export function useWiki() {
   async function loadPages() {
@@ -54,7 +237,7 @@ export function useWiki() {
     try {
       pages.value = await invoke<WikiPageList[]>('list_wiki_pages');
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to load pages:', e);
     } finally {
       isLoading.value = false;
@@ -67,7 +250,7 @@ export function useWiki() {
     try {
       currentPage.value = await invoke<WikiPage>('get_wiki_page', { id });
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to load page:', e);
     } finally {
       isLoading.value = false;
@@ -83,7 +266,7 @@ export function useWiki() {
       currentPage.value = page;
       return page;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to create page:', e);
       throw e;
     } finally {
@@ -91,16 +274,26 @@ export function useWiki() {
     }
   }
 
-  async function updatePage(id: string, title: string, content: string, tags: string[], sectionId?: string) {
+  async function updatePage(
+    id: string,
+    title: string,
+    content: string,
+    tags: string[],
+    sectionId?: string,
+    expectedVersion?: number,
+    rewriteLinks = false
+  ) {
     isLoading.value = true;
     error.value = null;
     try {
-      const page = await invoke<WikiPage>('update_wiki_page', { id, title, content, tags, sectionId });
+      const page = await invoke<WikiPage>('update_wiki_page', {
+        id, title, content, tags, sectionId, expectedVersion, rewriteLinks
+      });
       await loadPages();
       currentPage.value = page;
       return page;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to update page:', e);
       throw e;
     } finally {
@@ -108,13 +301,47 @@ export function useWiki() {
     }
   }
 
+  // Merges an edit based on an older version into the saved page without saving it
+  async function mergePage(id: string, baseVersion: number, title: string, content: string, tags: string[]) {
+    try {
+      const result = await invoke<WikiMergeResult>('merge_wiki_page', { id, baseVersion, title, content, tags });
+      currentPage.value = result.current;
+      return result;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to merge page:', e);
+      throw e;
+    }
+  }
+
+  // `to` defaults to the current page
+  async function diffRevisions(pageId: string, from: string, to?: string) {
+    try {
+      return await invoke<WikiRevisionDiff>('diff_wiki_revisions', { pageId, from, to });
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to diff revisions:', e);
+      throw e;
+    }
+  }
+
+  async function blamePage(pageId: string) {
+    try {
+      return await invoke<BlameLine[]>('blame_wiki_page', { pageId });
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to load page history:', e);
+      throw e;
+    }
+  }
+
   async function loadSections() {
     isLoading.value = true;
     error.value = null;
     try {
       sections.value = await invoke<Section[]>('list_sections');
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to load sections:', e);
     } finally {
       isLoading.value = false;
@@ -129,7 +356,7 @@ export function useWiki() {
       await loadSections();
       return section;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to create section:', e);
       throw e;
     } finally {
@@ -145,7 +372,7 @@ export function useWiki() {
       await loadSections();
       return section;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to update section:', e);
       throw e;
     } finally {
@@ -153,14 +380,189 @@ export function useWiki() {
     }
   }
 
-  async function deleteSection(id: string) {
+  async function listTemplates() {
+    try {
+      return await invoke<WikiTemplate[]>('list_templates');
+    } catch (e) {
+      console.error('[WIKI] Failed to list templates:', e);
+      return [];
+    }
+  }
+
+  async function createTemplate(template: WikiTemplateDraft) {
+    return await invoke<WikiTemplate>('create_template', { template });
+  }
+
+  async function updateTemplate(id: string, template: WikiTemplateDraft, expectedVersion?: number) {
+    return await invoke<WikiTemplate>('update_template', { id, template, expectedVersion });
+  }
+
+  async function deleteTemplate(id: string) {
+    await invoke('delete_template', { id });
+  }
+
+  async function listTemplateRevisions(templateId: string) {
+    return await invoke<WikiTemplateRevision[]>('list_template_revisions', { templateId });
+  }
+
+  async function restoreTemplateRevision(templateId: string, revisionId: number) {
+    return await invoke<WikiTemplate>('restore_template_revision', { templateId, revisionId });
+  }
+
+  async function getTemplateVariables(id: string) {
+    return await invoke<string[]>('get_template_variables', { id });
+  }
+
+  async function createPageFromTemplate(templateId: string, vars: Record<string, string>, sectionId?: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      const page = await invoke<WikiPage>('create_wiki_page_from_template', { templateId, vars, sectionId });
+      await loadPages();
+      currentPage.value = page;
+      return page;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to create page from template:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function loadNotebooks() {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      notebooks.value = await invoke<Notebook[]>('list_notebooks');
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to load notebooks:', e);
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function createNotebook(name: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      const notebook = await invoke<Notebook>('create_notebook', { name });
+      await Promise.all([loadNotebooks(), loadSections()]);
+      return notebook;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to create notebook:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function renameNotebook(id: string, name: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      const notebook = await invoke<Notebook>('rename_notebook', { id, name });
+      await Promise.all([loadNotebooks(), loadSections(), loadPages()]);
+      return notebook;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to rename notebook:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function deleteNotebook(id: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      await invoke('delete_notebook', { id });
+      await Promise.all([loadNotebooks(), loadSections(), loadPages()]);
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to delete notebook:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function listNotebookPages(notebookId: string) {
+    try {
+      return await invoke<WikiPageList[]>('list_notebook_pages', { notebookId });
+    } catch (e) {
+      console.error('[WIKI] Failed to list notebook pages:', e);
+      return [];
+    }
+  }
+
+  async function movePagesToNotebook(pageIds: string[], notebookId: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      const moved = await invoke<WikiPage[]>('move_pages_to_notebook', { pageIds, notebookId });
+      const current = currentPage.value;
+      if (current) {
+        currentPage.value = moved.find(p => p.id === current.id) ?? current;
+      }
+      await loadPages();
+      return moved;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to move pages to notebook:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function moveSection(id: string, newParentId: string, position?: number) {
     isLoading.value = true;
     error.value = null;
     try {
-      await invoke('delete_section', { id });
+      const section = await invoke<Section>('move_section', { id, newParentId, position });
       await loadSections();
+      return section;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to move section:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function movePages(pageIds: string[], sectionId: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      const moved = await invoke<WikiPage[]>('move_pages', { pageIds, sectionId });
+      const current = currentPage.value;
+      if (current) {
+        currentPage.value = moved.find(p => p.id === current.id) ?? current;
+      }
+      await loadPages();
+      return moved;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to move pages:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  async function deleteSection(id: string, mode: SectionDeleteMode = 'empty') {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      await invoke('delete_section', { id, mode });
+      await Promise.all([loadSections(), loadPages()]);
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to delete section:', e);
       throw e;
     } finally {
@@ -178,7 +580,7 @@ export function useWiki() {
         currentPage.value = null;
       }
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to delete page:', e);
       throw e;
     } finally {
@@ -190,10 +592,10 @@ export function useWiki() {
     isLoading.value = true;
     error.value = null;
     try {
-      const results = await invoke<WikiPageList[]>('search_wiki_pages', { query });
+      const results = await invoke<WikiSearchResult[]>('search_wiki_pages', { query });
       return results;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to search pages:', e);
       return [];
     } finally {
@@ -201,6 +603,31 @@ export function useWiki() {
     }
   }
 
+  // With dryRun nothing is written; the report says what an import would do
+  async function importMarkdownDirectory(path: string, targetSectionId: string, dryRun: boolean) {
+    const report = await invoke<MarkdownImportReport>('import_markdown_directory', {
+      path,
+      targetSectionId,
+      dryRun,
+    });
+    if (!dryRun) {
+      await Promise.all([loadPages(), loadSections()]);
+    }
+    return report;
+  }
+
+  // Converts a PDF, HTML, ODT, RTF or text file on the backend into a new
+  // page; progress arrives as "document-import-progress" events
+  async function importDocumentFile(path: string, sectionId?: string) {
+    const page = await invoke<WikiPage>('import_document', { path, sectionId });
+    await Promise.all([loadPages(), loadSections()]);
+    return page;
+  }
+
+  async function exportWiki(target: string, format: WikiExportFormat = 'folder') {
+    return await invoke<WikiExportReport>('export_wiki', { target, format });
+  }
+
   async function listRevisions(pageId: string) {
     isLoading.value = true;
     error.value = null;
@@ -208,7 +635,7 @@ export function useWiki() {
       const revisions = await invoke<WikiRevisionMeta[]>('list_wiki_revisions', { pageId });
       return revisions;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to list revisions:', e);
       return [];
     } finally {
@@ -225,7 +652,7 @@ export function useWiki() {
       currentPage.value = page;
       return page;
     } catch (e) {
-      error.value = e as string;
+      error.value = errorMessage(e);
       console.error('[WIKI] Failed to restore revision:', e);
       throw e;
     } finally {
@@ -233,6 +660,135 @@ export function useWiki() {
     }
   }
 
+  async function getBacklinks(pageId: string) {
+    try {
+      return await invoke<WikiBacklink[]>('get_backlinks', { pageId });
+    } catch (e) {
+      console.error('[WIKI] Failed to load backlinks:', e);
+      return [];
+    }
+  }
+
+  async function findBrokenLinks() {
+    try {
+      return await invoke<BrokenWikiLink[]>('find_broken_links');
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to find broken links:', e);
+      return [];
+    }
+  }
+
+  async function getLinkGraph() {
+    try {
+      return await invoke<WikiLinkGraph>('get_link_graph');
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to load link graph:', e);
+      return { nodes: [], edges: [] };
+    }
+  }
+
+  async function listReminders() {
+    try {
+      return await invoke<LinkedReminder[]>('get_reminders');
+    } catch (e) {
+      console.error('[WIKI] Failed to load reminders:', e);
+      return [];
+    }
+  }
+
+  async function getPageReminders(pageId: string) {
+    try {
+      return await invoke<LinkedReminder[]>('get_page_reminders', { pageId });
+    } catch (e) {
+      console.error('[WIKI] Failed to load linked reminders:', e);
+      return [];
+    }
+  }
+
+  async function listPageAttachments(pageId: string) {
+    try {
+      return await invoke<PageAttachment[]>('list_page_attachments', { pageId });
+    } catch (e) {
+      console.error('[WIKI] Failed to load attachments:', e);
+      return [];
+    }
+  }
+
+  async function uploadPageAttachment(pageId: string, file: File) {
+    const fileData = Array.from(new Uint8Array(await file.arrayBuffer()));
+    return await invoke<PageAttachment>('upload_page_attachment', {
+      pageId,
+      fileName: file.name,
+      fileData,
+      description: null,
+    });
+  }
+
+  async function deletePageAttachment(pageId: string, evidenceId: number) {
+    await invoke('delete_page_attachment', { pageId, evidenceId });
+  }
+
+  async function linkReminder(reminderId: number, pageId: string) {
+    try {
+      await invoke('link_reminder_to_page', { reminderId, pageId });
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to link reminder:', e);
+      throw e;
+    }
+  }
+
+  async function unlinkReminder(reminderId: number, pageId: string) {
+    try {
+      await invoke('unlink_reminder_from_page', { reminderId, pageId });
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to unlink reminder:', e);
+      throw e;
+    }
+  }
+
+  async function listTrashedPages() {
+    error.value = null;
+    try {
+      return await invoke<TrashedWikiPage[]>('list_trashed_pages');
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to list trash:', e);
+      return [];
+    }
+  }
+
+  async function restoreTrashedPage(id: string) {
+    isLoading.value = true;
+    error.value = null;
+    try {
+      const page = await invoke<WikiPage>('restore_trashed_page', { id });
+      await Promise.all([loadPages(), loadSections()]);
+      return page;
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to restore page from trash:', e);
+      throw e;
+    } finally {
+      isLoading.value = false;
+    }
+  }
+
+  // Without `olderThan` (Unix seconds) the whole trash is emptied
+  async function purgeTrash(olderThan?: number) {
+    error.value = null;
+    try {
+      return await invoke<number>('purge_trash', { olderThan });
+    } catch (e) {
+      error.value = errorMessage(e);
+      console.error('[WIKI] Failed to purge trash:', e);
+      throw e;
+    }
+  }
+
   function clearCurrentPage() {
     currentPage.value = null;
   }
@@ -243,18 +799,54 @@ export function useWiki() {
     isLoading,
     error,
     sections,
+    notebooks,
     loadPages,
     loadPage,
     createPage,
     updatePage,
+    mergePage,
     deletePage,
+    getBacklinks,
+    findBrokenLinks,
+    getLinkGraph,
+    listReminders,
+    getPageReminders,
+    listPageAttachments,
+    uploadPageAttachment,
+    deletePageAttachment,
+    linkReminder,
+    unlinkReminder,
+    listTrashedPages,
+    restoreTrashedPage,
+    purgeTrash,
     searchPages,
+    importMarkdownDirectory,
+    importDocumentFile,
+    exportWiki,
     clearCurrentPage,
     listRevisions,
     restoreRevision,
+    diffRevisions,
+    blamePage,
     loadSections,
     createSection,
     updateSection,
+    moveSection,
+    movePages,
     deleteSection,
+    loadNotebooks,
+    createNotebook,
+    renameNotebook,
+    deleteNotebook,
+    listNotebookPages,
+    movePagesToNotebook,
+    listTemplates,
+    createTemplate,
+    updateTemplate,
+    deleteTemplate,
+    listTemplateRevisions,
+    restoreTemplateRevision,
+    getTemplateVariables,
+    createPageFromTemplate,
   };
 }
@@ -0,0 +1,33 @@
+// Shape of the errors returned by Tauri commands (see src-tauri/src/error.rs)
+export interface AppError {
+  code: string;
+  message: string;
+  details: string | null;
+  current?: unknown;  // The saved page, on edit_conflict
+}
+
+// Codes worth retrying after a short delay
+const TRANSIENT_CODES = ['busy'];
+
+export function isAppError(e: unknown): e is AppError {
+  return typeof e === 'object' && e !== null && 'code' in e && 'message' in e;
+}
+
+export function errorMessage(e: unknown): string {
+  if (isAppError(e)) {
+    return e.details ? `${e.message}: ${e.details}` : e.message;
+  }
+  if (e instanceof Error) {
+    return e.message;
+  }
+  return String(e);
+}
+
+// The page was saved elsewhere since the version an edit started from
+export function isEditConflict(e: unknown): boolean {
+  return isAppError(e) && e.code === 'edit_conflict';
+}
+
+export function isTransientError(e: unknown): boolean {
+  return isAppError(e) && TRANSIENT_CODES.includes(e.code);
+}